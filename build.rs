@@ -5,5 +5,8 @@ fn main() -> Result<()> {
         &["protos/event.proto", "protos/challenge_storage.proto"],
         &["protos"],
     )?;
+    tonic_build::configure()
+        .build_client(false)
+        .compile(&["proto/analyzer.proto"], &["proto"])?;
     Ok(())
 }
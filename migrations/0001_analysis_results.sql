@@ -0,0 +1,18 @@
+CREATE TABLE analysis_runs (
+    id BIGSERIAL PRIMARY KEY,
+    challenge_uuid UUID NOT NULL,
+    program TEXT NOT NULL,
+    level TEXT NOT NULL,
+    status TEXT NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL,
+    finished_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX analysis_runs_challenge_uuid_idx ON analysis_runs (challenge_uuid);
+
+CREATE TABLE analyzer_outputs (
+    run_id BIGINT NOT NULL REFERENCES analysis_runs (id) ON DELETE CASCADE,
+    analyzer TEXT NOT NULL,
+    output JSONB NOT NULL,
+    PRIMARY KEY (run_id, analyzer)
+);
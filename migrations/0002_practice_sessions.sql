@@ -0,0 +1,22 @@
+CREATE TABLE practice_sessions (
+    id BIGSERIAL PRIMARY KEY,
+    type SMALLINT NOT NULL,
+    mode SMALLINT,
+    party TEXT[] NOT NULL,
+    resets INTEGER NOT NULL DEFAULT 0,
+    early_resets INTEGER NOT NULL DEFAULT 0,
+    completions INTEGER NOT NULL DEFAULT 0,
+    best_ticks INTEGER,
+    started_at TIMESTAMPTZ NOT NULL,
+    ended_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX practice_sessions_party_idx ON practice_sessions USING GIN (party);
+
+CREATE TABLE practice_session_challenges (
+    challenge_uuid UUID PRIMARY KEY,
+    session_id BIGINT NOT NULL REFERENCES practice_sessions (id) ON DELETE CASCADE
+);
+
+CREATE INDEX practice_session_challenges_session_id_idx
+    ON practice_session_challenges (session_id);
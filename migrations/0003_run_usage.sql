@@ -0,0 +1 @@
+ALTER TABLE analysis_runs ADD COLUMN usage JSONB;
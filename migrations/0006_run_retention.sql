@@ -0,0 +1 @@
+CREATE INDEX analysis_runs_level_finished_at_idx ON analysis_runs (level, finished_at);
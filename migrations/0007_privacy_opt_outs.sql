@@ -0,0 +1,4 @@
+CREATE TABLE player_privacy_opt_outs (
+    username TEXT PRIMARY KEY,
+    opted_out_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,4 @@
+ALTER TABLE analysis_runs ADD COLUMN program_version TEXT;
+
+CREATE INDEX analysis_runs_cache_idx
+    ON analysis_runs (challenge_uuid, program, level, program_version);
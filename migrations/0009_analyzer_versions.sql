@@ -0,0 +1 @@
+ALTER TABLE analyzer_outputs ADD COLUMN analyzer_version INTEGER;
@@ -0,0 +1,14 @@
+CREATE TABLE analyzer_runs (
+    run_id BIGINT NOT NULL REFERENCES analysis_runs (id) ON DELETE CASCADE,
+    challenge_uuid UUID NOT NULL,
+    run_number INTEGER NOT NULL,
+    analyzer TEXT NOT NULL,
+    status TEXT NOT NULL,
+    wall_time_us BIGINT NOT NULL,
+    output_bytes BIGINT,
+    error TEXT,
+    PRIMARY KEY (run_id, analyzer)
+);
+
+CREATE INDEX analyzer_runs_challenge_run_idx ON analyzer_runs (challenge_uuid, run_number);
+CREATE INDEX analyzer_runs_analyzer_wall_time_idx ON analyzer_runs (analyzer, wall_time_us);
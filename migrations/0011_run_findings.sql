@@ -0,0 +1 @@
+ALTER TABLE analysis_runs ADD COLUMN findings JSONB;
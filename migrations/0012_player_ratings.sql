@@ -0,0 +1,13 @@
+CREATE TABLE player_ratings (
+    username TEXT NOT NULL,
+    role TEXT NOT NULL,
+    rating DOUBLE PRECISION NOT NULL,
+    challenges INTEGER NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (username, role)
+);
+
+CREATE TABLE rated_challenges (
+    challenge_uuid UUID PRIMARY KEY,
+    rated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,9 @@
+CREATE TABLE personal_bests (
+    holder TEXT NOT NULL,
+    category TEXT NOT NULL,
+    split TEXT NOT NULL,
+    ticks INTEGER NOT NULL,
+    challenge_uuid UUID NOT NULL,
+    achieved_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (holder, category, split)
+);
@@ -0,0 +1,3 @@
+ALTER TABLE analysis_runs ADD COLUMN player_scope TEXT[];
+ALTER TABLE analysis_runs ADD COLUMN stage_scope TEXT[];
+ALTER TABLE analysis_runs ADD COLUMN locale TEXT;
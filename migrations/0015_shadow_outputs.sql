@@ -0,0 +1,7 @@
+CREATE TABLE shadow_outputs (
+    run_id BIGINT NOT NULL REFERENCES analysis_runs (id) ON DELETE CASCADE,
+    analyzer TEXT NOT NULL,
+    output JSONB NOT NULL,
+    analyzer_version INTEGER,
+    PRIMARY KEY (run_id, analyzer)
+);
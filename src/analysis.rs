@@ -1,21 +1,49 @@
 use std::any::Any;
-use std::collections::{BTreeMap, HashMap};
-use std::path::Path;
-use std::sync::{Arc, RwLock};
-use std::time::Instant;
-
-use futures::future::{self, TryFutureExt};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use futures::future::{self, BoxFuture, TryFutureExt};
+use futures::stream::{self, StreamExt};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::types::time::OffsetDateTime;
 use tokio::fs;
-use tokio::sync::mpsc;
+use tokio::runtime::Handle;
+use tokio::sync::{mpsc, oneshot, Notify};
 use tokio::task::JoinHandle;
+use tracing::Instrument;
+use utoipa::ToSchema;
+use uuid::Uuid;
 
 use crate::analyzers::init_analyzer;
+use crate::analyzers::personal_best_analyzer::PersonalBests;
+use crate::blackboard::Blackboard;
 use crate::challenge::Challenge;
+use crate::data_repository::DataRepository;
+use crate::dispatch::{self, Priority};
 use crate::error::{Error, Result};
-use crate::item;
-
-#[derive(Debug, Clone, Copy)]
+use crate::findings::{Finding, Findings};
+use crate::history::HistoryProvider;
+use crate::jobs::{self, CancellationToken, Event, Job, Status};
+use crate::links::ReplayLinks;
+use crate::load::{LoadMetrics, LoadSheddingPolicy, RunLimiter, ShedAction, ShedCounters};
+use crate::messages::Catalog;
+use crate::privacy::Pseudonymizer;
+use crate::ratings::{self, Performance};
+use crate::report::discord::{ReportedOutput, Reporter};
+use crate::results::{self, AnalyzerTelemetry, RunRecord, RunStatus};
+use crate::sandbox::{self, Limits, Sandbox};
+use crate::sessions::Session;
+use crate::usage::ResourceUsage;
+use crate::{blert, item, npc, webhook};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema, JsonSchema)]
+#[serde(rename_all = "lowercase")]
 pub enum Level {
     /// Base level of analysis run on every recorded challenge. Prioritizes
     /// speed and simplicity.
@@ -34,25 +62,56 @@ pub enum Level {
     MaxEff,
 }
 
+impl std::fmt::Display for Level {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Level::Basic => write!(f, "basic"),
+            Level::Learner => write!(f, "learner"),
+            Level::Casual => write!(f, "casual"),
+            Level::MaxEff => write!(f, "maxeff"),
+        }
+    }
+}
+
 /// An analysis `Context` provides information about the active analysis program run.
 pub struct Context {
     challenge: Arc<Challenge>,
     item_registry: Arc<item::Registry>,
+    npc_registry: Arc<npc::Registry>,
     level: Level,
+    player_scope: Option<Arc<HashSet<String>>>,
+    blackboard: Arc<Blackboard>,
+    /// Name of the analyzer to which the context was given, within its program.
+    analyzer: String,
+    findings: Arc<Findings>,
+    history: Option<Arc<HistoryProvider>>,
     completed_analyzers: Arc<RwLock<HashMap<String, Box<dyn RunnableAnalyzer>>>>,
 }
 
 impl Context {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         challenge: Arc<Challenge>,
         item_registry: Arc<item::Registry>,
+        npc_registry: Arc<npc::Registry>,
         level: Level,
+        player_scope: Option<Arc<HashSet<String>>>,
+        blackboard: Arc<Blackboard>,
+        analyzer: &str,
+        findings: Arc<Findings>,
+        history: Option<Arc<HistoryProvider>>,
         completed_analyzers: Arc<RwLock<HashMap<String, Box<dyn RunnableAnalyzer>>>>,
     ) -> Self {
         Self {
             challenge,
             item_registry,
+            npc_registry,
             level,
+            player_scope,
+            blackboard,
+            analyzer: analyzer.to_owned(),
+            findings,
+            history,
             completed_analyzers,
         }
     }
@@ -64,14 +123,93 @@ impl Context {
 
     /// Returns the challenge being analyzed.
     pub fn challenge(&self) -> &Challenge {
+        sandbox::checkpoint();
         &self.challenge
     }
 
+    /// Returns whether a player is within the scope of the analysis. If the run was not scoped
+    /// to specific players, every player is in scope.
+    pub fn is_player_in_scope(&self, username: &str) -> bool {
+        self.player_scope
+            .as_ref()
+            .map_or(true, |players| players.contains(username))
+    }
+
+    /// Returns whether a stage is within the scope of the analysis. If the challenge was not
+    /// loaded for specific stages, every stage is in scope.
+    pub fn is_stage_in_scope(&self, stage: blert::Stage) -> bool {
+        self.challenge
+            .stage_scope()
+            .map_or(true, |stages| stages.contains(&stage))
+    }
+
+    /// Fails with [`Error::FailedPrecondition`] unless every one of `stages` is within the scope
+    /// of the analysis and has its events, for analyzers whose results are meaningless without
+    /// them.
+    pub fn require_stages(&self, stages: &[blert::Stage]) -> Result<()> {
+        for &stage in stages {
+            if !self.is_stage_in_scope(stage) {
+                return Err(Error::FailedPrecondition(format!(
+                    "{} requires stage {}, which is outside the analysis's stage scope",
+                    self.analyzer,
+                    stage.as_str_name()
+                )));
+            }
+            if self.challenge.missing_stages().contains(&stage) {
+                return Err(Error::FailedPrecondition(format!(
+                    "{} requires stage {}, whose events are missing",
+                    self.analyzer,
+                    stage.as_str_name()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails with [`Error::FailedPrecondition`] if the analysis is scoped to specific stages or
+    /// any of the challenge's recorded stages is missing its events.
+    pub fn require_all_stages(&self) -> Result<()> {
+        if self.challenge.stage_scope().is_some() {
+            return Err(Error::FailedPrecondition(format!(
+                "{} requires every stage of the challenge",
+                self.analyzer
+            )));
+        }
+        self.require_stages(self.challenge.missing_stages())
+    }
+
+    /// Returns the blackboard shared by every analyzer in the program run.
+    ///
+    /// Values published to the blackboard are only guaranteed to be visible to analyzers which
+    /// depend on the publishing analyzer.
+    pub fn blackboard(&self) -> &Blackboard {
+        sandbox::checkpoint();
+        &self.blackboard
+    }
+
+    /// Reports a finding about the challenge. Findings of every analyzer which completes are
+    /// collected into the results of the program run.
+    pub fn emit_finding(&self, finding: Finding) {
+        sandbox::checkpoint();
+        self.findings.add(&self.analyzer, finding);
+    }
+
+    /// Returns a provider of the results of previous analyses, if the engine stores results.
+    pub fn history(&self) -> Option<&HistoryProvider> {
+        sandbox::checkpoint();
+        self.history.as_deref()
+    }
+
     /// Returns a registry of all known game items.
     pub fn item_registry(&self) -> &item::Registry {
         &self.item_registry
     }
 
+    /// Returns a registry of all known NPCs.
+    pub fn npc_registry(&self) -> &npc::Registry {
+        &self.npc_registry
+    }
+
     /// Returns the output of a dependency of the current analyzer.
     /// If the dependency is optional, may return `None`.
     pub fn get_dependency_output<A: Analyzer + 'static>(&self) -> Option<Arc<A::Output>> {
@@ -85,6 +223,40 @@ impl Context {
                     .and_then(|a| a.output.clone())
             })
     }
+
+    /// Returns the findings emitted by every analyzer in the program run which has completed,
+    /// including all of the current analyzer's dependencies.
+    pub fn dependency_findings(&self) -> Vec<Finding> {
+        let completed = self.completed_analyzers.read().unwrap();
+        self.findings
+            .collect(|analyzer| completed.contains_key(analyzer))
+    }
+
+    /// Returns the JSON outputs of every analyzer in the program run which has completed,
+    /// keyed by analyzer name. Used to expose dependency outputs to analyzer plugins, which
+    /// cannot access them by type.
+    #[cfg(feature = "plugins")]
+    pub(crate) fn completed_outputs(&self) -> BTreeMap<String, serde_json::Value> {
+        self.completed_analyzers
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(|(name, analyzer)| {
+                let output = analyzer.output_json()?.ok()?;
+                Some((name.clone(), output))
+            })
+            .collect()
+    }
+
+    /// Returns the per-player output of a `PlayerAnalyzer` dependency of the current analyzer.
+    /// If the dependency is optional, may return `None`.
+    pub fn get_player_dependency_output<A>(&self) -> Option<Arc<PlayerOutputs<A::Output>>>
+    where
+        A: PlayerAnalyzer + Sync + 'static,
+        <A as PlayerAnalyzer>::Output: Send,
+    {
+        self.get_dependency_output::<PerPlayer<A>>()
+    }
 }
 
 pub trait Analyzer {
@@ -94,14 +266,140 @@ pub trait Analyzer {
     /// Returns a globally unique name for the analyzer implementation.
     fn name(&self) -> &str;
 
+    /// Returns the version of the analyzer implementation. Bump it whenever a change affects the
+    /// analyzer's output, so challenges analyzed by older versions can be found and reanalyzed.
+    fn version(&self) -> u32 {
+        1
+    }
+
+    /// Returns the fields of the analyzer's serialized output which hold player usernames: maps
+    /// keyed by username, lists of usernames, or single usernames. Each field is a path of object
+    /// keys separated by `/`, in which `*` matches every element of an array, and the empty path
+    /// refers to the whole output. The names of opted-out players are pseudonymized in these
+    /// fields, and only these, when the output is persisted.
+    fn player_fields(&self) -> &'static [&'static str] {
+        &[]
+    }
+
     fn analyze(&self, context: &Context) -> Result<Self::Output>;
 }
 
+/// An analyzer which independently analyzes each player in a challenge.
+///
+/// The engine runs a `PlayerAnalyzer` once for every member of the party within the scope of the
+/// analysis, in parallel, and merges the results into a [`PlayerOutputs`] map keyed by username.
+pub trait PlayerAnalyzer {
+    /// Output produced for a single player.
+    type Output;
+
+    /// Returns a globally unique name for the analyzer implementation.
+    fn name(&self) -> &str;
+
+    /// Returns the version of the analyzer implementation. See [`Analyzer::version`].
+    fn version(&self) -> u32 {
+        1
+    }
+
+    fn analyze_player(&self, context: &Context, username: &str) -> Result<Self::Output>;
+
+    /// Returns whether the analyzer must run for every member of the party regardless of the
+    /// run's player scope, e.g. because its outputs are needed by party-wide analyzers.
+    fn ignores_player_scope(&self) -> bool {
+        false
+    }
+}
+
+/// Per-player outputs of a `PlayerAnalyzer`.
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(transparent)]
+pub struct PlayerOutputs<T> {
+    outputs: HashMap<String, T>,
+}
+
+impl<T> PlayerOutputs<T> {
+    /// Returns the output for the specified player.
+    pub fn player(&self, username: &str) -> Option<&T> {
+        self.outputs.get(username)
+    }
+
+    /// Returns an iterator over every player's output.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &T)> {
+        self.outputs.iter().map(|(k, v)| (k.as_str(), v))
+    }
+}
+
+/// Adapts a `PlayerAnalyzer` into an `Analyzer` which fans out over the party.
+#[derive(Debug)]
+pub struct PerPlayer<A>(A);
+
+impl<A> Analyzer for PerPlayer<A>
+where
+    A: PlayerAnalyzer + Sync,
+    <A as PlayerAnalyzer>::Output: Send,
+{
+    type Output = PlayerOutputs<A::Output>;
+
+    fn name(&self) -> &str {
+        self.0.name()
+    }
+
+    fn version(&self) -> u32 {
+        self.0.version()
+    }
+
+    fn player_fields(&self) -> &'static [&'static str] {
+        &[""]
+    }
+
+    fn analyze(&self, context: &Context) -> Result<Self::Output> {
+        let ignore_scope = self.0.ignores_player_scope();
+        let sandbox = Sandbox::current();
+
+        let outputs = std::thread::scope(|scope| {
+            let handles = context
+                .challenge()
+                .party()
+                .iter()
+                .filter(|username| ignore_scope || context.is_player_in_scope(username))
+                .map(|username| {
+                    let sandbox = sandbox.clone();
+                    scope.spawn(move || {
+                        let _guard = sandbox.as_ref().map(Sandbox::enter);
+                        self.0
+                            .analyze_player(context, username)
+                            .map(|output| (username.clone(), output))
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle
+                        .join()
+                        .unwrap_or_else(|e| std::panic::resume_unwind(e))
+                })
+                .collect::<Result<HashMap<_, _>>>()
+        })?;
+
+        Ok(PlayerOutputs { outputs })
+    }
+}
+
 /// A specific instantiation of an `Analyzer` run within an analysis program.
 pub trait RunnableAnalyzer: Send + Sync {
     fn name(&self) -> &str;
+    /// Returns the version of the underlying analyzer implementation.
+    fn version(&self) -> u32;
+    /// Returns the fields of the analyzer's output which hold player usernames. See
+    /// [`Analyzer::player_fields`].
+    fn player_fields(&self) -> &'static [&'static str];
     fn run(&mut self, context: &Context) -> Result<()>;
     fn as_any(&self) -> &dyn Any;
+
+    /// Serializes the analyzer's output to JSON for export outside of the engine.
+    /// Returns `None` if the analyzer has not yet successfully run.
+    fn output_json(&self) -> Option<Result<serde_json::Value>>;
 }
 
 #[derive(Debug)]
@@ -114,12 +412,20 @@ struct AnalyzerRun<A: Analyzer> {
 impl<A> RunnableAnalyzer for AnalyzerRun<A>
 where
     A: Analyzer + Send + Sync + 'static,
-    <A as Analyzer>::Output: Send + Sync,
+    <A as Analyzer>::Output: Send + Sync + Serialize,
 {
     fn name(&self) -> &str {
         self.analyzer_name.as_str()
     }
 
+    fn version(&self) -> u32 {
+        self.analyzer.version()
+    }
+
+    fn player_fields(&self) -> &'static [&'static str] {
+        self.analyzer.player_fields()
+    }
+
     fn run(&mut self, context: &Context) -> Result<()> {
         let output = self.analyzer.analyze(context)?;
         self.output = Some(Arc::new(output));
@@ -129,13 +435,19 @@ where
     fn as_any(&self) -> &dyn Any {
         self
     }
+
+    fn output_json(&self) -> Option<Result<serde_json::Value>> {
+        self.output
+            .as_ref()
+            .map(|output| serde_json::to_value(output.as_ref()).map_err(Error::from))
+    }
 }
 
 /// Wraps an instance of an `Analyzer` in a form runnable by the engine.
 pub fn wrap_analyzer<A>(name: String, analyzer: A) -> Box<dyn RunnableAnalyzer>
 where
     A: Analyzer + Send + Sync + 'static,
-    <A as Analyzer>::Output: Send + Sync,
+    <A as Analyzer>::Output: Send + Sync + Serialize,
 {
     Box::new(AnalyzerRun {
         analyzer_name: name,
@@ -144,48 +456,143 @@ where
     })
 }
 
+/// Wraps an instance of a `PlayerAnalyzer` in a form runnable by the engine.
+pub fn wrap_player_analyzer<A>(name: String, analyzer: A) -> Box<dyn RunnableAnalyzer>
+where
+    A: PlayerAnalyzer + Send + Sync + 'static,
+    <A as PlayerAnalyzer>::Output: Send + Sync + Serialize,
+{
+    wrap_analyzer(name, PerPlayer(analyzer))
+}
+
 struct WorkerRunRequest {
     analyzer: Box<dyn RunnableAnalyzer>,
     context: Context,
+    cancellation: CancellationToken,
+    label: RunLabel,
+    /// Span of the program run to which the analyzer belongs.
+    span: tracing::Span,
+    limits: Limits,
     notify_tx: mpsc::Sender<WorkerRunResponse>,
 }
 
 struct WorkerRunResponse {
-    analyzer: Box<dyn RunnableAnalyzer>,
+    name: String,
+    /// The analyzer, unless it was abandoned after exceeding its time limit.
+    analyzer: Option<Box<dyn RunnableAnalyzer>>,
     result: Result<()>,
+    elapsed: Duration,
+}
+
+/// Identifies a program run in log messages, along with the API request which started it.
+#[derive(Debug, Clone)]
+struct RunLabel {
+    run_number: u32,
+    request_id: Option<Arc<str>>,
+}
+
+impl std::fmt::Display for RunLabel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "run {}", self.run_number)?;
+        if let Some(request_id) = &self.request_id {
+            write!(f, " (request {request_id})")?;
+        }
+        Ok(())
+    }
 }
 
 struct ProgramRun {
     program: Arc<ProgramConfig>,
     run_number: u32,
+    label: RunLabel,
     level: Level,
+    priority: Priority,
     analyzers_to_run: u32,
-    dispatch_tx: async_channel::Sender<WorkerRunRequest>,
+    dispatch_tx: dispatch::Sender<WorkerRunRequest>,
     notify_tx: mpsc::Sender<WorkerRunResponse>,
     notify_rx: mpsc::Receiver<WorkerRunResponse>,
     blocked: BTreeMap<String, Box<dyn RunnableAnalyzer>>,
     pending: BTreeMap<String, Box<dyn RunnableAnalyzer>>,
     completed: Arc<RwLock<HashMap<String, Box<dyn RunnableAnalyzer>>>>,
+    shadow_completed: HashMap<String, Box<dyn RunnableAnalyzer>>,
+    outcomes: BTreeMap<String, AnalyzerOutcome>,
+    /// Wall time taken by each analyzer which finished running.
+    analyzer_times: HashMap<String, Duration>,
+    /// Analyzers not run because the challenge does not meet their conditions.
+    excluded: HashSet<String>,
+    /// Responses of analyzers already run by an inline run, which runs each analyzer as soon as
+    /// it is scheduled rather than dispatching it to a worker. `None` for regular runs.
+    inline_responses: Option<VecDeque<WorkerRunResponse>>,
     challenge: Arc<Challenge>,
     item_registry: Arc<item::Registry>,
+    npc_registry: Arc<npc::Registry>,
+    jobs: Arc<jobs::Registry>,
+    /// Span covering the run, carrying its identifying fields into the logs of its analyzers.
+    span: tracing::Span,
+    player_scope: Option<Arc<HashSet<String>>>,
+    blackboard: Arc<Blackboard>,
+    findings: Arc<Findings>,
+    locale: Option<String>,
+    history: Option<Arc<HistoryProvider>>,
+    cancellation: CancellationToken,
+    usage: ResourceUsage,
+}
+
+/// Payload sent to a program run's callback URL once the run finishes.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CompletionPayload {
+    #[serde(flatten)]
+    job: Job,
+    duration_ms: u64,
+}
+
+/// Payload sent to a request's callback URL when the request is served from the stored results
+/// of an earlier run. It matches [`CompletionPayload`], except that there is no job.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedCompletionPayload<'a> {
+    program: &'a str,
+    challenge_uuid: Uuid,
+    status: Status,
+    analyzers: BTreeMap<String, Status>,
+    cached: bool,
 }
 
 impl ProgramRun {
     fn new(
         program: Arc<ProgramConfig>,
         run_number: u32,
-        level: Level,
-        dispatch_tx: async_channel::Sender<WorkerRunRequest>,
-        challenge: Challenge,
+        dispatch_tx: dispatch::Sender<WorkerRunRequest>,
+        challenge: Arc<Challenge>,
         item_registry: Arc<item::Registry>,
+        npc_registry: Arc<npc::Registry>,
+        jobs: Arc<jobs::Registry>,
+        history: Option<Arc<HistoryProvider>>,
+        cancellation: CancellationToken,
+        options: RunOptions,
     ) -> Self {
         let (notify_tx, notify_rx) = mpsc::channel(8);
         let analyzers_to_run = program.analyzers.len() as u32;
+        let usage = *challenge.resource_usage();
+        let span = tracing::info_span!(
+            "program_run",
+            run = run_number,
+            program = %program.program.name,
+            challenge = %challenge.uuid(),
+            level = %options.level,
+            request_id = options.request_id.as_deref(),
+        );
 
         Self {
             program,
             run_number,
-            level,
+            label: RunLabel {
+                run_number,
+                request_id: options.request_id.map(Arc::from),
+            },
+            level: options.level,
+            priority: options.priority,
             analyzers_to_run,
             dispatch_tx,
             notify_tx,
@@ -193,8 +600,23 @@ impl ProgramRun {
             blocked: BTreeMap::new(),
             pending: BTreeMap::new(),
             completed: Arc::new(RwLock::new(HashMap::new())),
-            challenge: Arc::new(challenge),
+            shadow_completed: HashMap::new(),
+            outcomes: BTreeMap::new(),
+            analyzer_times: HashMap::new(),
+            excluded: HashSet::new(),
+            inline_responses: None,
+            challenge,
             item_registry,
+            npc_registry,
+            jobs,
+            span,
+            player_scope: options.players.map(Arc::new),
+            blackboard: Arc::new(Blackboard::new()),
+            findings: Arc::new(Findings::new()),
+            locale: options.locale,
+            history,
+            cancellation,
+            usage,
         }
     }
 
@@ -204,37 +626,267 @@ impl ProgramRun {
 
     async fn run(&mut self) -> Result<()> {
         self.initialize_analyzers()?;
-        self.schedule_all_pending().await?;
+        self.schedule_all_pending()?;
 
         while self.analyzers_to_run > 0 {
-            let response = self.notify_rx.recv().await.ok_or(Error::IncompleteData)?;
-            if let Err(e) = response.result {
-                log::error!(r#"Analyzer "{}" failed: {e:?}"#, response.analyzer.name());
-                return Err(e);
+            let inline_response = self.inline_responses.as_mut().and_then(VecDeque::pop_front);
+            let response = match inline_response {
+                Some(response) => response,
+                None => self.notify_rx.recv().await.ok_or(Error::IncompleteData)?,
+            };
+            self.usage.add_analyzer_time(response.elapsed);
+            self.analyzer_times
+                .insert(response.name.clone(), response.elapsed);
+
+            if self.cancellation.is_cancelled() {
+                tracing::info!(
+                    r#"{}: Program "{}" cancelled"#,
+                    self.label,
+                    self.program_name()
+                );
+                return Err(Error::Cancelled);
+            }
+            let is_shadow = self.program.analyzers[&response.name].shadow.is_some();
+
+            let analyzer = response.name.clone();
+            let (status, event) = match &response.result {
+                Ok(()) => (Status::Completed, Event::Completed { analyzer }),
+                Err(e) => (
+                    Status::Failed,
+                    Event::Failed {
+                        analyzer,
+                        error: e.to_string(),
+                    },
+                ),
+            };
+            self.jobs
+                .set_analyzer_status(self.run_number, &response.name, status);
+            self.jobs.publish(self.run_number, event);
+
+            if is_shadow {
+                // Shadow analyzers are experimental and never affect the rest of the program.
+                let result = match (response.result, response.analyzer) {
+                    (Ok(()), Some(analyzer)) => Ok(analyzer),
+                    (Ok(()), None) => Err(Error::IncompleteData),
+                    (Err(e), _) => Err(e),
+                };
+                match result {
+                    Ok(analyzer) => {
+                        self.outcomes
+                            .insert(response.name.clone(), AnalyzerOutcome::completed());
+                        self.shadow_completed.insert(response.name, analyzer);
+                    }
+                    Err(e) => {
+                        tracing::warn!(
+                            r#"{}: Shadow analyzer "{}" failed: {e:?}"#,
+                            self.label,
+                            response.name
+                        );
+                        self.outcomes
+                            .insert(response.name, AnalyzerOutcome::failed(&e));
+                    }
+                }
+                self.analyzers_to_run -= 1;
+                continue;
             }
 
-            self.handle_completed(response.analyzer);
-            self.schedule_all_pending().await?;
+            let result = match (response.result, response.analyzer) {
+                (Ok(()), Some(analyzer)) => Ok(analyzer),
+                (Ok(()), None) => Err(Error::IncompleteData),
+                (Err(e), _) => Err(e),
+            };
             self.analyzers_to_run -= 1;
+
+            match result {
+                Ok(analyzer) => {
+                    self.outcomes
+                        .insert(response.name, AnalyzerOutcome::completed());
+                    self.handle_completed(analyzer);
+                    self.schedule_all_pending()?;
+                }
+                Err(e) => {
+                    let e = e
+                        .with_analyzer(&response.name)
+                        .with_challenge(self.challenge.uuid());
+                    if self.program.analyzers[&response.name].optional {
+                        tracing::warn!(
+                            r#"{}: Optional analyzer "{}" failed: {e}"#,
+                            self.label,
+                            response.name
+                        );
+                    } else {
+                        tracing::error!(
+                            r#"{}: Analyzer "{}" failed: {e}"#,
+                            self.label,
+                            response.name
+                        );
+                    }
+                    self.outcomes
+                        .insert(response.name.clone(), AnalyzerOutcome::failed(&e));
+                    self.skip_dependents(&response.name);
+
+                    // Analyzers which only softly depend on the failed analyzer can now run.
+                    self.unblock_analyzers();
+                    self.schedule_all_pending()?;
+                }
+            }
         }
 
         Ok(())
     }
 
+    /// Skips every blocked analyzer which depends, directly or transitively, on a failed
+    /// analyzer. Analyzers independent of the failure, or with only a soft dependency on it,
+    /// continue to run.
+    fn skip_dependents(&mut self, failed: &str) {
+        let mut unavailable = vec![failed.to_owned()];
+
+        while let Some(name) = unavailable.pop() {
+            let dependents = self
+                .blocked
+                .keys()
+                .filter(|dependent| {
+                    self.program.analyzers[*dependent]
+                        .dependencies
+                        .as_ref()
+                        .is_some_and(|dependencies| dependencies.contains(&name))
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+
+            for dependent in dependents {
+                self.blocked.remove(&dependent);
+                tracing::warn!(
+                    r#"{}: Skipping analyzer "{dependent}" as its dependency "{name}" did not complete"#,
+                    self.label
+                );
+                self.jobs
+                    .set_analyzer_status(self.run_number, &dependent, Status::Skipped);
+                self.outcomes.insert(
+                    dependent.clone(),
+                    AnalyzerOutcome::skipped(format!(r#"dependency "{name}" did not complete"#)),
+                );
+                self.analyzers_to_run -= 1;
+                unavailable.push(dependent);
+            }
+        }
+    }
+
+    /// Returns the final status of a run which finished without being cancelled.
+    fn final_status(&self) -> RunStatus {
+        // Shadow and optional analyzers never affect the status of the run.
+        let failed = self.outcomes.iter().any(|(name, outcome)| {
+            let definition = &self.program.analyzers[name];
+            outcome.status != AnalyzerStatus::Completed
+                && definition.shadow.is_none()
+                && !definition.optional
+        });
+        let completed = !self.completed.read().unwrap().is_empty();
+
+        match (failed, completed) {
+            (false, _) => RunStatus::Completed,
+            (true, true) => RunStatus::Partial,
+            (true, false) => RunStatus::Failed,
+        }
+    }
+
     fn initialize_analyzers(&mut self) -> Result<()> {
+        self.exclude_inapplicable_analyzers();
+
         self.program
             .analyzers
             .iter()
+            .filter(|(name, _)| !self.excluded.contains(*name))
             .try_for_each(|(name, definition)| {
-                let analyzer =
-                    init_analyzer(name, &definition.implementation, definition.config.clone())?;
+                if let Some(shadow) = &definition.shadow {
+                    // Inline runs are deterministic, so they run every shadow analyzer.
+                    if self.inline_responses.is_none()
+                        && rand::random::<f64>() >= shadow.sample_rate
+                    {
+                        tracing::debug!(
+                            r#"{}: Shadow analyzer "{name}" not sampled for this run"#,
+                            self.label
+                        );
+                        self.jobs
+                            .set_analyzer_status(self.run_number, name, Status::Skipped);
+                        self.analyzers_to_run -= 1;
+                        return Ok(());
+                    }
+                }
+
+                let analyzer = match init_analyzer(
+                    name,
+                    &definition.implementation,
+                    definition.config_for(self.level),
+                ) {
+                    Ok(analyzer) => analyzer,
+                    // Shadow analyzers never affect the program, even if they cannot be created.
+                    Err(e) if definition.shadow.is_some() => {
+                        tracing::warn!(
+                            r#"{}: Failed to initialize shadow analyzer "{name}": {e:?}"#,
+                            self.label
+                        );
+                        self.jobs
+                            .set_analyzer_status(self.run_number, name, Status::Failed);
+                        self.outcomes
+                            .insert(name.clone(), AnalyzerOutcome::failed(&e));
+                        self.analyzers_to_run -= 1;
+                        return Ok(());
+                    }
+                    Err(e) => return Err(e),
+                };
                 self.blocked.insert(name.clone(), analyzer);
+                self.jobs
+                    .set_analyzer_status(self.run_number, name, Status::Queued);
                 Ok::<(), Error>(())
             })?;
         self.unblock_analyzers();
         Ok(())
     }
 
+    /// Excludes analyzers whose conditions the challenge does not meet, along with every analyzer
+    /// which has a hard dependency on an excluded analyzer.
+    fn exclude_inapplicable_analyzers(&mut self) {
+        self.excluded = self
+            .program
+            .analyzers
+            .iter()
+            .filter(|(_, definition)| !definition.conditions.matches(&self.challenge))
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        loop {
+            let dependents = self
+                .program
+                .analyzers
+                .iter()
+                .filter(|(name, definition)| {
+                    !self.excluded.contains(*name)
+                        && definition
+                            .dependencies
+                            .iter()
+                            .flatten()
+                            .any(|d| self.excluded.contains(d))
+                })
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>();
+            if dependents.is_empty() {
+                break;
+            }
+            self.excluded.extend(dependents);
+        }
+
+        for name in &self.excluded {
+            tracing::debug!(
+                r#"{}: Analyzer "{name}" does not apply to this challenge"#,
+                self.label
+            );
+            self.jobs
+                .set_analyzer_status(self.run_number, name, Status::Skipped);
+            self.analyzers_to_run -= 1;
+        }
+    }
+
     fn unblock_analyzers(&mut self) {
         let completed = self.completed.read().unwrap();
 
@@ -242,13 +894,20 @@ impl ProgramRun {
         self.blocked = blocked
             .into_iter()
             .filter_map(|(name, analyzer)| {
-                let runnable = match self.program.analyzers[&name].dependencies.as_ref() {
-                    Some(dependencies) => dependencies.iter().all(|d| completed.contains_key(d)),
-                    None => true,
-                };
+                let definition = &self.program.analyzers[&name];
+                let runnable = definition
+                    .dependencies
+                    .iter()
+                    .flatten()
+                    .all(|d| completed.contains_key(d))
+                    && definition
+                        .soft_dependencies
+                        .iter()
+                        .flatten()
+                        .all(|d| self.outcomes.contains_key(d) || self.excluded.contains(d));
 
                 if runnable {
-                    log::debug!(r#"Unblocked analyzer "{name}""#);
+                    tracing::debug!(r#"{}: Unblocked analyzer "{name}""#, self.label);
                     self.pending.insert(name, analyzer);
                     None
                 } else {
@@ -258,31 +917,347 @@ impl ProgramRun {
             .collect();
     }
 
-    async fn schedule_all_pending(&mut self) -> Result<()> {
+    fn schedule_all_pending(&mut self) -> Result<()> {
         let pending = std::mem::take(&mut self.pending);
 
-        future::try_join_all(pending.into_values().map(|analyzer| {
+        for analyzer in pending.into_values() {
+            let limits = self.program.analyzers[analyzer.name()].limits;
+            let context = Context::new(
+                self.challenge.clone(),
+                self.item_registry.clone(),
+                self.npc_registry.clone(),
+                self.level,
+                self.player_scope.clone(),
+                self.blackboard.clone(),
+                analyzer.name(),
+                self.findings.clone(),
+                self.history.clone(),
+                self.completed.clone(),
+            );
             let request = WorkerRunRequest {
                 analyzer,
-                context: Context::new(
-                    self.challenge.clone(),
-                    self.item_registry.clone(),
-                    self.level,
-                    self.completed.clone(),
-                ),
+                context,
+                cancellation: self.cancellation.clone(),
+                label: self.label.clone(),
+                span: self.span.clone(),
+                limits,
                 notify_tx: self.notify_tx.clone(),
             };
 
-            log::debug!(r#"Scheduled analyzer "{}" to run"#, request.analyzer.name());
-            self.dispatch_tx
-                .send(request)
-                .map_err(|_| Error::FailedPrecondition("Worker channel closed".into()))
-        }))
-        .await?;
+            tracing::debug!(
+                r#"{}: Scheduled analyzer "{}" to run"#,
+                self.label,
+                request.analyzer.name()
+            );
+            self.jobs.set_analyzer_status(
+                self.run_number,
+                request.analyzer.name(),
+                Status::Running,
+            );
+            self.jobs.publish(
+                self.run_number,
+                Event::Scheduled {
+                    analyzer: request.analyzer.name().to_owned(),
+                },
+            );
+            match &mut self.inline_responses {
+                Some(responses) => responses.push_back(Worker::run_inline(request)),
+                None => self
+                    .dispatch_tx
+                    .send(request, self.priority)
+                    .map_err(|_| Error::FailedPrecondition("Worker channel closed".into()))?,
+            }
+        }
 
         Ok(())
     }
 
+    /// Notifies the callback URLs of the callers of the run that the run has finished.
+    async fn send_callbacks(&self, client: &reqwest::Client, urls: &[String], duration: Duration) {
+        if urls.is_empty() {
+            return;
+        }
+        let Some(job) = self.jobs.get(self.run_number) else {
+            return;
+        };
+
+        let payload = CompletionPayload {
+            job,
+            duration_ms: duration.as_millis() as u64,
+        };
+
+        for url in urls {
+            if let Err(e) = webhook::post(client, url, &payload).await {
+                tracing::warn!(
+                    r#"{}: Failed to send completion callback for program "{}" to {url}: {e}"#,
+                    self.label,
+                    self.program_name()
+                );
+            }
+        }
+    }
+
+    /// Delivers the outputs of the analyzers which the program opts into reporting, if any, to
+    /// the clans involved in the challenge. Runs which failed or were cancelled are not reported.
+    ///
+    /// The names of players who have opted out of publication are pseudonymized using the opt-outs
+    /// in `results`. The run is not reported if they cannot be loaded.
+    async fn send_report(
+        &self,
+        reporter: &Reporter,
+        results: Option<&results::Store>,
+        record: &RunRecord,
+        session: Option<&Session>,
+        duration: Duration,
+    ) {
+        if !matches!(record.status, RunStatus::Completed | RunStatus::Partial) {
+            return;
+        }
+
+        let mut findings = record
+            .outputs
+            .iter()
+            .filter(|(name, _)| self.program.analyzers.get(name).is_some_and(|a| a.report))
+            .map(|(name, output)| ReportedOutput {
+                analyzer: name,
+                output,
+                player_fields: record.player_fields.get(name).copied().unwrap_or_default(),
+            })
+            .collect::<Vec<_>>();
+        if findings.is_empty() {
+            return;
+        }
+        findings.sort_by(|a, b| a.analyzer.cmp(b.analyzer));
+
+        let pseudonymizer = match results {
+            Some(results) => match results.load_pseudonymizer().await {
+                Ok(pseudonymizer) => pseudonymizer,
+                Err(e) => {
+                    tracing::warn!(
+                        r#"{}: Not reporting program "{}": failed to load privacy opt-outs: {e}"#,
+                        self.label,
+                        self.program_name()
+                    );
+                    return;
+                }
+            },
+            None => Pseudonymizer::default(),
+        };
+
+        reporter
+            .deliver(
+                &self.challenge,
+                self.program_name(),
+                record.status,
+                duration,
+                &findings,
+                session,
+                &pseudonymizer,
+            )
+            .await;
+    }
+
+    /// Updates the skill ratings of the party from the performance scores computed by the
+    /// program's `ScoreAnalyzer`, if it has one. Players are rated in the role assigned to them by
+    /// a `TobRoleAnalyzer`, or in an unclassified role otherwise. Runs scoped to a subset of the
+    /// party are not rated, as players are rated relative to each other, and neither are runs
+    /// scoped to a subset of the challenge's stages.
+    async fn update_ratings(&self, results: &results::Store, record: &RunRecord) {
+        if !matches!(record.status, RunStatus::Completed | RunStatus::Partial)
+            || self.player_scope.is_some()
+            || self.challenge.stage_scope().is_some()
+        {
+            return;
+        }
+
+        let output_of = |implementation: &str| {
+            let (name, _) = self
+                .program
+                .analyzers
+                .iter()
+                .find(|(_, definition)| definition.implementation == implementation)?;
+            record
+                .outputs
+                .iter()
+                .find_map(|(analyzer, output)| (analyzer == name).then_some(output))
+        };
+        let Some(scores) = output_of("ScoreAnalyzer") else {
+            return;
+        };
+        let roles = output_of("TobRoleAnalyzer");
+
+        let performances = self
+            .challenge
+            .party()
+            .iter()
+            .filter_map(|username| {
+                let score = scores.get(username)?.get("overall")?.as_u64()?;
+                let role = roles
+                    .and_then(|roles| roles.get(username)?.get(0)?.as_str())
+                    .unwrap_or(ratings::UNCLASSIFIED_ROLE);
+                Some(Performance {
+                    username: username.clone(),
+                    role: role.to_owned(),
+                    score: score.min(100) as u8,
+                })
+            })
+            .collect::<Vec<_>>();
+        if performances.len() < 2 {
+            return;
+        }
+
+        match results
+            .update_ratings(record.challenge_uuid, &performances)
+            .await
+        {
+            Ok(true) => tracing::debug!(
+                "{}: Updated ratings of {} players",
+                self.label,
+                performances.len()
+            ),
+            Ok(false) => {}
+            Err(e) => tracing::warn!(
+                "{}: Failed to update ratings for challenge {}: {e:?}",
+                self.label,
+                record.challenge_uuid
+            ),
+        }
+    }
+
+    /// Records the new personal bests reported by the program's `PersonalBestAnalyzer`, if it has
+    /// one and the run completed. Outputs of shadow analyzers are not part of the record, so their
+    /// bests are never recorded.
+    async fn record_personal_bests(&self, results: &results::Store, record: &RunRecord) {
+        if !matches!(record.status, RunStatus::Completed | RunStatus::Partial) {
+            return;
+        }
+
+        let Some((name, _)) = self
+            .program
+            .analyzers
+            .iter()
+            .find(|(_, definition)| definition.implementation == "PersonalBestAnalyzer")
+        else {
+            return;
+        };
+        let Some(output) = record
+            .outputs
+            .iter()
+            .find_map(|(analyzer, output)| (analyzer == name).then_some(output))
+        else {
+            return;
+        };
+
+        let bests = match PersonalBests::deserialize(output) {
+            Ok(bests) => bests,
+            Err(e) => {
+                tracing::warn!("{}: Unreadable personal bests: {e}", self.label);
+                return;
+            }
+        };
+        let new_bests = bests.new_bests(self.challenge.party());
+        if new_bests.is_empty() {
+            return;
+        }
+
+        if let Err(e) = results
+            .record_personal_bests(record.challenge_uuid, &bests.category, &new_bests)
+            .await
+        {
+            tracing::warn!(
+                "{}: Failed to record personal bests for challenge {}: {e:?}",
+                self.label,
+                record.challenge_uuid
+            );
+        }
+    }
+
+    /// Returns telemetry for every analyzer with an outcome in the run, given the run's serialized
+    /// outputs.
+    fn analyzer_telemetry(
+        &self,
+        outputs: &[(String, serde_json::Value)],
+    ) -> Vec<AnalyzerTelemetry> {
+        self.outcomes
+            .iter()
+            .map(|(name, outcome)| AnalyzerTelemetry {
+                analyzer: name.clone(),
+                status: outcome.status.as_str(),
+                error: outcome.error.clone(),
+                wall_time: self.analyzer_times.get(name).copied().unwrap_or_default(),
+                output_bytes: outputs
+                    .iter()
+                    .find(|(analyzer, _)| analyzer == name)
+                    .and_then(|(_, output)| serde_json::to_vec(output).ok())
+                    .map(|output| output.len() as u64),
+            })
+            .collect()
+    }
+
+    /// Returns the version of every analyzer which completed successfully.
+    fn analyzer_versions(&self) -> HashMap<String, u32> {
+        let completed = self.completed.read().unwrap();
+        completed
+            .iter()
+            .chain(&self.shadow_completed)
+            .map(|(name, analyzer)| (name.clone(), analyzer.version()))
+            .collect()
+    }
+
+    /// Returns the fields holding player usernames in the output of every analyzer, including
+    /// shadow analyzers, which completed successfully.
+    fn player_fields(&self) -> HashMap<String, &'static [&'static str]> {
+        let completed = self.completed.read().unwrap();
+        completed
+            .iter()
+            .chain(&self.shadow_completed)
+            .map(|(name, analyzer)| (name.clone(), analyzer.player_fields()))
+            .collect()
+    }
+
+    /// Returns the serialized outputs of every analyzer which completed successfully. Analyzers
+    /// whose outputs fail to serialize are omitted.
+    fn serialized_outputs(&self) -> Vec<(String, serde_json::Value)> {
+        Self::serialize_outputs(self.completed.read().unwrap().iter())
+    }
+
+    /// Returns the serialized outputs of every shadow analyzer which completed successfully,
+    /// which are kept apart from the program's regular outputs.
+    fn serialized_shadow_outputs(&self) -> Vec<(String, serde_json::Value)> {
+        Self::serialize_outputs(self.shadow_completed.iter())
+    }
+
+    fn serialize_outputs<'a>(
+        analyzers: impl Iterator<Item = (&'a String, &'a Box<dyn RunnableAnalyzer>)>,
+    ) -> Vec<(String, serde_json::Value)> {
+        analyzers
+            .filter_map(|(name, analyzer)| match analyzer.output_json()? {
+                Ok(output) => Some((name.clone(), output)),
+                Err(e) => {
+                    tracing::warn!(r#"Failed to serialize output of analyzer "{name}": {e:?}"#);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the findings emitted by the analyzers which completed, with messages in the run's
+    /// locale and linked to their moments in the challenge's replay. Findings of failed and
+    /// shadow analyzers are discarded.
+    fn collected_findings(&self, links: &ReplayLinks, messages: &Catalog) -> Vec<Finding> {
+        let completed = self.completed.read().unwrap();
+        let links = links.challenge(self.challenge.r#type(), self.challenge.uuid());
+        let localizer = messages.localizer(self.locale.as_deref());
+        let mut findings = self
+            .findings
+            .collect(|analyzer| completed.contains_key(analyzer));
+        for finding in &mut findings {
+            localizer.localize_finding(finding);
+            finding.link = links.finding(finding);
+        }
+        findings
+    }
+
     fn handle_completed(&mut self, analyzer: Box<dyn RunnableAnalyzer>) {
         self.completed
             .write()
@@ -297,7 +1272,9 @@ impl std::fmt::Debug for ProgramRun {
         f.debug_struct("ProgramRun")
             .field("program", &self.program)
             .field("run_number", &self.run_number)
+            .field("label", &self.label)
             .field("level", &self.level)
+            .field("priority", &self.priority)
             .field("analyzers_to_run", &self.analyzers_to_run)
             .field("notify_tx", &self.notify_tx)
             .field("notify_rx", &self.notify_rx)
@@ -308,177 +1285,1946 @@ impl std::fmt::Debug for ProgramRun {
                 "completed",
                 &self.completed.try_read().map(|r| r.len()).unwrap_or(0),
             )
+            .field("shadow_completed", &self.shadow_completed.len())
             .field("challenge", &self.challenge)
             .field("item_registry", &self.item_registry)
+            .field("npc_registry", &self.npc_registry)
+            .field("jobs", &self.jobs)
+            .field("player_scope", &self.player_scope)
+            .field("blackboard", &self.blackboard)
+            .field("usage", &self.usage)
             .finish()
     }
 }
 
-pub struct Engine {
-    programs: HashMap<String, Arc<ProgramConfig>>,
-    workers: Vec<JoinHandle<()>>,
-    dispatch_tx: Option<async_channel::Sender<WorkerRunRequest>>,
-    num_programs_run: u32,
-    item_registry: Arc<item::Registry>,
-}
+/// Options controlling a single program run.
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    /// Level of analysis to perform.
+    pub level: Level,
 
-impl Engine {
-    /// Loads analysis programs defined in TOML files from the directory at `path`.
-    pub async fn load_from_directory(
-        path: impl AsRef<Path>,
-        item_registry: item::Registry,
-    ) -> Result<Self> {
-        let mut programs = HashMap::new();
-        let mut dir = fs::read_dir(path).await?;
+    /// URL to which a summary of the run is POSTed once it finishes and its results are stored.
+    /// Must be an HTTPS URL of a public host.
+    pub callback_url: Option<String>,
 
-        while let Some(entry) = dir.next_entry().await? {
-            let path = entry.path();
-            if !path.is_file() || path.extension().map_or(true, |ext| ext != "toml") {
+    /// Players to which the analysis is restricted. If `None`, the whole party is analyzed.
+    pub players: Option<HashSet<String>>,
+
+    /// ID of the API request which started the run, included in its log messages.
+    pub request_id: Option<String>,
+
+    /// How to handle an identical run of the program which is already in progress.
+    pub on_duplicate: DuplicatePolicy,
+
+    /// Priority of the run's analyzers relative to those of other runs.
+    pub priority: Priority,
+
+    /// Whether the run's results are only returned to its callers. Ephemeral runs are not
+    /// persisted and have no access to the history of previous analyses, for challenges which do
+    /// not exist outside of the request that analyzes them.
+    pub ephemeral: bool,
+
+    /// Locale in which the messages of the run's findings are written, e.g. `pt-BR`. Defaults to
+    /// English.
+    pub locale: Option<String>,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            level: Level::Basic,
+            callback_url: None,
+            players: None,
+            request_id: None,
+            on_duplicate: DuplicatePolicy::Attach,
+            priority: Priority::Interactive,
+            ephemeral: false,
+            locale: None,
+        }
+    }
+}
+
+/// Action taken when a program is run on a challenge while an identical run is in progress.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicatePolicy {
+    /// Returns a handle to the existing run. The duplicate's callback URL, if any, is notified
+    /// along with the existing run's when the run finishes.
+    #[default]
+    Attach,
+    /// Fails with [`Error::AlreadyRunning`].
+    Reject,
+}
+
+/// Identifies program runs which produce identical results.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct RunKey {
+    challenge: Uuid,
+    program: String,
+    level: Level,
+    players: Option<Vec<String>>,
+    stages: Option<Vec<blert::Stage>>,
+    locale: Option<String>,
+    /// Ephemeral runs are not persisted, so they are never shared with runs which are.
+    ephemeral: bool,
+}
+
+impl RunKey {
+    fn new(program: &str, challenge: &Challenge, options: &RunOptions) -> Self {
+        let players = options.players.as_ref().map(|players| {
+            let mut players = players.iter().cloned().collect::<Vec<_>>();
+            players.sort();
+            players
+        });
+
+        Self {
+            challenge: challenge.uuid(),
+            program: program.to_owned(),
+            level: options.level,
+            players,
+            stages: challenge.stage_scope().map(<[_]>::to_vec),
+            locale: options.locale.clone(),
+            ephemeral: options.ephemeral,
+        }
+    }
+}
+
+/// A program run in progress, along with the callers waiting for its results.
+struct InFlightRun {
+    run_number: u32,
+    waiters: Vec<oneshot::Sender<ProgramResult>>,
+    /// Callback URLs of the run's callers, notified once the run finishes.
+    callback_urls: Vec<String>,
+}
+
+/// Final results of a program run.
+#[derive(Debug, Clone, Serialize, ToSchema, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgramResult {
+    /// UUID of the analyzed challenge.
+    #[schemars(with = "String")]
+    pub challenge_uuid: Uuid,
+    pub status: RunStatus,
+    /// Outcome of every analyzer which ran or was skipped, keyed by analyzer name.
+    pub analyzers: BTreeMap<String, AnalyzerOutcome>,
+    pub outputs: BTreeMap<String, serde_json::Value>,
+    pub blackboard: BTreeMap<String, serde_json::Value>,
+    /// Findings emitted by the program's analyzers.
+    pub findings: Vec<Finding>,
+    pub usage: ResourceUsage,
+    /// Level originally requested for the run, if it was downgraded to basic analysis because
+    /// the engine was saturated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub downgraded_from: Option<Level>,
+}
+
+/// Outcome of a single analyzer within a program run.
+#[derive(Debug, Clone, Serialize, ToSchema, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalyzerOutcome {
+    pub status: AnalyzerStatus,
+    /// Why the analyzer failed or was skipped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl AnalyzerOutcome {
+    pub(crate) fn completed() -> Self {
+        Self {
+            status: AnalyzerStatus::Completed,
+            error: None,
+        }
+    }
+
+    fn failed(error: &Error) -> Self {
+        Self {
+            status: AnalyzerStatus::Failed,
+            error: Some(error.to_string()),
+        }
+    }
+
+    fn skipped(reason: String) -> Self {
+        Self {
+            status: AnalyzerStatus::Skipped,
+            error: Some(reason),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AnalyzerStatus {
+    Completed,
+    Failed,
+    /// Not run because an analyzer it depends on did not complete.
+    Skipped,
+}
+
+impl AnalyzerStatus {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            AnalyzerStatus::Completed => "completed",
+            AnalyzerStatus::Failed => "failed",
+            AnalyzerStatus::Skipped => "skipped",
+        }
+    }
+
+    pub(crate) fn from_str(status: &str) -> Option<Self> {
+        match status {
+            "completed" => Some(AnalyzerStatus::Completed),
+            "failed" => Some(AnalyzerStatus::Failed),
+            "skipped" => Some(AnalyzerStatus::Skipped),
+            _ => None,
+        }
+    }
+}
+
+/// A handle to a running analysis program which can be awaited for its results. Dropping the
+/// handle does not stop the run.
+#[derive(Debug)]
+pub struct ProgramRunHandle {
+    run_number: u32,
+    /// Task executing the run. Handles attached to a run started by another caller do not own
+    /// its task.
+    task: Option<JoinHandle<()>>,
+    result_rx: oneshot::Receiver<ProgramResult>,
+}
+
+impl ProgramRunHandle {
+    /// Returns the number of the run, which doubles as the ID of the job tracking it.
+    pub fn run_number(&self) -> u32 {
+        self.run_number
+    }
+
+    /// Returns whether the run's task has finished. Always `false` for handles which do not
+    /// own the task.
+    pub fn is_finished(&self) -> bool {
+        self.task.as_ref().is_some_and(JoinHandle::is_finished)
+    }
+
+    /// Waits for the program to finish and returns its results.
+    pub async fn wait(self) -> Result<ProgramResult> {
+        if let Ok(result) = self.result_rx.await {
+            return Ok(result);
+        }
+
+        // The results are only dropped if the run's task terminated abnormally.
+        if let Some(task) = self.task {
+            if let Err(e) = task.await {
+                return Err(Error::FailedPrecondition(format!(
+                    "Program run terminated: {e}"
+                )));
+            }
+        }
+        Err(Error::FailedPrecondition("Program run was dropped".into()))
+    }
+}
+
+/// Runs analysis programs on a pool of workers. Once started, the engine is shared between
+/// callers without locking: program runs are submitted to the workers over a channel.
+pub struct Engine {
+    programs: HashMap<String, Arc<ProgramConfig>>,
+    default_programs: HashMap<String, String>,
+    supervisor: Mutex<Option<JoinHandle<()>>>,
+    dispatch_tx: Option<dispatch::Sender<WorkerRunRequest>>,
+    num_programs_run: AtomicU32,
+    item_registry: Arc<item::Registry>,
+    npc_registry: Arc<npc::Registry>,
+    results: Option<Arc<results::Store>>,
+    reporter: Option<Arc<Reporter>>,
+    replay_links: ReplayLinks,
+    messages: Arc<Catalog>,
+    jobs: Arc<jobs::Registry>,
+    http_client: reqwest::Client,
+    in_flight: Arc<Mutex<HashMap<RunKey, InFlightRun>>>,
+    /// Notified whenever a program run finishes.
+    run_finished: Arc<Notify>,
+    shutting_down: AtomicBool,
+    load_shedding: Option<LoadSheddingPolicy>,
+    run_limiter: Option<Arc<RunLimiter>>,
+    shed_counters: ShedCounters,
+    workers_respawned: Arc<AtomicU64>,
+}
+
+impl Engine {
+    /// Interval at which a deferred run checks whether the engine is still saturated.
+    const DEFER_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// Maximum number of challenges loaded at once when requeueing outdated runs.
+    const REQUEUE_LOAD_CONCURRENCY: usize = 8;
+
+    /// Loads analysis programs defined in TOML files from the directory at `path`.
+    pub async fn load_from_directory(
+        path: impl AsRef<Path>,
+        item_registry: item::Registry,
+        npc_registry: Arc<npc::Registry>,
+    ) -> Result<Self> {
+        // Plugins must be loaded before programs so that analyzers using them can be validated.
+        #[cfg(feature = "plugins")]
+        crate::analyzers::plugin::load_from_directory(
+            std::env::var("BLERT_PLUGINS_DIR").unwrap_or_else(|_| "./plugins".into()),
+        )?;
+
+        let mut programs = HashMap::new();
+        let mut default_programs = HashMap::new();
+        let mut dir = fs::read_dir(path).await?;
+
+        while let Some(entry) = dir.next_entry().await? {
+            let path = entry.path();
+            if !path.is_file() || path.extension().map_or(true, |ext| ext != "toml") {
                 continue;
             }
 
-            let config = fs::read(path).await?;
-            let config = String::from_utf8(config).map_err(|_| Error::IncompleteData)?;
-            let program: ProgramConfig =
-                toml::from_str(&config).map_err(|_| Error::IncompleteData)?;
+            let program = Self::parse_program(&path).await.map_err(|e| match e {
+                Error::Config(message) => Error::Config(format!("{}: {message}", path.display())),
+                e => e,
+            })?;
+
+            for key in &program.program.default_for {
+                if let Some(existing) =
+                    default_programs.insert(key.clone(), program.program.name.clone())
+                {
+                    return Err(Error::Config(format!(
+                        r#"Programs "{existing}" and "{}" are both the default for "{key}""#,
+                        program.program.name
+                    )));
+                }
+            }
 
             programs.insert(program.program.name.clone(), Arc::new(program));
         }
 
         Ok(Self {
             programs,
-            workers: Vec::new(),
+            default_programs,
+            supervisor: Mutex::new(None),
             dispatch_tx: None,
-            num_programs_run: 0,
+            num_programs_run: AtomicU32::new(0),
             item_registry: Arc::new(item_registry),
+            npc_registry,
+            results: None,
+            reporter: None,
+            replay_links: ReplayLinks::default(),
+            messages: Arc::new(Catalog::default()),
+            jobs: Arc::new(jobs::Registry::new()),
+            http_client: webhook::client()?,
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+            run_finished: Arc::new(Notify::new()),
+            shutting_down: AtomicBool::new(false),
+            load_shedding: None,
+            run_limiter: None,
+            shed_counters: ShedCounters::default(),
+            workers_respawned: Arc::new(AtomicU64::new(0)),
         })
     }
 
+    /// Reads and validates a single program definition.
+    async fn parse_program(path: &Path) -> Result<ProgramConfig> {
+        let config = Self::read_program_table(path, &mut Vec::new()).await?;
+        let definition = config.to_string();
+        let mut program: ProgramConfig = serde_path_to_error::deserialize(config).map_err(|e| {
+            Error::Config(format!("invalid program at `{}`: {}", e.path(), e.inner()))
+        })?;
+        program.validate_dependencies()?;
+        program.validate_shadow_analyzers()?;
+        program.analyzer_versions = program.validate_analyzer_configs()?;
+        program.validate_conditions()?;
+        program.version = ProgramConfig::version_of(&definition, &program.analyzer_versions);
+        Ok(program)
+    }
+
+    /// Reads a program file as a TOML table, merging in the files listed in its `include` key.
+    /// Included files are resolved relative to the including file and merged in order, with the
+    /// including file's own definitions taking precedence.
+    fn read_program_table<'a>(
+        path: &'a Path,
+        including: &'a mut Vec<PathBuf>,
+    ) -> BoxFuture<'a, Result<toml::Value>> {
+        Box::pin(async move {
+            let canonical = fs::canonicalize(path).await?;
+            if including.contains(&canonical) {
+                return Err(Error::Config("program includes itself".into()));
+            }
+
+            let config = fs::read(path).await?;
+            let config = String::from_utf8(config)
+                .map_err(|e| Error::Config(format!("not valid UTF-8: {e}")))?;
+            let mut config: toml::Value =
+                toml::from_str(&config).map_err(|e| Error::Config(e.to_string()))?;
+
+            let includes: Vec<String> = match config
+                .as_table_mut()
+                .and_then(|table| table.remove(PROGRAM_INCLUDE_KEY))
+            {
+                Some(includes) => includes.try_into().map_err(|_| {
+                    Error::Config(format!(
+                        "`{PROGRAM_INCLUDE_KEY}` must be a list of file paths"
+                    ))
+                })?,
+                None => Vec::new(),
+            };
+
+            including.push(canonical);
+            let mut merged = toml::Value::Table(toml::Table::new());
+            for include in includes {
+                let include_path = path.parent().unwrap_or(Path::new(".")).join(&include);
+                let included = Self::read_program_table(&include_path, including)
+                    .await
+                    .map_err(|e| match e {
+                        Error::Config(message) => Error::Config(format!("{include}: {message}")),
+                        Error::Io(e) => Error::Config(format!("{include}: {e}")),
+                        e => e,
+                    })?;
+                merge_config(&mut merged, included);
+            }
+            including.pop();
+
+            merge_config(&mut merged, config);
+            Ok(merged)
+        })
+    }
+
+    /// Sets the store to which the results of completed program runs are persisted.
+    pub fn set_result_store(&mut self, store: Arc<results::Store>) {
+        self.results = Some(store);
+    }
+
+    /// Notifies a request's callback URL that the request was served from the stored results of
+    /// an earlier run of `program` on a challenge, without running the program again.
+    pub async fn send_cached_callback(
+        &self,
+        url: &str,
+        program: &str,
+        challenge_uuid: Uuid,
+        result: &ProgramResult,
+    ) {
+        let payload = CachedCompletionPayload {
+            program,
+            challenge_uuid,
+            status: Status::Completed,
+            analyzers: result
+                .analyzers
+                .iter()
+                .map(|(name, outcome)| {
+                    let status = match outcome.status {
+                        AnalyzerStatus::Completed => Status::Completed,
+                        AnalyzerStatus::Failed => Status::Failed,
+                        AnalyzerStatus::Skipped => Status::Skipped,
+                    };
+                    (name.clone(), status)
+                })
+                .collect(),
+            cached: true,
+        };
+
+        if let Err(e) = webhook::post(&self.http_client, url, &payload).await {
+            tracing::warn!(
+                r#"Failed to send completion callback for cached results of program "{program}" to {url}: {e}"#
+            );
+        }
+    }
+
+    /// Sets the reporter to which the findings of completed program runs are delivered.
+    pub fn set_reporter(&mut self, reporter: Reporter) {
+        self.reporter = Some(Arc::new(reporter));
+    }
+
+    /// Sets the website to which findings link.
+    pub fn set_replay_links(&mut self, links: ReplayLinks) {
+        self.replay_links = links;
+    }
+
+    /// Returns the links to the website to which findings link.
+    pub fn replay_links(&self) -> &ReplayLinks {
+        &self.replay_links
+    }
+
+    /// Sets the catalog from which the messages of findings are formatted.
+    pub fn set_message_catalog(&mut self, messages: Catalog) {
+        self.messages = Arc::new(messages);
+    }
+
+    /// Returns the catalog from which the messages of findings are formatted.
+    pub fn message_catalog(&self) -> &Catalog {
+        &self.messages
+    }
+
+    /// Sets the policy for shedding deep analysis while the engine is saturated.
+    pub fn set_load_shedding(&mut self, policy: LoadSheddingPolicy) {
+        self.load_shedding = Some(policy);
+    }
+
+    /// Bounds the number of program runs in progress at once.
+    pub fn set_run_limiter(&mut self, limiter: RunLimiter) {
+        self.run_limiter = Some(Arc::new(limiter));
+    }
+
+    /// Returns a snapshot of the engine's current load.
+    pub fn load_metrics(&self) -> LoadMetrics {
+        LoadMetrics {
+            queue_depth: self.queue_depth(),
+            runs_in_progress: self.in_flight.lock().unwrap().len(),
+            runs_queued: self.run_limiter.as_ref().map_or(0, |l| l.queued()),
+            runs_rejected: self.run_limiter.as_ref().map_or(0, |l| l.rejected()),
+            runs_downgraded: self.shed_counters.downgraded(),
+            runs_deferred: self.shed_counters.deferred(),
+            workers_respawned: self.workers_respawned.load(Ordering::Relaxed),
+            analyzers_abandoned: sandbox::abandoned(),
+        }
+    }
+
+    /// Returns the number of analyzers waiting for a worker.
+    fn queue_depth(&self) -> usize {
+        self.dispatch_tx.as_ref().map_or(0, dispatch::Sender::len)
+    }
+
+    /// Returns the load shedding action to take for a run at the given level, if any. Basic
+    /// analysis is never shed.
+    fn shed_action(&self, level: Level) -> Option<(ShedAction, usize)> {
+        let policy = self.load_shedding?;
+        let action = policy.action_for(level, self.queue_depth())?;
+        Some((action, policy.max_queue_depth))
+    }
+
+    /// Returns the version of a loaded program.
+    pub fn program_version(&self, program: &str) -> Option<&str> {
+        self.programs.get(program).map(|p| p.version.as_str())
+    }
+
+    /// Returns the implementation of an analyzer within a loaded program.
+    pub fn analyzer_implementation(&self, program: &str, analyzer: &str) -> Option<&str> {
+        self.programs
+            .get(program)?
+            .analyzers
+            .get(analyzer)
+            .map(|definition| definition.implementation.as_str())
+    }
+
+    /// Returns every analyzer of a loaded program which uses `implementation`, as `(program,
+    /// analyzer)` pairs.
+    pub fn analyzers_implementing(&self, implementation: &str) -> Vec<(String, String)> {
+        self.programs
+            .values()
+            .flat_map(|program| {
+                program
+                    .analyzers
+                    .iter()
+                    .filter(|(_, definition)| definition.implementation == implementation)
+                    .map(|(name, _)| (program.program.name.clone(), name.clone()))
+            })
+            .collect()
+    }
+
+    /// Returns the analyzers of every loaded program as `(program, analyzer)` pairs, grouped by
+    /// the implementation which they use.
+    fn analyzers_by_implementation(&self) -> HashMap<String, Vec<(String, String)>> {
+        let mut analyzers = HashMap::<String, Vec<(String, String)>>::new();
+        for program in self.programs.values() {
+            for (name, definition) in &program.analyzers {
+                analyzers
+                    .entry(definition.implementation.clone())
+                    .or_default()
+                    .push((program.program.name.clone(), name.clone()));
+            }
+        }
+        analyzers
+    }
+
+    /// Returns descriptions of every loaded program, ordered by name.
+    pub fn programs(&self) -> Vec<ProgramInfo> {
+        let mut programs = self
+            .programs
+            .values()
+            .map(|program| ProgramInfo::new(program))
+            .collect::<Vec<_>>();
+        programs.sort_by(|a, b| a.name.cmp(&b.name));
+        programs
+    }
+
+    /// Returns the name of the program to run by default on challenges of the given type and
+    /// mode. A program configured for the specific mode takes priority over one configured for
+    /// the challenge type as a whole.
+    pub fn default_program(
+        &self,
+        r#type: blert::Challenge,
+        mode: blert::ChallengeMode,
+    ) -> Option<&str> {
+        self.default_programs
+            .get(&mode.as_str_name().to_lowercase())
+            .or_else(|| {
+                self.default_programs
+                    .get(&r#type.as_str_name().to_lowercase())
+            })
+            .map(String::as_str)
+    }
+
+    /// Returns the registry tracking the status of program runs.
+    pub fn jobs(&self) -> Arc<jobs::Registry> {
+        self.jobs.clone()
+    }
+
     /// Begins running the analysis engine with the specified number of workers.
     pub fn start(&mut self, worker_count: u32) {
-        let (dispatch_tx, dispatch_rx) = async_channel::unbounded();
+        let (dispatch_tx, dispatch_rx) = dispatch::queue();
 
         self.dispatch_tx = Some(dispatch_tx);
-        for id in 0..worker_count {
-            self.workers.push(Worker::spawn(id, dispatch_rx.clone()));
+        *self.supervisor.get_mut().unwrap() = Some(tokio::spawn(Worker::supervise(
+            worker_count,
+            dispatch_rx,
+            self.workers_respawned.clone(),
+        )));
+    }
+
+    /// Stops the engine. New program runs are rejected, while runs in progress are given until
+    /// `timeout` to finish before they are cancelled. Workers exit once they complete their
+    /// current analyzers.
+    pub async fn shutdown(&self, timeout: Duration) {
+        self.shutting_down.store(true, Ordering::Relaxed);
+        let deadline = tokio::time::Instant::now() + timeout;
+
+        if tokio::time::timeout_at(deadline, self.wait_for_runs())
+            .await
+            .is_err()
+        {
+            let remaining = self
+                .in_flight
+                .lock()
+                .unwrap()
+                .values()
+                .map(|run| run.run_number)
+                .collect::<Vec<_>>();
+            tracing::warn!(
+                "Cancelling {} program run(s) still in progress at shutdown",
+                remaining.len()
+            );
+            for run_number in remaining {
+                let _ = self.jobs.cancel(run_number);
+            }
+        }
+
+        if let Some(dispatch_tx) = &self.dispatch_tx {
+            dispatch_tx.close();
         }
+
+        let supervisor = self.supervisor.lock().unwrap().take();
+        if let Some(supervisor) = supervisor {
+            if tokio::time::timeout_at(deadline, supervisor).await.is_err() {
+                tracing::warn!("Workers did not stop before the shutdown deadline");
+            }
+        }
+    }
+
+    /// Waits until no program runs are in progress.
+    async fn wait_for_runs(&self) {
+        loop {
+            // Registered before checking so that a run finishing in between is not missed.
+            let run_finished = self.run_finished.notified();
+            let idle = self.in_flight.lock().unwrap().is_empty();
+            if idle {
+                return;
+            }
+            run_finished.await;
+        }
+    }
+
+    /// Runs several analysis programs on a single loaded challenge, returning a handle to each run
+    /// in the same order as `programs`. The runs share the challenge rather than each loading
+    /// their own copy.
+    ///
+    /// Every program is checked to exist before any run starts. If starting one of the runs fails,
+    /// runs started before it continue in the background.
+    pub fn run_programs(
+        &self,
+        programs: &[&str],
+        challenge: Arc<Challenge>,
+        options: &RunOptions,
+    ) -> Result<Vec<ProgramRunHandle>> {
+        if let Some(unknown) = programs.iter().find(|p| !self.programs.contains_key(**p)) {
+            return Err(Error::InvalidField(format!("unknown program {unknown}")));
+        }
+
+        programs
+            .iter()
+            .map(|program| self.run_program(program, challenge.clone(), options.clone()))
+            .collect()
+    }
+
+    /// Finds up to `limit` challenges whose latest stored results of a program include outputs
+    /// from outdated analyzer versions, and starts bulk runs to reanalyze them. Returns the job
+    /// IDs of the started runs.
+    ///
+    /// Challenges are loaded concurrently, up to [`Self::REQUEUE_LOAD_CONCURRENCY`] at a time, and
+    /// their runs are started in order as they load. Challenges which fail to load are logged and
+    /// skipped. Requeueing stops early if the engine is too busy to accept more runs.
+    pub async fn requeue_outdated(
+        &self,
+        pool: &sqlx::PgPool,
+        repository: &DataRepository,
+        limit: usize,
+    ) -> Result<Vec<u32>> {
+        let results = self.results.as_ref().ok_or_else(|| {
+            Error::FailedPrecondition("Results persistence is not configured".into())
+        })?;
+
+        let mut programs = self.programs.values().collect::<Vec<_>>();
+        programs.sort_by(|a, b| a.program.name.cmp(&b.program.name));
+
+        let mut job_ids = Vec::new();
+        for program in programs {
+            let remaining = limit - job_ids.len();
+            if remaining == 0 {
+                break;
+            }
+
+            let name = &program.program.name;
+            let outdated = results
+                .find_outdated_runs(name, &program.analyzer_versions, remaining)
+                .await?;
+
+            let mut loaded = stream::iter(outdated)
+                .map(|(uuid, level)| async move {
+                    (uuid, level, Challenge::load(pool, repository, uuid).await)
+                })
+                .buffered(Self::REQUEUE_LOAD_CONCURRENCY);
+
+            while let Some((uuid, level, challenge)) = loaded.next().await {
+                let challenge = match challenge {
+                    Ok(challenge) => challenge,
+                    Err(e) => {
+                        tracing::warn!("Failed to load challenge {uuid} for reanalysis: {e}");
+                        continue;
+                    }
+                };
+
+                let options = RunOptions {
+                    level,
+                    priority: Priority::Bulk,
+                    on_duplicate: DuplicatePolicy::Attach,
+                    ..RunOptions::default()
+                };
+                match self.run_program(name, Arc::new(challenge), options) {
+                    Ok(handle) => job_ids.push(handle.run_number()),
+                    Err(Error::Busy) => {
+                        tracing::info!(
+                            "Engine busy; stopping reanalysis after {} run(s)",
+                            job_ids.len()
+                        );
+                        return Ok(job_ids);
+                    }
+                    Err(e) => tracing::warn!("Failed to requeue challenge {uuid}: {e}"),
+                }
+            }
+        }
+
+        Ok(job_ids)
     }
 
-    /// Runs an analysis program on a challenge, at the specified level.
+    /// Runs a program to completion without dispatching its analyzers to the engine's workers.
+    /// Analyzers run one at a time in a deterministic topological order, with ties broken by
+    /// name, so repeated runs on the same challenge produce the same outputs in the same order.
+    ///
+    /// Inline runs are meant for tests and local debugging: they do not require the engine to be
+    /// started, and they are not deduplicated, persisted, or given access to analysis history.
+    pub async fn run_program_inline(
+        &self,
+        program: &str,
+        challenge: Arc<Challenge>,
+        level: Level,
+    ) -> Result<ProgramResult> {
+        let program = self.programs.get(program).ok_or(Error::InvalidArgument)?;
+
+        let jobs = Arc::new(jobs::Registry::new());
+        let cancellation = jobs.create(0, &program.program.name, challenge.uuid());
+        let mut program_run = ProgramRun::new(
+            program.clone(),
+            0,
+            dispatch::queue().0,
+            challenge,
+            self.item_registry.clone(),
+            self.npc_registry.clone(),
+            jobs,
+            None,
+            cancellation,
+            RunOptions {
+                level,
+                ..RunOptions::default()
+            },
+        );
+        program_run.inline_responses = Some(VecDeque::new());
+
+        program_run
+            .run()
+            .instrument(program_run.span.clone())
+            .await?;
+
+        let outputs = program_run.serialized_outputs();
+        Ok(ProgramResult {
+            challenge_uuid: program_run.challenge.uuid(),
+            status: program_run.final_status(),
+            analyzers: std::mem::take(&mut program_run.outcomes),
+            outputs: outputs.into_iter().collect(),
+            blackboard: program_run.blackboard.to_json(),
+            findings: program_run.collected_findings(&self.replay_links, &self.messages),
+            usage: program_run.usage,
+            downgraded_from: None,
+        })
+    }
+
+    /// Runs an analysis program on a challenge with the given options, returning a handle to the
+    /// run.
+    ///
+    /// If an identical run is already in progress, it is either shared or rejected depending on
+    /// the `on_duplicate` option. If the engine bounds its concurrent runs, the run may wait for
+    /// others to finish before starting, and fails with [`Error::Busy`] if too many are waiting.
     ///
     /// [`start`](#method.start) must have been called before this method, or it will fail.
-    pub fn run_program(&mut self, program: &str, level: Level, challenge: Challenge) -> Result<()> {
+    pub fn run_program(
+        &self,
+        program: &str,
+        challenge: Arc<Challenge>,
+        mut options: RunOptions,
+    ) -> Result<ProgramRunHandle> {
+        if self.shutting_down.load(Ordering::Relaxed) {
+            return Err(Error::Unavailable("Engine is shutting down".into()));
+        }
+
         let Some(program) = self.programs.get(program) else {
             return Err(Error::InvalidArgument);
         };
 
+        if let Some(players) = &options.players {
+            if let Some(player) = players.iter().find(|p| !challenge.party().contains(p)) {
+                return Err(Error::InvalidField(format!(
+                    "{player} is not a member of the party"
+                )));
+            }
+        }
+
+        if let Some(url) = &options.callback_url {
+            webhook::validate_url(url)?;
+        }
+
         let dispatch_tx = match &self.dispatch_tx {
             Some(tx) => tx.clone(),
             None => return Err(Error::FailedPrecondition("Engine not started".into())),
         };
 
-        log::info!(
-            "Running program {} on challenge {}",
-            program.program.name,
-            challenge.uuid(),
-        );
+        let mut downgraded_from = None;
+        let mut defer_below = None;
+        if let Some((action, max_queue_depth)) = self.shed_action(options.level) {
+            self.shed_counters.record(action);
+            match action {
+                ShedAction::Downgrade => {
+                    downgraded_from = Some(options.level);
+                    options.level = Level::Basic;
+                }
+                ShedAction::Defer => defer_below = Some(max_queue_depth),
+            }
+        }
+
+        let key = RunKey::new(&program.program.name, &challenge, &options);
+        let (result_tx, result_rx) = oneshot::channel();
+
+        let mut queued = None;
+        let run_number = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            if let Some(run) = in_flight.get_mut(&key) {
+                return match options.on_duplicate {
+                    DuplicatePolicy::Attach => {
+                        tracing::info!(
+                            "Attaching to run {} of program {} on challenge {}",
+                            run.run_number,
+                            key.program,
+                            key.challenge,
+                        );
+                        run.waiters.push(result_tx);
+                        run.callback_urls.extend(options.callback_url);
+                        Ok(ProgramRunHandle {
+                            run_number: run.run_number,
+                            task: None,
+                            result_rx,
+                        })
+                    }
+                    DuplicatePolicy::Reject => Err(Error::AlreadyRunning(run.run_number)),
+                };
+            }
+
+            if let Some(limiter) = &self.run_limiter {
+                queued = Some(limiter.try_enqueue().ok_or(Error::Busy)?);
+            }
+
+            let run_number = self.num_programs_run.fetch_add(1, Ordering::Relaxed) + 1;
+            in_flight.insert(
+                key.clone(),
+                InFlightRun {
+                    run_number,
+                    waiters: vec![result_tx],
+                    callback_urls: options.callback_url.take().into_iter().collect(),
+                },
+            );
+            run_number
+        };
 
-        self.num_programs_run += 1;
-        let run_number = self.num_programs_run;
+        let cancellation = self
+            .jobs
+            .create(run_number, &program.program.name, challenge.uuid());
+
+        let results = self.results.as_ref().filter(|_| !options.ephemeral);
+        let reporter = self.reporter.clone().filter(|_| !options.ephemeral);
+        let history = results.map(|store| {
+            Arc::new(HistoryProvider::new(
+                store.clone(),
+                Handle::current(),
+                challenge.uuid(),
+                self.analyzers_by_implementation(),
+            ))
+        });
 
         let mut program_run = ProgramRun::new(
             program.clone(),
             run_number,
-            level,
-            dispatch_tx,
+            dispatch_tx.clone(),
             challenge,
             self.item_registry.clone(),
+            self.npc_registry.clone(),
+            self.jobs.clone(),
+            history,
+            cancellation,
+            options,
         );
 
-        tokio::spawn(async move {
-            let run_start = Instant::now();
+        tracing::info!(
+            "{}: Running program {} on challenge {}",
+            program_run.label,
+            program_run.program_name(),
+            program_run.challenge.uuid(),
+        );
 
-            match program_run.run().await {
-                Ok(()) => {
-                    log::debug!(
-                        r#"Program "{}" completed in {:?}"#,
-                        program_run.program_name(),
-                        run_start.elapsed(),
-                    );
+        if let Some(level) = downgraded_from {
+            tracing::info!(
+                "{}: Engine saturated; downgraded from {level} to basic analysis",
+                program_run.label,
+            );
+        }
+
+        let results = results.cloned();
+        let jobs = self.jobs.clone();
+        let http_client = self.http_client.clone();
+        let in_flight = self.in_flight.clone();
+        let run_finished = self.run_finished.clone();
+        let replay_links = self.replay_links.clone();
+        let messages = self.messages.clone();
+        let queue = dispatch_tx;
+
+        let span = program_run.span.clone();
+        let task = tokio::spawn(
+            async move {
+                // Held until the run finishes to keep its place among the runs in progress.
+                let _permit = match queued {
+                    Some(queued) => Some(queued.start().await),
+                    None => None,
+                };
+
+                if let Some(max_queue_depth) = defer_below {
+                    tracing::info!("{}: Engine saturated; deferring run", program_run.label);
+                    while queue.len() >= max_queue_depth && !program_run.cancellation.is_cancelled()
+                    {
+                        tokio::time::sleep(Self::DEFER_POLL_INTERVAL).await;
+                    }
                 }
-                Err(e) => {
-                    log::error!(
-                        r#"Program "{}" failed in {:?}: {e:?}"#,
-                        program_run.program_name(),
-                        run_start.elapsed()
-                    );
+
+                let run_start = Instant::now();
+                let started_at = OffsetDateTime::now_utc();
+                jobs.set_status(run_number, Status::Running);
+
+                let status = match program_run.run().await {
+                    Ok(()) => {
+                        let status = program_run.final_status();
+                        tracing::debug!(
+                            r#"{}: Program "{}" finished as {status:?} in {:?}"#,
+                            program_run.label,
+                            program_run.program_name(),
+                            run_start.elapsed(),
+                        );
+                        if status == RunStatus::Failed {
+                            jobs.set_failed(run_number, "Every analyzer failed".into());
+                        } else {
+                            jobs.set_status(run_number, Status::Completed);
+                        }
+                        status
+                    }
+                    Err(Error::Cancelled) => {
+                        jobs.set_status(run_number, Status::Cancelled);
+                        RunStatus::Cancelled
+                    }
+                    Err(e) => {
+                        tracing::error!(
+                            r#"{}: Program "{}" failed in {:?}: {e:?}"#,
+                            program_run.label,
+                            program_run.program_name(),
+                            run_start.elapsed()
+                        );
+                        jobs.set_failed(run_number, e.to_string());
+                        RunStatus::Failed
+                    }
+                };
+
+                let duration = run_start.elapsed();
+                let outputs = program_run.serialized_outputs();
+                let record = RunRecord {
+                    challenge_uuid: program_run.challenge.uuid(),
+                    party: program_run.challenge.party().to_vec(),
+                    run_number,
+                    program: program_run.program_name().to_owned(),
+                    program_version: program_run.program.version.clone(),
+                    level: program_run.level,
+                    status,
+                    started_at,
+                    player_scope: program_run.player_scope.as_ref().map(|players| {
+                        let mut players = players.iter().cloned().collect::<Vec<_>>();
+                        players.sort();
+                        players
+                    }),
+                    stage_scope: program_run.challenge.stage_scope().map(|stages| {
+                        stages
+                            .iter()
+                            .map(|stage| stage.as_str_name().to_owned())
+                            .collect()
+                    }),
+                    locale: program_run.locale.clone(),
+                    analyzer_telemetry: program_run.analyzer_telemetry(&outputs),
+                    outputs,
+                    shadow_outputs: program_run.serialized_shadow_outputs(),
+                    analyzer_versions: program_run.analyzer_versions(),
+                    player_fields: program_run.player_fields(),
+                    blackboard: program_run.blackboard.to_json(),
+                    blackboard_player_fields: program_run.blackboard.player_fields(),
+                    findings: program_run.collected_findings(&replay_links, &messages),
+                    usage: program_run.usage,
+                };
+
+                tracing::info!(
+                    r#"{}: Program "{}" on challenge {} used {:?}"#,
+                    program_run.label,
+                    record.program,
+                    record.challenge_uuid,
+                    record.usage,
+                );
+
+                let mut session = None;
+                if let Some(results) = &results {
+                    match results.save_run(&record).await {
+                        // New bests are only recorded for runs whose results are kept.
+                        Ok(_) => program_run.record_personal_bests(results, &record).await,
+                        Err(e) => tracing::error!(
+                            r#"{}: Failed to save results of program "{}": {e:?}"#,
+                            program_run.label,
+                            program_run.program_name()
+                        ),
+                    }
+                    session = match results.update_session(record.challenge_uuid).await {
+                        Ok(session) => Some(session),
+                        Err(e) => {
+                            tracing::warn!(
+                                "Failed to update session of challenge {}: {e:?}",
+                                record.challenge_uuid
+                            );
+                            None
+                        }
+                    };
+                    program_run.update_ratings(results, &record).await;
+                }
+
+                // Consumers are only notified once the run is persisted, so that they can fetch
+                // its results in response.
+                let callback_urls = in_flight
+                    .lock()
+                    .unwrap()
+                    .get_mut(&key)
+                    .map(|run| std::mem::take(&mut run.callback_urls))
+                    .unwrap_or_default();
+                program_run
+                    .send_callbacks(&http_client, &callback_urls, duration)
+                    .await;
+                if let Some(reporter) = &reporter {
+                    program_run
+                        .send_report(
+                            reporter,
+                            results.as_deref(),
+                            &record,
+                            session.as_ref(),
+                            duration,
+                        )
+                        .await;
+                }
+
+                let result = ProgramResult {
+                    challenge_uuid: record.challenge_uuid,
+                    status: record.status,
+                    analyzers: std::mem::take(&mut program_run.outcomes),
+                    outputs: record.outputs.into_iter().collect(),
+                    blackboard: record.blackboard,
+                    findings: record.findings,
+                    usage: record.usage,
+                    downgraded_from,
+                };
+
+                let (waiters, callback_urls) = in_flight
+                    .lock()
+                    .unwrap()
+                    .remove(&key)
+                    .map_or_else(Default::default, |run| (run.waiters, run.callback_urls));
+                run_finished.notify_waiters();
+                // Callers which attached while the others were being notified.
+                program_run
+                    .send_callbacks(&http_client, &callback_urls, duration)
+                    .await;
+                for waiter in waiters {
+                    // The receiver is dropped if the caller is not interested in the results.
+                    let _ = waiter.send(result.clone());
                 }
             }
-        });
+            .instrument(span),
+        );
 
-        Ok(())
+        Ok(ProgramRunHandle {
+            run_number,
+            task: Some(task),
+            result_rx,
+        })
     }
 }
 
 struct Worker {
     id: u32,
-    dispatch_rx: async_channel::Receiver<WorkerRunRequest>,
+    dispatch_rx: dispatch::Receiver<WorkerRunRequest>,
 }
 
 impl Worker {
-    fn spawn(id: u32, dispatch_rx: async_channel::Receiver<WorkerRunRequest>) -> JoinHandle<()> {
+    fn spawn(id: u32, dispatch_rx: dispatch::Receiver<WorkerRunRequest>) -> JoinHandle<()> {
         let worker = Self { id, dispatch_rx };
         tokio::spawn(worker.run())
     }
 
+    /// Runs a pool of `count` workers, replacing any worker which dies unexpectedly so that the
+    /// pool never shrinks. Returns once every worker has exited because the dispatch channel
+    /// closed.
+    async fn supervise(
+        count: u32,
+        dispatch_rx: dispatch::Receiver<WorkerRunRequest>,
+        respawned: Arc<AtomicU64>,
+    ) {
+        let mut workers = (0..count)
+            .map(|id| (id, Worker::spawn(id, dispatch_rx.clone())))
+            .collect::<Vec<_>>();
+
+        while !workers.is_empty() {
+            let (result, index, _) =
+                future::select_all(workers.iter_mut().map(|(_, handle)| handle)).await;
+            let (id, _) = workers.swap_remove(index);
+
+            match result {
+                Ok(()) => tracing::debug!("Worker {id} exited"),
+                Err(e) => {
+                    tracing::error!("Worker {id} died: {e}; respawning");
+                    respawned.fetch_add(1, Ordering::Relaxed);
+                    workers.push((id, Worker::spawn(id, dispatch_rx.clone())));
+                }
+            }
+        }
+    }
+
     async fn run(self) {
         loop {
-            let Ok(mut request) = self.dispatch_rx.recv().await else {
+            let Some(request) = self.dispatch_rx.recv().await else {
                 break;
             };
 
-            log::debug!(
-                r#"Worker {} running analyzer "{}""#,
-                self.id,
-                request.analyzer.name(),
+            let name = request.analyzer.name().to_owned();
+            let label = request.label.clone();
+            let notify_tx = request.notify_tx.clone();
+            let span = tracing::info_span!(
+                parent: &request.span,
+                "analyzer",
+                analyzer = %name,
+                worker = self.id,
             );
             let start = Instant::now();
 
-            let result = request.analyzer.run(&request.context);
-
-            log::debug!(
-                r#"Worker {} completed analyzer "{}" in {:?}"#,
-                self.id,
-                request.analyzer.name(),
-                start.elapsed(),
-            );
-
-            request
-                .notify_tx
+            let (analyzer, result) = async {
+                if request.cancellation.is_cancelled() {
+                    tracing::debug!(
+                        r#"Worker {} skipping analyzer "{name}" of cancelled {label}"#,
+                        self.id,
+                    );
+                    (Some(request.analyzer), Err(Error::Cancelled))
+                } else {
+                    tracing::debug!(
+                        r#"Worker {} running analyzer "{name}" for {label}"#,
+                        self.id,
+                    );
+                    Self::run_sandboxed(request).await
+                }
+            }
+            .instrument(span.clone())
+            .await;
+            let elapsed = start.elapsed();
+
+            span.in_scope(|| {
+                tracing::debug!(
+                    r#"Worker {} completed analyzer "{name}" for {label} in {elapsed:?}"#,
+                    self.id,
+                );
+            });
+
+            // The program run stops listening for responses once it fails or is cancelled.
+            let _ = notify_tx
                 .send(WorkerRunResponse {
-                    analyzer: request.analyzer,
+                    name,
+                    analyzer,
                     result,
+                    elapsed,
                 })
-                .await
-                .unwrap();
+                .await;
+        }
+    }
+
+    /// Runs an analyzer on a blocking thread within a sandbox enforcing its limits.
+    ///
+    /// If the analyzer exceeds its time limit, its sandbox is killed and the analyzer is
+    /// abandoned: its thread cannot be forcibly stopped, but unwinds at the analyzer's next
+    /// checkpoint. An abandoned analyzer is not returned. Analyzers are refused while too many
+    /// abandoned analyzers are still running, so that they cannot exhaust the blocking threads.
+    async fn run_sandboxed(
+        request: WorkerRunRequest,
+    ) -> (Option<Box<dyn RunnableAnalyzer>>, Result<()>) {
+        let WorkerRunRequest {
+            mut analyzer,
+            context,
+            label,
+            limits,
+            ..
+        } = request;
+        if sandbox::abandoned() >= sandbox::MAX_ABANDONED {
+            tracing::error!("{label}: Too many abandoned analyzers are still running");
+            return (
+                Some(analyzer),
+                Err(Error::LimitExceeded(
+                    "too many abandoned analyzers are still running".into(),
+                )),
+            );
+        }
+        let sandbox = Arc::new(Sandbox::new(limits));
+
+        let task = tokio::task::spawn_blocking({
+            let sandbox = sandbox.clone();
+            let label = label.clone();
+            let span = tracing::Span::current();
+            move || {
+                let _span = span.enter();
+                let result = {
+                    let _guard = sandbox.enter();
+                    Self::run_analyzer(analyzer.as_mut(), &context, &label)
+                };
+                sandbox.finish();
+                (analyzer, result)
+            }
+        });
+
+        let joined = match limits.timeout() {
+            Some(timeout) => match tokio::time::timeout(timeout, task).await {
+                Ok(joined) => joined,
+                Err(_) => {
+                    sandbox.abandon();
+                    tracing::error!("{label}: Analyzer abandoned after {timeout:?}");
+                    return (
+                        None,
+                        Err(Error::LimitExceeded(sandbox::Violation::Killed.to_string())),
+                    );
+                }
+            },
+            None => task.await,
+        };
+
+        match joined {
+            Ok((analyzer, result)) => (Some(analyzer), result),
+            Err(e) => (None, Err(Error::AnalyzerPanic(e.to_string()))),
         }
     }
+
+    /// Runs an analyzer on the current thread within a sandbox enforcing its allocation limit.
+    /// Time limits are not enforced, as the analyzer cannot be abandoned.
+    fn run_inline(request: WorkerRunRequest) -> WorkerRunResponse {
+        let WorkerRunRequest {
+            mut analyzer,
+            context,
+            label,
+            span,
+            limits,
+            ..
+        } = request;
+
+        let span = tracing::info_span!(parent: &span, "analyzer", analyzer = analyzer.name());
+        let start = Instant::now();
+        let result = span.in_scope(|| {
+            let _guard = Arc::new(Sandbox::new(limits)).enter();
+            Self::run_analyzer(analyzer.as_mut(), &context, &label)
+        });
+
+        WorkerRunResponse {
+            name: analyzer.name().to_owned(),
+            analyzer: Some(analyzer),
+            result,
+            elapsed: start.elapsed(),
+        }
+    }
+
+    /// Runs an analyzer, converting a panic within it into an error so that the failure is
+    /// reported to its program run rather than taking down the worker.
+    fn run_analyzer(
+        analyzer: &mut dyn RunnableAnalyzer,
+        context: &Context,
+        label: &RunLabel,
+    ) -> Result<()> {
+        panic::catch_unwind(AssertUnwindSafe(|| analyzer.run(context))).unwrap_or_else(|payload| {
+            if let Some(violation) = sandbox::violation(payload.as_ref()) {
+                tracing::error!(r#"{label}: Analyzer "{}" {violation}"#, analyzer.name());
+                return Err(Error::LimitExceeded(violation.to_string()));
+            }
+
+            let message = panic_message(payload.as_ref());
+            tracing::error!(
+                r#"{label}: Analyzer "{}" panicked: {message}"#,
+                analyzer.name()
+            );
+            Err(Error::AnalyzerPanic(message))
+        })
+    }
 }
 
+/// Extracts the message from the payload of a panic raised with `panic!`.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(ToString::to_string)
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+/// Key of a program file listing other TOML files whose definitions the program builds on.
+const PROGRAM_INCLUDE_KEY: &str = "include";
+
 #[derive(Debug, Serialize, Deserialize)]
 struct ProgramConfig {
     program: ProgramDefinition,
     analyzers: HashMap<String, AnalyzerDefinition>,
+    /// Identifies the program's definition and the analyzer implementations it runs, changing
+    /// whenever either does.
+    #[serde(skip)]
+    version: String,
+    /// Versions of the implementations of the program's analyzers, keyed by analyzer name.
+    #[serde(skip)]
+    analyzer_versions: HashMap<String, u32>,
+}
+
+impl ProgramConfig {
+    /// Computes the version of a program from its full definition, including any included files,
+    /// the version of the analyzer, and the versions of the program's analyzer implementations.
+    fn version_of(definition: &str, analyzer_versions: &HashMap<String, u32>) -> String {
+        let mut analyzers = analyzer_versions.iter().collect::<Vec<_>>();
+        analyzers.sort();
+
+        let mut hasher = Sha256::new()
+            .chain_update(env!("CARGO_PKG_VERSION"))
+            .chain_update(definition);
+        for (name, version) in analyzers {
+            hasher.update(format!("\n{name}={version}"));
+        }
+        format!("{:x}", hasher.finalize())[..16].to_owned()
+    }
+
+    /// Returns the hard and soft dependencies of an analyzer.
+    fn all_dependencies(definition: &AnalyzerDefinition) -> impl Iterator<Item = &String> {
+        definition
+            .dependencies
+            .iter()
+            .chain(&definition.soft_dependencies)
+            .flatten()
+    }
+
+    /// Ensures that every dependency refers to an analyzer in the program and that dependencies
+    /// do not form a cycle, either of which would leave analyzers blocked forever.
+    fn validate_dependencies(&self) -> Result<()> {
+        let mut names = self.analyzers.keys().collect::<Vec<_>>();
+        names.sort();
+
+        for name in &names {
+            if let Some(missing) = Self::all_dependencies(&self.analyzers[*name])
+                .find(|d| !self.analyzers.contains_key(*d))
+            {
+                return Err(Error::Config(format!(
+                    r#"Analyzer "{name}" depends on unknown analyzer "{missing}""#
+                )));
+            }
+        }
+
+        // Depth-first search, tracking the current path to report the analyzers in a cycle.
+        let mut visited = HashSet::new();
+        for name in names {
+            let mut path = Vec::new();
+            self.find_cycle(name, &mut visited, &mut path)?;
+        }
+
+        Ok(())
+    }
+
+    fn find_cycle<'a>(
+        &'a self,
+        name: &'a String,
+        visited: &mut HashSet<&'a String>,
+        path: &mut Vec<&'a String>,
+    ) -> Result<()> {
+        if let Some(start) = path.iter().position(|n| *n == name) {
+            let cycle = path[start..]
+                .iter()
+                .chain([&name])
+                .map(|n| format!(r#""{n}""#))
+                .collect::<Vec<_>>();
+            return Err(Error::Config(format!(
+                "Analyzer dependencies form a cycle: {}",
+                cycle.join(" -> ")
+            )));
+        }
+        if !visited.insert(name) {
+            return Ok(());
+        }
+
+        path.push(name);
+        for dependency in Self::all_dependencies(&self.analyzers[name]) {
+            self.find_cycle(dependency, visited, path)?;
+        }
+        path.pop();
+
+        Ok(())
+    }
+
+    /// Ensures that every analyzer has a known implementation and valid configuration options by
+    /// initializing it once, so mistakes are reported at load time rather than on every run.
+    /// Returns the version of each analyzer's implementation.
+    fn validate_analyzer_configs(&self) -> Result<HashMap<String, u32>> {
+        let mut versions = HashMap::new();
+        for (name, definition) in &self.analyzers {
+            let analyzer =
+                init_analyzer(name, &definition.implementation, definition.base_config())?;
+            versions.insert(name.clone(), analyzer.version());
+            for level in definition.override_levels()? {
+                init_analyzer(
+                    name,
+                    &definition.implementation,
+                    definition.config_for(level),
+                )?;
+            }
+        }
+        Ok(versions)
+    }
+
+    /// Ensures that analyzer conditions refer to known challenge types and can be satisfied.
+    fn validate_conditions(&self) -> Result<()> {
+        for (name, definition) in &self.analyzers {
+            let conditions = &definition.conditions;
+            if let Some(challenge) = &conditions.challenge {
+                if blert::Challenge::from_str_name(&challenge.to_uppercase()).is_none() {
+                    return Err(Error::Config(format!(
+                        r#"Analyzer "{name}" has unknown challenge condition "{challenge}""#
+                    )));
+                }
+            }
+            if let Some(mode) = &conditions.mode {
+                let known = (0..=i32::from(u8::MAX))
+                    .filter_map(|v| blert::ChallengeMode::try_from(v).ok())
+                    .any(|m| conditions.matches_mode(m));
+                if !known {
+                    return Err(Error::Config(format!(
+                        r#"Analyzer "{name}" has unknown mode condition "{mode}""#
+                    )));
+                }
+            }
+            if let (Some(min), Some(max)) = (conditions.min_scale, conditions.max_scale) {
+                if min > max {
+                    return Err(Error::Config(format!(
+                        r#"Analyzer "{name}" has min_scale {min} greater than max_scale {max}"#
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Ensures that shadow analyzers have valid sample rates and that no analyzer depends on a
+    /// shadow analyzer, as shadow analyzers may not run.
+    fn validate_shadow_analyzers(&self) -> Result<()> {
+        for (name, definition) in &self.analyzers {
+            if let Some(shadow) = &definition.shadow {
+                if !(0.0..=1.0).contains(&shadow.sample_rate) {
+                    return Err(Error::Config(format!(
+                        r#"Analyzer "{name}" has shadow sample_rate {} outside of 0 to 1"#,
+                        shadow.sample_rate
+                    )));
+                }
+            }
+            if let Some(shadow) = Self::all_dependencies(definition)
+                .find(|d| self.analyzers.get(*d).is_some_and(|a| a.shadow.is_some()))
+            {
+                return Err(Error::Config(format!(
+                    r#"Analyzer "{name}" cannot depend on shadow analyzer "{shadow}""#
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Public description of a loaded program.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgramInfo {
+    pub name: String,
+    pub version: String,
+    pub default_for: Vec<String>,
+    pub analyzers: Vec<AnalyzerInfo>,
+}
+
+/// Public description of an analyzer within a program.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalyzerInfo {
+    pub name: String,
+    pub implementation: String,
+    pub dependencies: Vec<String>,
+    pub soft_dependencies: Vec<String>,
+    #[schema(value_type = Option<Object>)]
+    pub config: Option<toml::Value>,
+    /// Sample rate of the analyzer if it runs in shadow mode.
+    pub shadow_sample_rate: Option<f64>,
+    /// Whether failures of the analyzer are tolerated by the program.
+    pub optional: bool,
+}
+
+impl ProgramInfo {
+    fn new(config: &ProgramConfig) -> Self {
+        let mut analyzers = config
+            .analyzers
+            .iter()
+            .map(|(name, definition)| AnalyzerInfo {
+                name: name.clone(),
+                implementation: definition.implementation.clone(),
+                dependencies: definition.dependencies.clone().unwrap_or_default(),
+                soft_dependencies: definition.soft_dependencies.clone().unwrap_or_default(),
+                config: definition.config.clone(),
+                shadow_sample_rate: definition.shadow.as_ref().map(|s| s.sample_rate),
+                optional: definition.optional,
+            })
+            .collect::<Vec<_>>();
+        analyzers.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Self {
+            name: config.program.name.clone(),
+            version: config.version.clone(),
+            default_for: config.program.default_for.clone(),
+            analyzers,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct ProgramDefinition {
     name: String,
+
+    /// Challenge types or modes for which the program is run by default, identified by their
+    /// lowercase proto names, e.g. `tob` or `tob_hard`.
+    #[serde(default)]
+    default_for: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct AnalyzerDefinition {
     implementation: String,
     dependencies: Option<Vec<String>>,
+    /// Analyzers which must finish before this one runs, but whose failure does not prevent it
+    /// from running. Outputs of failed soft dependencies are unavailable to the analyzer.
+    soft_dependencies: Option<Vec<String>>,
     config: Option<toml::Value>,
+    shadow: Option<ShadowConfig>,
+    /// Whether the analyzer is experimental. Failures of optional analyzers do not affect the
+    /// status of the program run, though their hard dependents are still skipped.
+    #[serde(default)]
+    optional: bool,
+    /// Whether the analyzer's output is included in the reports delivered to clans when the
+    /// program finishes.
+    #[serde(default)]
+    report: bool,
+    /// Resource limits enforced on each run of the analyzer.
+    #[serde(default)]
+    limits: Limits,
+    /// Conditions under which the analyzer runs, e.g. `[analyzers.X.conditions]`.
+    #[serde(default)]
+    conditions: Conditions,
+}
+
+impl AnalyzerDefinition {
+    /// Key of the table within an analyzer's config holding overrides for specific levels, e.g.
+    /// `[analyzers.X.config.levels.maxeff]`.
+    const LEVELS_KEY: &'static str = "levels";
+
+    /// Returns the analyzer's config options without any level overrides.
+    fn base_config(&self) -> Option<toml::Value> {
+        let mut config = self.config.clone()?;
+        if let toml::Value::Table(table) = &mut config {
+            table.remove(Self::LEVELS_KEY);
+        }
+        Some(config)
+    }
+
+    /// Returns the analyzer's config options for a run at `level`, with the level's overrides
+    /// merged over the base options.
+    fn config_for(&self, level: Level) -> Option<toml::Value> {
+        let mut config = self.base_config()?;
+        let overrides = self
+            .config
+            .as_ref()
+            .and_then(|c| c.get(Self::LEVELS_KEY))
+            .and_then(|levels| levels.get(level.to_string()));
+        if let Some(overrides) = overrides {
+            merge_config(&mut config, overrides.clone());
+        }
+        Some(config)
+    }
+
+    /// Returns the levels for which the analyzer's config has overrides.
+    fn override_levels(&self) -> Result<Vec<Level>> {
+        let Some(levels) = self.config.as_ref().and_then(|c| c.get(Self::LEVELS_KEY)) else {
+            return Ok(Vec::new());
+        };
+        let levels = levels.as_table().ok_or_else(|| {
+            Error::Config(format!("`{}` must be a table of levels", Self::LEVELS_KEY))
+        })?;
+
+        levels
+            .keys()
+            .map(|key| {
+                toml::Value::String(key.clone())
+                    .try_into()
+                    .map_err(|_| Error::Config(format!(r#"Unknown analysis level "{key}""#)))
+            })
+            .collect()
+    }
+}
+
+/// Recursively merges TOML `overrides` into `base`. Tables are merged key by key, while any other
+/// value replaces the base value outright.
+fn merge_config(base: &mut toml::Value, overrides: toml::Value) {
+    match (base, overrides) {
+        (toml::Value::Table(base), toml::Value::Table(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_config(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+/// Conditions on the analyzed challenge which must be met for an analyzer to run. Analyzers
+/// whose conditions are not met are left out of the run without affecting its status, as are
+/// analyzers with hard dependencies on them.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Conditions {
+    /// Challenge type to which the analyzer applies, identified by its lowercase proto name,
+    /// e.g. `tob`.
+    challenge: Option<String>,
+    /// Challenge mode to which the analyzer applies, either by its full lowercase proto name
+    /// (`tob_hard`) or without the challenge prefix (`hard`).
+    mode: Option<String>,
+    /// Minimum number of players in the challenge.
+    min_scale: Option<usize>,
+    /// Maximum number of players in the challenge.
+    max_scale: Option<usize>,
+}
+
+impl Conditions {
+    fn matches(&self, challenge: &Challenge) -> bool {
+        self.matches_attributes(challenge.r#type(), challenge.mode(), challenge.scale())
+    }
+
+    fn matches_attributes(
+        &self,
+        r#type: blert::Challenge,
+        mode: blert::ChallengeMode,
+        scale: usize,
+    ) -> bool {
+        let type_matches = self
+            .challenge
+            .as_ref()
+            .map_or(true, |c| c.eq_ignore_ascii_case(r#type.as_str_name()));
+
+        type_matches
+            && self.matches_mode(mode)
+            && self.min_scale.map_or(true, |min| scale >= min)
+            && self.max_scale.map_or(true, |max| scale <= max)
+    }
+
+    /// Checks the mode condition, which may name either the full mode (`tob_hard`) or only its
+    /// suffix (`hard`).
+    fn matches_mode(&self, mode: blert::ChallengeMode) -> bool {
+        let mode_name = mode.as_str_name().to_lowercase();
+        self.mode.as_ref().map_or(true, |m| {
+            let m = m.to_lowercase();
+            mode_name == m || mode_name.strip_suffix(&m).is_some_and(|p| p.ends_with('_'))
+        })
+    }
+}
+
+/// Configuration for an experimental analyzer run in "shadow" mode. Shadow analyzers only run on
+/// a sample of program runs, their outputs are kept separately from the program's regular
+/// outputs, and their failures never affect the program.
+#[derive(Debug, Serialize, Deserialize)]
+struct ShadowConfig {
+    /// Fraction of program runs, between 0 and 1, on which the analyzer runs.
+    sample_rate: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn panic_messages_are_extracted() {
+        let payload = panic::catch_unwind(|| panic!("static message")).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "static message");
+
+        let payload = panic::catch_unwind(|| panic!("formatted {}", 42)).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "formatted 42");
+
+        let payload = panic::catch_unwind(|| panic::panic_any(7_u32)).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "unknown panic");
+    }
+
+    fn program(analyzers: &str) -> ProgramConfig {
+        toml::from_str(&format!("[program]\nname = \"test\"\n{analyzers}")).unwrap()
+    }
+
+    #[test]
+    fn program_version_covers_analyzer_versions() {
+        let definition = "[program]\nname = \"test\"";
+        let versions = HashMap::from([("A".to_owned(), 1), ("B".to_owned(), 1)]);
+        let version = ProgramConfig::version_of(definition, &versions);
+        let reordered = HashMap::from([("B".to_owned(), 1), ("A".to_owned(), 1)]);
+        assert_eq!(version, ProgramConfig::version_of(definition, &reordered));
+
+        let mut bumped = versions.clone();
+        bumped.insert("B".to_owned(), 2);
+        assert_ne!(version, ProgramConfig::version_of(definition, &bumped));
+
+        let renamed = HashMap::from([("A".to_owned(), 1), ("C".to_owned(), 1)]);
+        assert_ne!(version, ProgramConfig::version_of(definition, &renamed));
+    }
+
+    #[test]
+    fn invalid_dependencies_are_rejected() {
+        let missing = program(
+            r#"
+            [analyzers.A]
+            implementation = "GearAnalyzer"
+            dependencies = ["B"]
+            "#,
+        );
+        let Err(Error::Config(message)) = missing.validate_dependencies() else {
+            panic!("Expected missing dependency to be rejected");
+        };
+        assert!(message.contains(r#"unknown analyzer "B""#));
+
+        let cycle = program(
+            r#"
+            [analyzers.A]
+            implementation = "GearAnalyzer"
+            dependencies = ["B"]
+
+            [analyzers.B]
+            implementation = "GearAnalyzer"
+            soft_dependencies = ["C"]
+
+            [analyzers.C]
+            implementation = "GearAnalyzer"
+            dependencies = ["A"]
+            "#,
+        );
+        let Err(Error::Config(message)) = cycle.validate_dependencies() else {
+            panic!("Expected dependency cycle to be rejected");
+        };
+        assert!(message.ends_with(r#""A" -> "B" -> "C" -> "A""#));
+
+        let valid = program(
+            r#"
+            [analyzers.A]
+            implementation = "GearAnalyzer"
+
+            [analyzers.B]
+            implementation = "GearAnalyzer"
+            dependencies = ["A"]
+
+            [analyzers.C]
+            implementation = "GearAnalyzer"
+            dependencies = ["A", "B"]
+            "#,
+        );
+        assert!(valid.validate_dependencies().is_ok());
+    }
+
+    #[tokio::test]
+    async fn included_analyzers_are_merged_into_program() {
+        let program = Engine::parse_program(Path::new("programs/tob_basic.toml"))
+            .await
+            .unwrap();
+        assert_eq!(program.program.name, "tob_basic");
+        assert!(program.analyzers.contains_key("GearAnalyzer"));
+        assert!(program.analyzers.contains_key("TobRoleAnalyzer"));
+    }
+
+    #[test]
+    fn level_overrides_are_merged_into_config() {
+        let config = program(
+            r#"
+            [analyzers.A]
+            implementation = "TestAnalyzer"
+
+            [analyzers.A.config]
+            threshold = 5
+            options = { a = 1, b = 2 }
+
+            [analyzers.A.config.levels.maxeff]
+            threshold = 3
+            options = { b = 4 }
+            "#,
+        );
+        let definition = &config.analyzers["A"];
+        assert_eq!(definition.override_levels().unwrap(), vec![Level::MaxEff]);
+
+        let expected: toml::Value =
+            toml::from_str("threshold = 5\noptions = { a = 1, b = 2 }").unwrap();
+        assert_eq!(definition.config_for(Level::Learner), Some(expected));
+
+        let expected: toml::Value =
+            toml::from_str("threshold = 3\noptions = { a = 1, b = 4 }").unwrap();
+        assert_eq!(definition.config_for(Level::MaxEff), Some(expected));
+    }
+
+    #[test]
+    fn conditions_match_challenge_attributes() {
+        let config = program(
+            r#"
+            [analyzers.A]
+            implementation = "GearAnalyzer"
+
+            [analyzers.A.conditions]
+            challenge = "tob"
+            mode = "hard"
+            min_scale = 3
+            "#,
+        );
+        assert!(config.validate_conditions().is_ok());
+
+        let unknown_mode = program(
+            r#"
+            [analyzers.A]
+            implementation = "GearAnalyzer"
+            conditions = { mode = "nightmare" }
+            "#,
+        );
+        assert!(unknown_mode.validate_conditions().is_err());
+
+        let misspelled = toml::from_str::<ProgramConfig>(
+            r#"
+            [program]
+            name = "test"
+
+            [analyzers.A]
+            implementation = "GearAnalyzer"
+            conditions = { min_scael = 3 }
+            "#,
+        );
+        assert!(misspelled.is_err_and(|e| e.to_string().contains("min_scael")));
+
+        let conditions = &config.analyzers["A"].conditions;
+        let tob = blert::Challenge::Tob;
+        assert!(conditions.matches_attributes(tob, blert::ChallengeMode::TobHard, 4));
+        assert!(!conditions.matches_attributes(tob, blert::ChallengeMode::TobHard, 2));
+        assert!(!conditions.matches_attributes(tob, blert::ChallengeMode::TobRegular, 4));
+        assert!(!conditions.matches_attributes(
+            blert::Challenge::Colosseum,
+            blert::ChallengeMode::NoMode,
+            1
+        ));
+        assert!(Conditions::default().matches_attributes(
+            blert::Challenge::Colosseum,
+            blert::ChallengeMode::NoMode,
+            1
+        ));
+    }
+
+    #[test]
+    fn soft_dependencies_on_shadow_analyzers_are_rejected() {
+        let config: ProgramConfig = toml::from_str(
+            r#"
+            [program]
+            name = "test"
+
+            [analyzers.Experimental]
+            implementation = "GearAnalyzer"
+            optional = true
+            shadow = { sample_rate = 0.5 }
+
+            [analyzers.Dependent]
+            implementation = "GearAnalyzer"
+            soft_dependencies = ["Experimental"]
+            "#,
+        )
+        .unwrap();
+        assert!(config.analyzers["Experimental"].optional);
+        assert!(matches!(
+            config.validate_shadow_analyzers(),
+            Err(Error::Config(_))
+        ));
+    }
+
+    #[test]
+    fn shadow_sample_rates_must_be_fractions() {
+        let shadow = |sample_rate: &str| {
+            program(&format!(
+                r#"
+                [analyzers.A]
+                implementation = "GearAnalyzer"
+                shadow = {{ sample_rate = {sample_rate} }}
+                "#
+            ))
+        };
+
+        assert!(shadow("0.0").validate_shadow_analyzers().is_ok());
+        assert!(shadow("0.25").validate_shadow_analyzers().is_ok());
+        assert!(shadow("1.0").validate_shadow_analyzers().is_ok());
+        for invalid in ["-0.1", "1.5", "nan"] {
+            assert!(
+                matches!(
+                    shadow(invalid).validate_shadow_analyzers(),
+                    Err(Error::Config(_))
+                ),
+                "sample_rate {invalid} was accepted"
+            );
+        }
+    }
 }
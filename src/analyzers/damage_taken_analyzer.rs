@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+
+use schemars::JsonSchema;
+use serde::{Serialize, Serializer};
+
+use crate::analysis::{Context, PlayerAnalyzer};
+use crate::blert;
+use crate::challenge::StageInfo;
+use crate::error::Result;
+use crate::history::PlayerMetric;
+
+/// The `DamageTakenAnalyzer` attributes all damage taken by each player to its source, answering
+/// the question of where a player's hitpoints went.
+///
+/// Damage is derived from drops in a player's recorded hitpoints between updates. Each drop is
+/// attributed to the most recent NPC attack targeting the player within a short window, falling
+/// back to untargeted (area of effect) NPC attacks. Damage which cannot be attributed to any NPC
+/// attack is considered environmental.
+///
+/// Where previous results are available, each player's total is accompanied by their typical
+/// total over recent raids.
+pub struct DamageTakenAnalyzer {}
+
+impl DamageTakenAnalyzer {
+    /// Maximum number of ticks between an NPC attack and the hitpoints drop it is considered to
+    /// have caused, accounting for projectile travel time.
+    const ATTRIBUTION_WINDOW: u32 = 4;
+
+    /// Number of a player's previous raids over which their typical damage taken is computed.
+    const HISTORY_RAIDS: u32 = 20;
+
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    /// Attributes the damage a player took during a stage to its sources. Returns `None` if the
+    /// player was not recorded in the stage.
+    fn analyze_stage(stage: &StageInfo, username: &str) -> Option<HashMap<Source, u32>> {
+        stage.player_state(username)?;
+
+        // Most recent targeted and untargeted NPC attacks, with the ticks on which they occurred.
+        let mut last_targeted: Option<(u32, blert::NpcAttack)> = None;
+        let mut last_untargeted: Option<(u32, blert::NpcAttack)> = None;
+
+        let mut damage = HashMap::new();
+        let mut last_hitpoints: Option<i16> = None;
+
+        for view in stage.replay() {
+            let tick = view.tick();
+
+            for event in view.events_of_type(blert::event::Type::NpcAttack) {
+                let Some(npc_attack) = &event.npc_attack else {
+                    continue;
+                };
+                match npc_attack.target.as_deref() {
+                    Some(target) if target == username => {
+                        last_targeted = Some((tick, npc_attack.attack()));
+                    }
+                    Some("") | None => last_untargeted = Some((tick, npc_attack.attack())),
+                    Some(_) => {}
+                }
+            }
+
+            let Some(hitpoints) = view.player(username).and_then(|p| p.stats.hitpoints()) else {
+                continue;
+            };
+
+            if let Some(previous) = last_hitpoints {
+                if hitpoints.current < previous {
+                    let recent = |attack: Option<(u32, blert::NpcAttack)>| {
+                        attack
+                            .filter(|(t, _)| tick - t <= Self::ATTRIBUTION_WINDOW)
+                            .map(|(_, attack)| Source::Attack(attack))
+                    };
+                    let source = recent(last_targeted)
+                        .or_else(|| recent(last_untargeted))
+                        .unwrap_or(Source::Environmental);
+                    *damage.entry(source).or_default() +=
+                        u32::from((previous - hitpoints.current).unsigned_abs());
+                }
+            }
+
+            last_hitpoints = Some(hitpoints.current);
+        }
+
+        Some(damage)
+    }
+}
+
+/// A source of damage taken by a player.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Source {
+    /// A specific NPC attack, either targeted at the player or an area of effect.
+    Attack(blert::NpcAttack),
+
+    /// Damage which could not be attributed to any NPC attack.
+    Environmental,
+}
+
+impl Serialize for Source {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self {
+            Source::Attack(attack) => serializer.serialize_str(attack.as_str_name()),
+            Source::Environmental => serializer.serialize_str("ENVIRONMENTAL"),
+        }
+    }
+}
+
+/// Breakdown of the damage taken by a player throughout a challenge.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct DamageTaken {
+    total: u32,
+    /// The player's median total damage taken over their recent raids, if known.
+    typical_total: Option<f64>,
+    /// Damage taken from each source across the challenge.
+    #[schemars(with = "HashMap<String, u32>")]
+    by_source: HashMap<Source, u32>,
+    #[serde(serialize_with = "super::serialize_by_stage")]
+    #[schemars(with = "HashMap<String, HashMap<String, u32>>")]
+    by_stage: HashMap<blert::Stage, HashMap<Source, u32>>,
+}
+
+impl DamageTaken {
+    /// Returns the total damage the player took across the challenge.
+    pub fn total(&self) -> u32 {
+        self.total
+    }
+
+    /// Returns the player's median total damage taken over their recent raids, if known.
+    pub fn typical_total(&self) -> Option<f64> {
+        self.typical_total
+    }
+
+    /// Returns the damage the player took from each source during a stage.
+    pub fn stage(&self, stage: blert::Stage) -> Option<&HashMap<Source, u32>> {
+        self.by_stage.get(&stage)
+    }
+}
+
+impl PlayerAnalyzer for DamageTakenAnalyzer {
+    type Output = DamageTaken;
+
+    fn name(&self) -> &str {
+        "DamageTakenAnalyzer"
+    }
+
+    fn analyze_player(&self, context: &Context, username: &str) -> Result<Self::Output> {
+        let by_stage = context
+            .challenge()
+            .stage_infos()
+            .iter()
+            .filter_map(|stage| Self::analyze_stage(stage, username).map(|d| (stage.stage(), d)))
+            .collect::<HashMap<_, _>>();
+        let total = by_stage.values().flat_map(HashMap::values).sum();
+        let mut by_source = HashMap::new();
+        for (&source, &damage) in by_stage.values().flatten() {
+            *by_source.entry(source).or_default() += damage;
+        }
+
+        let metric = PlayerMetric {
+            analyzer: self.name(),
+            path: &["total"],
+        };
+        let typical_total = context.history().and_then(|history| {
+            history
+                .player_median(username, metric, Self::HISTORY_RAIDS)
+                .unwrap_or_else(|e| {
+                    tracing::warn!("Failed to load damage taken history of {username}: {e}");
+                    None
+                })
+        });
+
+        Ok(DamageTaken {
+            total,
+            typical_total,
+            by_source,
+            by_stage,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenge::Challenge;
+    use crate::data_repository::{DataRepository, MemoryBackend};
+    use crate::synthetic::{Generator, SyntheticConfig};
+
+    #[tokio::test]
+    async fn damage_is_attributed_to_recent_attacks() {
+        let mut synthetic = Generator::new(SyntheticConfig {
+            scale: 2,
+            stages: 2,
+            ticks_per_stage: 40,
+            npcs_per_tick: 0,
+            seed: 3,
+        })
+        .generate();
+
+        // The first player is hit by an attack on tick 8 and takes damage from it on tick 10,
+        // then takes damage long after any attack on tick 30.
+        let events = &mut synthetic.stages[0].events;
+        for event in events.iter_mut() {
+            if event.r#type() != blert::event::Type::PlayerUpdate {
+                continue;
+            }
+            if let Some(player) = event.player.as_mut().filter(|p| p.party_index == 0) {
+                let current = match event.tick {
+                    0..=9 => 99,
+                    10..=29 => 90,
+                    _ => 85,
+                };
+                player.hitpoints = Some((current << 16) | 99);
+            }
+        }
+        let mut attack = blert::Event {
+            tick: 8,
+            npc_attack: Some(Default::default()),
+            ..Default::default()
+        };
+        attack.set_type(blert::event::Type::NpcAttack);
+        if let Some(npc_attack) = attack.npc_attack.as_mut() {
+            npc_attack.target = Some("synthetic 0".into());
+        }
+        events.push(attack);
+        events.sort_by_key(|event| event.tick);
+
+        let backend = MemoryBackend::new();
+        synthetic.store(&backend);
+        let repository = DataRepository::new(Box::new(backend));
+        let challenge = Challenge::load_from_repository(&repository, synthetic.uuid)
+            .await
+            .unwrap();
+
+        let damage =
+            DamageTakenAnalyzer::analyze_stage(&challenge.stage_infos()[0], "synthetic 0").unwrap();
+        assert_eq!(
+            damage,
+            HashMap::from([
+                (Source::Attack(blert::NpcAttack::default()), 9),
+                (Source::Environmental, 5),
+            ]),
+        );
+    }
+
+    #[tokio::test]
+    async fn stages_without_the_player_are_skipped() {
+        let mut synthetic = Generator::new(SyntheticConfig {
+            scale: 2,
+            stages: 2,
+            ticks_per_stage: 20,
+            npcs_per_tick: 0,
+            seed: 5,
+        })
+        .generate();
+
+        // The second player was not recorded in the second stage.
+        let stage = &mut synthetic.stages[1];
+        stage.party_names.truncate(1);
+        stage
+            .events
+            .retain(|event| event.player.as_ref().is_none_or(|p| p.party_index == 0));
+
+        let backend = MemoryBackend::new();
+        synthetic.store(&backend);
+        let repository = DataRepository::new(Box::new(backend));
+        let challenge = Challenge::load_from_repository(&repository, synthetic.uuid)
+            .await
+            .unwrap();
+
+        let [first, second] = challenge.stage_infos() else {
+            panic!("expected two stages");
+        };
+        assert!(DamageTakenAnalyzer::analyze_stage(first, "synthetic 1").is_some());
+        assert!(DamageTakenAnalyzer::analyze_stage(second, "synthetic 1").is_none());
+        assert!(DamageTakenAnalyzer::analyze_stage(second, "synthetic 0").is_some());
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::{HashMap, HashSet};
+
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::analysis::{Analyzer, Context};
+use crate::blert;
+use crate::challenge::{AttackState, Challenge, StageInfo};
+use crate::error::Result;
+
+/// The `DataQualityAnalyzer` scores how completely a challenge was recorded, giving consumers an
+/// indication of how much to trust the conclusions of other analyzers for the challenge.
+///
+/// Each stage is scored on four metrics:
+/// - Event density: how many events were recorded per tick relative to the party size.
+/// - Player update coverage: the fraction of player-ticks with a recorded `PlayerUpdate`.
+/// - NPC coverage: the fraction of player attacks whose target NPC is known.
+/// - Cooldown consistency: the fraction of player attacks which did not occur while the player
+///   was recorded as being on cooldown.
+///
+/// Scores range from 0 (unusable) to 100 (complete recording). Recorded stages whose events are
+/// missing entirely are listed separately and do not contribute to the score.
+///
+/// The names of stages scoring below [`LOW_QUALITY_SCORE`] are published to the blackboard under
+/// [`LOW_QUALITY_STAGES`], so that dependent analyzers can discount their conclusions about them.
+pub struct DataQualityAnalyzer {}
+
+/// Blackboard key under which the names of poorly recorded stages are published.
+pub const LOW_QUALITY_STAGES: &str = "low_quality_stages";
+
+/// Score below which a stage is considered poorly recorded.
+pub const LOW_QUALITY_SCORE: f64 = 50.0;
+
+impl DataQualityAnalyzer {
+    const DENSITY_WEIGHT: f64 = 0.2;
+    const PLAYER_UPDATE_WEIGHT: f64 = 0.4;
+    const NPC_WEIGHT: f64 = 0.2;
+    const COOLDOWN_WEIGHT: f64 = 0.2;
+
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    /// Scores the recording of every loaded stage of `challenge`.
+    fn assess(challenge: &Challenge) -> DataQuality {
+        let stages = challenge
+            .stage_infos()
+            .iter()
+            .map(|stage| (stage.stage(), Self::analyze_stage(stage, challenge.party())))
+            .collect::<HashMap<_, _>>();
+
+        let mut quality = DataQuality {
+            score: 0.0,
+            stages,
+            missing_stages: challenge
+                .missing_stages()
+                .iter()
+                .map(|stage| stage.as_str_name().to_owned())
+                .collect(),
+        };
+        quality.score = quality.compute_score();
+        quality
+    }
+
+    fn analyze_stage(stage: &StageInfo, party: &[String]) -> StageQuality {
+        let total_ticks = stage.total_ticks();
+        let expected_updates = u64::from(total_ticks) * party.len() as u64;
+
+        let recorded_updates = stage
+            .events_for_type(blert::event::Type::PlayerUpdate)
+            .filter_map(|event| event.player.as_ref().map(|p| (p.party_index, event.tick)))
+            .collect::<HashSet<_>>()
+            .len() as u64;
+
+        let mut total_attacks = 0;
+        let mut unknown_targets = 0;
+        stage
+            .events_for_type(blert::event::Type::PlayerAttack)
+            .filter_map(|event| event.player_attack.as_ref())
+            .for_each(|attack| {
+                total_attacks += 1;
+                if attack
+                    .target
+                    .as_ref()
+                    .is_some_and(|target| stage.npc(target.room_id).is_none())
+                {
+                    unknown_targets += 1;
+                }
+            });
+
+        let cooldown_inconsistencies = party
+            .iter()
+            .filter_map(|username| stage.player_state(username))
+            .map(|states| {
+                states
+                    .attacks()
+                    .filter(|(tick, _)| {
+                        tick.checked_sub(1)
+                            .and_then(|previous| states.get_tick(previous as usize))
+                            .is_some_and(|state| match state.attack_state {
+                                AttackState::OnCooldown(ticks) => ticks > 1,
+                                _ => false,
+                            })
+                    })
+                    .count() as u32
+            })
+            .sum();
+
+        let mut quality = StageQuality {
+            score: 0.0,
+            total_ticks,
+            total_events: stage.total_events(),
+            missing_player_updates: expected_updates.saturating_sub(recorded_updates),
+            expected_player_updates: expected_updates,
+            total_attacks,
+            unknown_attack_targets: unknown_targets,
+            cooldown_inconsistencies,
+            scale: party.len(),
+        };
+        quality.score = quality.compute_score();
+        quality
+    }
+}
+
+/// Recording completeness metrics for a single stage of a challenge.
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StageQuality {
+    /// Quality score of the stage, from 0 to 100.
+    pub score: f64,
+    pub total_ticks: u32,
+    pub total_events: usize,
+    pub expected_player_updates: u64,
+    pub missing_player_updates: u64,
+    pub total_attacks: u32,
+    pub unknown_attack_targets: u32,
+    pub cooldown_inconsistencies: u32,
+    #[serde(skip)]
+    scale: usize,
+}
+
+impl StageQuality {
+    /// Returns the average number of events recorded per tick of the stage.
+    pub fn event_density(&self) -> f64 {
+        if self.total_ticks == 0 {
+            0.0
+        } else {
+            self.total_events as f64 / f64::from(self.total_ticks)
+        }
+    }
+
+    fn compute_score(&self) -> f64 {
+        if self.total_ticks == 0 {
+            return 0.0;
+        }
+
+        // Every player should have at least one update per tick, so anything below that is
+        // considered sparse.
+        let density = (self.event_density() / self.scale.max(1) as f64).min(1.0);
+        let player_updates = ratio(
+            self.expected_player_updates - self.missing_player_updates,
+            self.expected_player_updates,
+        );
+        let npcs = ratio(
+            u64::from(
+                self.total_attacks
+                    .saturating_sub(self.unknown_attack_targets),
+            ),
+            u64::from(self.total_attacks),
+        );
+        let cooldowns = ratio(
+            u64::from(
+                self.total_attacks
+                    .saturating_sub(self.cooldown_inconsistencies),
+            ),
+            u64::from(self.total_attacks),
+        );
+
+        100.0
+            * (DataQualityAnalyzer::DENSITY_WEIGHT * density
+                + DataQualityAnalyzer::PLAYER_UPDATE_WEIGHT * player_updates
+                + DataQualityAnalyzer::NPC_WEIGHT * npcs
+                + DataQualityAnalyzer::COOLDOWN_WEIGHT * cooldowns)
+    }
+}
+
+/// Returns `numerator / denominator`, treating an empty denominator as a perfect ratio.
+fn ratio(numerator: u64, denominator: u64) -> f64 {
+    if denominator == 0 {
+        1.0
+    } else {
+        numerator as f64 / denominator as f64
+    }
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DataQuality {
+    /// Overall quality score of the challenge, from 0 to 100, weighting each stage by its length.
+    score: f64,
+    #[serde(serialize_with = "super::serialize_by_stage")]
+    #[schemars(with = "HashMap<String, StageQuality>")]
+    stages: HashMap<blert::Stage, StageQuality>,
+    /// Recorded stages whose events are missing from the data repository.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    missing_stages: Vec<String>,
+}
+
+impl DataQuality {
+    /// Returns the overall quality score of the challenge, from 0 to 100.
+    pub fn score(&self) -> f64 {
+        self.score
+    }
+
+    fn compute_score(&self) -> f64 {
+        let total_ticks: u32 = self.stages.values().map(|s| s.total_ticks).sum();
+        if total_ticks == 0 {
+            return 0.0;
+        }
+
+        self.stages
+            .values()
+            .map(|s| s.score * f64::from(s.total_ticks))
+            .sum::<f64>()
+            / f64::from(total_ticks)
+    }
+
+    /// Returns the quality metrics of a specific stage, if it was recorded.
+    pub fn stage(&self, stage: blert::Stage) -> Option<&StageQuality> {
+        self.stages.get(&stage)
+    }
+}
+
+impl Analyzer for DataQualityAnalyzer {
+    type Output = DataQuality;
+
+    fn name(&self) -> &str {
+        "DataQualityAnalyzer"
+    }
+
+    fn analyze(&self, context: &Context) -> Result<Self::Output> {
+        let challenge = context.challenge();
+        let quality = Self::assess(challenge);
+        tracing::debug!(
+            "Challenge {} data quality score: {:.1}",
+            challenge.uuid(),
+            quality.score()
+        );
+
+        let mut low_quality_stages = quality
+            .stages
+            .iter()
+            .filter(|(_, stage)| stage.score < LOW_QUALITY_SCORE)
+            .map(|(stage, _)| stage.as_str_name().to_owned())
+            .collect::<Vec<_>>();
+        low_quality_stages.sort();
+        context
+            .blackboard()
+            .publish(LOW_QUALITY_STAGES, low_quality_stages, &[])?;
+
+        Ok(quality)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_repository::{DataRepository, MemoryBackend};
+    use crate::synthetic::{Generator, SyntheticChallenge, SyntheticConfig};
+
+    fn generate(stages: usize) -> SyntheticChallenge {
+        Generator::new(SyntheticConfig {
+            scale: 2,
+            stages,
+            ticks_per_stage: 20,
+            npcs_per_tick: 1,
+            seed: 7,
+        })
+        .generate()
+    }
+
+    async fn assess(backend: MemoryBackend, synthetic: &SyntheticChallenge) -> DataQuality {
+        let repository = DataRepository::new(Box::new(backend));
+        let challenge = Challenge::load_from_repository(&repository, synthetic.uuid)
+            .await
+            .unwrap();
+        DataQualityAnalyzer::assess(&challenge)
+    }
+
+    #[tokio::test]
+    async fn complete_challenge_is_scored() {
+        let synthetic = generate(2);
+        let backend = MemoryBackend::new();
+        synthetic.store(&backend);
+
+        let quality = assess(backend, &synthetic).await;
+        assert!(quality.missing_stages.is_empty());
+        assert_eq!(quality.stages.len(), 2);
+        for stage in quality.stages.values() {
+            assert_eq!(stage.missing_player_updates, 0);
+            assert!(stage.score > 0.0 && stage.score <= 100.0);
+        }
+        assert!(quality.score() > 0.0);
+
+        let output = serde_json::to_value(&quality).unwrap();
+        assert_eq!(output["score"], quality.score());
+        assert!(output["stages"]["TOB_MAIDEN"]["score"].is_number());
+    }
+
+    #[tokio::test]
+    async fn missing_stages_are_listed_but_not_scored() {
+        let synthetic = generate(2);
+        let backend = MemoryBackend::new();
+        backend.insert_challenge(synthetic.uuid, &synthetic.data);
+        backend.insert_stage_events(synthetic.uuid, &synthetic.stages[0]);
+
+        let quality = assess(backend, &synthetic).await;
+        assert_eq!(quality.missing_stages, ["TOB_BLOAT"]);
+        assert_eq!(quality.stages.len(), 1);
+        assert!(quality.stage(blert::Stage::TobBloat).is_none());
+
+        let maiden = quality.stage(blert::Stage::TobMaiden).unwrap();
+        assert!((quality.score() - maiden.score).abs() < f64::EPSILON);
+    }
+
+    #[tokio::test]
+    async fn partially_recorded_ticks_lower_the_score() {
+        let complete = generate(1);
+        let backend = MemoryBackend::new();
+        complete.store(&backend);
+        let complete_score = assess(backend, &complete).await.score();
+
+        // Drop every other update of the first player.
+        let mut partial = complete.clone();
+        partial.stages[0].events.retain(|event| {
+            event.r#type() != blert::event::Type::PlayerUpdate
+                || event.tick % 2 == 0
+                || event.player.as_ref().is_some_and(|p| p.party_index != 0)
+        });
+        let backend = MemoryBackend::new();
+        partial.store(&backend);
+
+        let quality = assess(backend, &partial).await;
+        let maiden = quality.stage(blert::Stage::TobMaiden).unwrap();
+        assert!(maiden.missing_player_updates > 0);
+        assert!(quality.score() < complete_score);
+    }
+}
@@ -1,7 +1,10 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 
-use crate::analysis::{Analyzer, Context};
+use schemars::JsonSchema;
+use serde::{Serialize, Serializer};
+
+use crate::analysis::{Context, PlayerAnalyzer, PlayerOutputs};
 use crate::error::{Error, Result};
 use crate::item::{EquipmentSlot, Item};
 use crate::{blert, item};
@@ -15,60 +18,45 @@ impl GearAnalyzer {
     }
 }
 
-#[derive(Debug)]
-struct GearInfo {
-    items_by_stage: HashMap<blert::Stage, HashMap<i32, Arc<Item>>>,
-    has_void: bool,
-}
-
-#[derive(Debug)]
-pub struct PlayerGear {
-    players: HashMap<String, GearInfo>,
-}
-
-impl PlayerGear {
-    /// Returns gear information for the specified player.
-    pub fn player<'a>(&'a self, username: &str) -> Option<Player<'a>> {
-        self.players.get(username).map(|gear| Player { gear })
-    }
-}
+/// Per-player outputs of the `GearAnalyzer`.
+pub type PlayerGear = PlayerOutputs<Player>;
 
 /// Holds information about the gear a player owns during a challenge.
 /// Gear ownership is split by stage, as players may trade items between stages.
-#[derive(Debug)]
-pub struct Player<'a> {
-    gear: &'a GearInfo,
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Player {
+    #[serde(serialize_with = "serialize_item_ids")]
+    #[schemars(with = "HashMap<String, Vec<i32>>")]
+    items_by_stage: HashMap<blert::Stage, HashMap<i32, Arc<Item>>>,
+    has_void: bool,
 }
 
-impl<'a> Player<'a> {
+impl Player {
     /// Returns whether the player has an item with the given ID during the specified stage.
     pub fn has(&self, stage: blert::Stage, item_id: i32) -> bool {
-        self.gear
-            .items_by_stage
+        self.items_by_stage
             .get(&stage)
             .is_some_and(|gear| gear.contains_key(&item_id))
     }
 
     /// Returns whether the player has an item with any of the given IDs during the specified stage.
     pub fn has_any(&self, stage: blert::Stage, item_ids: &[i32]) -> bool {
-        self.gear
-            .items_by_stage
+        self.items_by_stage
             .get(&stage)
             .is_some_and(|gear| item_ids.iter().any(|id| gear.contains_key(id)))
     }
 
     /// Returns whether the player has an item with the given ID during any stage of the challenge.
     pub fn has_in_challenge(&self, item_id: i32) -> bool {
-        self.gear
-            .items_by_stage
+        self.items_by_stage
             .values()
             .any(|gear| gear.contains_key(&item_id))
     }
 
     /// Returns whether the player has an item with any of the given IDs during any stage of the challenge.
     pub fn has_any_in_challenge(&self, item_ids: &[i32]) -> bool {
-        self.gear
-            .items_by_stage
+        self.items_by_stage
             .values()
             .any(|gear| item_ids.iter().any(|id| gear.contains_key(id)))
     }
@@ -82,57 +70,67 @@ impl<'a> Player<'a> {
                 vec![item::Id::VOID_RANGER_HELM, item::Id::VOID_RANGER_HELM_OR]
             }
             item::VoidStyle::Melee => vec![item::Id::VOID_MELEE_HELM, item::Id::VOID_MELEE_HELM_OR],
-            item::VoidStyle::Any => return self.gear.has_void,
+            item::VoidStyle::Any => return self.has_void,
         };
 
         self.has_any_in_challenge(&items)
     }
 }
 
-impl Analyzer for GearAnalyzer {
-    type Output = PlayerGear;
+/// Serializes a player's gear as the sorted IDs of the items they own in each stage.
+fn serialize_item_ids<S: Serializer>(
+    items_by_stage: &HashMap<blert::Stage, HashMap<i32, Arc<Item>>>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    let ids_by_stage = items_by_stage
+        .iter()
+        .map(|(&stage, items)| {
+            let mut ids = items.keys().copied().collect::<Vec<_>>();
+            ids.sort_unstable();
+            (stage, ids)
+        })
+        .collect();
+    super::serialize_by_stage(&ids_by_stage, serializer)
+}
+
+impl PlayerAnalyzer for GearAnalyzer {
+    type Output = Player;
 
     fn name(&self) -> &str {
         "GearAnalyzer"
     }
 
-    fn analyze(&self, context: &Context) -> Result<Self::Output> {
-        let mut players = HashMap::new();
-
-        let challenge = context.challenge();
-
-        for player in challenge.party() {
-            let mut items_by_stage = HashMap::new();
-            let mut has_void = false;
-
-            for stage in challenge.stage_infos() {
-                let mut gear = HashMap::new();
-
-                let state = stage.player_state(player).ok_or(Error::IncompleteData)?;
-                state.iter().for_each(|s| {
-                    EquipmentSlot::iter()
-                        .filter_map(|slot| {
-                            s.equipped_item(slot)
-                                .and_then(|item| context.item_registry().get(item.id()))
-                        })
-                        .for_each(|item| {
-                            gear.insert(item.id, item.clone());
-                            has_void |= item::is_void(item.id);
-                        });
-                });
-
-                items_by_stage.insert(stage.stage(), gear);
-            }
+    // Player gear is needed by party-wide analyzers such as role assignment.
+    fn ignores_player_scope(&self) -> bool {
+        true
+    }
 
-            players.insert(
-                player.clone(),
-                GearInfo {
-                    items_by_stage,
-                    has_void,
-                },
-            );
+    fn analyze_player(&self, context: &Context, username: &str) -> Result<Self::Output> {
+        let mut items_by_stage = HashMap::new();
+        let mut has_void = false;
+
+        for stage in context.challenge().stage_infos() {
+            let mut gear = HashMap::new();
+
+            let state = stage.player_state(username).ok_or(Error::IncompleteData)?;
+            state.iter().for_each(|s| {
+                EquipmentSlot::iter()
+                    .filter_map(|slot| {
+                        s.equipped_item(slot)
+                            .and_then(|item| context.item_registry().get(item.id()))
+                    })
+                    .for_each(|item| {
+                        gear.insert(item.id, item.clone());
+                        has_void |= item::is_void(item.id);
+                    });
+            });
+
+            items_by_stage.insert(stage.stage(), gear);
         }
 
-        Ok(PlayerGear { players })
+        Ok(Player {
+            items_by_stage,
+            has_void,
+        })
     }
 }
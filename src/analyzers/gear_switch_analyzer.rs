@@ -0,0 +1,427 @@
+use std::collections::{BTreeSet, HashMap};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::analysis::{Context, PlayerAnalyzer};
+use crate::blert;
+use crate::challenge::{AttackState, DeathState, ItemQuantity, PlayerState, StageInfo};
+use crate::error::{Error, Result};
+use crate::findings::{Finding, Severity};
+use crate::item::{self, EquipmentSlot};
+use crate::tob::phases::{Phase, PhaseInterval};
+
+/// The `GearSwitchAnalyzer` measures how each player manages their equipment in each room of a
+/// challenge.
+///
+/// It records the combat style each player enters a room with, counts the ticks on which they
+/// change equipment, determines which combat styles they switch between, and flags expected
+/// switches which never happened (e.g. never equipping a mage weapon during Verzik P2).
+/// Expectations are configured per room, optionally restricted to a single boss phase. An
+/// expectation only applies if the room reached its phase and the player was alive for some of it.
+///
+/// Switching a full setup should take a single tick. Every additional tick spent switching while
+/// the player was able to attack is counted as lost to slow inventory management.
+pub struct GearSwitchAnalyzer {
+    expectations: HashMap<blert::Stage, Vec<(Option<Phase>, Style)>>,
+}
+
+/// Configuration options for the `GearSwitchAnalyzer`.
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Combat styles which players are expected to switch to in specific rooms.
+    #[serde(default)]
+    expectations: Vec<Expectation>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct Expectation {
+    /// Canonical name of the room's stage, e.g. `TOB_VERZIK`.
+    stage: String,
+    /// Phase of the room within which the style is expected. If unset, the style is expected at
+    /// some point during the room.
+    phase: Option<Phase>,
+    style: Style,
+}
+
+impl GearSwitchAnalyzer {
+    pub fn new(config: &Config) -> Result<Self> {
+        let mut expectations: HashMap<_, Vec<_>> = HashMap::new();
+        for expectation in &config.expectations {
+            let stage = blert::Stage::from_str_name(&expectation.stage)
+                .ok_or_else(|| Error::Config(format!("Unknown stage: {}", expectation.stage)))?;
+            expectations
+                .entry(stage)
+                .or_default()
+                .push((expectation.phase, expectation.style));
+        }
+
+        Ok(Self { expectations })
+    }
+
+    fn analyze_stage(
+        &self,
+        stage: &StageInfo,
+        username: &str,
+        registry: &item::Registry,
+    ) -> Option<RoomSwitches> {
+        let states = stage.player_state(username)?;
+
+        let mut room = RoomSwitches {
+            starting_style: stage
+                .starting_equipment(username)
+                .and_then(|loadout| loadout.get(EquipmentSlot::Weapon))
+                .and_then(|weapon| Style::of_weapon(weapon, registry)),
+            ..RoomSwitches::default()
+        };
+        let mut styles = BTreeSet::new();
+        let mut styles_by_tick = Vec::new();
+        let mut alive_ticks = Vec::new();
+        let mut previous: Option<&PlayerState> = None;
+        let mut last_switch_tick = None;
+
+        for state in states
+            .iter()
+            .take_while(|state| state.death_state != DeathState::Dead)
+        {
+            alive_ticks.push(state.tick);
+            if let Some(style) = state
+                .equipped_item(EquipmentSlot::Weapon)
+                .and_then(|weapon| Style::of_weapon(weapon, registry))
+            {
+                styles.insert(style);
+                styles_by_tick.push((state.tick, style));
+            }
+
+            let Some(prev) = previous.replace(state) else {
+                continue;
+            };
+
+            let slots_changed = EquipmentSlot::iter()
+                .filter(|&slot| equipped_id(prev, slot) != equipped_id(state, slot))
+                .count() as u32;
+            if slots_changed == 0 {
+                continue;
+            }
+
+            room.switches += 1;
+            room.slots_changed += slots_changed;
+
+            let continues_switch = last_switch_tick.is_some_and(|tick| tick + 1 == state.tick);
+            if continues_switch && state.attack_state == AttackState::Idle {
+                room.ticks_lost += 1;
+            }
+            last_switch_tick = Some(state.tick);
+        }
+
+        room.missing_styles = missing_styles(
+            self.expectations
+                .get(&stage.stage())
+                .map_or(&[], Vec::as_slice),
+            stage.phases(),
+            &alive_ticks,
+            &styles_by_tick,
+        );
+        room.styles = styles.into_iter().collect();
+
+        Some(room)
+    }
+}
+
+/// Returns the expected styles which a player never switched to. Expectations for phases which the
+/// room never reached, or during which the player was never alive, are skipped.
+fn missing_styles(
+    expectations: &[(Option<Phase>, Style)],
+    phases: &[PhaseInterval],
+    alive_ticks: &[u32],
+    styles_by_tick: &[(u32, Style)],
+) -> Vec<Style> {
+    let in_phase = |tick: u32, phase: Option<Phase>| {
+        phase.map_or(true, |phase| {
+            phases
+                .iter()
+                .any(|interval| interval.phase == phase && interval.contains(tick))
+        })
+    };
+
+    expectations
+        .iter()
+        .filter(|&&(phase, _)| alive_ticks.iter().any(|&tick| in_phase(tick, phase)))
+        .filter(|&&(phase, style)| {
+            !styles_by_tick
+                .iter()
+                .any(|&(tick, s)| s == style && in_phase(tick, phase))
+        })
+        .map(|&(_, style)| style)
+        .collect()
+}
+
+fn equipped_id(state: &PlayerState, slot: EquipmentSlot) -> Option<i32> {
+    state.equipped_item(slot).map(ItemQuantity::id)
+}
+
+/// A combat style, as determined by a player's equipped weapon.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Style {
+    Melee,
+    Ranged,
+    Magic,
+}
+
+impl Style {
+    /// Returns the style of a weapon, based on its highest attack bonus. Weapons without any
+    /// attack bonuses have no style.
+    fn of_weapon(weapon: &ItemQuantity, registry: &item::Registry) -> Option<Self> {
+        let stats = registry.get(weapon.id())?.stats.as_ref()?;
+
+        let melee = stats
+            .stab_attack
+            .max(stats.slash_attack)
+            .max(stats.crush_attack);
+        [
+            (Style::Melee, melee),
+            (Style::Ranged, stats.ranged_attack),
+            (Style::Magic, stats.magic_attack),
+        ]
+        .into_iter()
+        .filter(|&(_, bonus)| bonus > 0)
+        .max_by_key(|&(_, bonus)| bonus)
+        .map(|(style, _)| style)
+    }
+}
+
+/// A player's equipment switches within a single room.
+#[derive(Debug, Default, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomSwitches {
+    /// Combat style of the weapon the player entered the room with.
+    pub starting_style: Option<Style>,
+    /// Number of ticks on which the player's equipment changed.
+    pub switches: u32,
+    /// Total number of equipment slots changed across all switches.
+    pub slots_changed: u32,
+    /// Combat styles of the weapons the player equipped in the room.
+    pub styles: Vec<Style>,
+    /// Combat styles expected in the room which the player never switched to.
+    pub missing_styles: Vec<Style>,
+    /// Estimated ticks lost to switches spread across multiple ticks.
+    pub ticks_lost: u32,
+}
+
+/// A player's equipment switches throughout a challenge.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct GearSwitches {
+    /// Total number of ticks the player lost to slow switches across the challenge.
+    total_ticks_lost: u32,
+    #[serde(serialize_with = "super::serialize_by_stage")]
+    #[schemars(with = "HashMap<String, RoomSwitches>")]
+    by_stage: HashMap<blert::Stage, RoomSwitches>,
+}
+
+impl GearSwitches {
+    /// Returns the player's switches during a stage, if they were present in it.
+    pub fn stage(&self, stage: blert::Stage) -> Option<&RoomSwitches> {
+        self.by_stage.get(&stage)
+    }
+}
+
+impl PlayerAnalyzer for GearSwitchAnalyzer {
+    type Output = GearSwitches;
+
+    fn name(&self) -> &str {
+        "GearSwitchAnalyzer"
+    }
+
+    fn analyze_player(&self, context: &Context, username: &str) -> Result<Self::Output> {
+        let by_stage = context
+            .challenge()
+            .stage_infos()
+            .iter()
+            .filter_map(|stage| {
+                self.analyze_stage(stage, username, context.item_registry())
+                    .map(|room| (stage.stage(), room))
+            })
+            .collect::<HashMap<_, _>>();
+
+        let mut stages = by_stage.keys().copied().collect::<Vec<_>>();
+        stages.sort_unstable();
+        for stage in stages {
+            let room = &by_stage[&stage];
+            for style in &room.missing_styles {
+                context.emit_finding(
+                    Finding::new(Severity::Major, "gear.missing_style")
+                        .with_player(username)
+                        .with_stage(stage)
+                        .with_data(json!({ "style": format!("{style:?}").to_lowercase() })),
+                );
+            }
+            if room.ticks_lost > 0 {
+                context.emit_finding(
+                    Finding::new(Severity::Minor, "gear.slow_switch")
+                        .with_player(username)
+                        .with_stage(stage)
+                        .with_data(json!({ "ticks": room.ticks_lost })),
+                );
+            }
+        }
+
+        Ok(GearSwitches {
+            total_ticks_lost: by_stage.values().map(|room| room.ticks_lost).sum(),
+            by_stage,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenge::Challenge;
+    use crate::data_repository::{DataRepository, MemoryBackend};
+    use crate::synthetic::{Generator, SyntheticConfig};
+
+    const VERZIK_PHASES: [PhaseInterval; 2] = [
+        PhaseInterval {
+            phase: Phase::VerzikP1,
+            start_tick: 0,
+            end_tick: 100,
+        },
+        PhaseInterval {
+            phase: Phase::VerzikP2,
+            start_tick: 100,
+            end_tick: 250,
+        },
+    ];
+
+    #[test]
+    fn config_rejects_unknown_fields() {
+        let config: Config = toml::from_str(
+            r#"
+            [[expectations]]
+            stage = "TOB_VERZIK"
+            phase = "verzikP2"
+            style = "magic"
+            "#,
+        )
+        .unwrap();
+        assert!(GearSwitchAnalyzer::new(&config).is_ok());
+
+        let misspelled = toml::from_str::<Config>(
+            r#"
+            [[expectations]]
+            stage = "TOB_VERZIK"
+            phsae = "verzikP2"
+            style = "magic"
+            "#,
+        );
+        assert!(misspelled.is_err());
+    }
+
+    #[test]
+    fn styles_are_expected_within_their_phase() {
+        let expectations = [(None, Style::Melee), (Some(Phase::VerzikP2), Style::Magic)];
+        let alive = (0..250).collect::<Vec<_>>();
+
+        let switched = [(10, Style::Melee), (150, Style::Magic)];
+        assert!(missing_styles(&expectations, &VERZIK_PHASES, &alive, &switched).is_empty());
+
+        // Magic only used during P1 does not satisfy the P2 expectation.
+        let early = [(10, Style::Melee), (50, Style::Magic)];
+        assert_eq!(
+            missing_styles(&expectations, &VERZIK_PHASES, &alive, &early),
+            [Style::Magic],
+        );
+    }
+
+    #[test]
+    fn unreached_phases_are_not_expected() {
+        let expectations = [
+            (Some(Phase::VerzikP1), Style::Melee),
+            (Some(Phase::VerzikP2), Style::Magic),
+            (Some(Phase::VerzikP3), Style::Ranged),
+        ];
+        let alive = (0..250).collect::<Vec<_>>();
+
+        // The party wiped during P2 without reaching P3.
+        assert_eq!(
+            missing_styles(&expectations, &VERZIK_PHASES, &alive, &[(10, Style::Melee)]),
+            [Style::Magic],
+        );
+
+        // A player who died in P1 is not expected to switch in P2.
+        let died_early = (0..60).collect::<Vec<_>>();
+        assert!(missing_styles(
+            &expectations,
+            &VERZIK_PHASES,
+            &died_early,
+            &[(10, Style::Melee)]
+        )
+        .is_empty());
+    }
+
+    #[tokio::test]
+    async fn switches_after_death_are_not_counted() {
+        const DEATH_TICK: u32 = 10;
+        const ADD_HELM: u64 = 0x0000_0001_8000_0001;
+        const REMOVE_HELM: u64 = 0x0000_0001_0000_0001;
+
+        let mut synthetic = Generator::new(SyntheticConfig {
+            scale: 2,
+            stages: 1,
+            ticks_per_stage: 40,
+            npcs_per_tick: 0,
+            seed: 11,
+        })
+        .generate();
+
+        // The first player switches once while alive, and their client keeps reporting equipment
+        // changes after they die.
+        let events = &mut synthetic.stages[0].events;
+        for event in events.iter_mut() {
+            let delta = match event.tick {
+                5 | 25 => ADD_HELM,
+                20 => REMOVE_HELM,
+                _ => continue,
+            };
+            if event.r#type() != blert::event::Type::PlayerUpdate {
+                continue;
+            }
+            if let Some(player) = event.player.as_mut().filter(|p| p.party_index == 0) {
+                player.equipment_deltas.push(delta);
+            }
+        }
+        let mut death = blert::Event {
+            tick: DEATH_TICK,
+            player: Some(blert::event::Player::default()),
+            ..Default::default()
+        };
+        death.set_type(blert::event::Type::PlayerDeath);
+        events.push(death);
+        events.sort_by_key(|event| event.tick);
+
+        let backend = MemoryBackend::new();
+        synthetic.store(&backend);
+        let repository = DataRepository::new(Box::new(backend));
+        let challenge = Challenge::load_from_repository(&repository, synthetic.uuid)
+            .await
+            .unwrap();
+        let registry = item::Registry::load_from_file("resources/runescape_items.json").unwrap();
+
+        let analyzer = GearSwitchAnalyzer::new(&Config::default()).unwrap();
+        let room = analyzer
+            .analyze_stage(&challenge.stage_infos()[0], "synthetic 0", &registry)
+            .unwrap();
+        assert_eq!(room.switches, 1);
+        assert_eq!(room.slots_changed, 1);
+
+        let survivor = analyzer
+            .analyze_stage(&challenge.stage_infos()[0], "synthetic 1", &registry)
+            .unwrap();
+        assert_eq!(survivor.switches, 0);
+    }
+}
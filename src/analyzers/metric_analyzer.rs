@@ -0,0 +1,388 @@
+use std::collections::{BTreeMap, HashSet};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::{Context, PlayerAnalyzer};
+use crate::blert;
+use crate::challenge::{AttackState, DeathState, PlayerAttackExt, PlayerAttacked, StageInfo};
+use crate::error::{Error, Result};
+use crate::npc;
+use crate::tob::phases::Phase;
+
+/// The `MetricAnalyzer` computes simple counting metrics for each player from declarative
+/// definitions in its config, so that metrics such as "barrages cast on Maiden crabs" do not each
+/// need a bespoke analyzer.
+///
+/// Each metric counts the occurrences of one kind of per-tick player event, such as an attack or
+/// a death, which match all of the metric's filters. Filters left unset match everything.
+pub struct MetricAnalyzer {
+    metrics: Vec<Metric>,
+}
+
+/// Configuration options for the `MetricAnalyzer`.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct Config {
+    /// Metrics to compute for each player.
+    metrics: Vec<MetricConfig>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct MetricConfig {
+    /// Name of the metric in the analyzer's output.
+    name: String,
+    /// What the metric counts.
+    count: Count,
+    /// Canonical names of the stages in which to count, e.g. `TOB_MAIDEN`.
+    #[serde(default)]
+    stages: Vec<String>,
+    /// Boss phases within which to count.
+    #[serde(default)]
+    phases: Vec<Phase>,
+    /// Attacks to count, by canonical name (e.g. `SANG_BARRAGE`) or by category: `barrage`,
+    /// `chin`, or `special`. Only applies to attacks.
+    #[serde(default)]
+    attacks: Vec<String>,
+    /// Roles of the NPCs whose attacks to count. Only applies to attacks.
+    #[serde(default)]
+    target_roles: Vec<npc::Role>,
+    /// IDs of the NPCs whose attacks to count. Only applies to attacks.
+    #[serde(default)]
+    target_ids: Vec<u32>,
+}
+
+/// Kinds of player events a metric can count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum Count {
+    /// Ticks on which the player attacked.
+    Attacks,
+    /// Ticks on which the player died.
+    Deaths,
+    /// Ticks on which the player was alive and able to attack, but did not.
+    IdleTicks,
+}
+
+#[derive(Debug)]
+enum AttackFilter {
+    Exact(blert::PlayerAttack),
+    Barrage,
+    Chin,
+    Special,
+}
+
+impl AttackFilter {
+    fn parse(name: &str) -> Result<Self> {
+        match name {
+            "barrage" => Ok(Self::Barrage),
+            "chin" => Ok(Self::Chin),
+            "special" => Ok(Self::Special),
+            _ => blert::PlayerAttack::from_str_name(name)
+                .map(Self::Exact)
+                .ok_or_else(|| Error::Config(format!("Unknown attack: {name}"))),
+        }
+    }
+
+    fn matches(&self, attack: blert::PlayerAttack) -> bool {
+        match self {
+            Self::Exact(expected) => attack == *expected,
+            Self::Barrage => attack.is_barrage(),
+            Self::Chin => attack.is_chin(),
+            Self::Special => attack.is_special(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Metric {
+    name: String,
+    count: Count,
+    stages: HashSet<blert::Stage>,
+    phases: HashSet<Phase>,
+    attacks: Vec<AttackFilter>,
+    target_roles: Vec<npc::Role>,
+    target_ids: HashSet<u32>,
+}
+
+impl Metric {
+    fn from_config(config: &MetricConfig) -> Result<Self> {
+        let stages = config
+            .stages
+            .iter()
+            .map(|stage| {
+                blert::Stage::from_str_name(stage)
+                    .ok_or_else(|| Error::Config(format!("Unknown stage: {stage}")))
+            })
+            .collect::<Result<_>>()?;
+        let attacks: Vec<_> = config
+            .attacks
+            .iter()
+            .map(|attack| AttackFilter::parse(attack))
+            .collect::<Result<_>>()?;
+
+        let filters_attacks =
+            !attacks.is_empty() || !config.target_roles.is_empty() || !config.target_ids.is_empty();
+        if filters_attacks && config.count != Count::Attacks {
+            return Err(Error::Config(format!(
+                r#"Metric "{}" filters attacks but does not count them"#,
+                config.name
+            )));
+        }
+
+        Ok(Self {
+            name: config.name.clone(),
+            count: config.count,
+            stages,
+            phases: config.phases.iter().copied().collect(),
+            attacks,
+            target_roles: config.target_roles.clone(),
+            target_ids: config.target_ids.iter().copied().collect(),
+        })
+    }
+
+    fn counts_stage(&self, stage: blert::Stage) -> bool {
+        self.stages.is_empty() || self.stages.contains(&stage)
+    }
+
+    fn counts_tick(&self, stage: &StageInfo, tick: u32) -> bool {
+        self.phases.is_empty()
+            || stage
+                .phase_at(tick)
+                .is_some_and(|phase| self.phases.contains(&phase))
+    }
+
+    fn counts_attack(&self, attacked: &PlayerAttacked, registry: &npc::Registry) -> bool {
+        if !self.attacks.is_empty()
+            && !self
+                .attacks
+                .iter()
+                .any(|filter| filter.matches(attacked.attack))
+        {
+            return false;
+        }
+
+        if self.target_roles.is_empty() && self.target_ids.is_empty() {
+            return true;
+        }
+        attacked.target.as_deref().is_some_and(|target| {
+            self.target_ids.contains(&target.spawn_npc_id)
+                || self
+                    .target_roles
+                    .iter()
+                    .any(|&role| registry.has_role(target.spawn_npc_id, role))
+        })
+    }
+
+    /// Counts the occurrences of the metric for a player within a single stage.
+    fn evaluate(&self, stage: &StageInfo, username: &str, registry: &npc::Registry) -> u32 {
+        let Some(states) = stage.player_state(username) else {
+            return 0;
+        };
+
+        states
+            .iter()
+            .filter(|state| self.counts_tick(stage, state.tick))
+            .filter(|state| match self.count {
+                Count::Attacks => match &state.attack_state {
+                    AttackState::Attacked(attacked) => self.counts_attack(attacked, registry),
+                    _ => false,
+                },
+                Count::Deaths => state.death_state == DeathState::JustDied,
+                Count::IdleTicks => {
+                    state.death_state == DeathState::Alive
+                        && state.attack_state == AttackState::Idle
+                }
+            })
+            .count() as u32
+    }
+}
+
+impl MetricAnalyzer {
+    pub fn new(config: &Config) -> Result<Self> {
+        let mut names = HashSet::new();
+        for metric in &config.metrics {
+            if !names.insert(metric.name.as_str()) {
+                return Err(Error::Config(format!(
+                    r#"Metric "{}" is defined more than once"#,
+                    metric.name
+                )));
+            }
+        }
+
+        let metrics = config
+            .metrics
+            .iter()
+            .map(Metric::from_config)
+            .collect::<Result<_>>()?;
+        Ok(Self { metrics })
+    }
+}
+
+/// A player's value for each configured metric, keyed by metric name.
+#[derive(Debug, Default, Serialize, JsonSchema)]
+pub struct Metrics(BTreeMap<String, u32>);
+
+impl Metrics {
+    /// Returns the player's value for a metric, if it is configured.
+    pub fn get(&self, name: &str) -> Option<u32> {
+        self.0.get(name).copied()
+    }
+}
+
+impl PlayerAnalyzer for MetricAnalyzer {
+    type Output = Metrics;
+
+    fn name(&self) -> &str {
+        "MetricAnalyzer"
+    }
+
+    fn analyze_player(&self, context: &Context, username: &str) -> Result<Self::Output> {
+        let stages = context.challenge().stage_infos();
+        let registry = context.npc_registry();
+
+        let values = self
+            .metrics
+            .iter()
+            .map(|metric| {
+                let value = stages
+                    .iter()
+                    .filter(|stage| metric.counts_stage(stage.stage()))
+                    .map(|stage| metric.evaluate(stage, username, registry))
+                    .sum();
+                (metric.name.clone(), value)
+            })
+            .collect();
+
+        Ok(Metrics(values))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenge::Challenge;
+    use crate::data_repository::{DataRepository, MemoryBackend};
+    use crate::synthetic::{Generator, SyntheticConfig};
+
+    fn parse(config: &str) -> Result<MetricAnalyzer> {
+        let config: Config = toml::from_str(config).unwrap();
+        MetricAnalyzer::new(&config)
+    }
+
+    #[test]
+    fn metrics_are_validated() {
+        let analyzer = parse(
+            r#"
+            [[metrics]]
+            name = "crab_barrages"
+            count = "attacks"
+            stages = ["TOB_MAIDEN"]
+            attacks = ["barrage"]
+            target_roles = ["maiden_matomenos"]
+
+            [[metrics]]
+            name = "deaths"
+            count = "deaths"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(analyzer.metrics.len(), 2);
+        assert!(analyzer.metrics[0].counts_stage(blert::Stage::TobMaiden));
+        assert!(!analyzer.metrics[0].counts_stage(blert::Stage::TobBloat));
+        assert!(analyzer.metrics[1].counts_stage(blert::Stage::TobBloat));
+
+        let unknown_attack = parse(
+            r#"
+            [[metrics]]
+            name = "bad"
+            count = "attacks"
+            attacks = ["NOT_AN_ATTACK"]
+            "#,
+        );
+        assert!(matches!(unknown_attack, Err(Error::Config(_))));
+
+        let misplaced_filter = parse(
+            r#"
+            [[metrics]]
+            name = "bad"
+            count = "deaths"
+            attacks = ["barrage"]
+            "#,
+        );
+        assert!(matches!(misplaced_filter, Err(Error::Config(_))));
+
+        let duplicate = parse(
+            r#"
+            [[metrics]]
+            name = "deaths"
+            count = "deaths"
+
+            [[metrics]]
+            name = "deaths"
+            count = "deaths"
+            "#,
+        );
+        assert!(matches!(duplicate, Err(Error::Config(_))));
+    }
+
+    #[tokio::test]
+    async fn dead_players_are_not_idle() {
+        const DEATH_TICK: u32 = 10;
+
+        let mut synthetic = Generator::new(SyntheticConfig {
+            scale: 2,
+            stages: 1,
+            ticks_per_stage: 50,
+            npcs_per_tick: 0,
+            seed: 3,
+        })
+        .generate();
+
+        // The first player dies partway through the stage and sends no events afterwards.
+        let events = &mut synthetic.stages[0].events;
+        events.retain(|event| {
+            event.tick <= DEATH_TICK || event.player.as_ref().is_some_and(|p| p.party_index != 0)
+        });
+        let mut death = blert::Event {
+            tick: DEATH_TICK,
+            player: Some(blert::event::Player::default()),
+            ..Default::default()
+        };
+        death.set_type(blert::event::Type::PlayerDeath);
+        events.push(death);
+        events.sort_by_key(|event| event.tick);
+
+        let backend = MemoryBackend::new();
+        synthetic.store(&backend);
+        let repository = DataRepository::new(Box::new(backend));
+        let challenge = Challenge::load_from_repository(&repository, synthetic.uuid)
+            .await
+            .unwrap();
+        let stage = &challenge.stage_infos()[0];
+
+        let analyzer = parse(
+            r#"
+            [[metrics]]
+            name = "deaths"
+            count = "deaths"
+
+            [[metrics]]
+            name = "idle"
+            count = "idle_ticks"
+            "#,
+        )
+        .unwrap();
+        let registry = npc::Registry::default();
+        let evaluate =
+            |index: usize| analyzer.metrics[index].evaluate(stage, "synthetic 0", &registry);
+
+        assert_eq!(evaluate(0), 1);
+        assert!(evaluate(1) <= DEATH_TICK + 1);
+        assert!(stage.total_ticks() > DEATH_TICK + 10);
+
+        let states = stage.player_state("synthetic 0").unwrap();
+        let last = states.into_iter().last().unwrap();
+        assert_eq!(last.death_state, DeathState::Dead);
+    }
+}
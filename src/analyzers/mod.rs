@@ -1,10 +1,32 @@
-use crate::analysis::{wrap_analyzer, RunnableAnalyzer};
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use schemars::{schema::RootSchema, schema_for};
+use serde::de::DeserializeOwned;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use utoipa::ToSchema;
+
+use crate::analysis::{wrap_analyzer, wrap_player_analyzer, PlayerOutputs, RunnableAnalyzer};
+use crate::blert;
 use crate::error::{Error, Result};
 
+pub mod damage_taken_analyzer;
+pub mod data_quality_analyzer;
 pub mod gear_analyzer;
+pub mod gear_switch_analyzer;
+pub mod metric_analyzer;
+pub mod personal_best_analyzer;
+#[cfg(feature = "plugins")]
+pub mod plugin;
+pub mod role_classifier;
+pub mod score_analyzer;
 pub mod test_analyzer;
 pub mod test_offset_analyzer;
+pub mod tob_bloat_analyzer;
 pub mod tob_role_analyzer;
+pub mod tob_role_features_analyzer;
+pub mod tob_splits_analyzer;
 
 /// Initializes a new instance of the analyzer with the given implementation name based on
 /// analyzer-specific configuration options.
@@ -14,34 +36,417 @@ pub fn init_analyzer(
     config: Option<toml::Value>,
 ) -> Result<Box<dyn RunnableAnalyzer>> {
     match implementation {
-        "GearAnalyzer" => Ok(wrap_analyzer(
+        "DamageTakenAnalyzer" => Ok(wrap_player_analyzer(
+            name.into(),
+            damage_taken_analyzer::DamageTakenAnalyzer::new(),
+        )),
+        "DataQualityAnalyzer" => Ok(wrap_analyzer(
+            name.into(),
+            data_quality_analyzer::DataQualityAnalyzer::new(),
+        )),
+        "GearAnalyzer" => Ok(wrap_player_analyzer(
             name.into(),
             gear_analyzer::GearAnalyzer::new(),
         )),
+        "GearSwitchAnalyzer" => {
+            let config = match config {
+                Some(config) => parse_config(name, config)?,
+                None => gear_switch_analyzer::Config::default(),
+            };
+            Ok(wrap_player_analyzer(
+                name.into(),
+                gear_switch_analyzer::GearSwitchAnalyzer::new(&config)?,
+            ))
+        }
+        "MetricAnalyzer" => {
+            let config = config.ok_or(Error::Config(
+                "MetricAnalyzer missing config options".into(),
+            ))?;
+            let config = parse_config(name, config)?;
+            Ok(wrap_player_analyzer(
+                name.into(),
+                metric_analyzer::MetricAnalyzer::new(&config)?,
+            ))
+        }
+        "PersonalBestAnalyzer" => {
+            let config = match config {
+                Some(config) => parse_config(name, config)?,
+                None => personal_best_analyzer::Config::default(),
+            };
+            Ok(wrap_analyzer(
+                name.into(),
+                personal_best_analyzer::PersonalBestAnalyzer::new(&config),
+            ))
+        }
+        "ScoreAnalyzer" => {
+            let config = match config {
+                Some(config) => parse_config(name, config)?,
+                None => score_analyzer::Config::default(),
+            };
+            Ok(wrap_player_analyzer(
+                name.into(),
+                score_analyzer::ScoreAnalyzer::new(&config)?,
+            ))
+        }
         "TestAnalyzer" => {
-            let config = config
-                .ok_or(Error::Config("TestAnalyzer missing config options".into()))?
-                .try_into()?;
+            let config =
+                config.ok_or(Error::Config("TestAnalyzer missing config options".into()))?;
+            let config = parse_config(name, config)?;
             Ok(wrap_analyzer(
                 name.into(),
                 test_analyzer::TestAnalyzer::new(&config),
             ))
         }
         "TestOffsetAnalyzer" => {
-            let config = config
-                .ok_or(Error::Config(
-                    "TestOffsetAnalyzer missing config options".into(),
-                ))?
-                .try_into()?;
+            let config = config.ok_or(Error::Config(
+                "TestOffsetAnalyzer missing config options".into(),
+            ))?;
+            let config = parse_config(name, config)?;
             Ok(wrap_analyzer(
                 name.into(),
                 test_offset_analyzer::TestOffsetAnalyzer::new(&config),
             ))
         }
-        "TobRoleAnalyzer" => Ok(wrap_analyzer(
+        "TobBloatAnalyzer" => Ok(wrap_analyzer(
             name.into(),
-            tob_role_analyzer::TobRoleAnalyzer::new(),
+            tob_bloat_analyzer::TobBloatAnalyzer::new(),
         )),
-        _ => Err(Error::Config(format!("Unknown analyzer: {name}"))),
+        "TobRoleAnalyzer" => {
+            let config = match config {
+                Some(config) => parse_config(name, config)?,
+                None => tob_role_analyzer::Config::default(),
+            };
+            Ok(wrap_analyzer(
+                name.into(),
+                tob_role_analyzer::TobRoleAnalyzer::new(&config)?,
+            ))
+        }
+        "TobRoleFeaturesAnalyzer" => Ok(wrap_player_analyzer(
+            name.into(),
+            tob_role_features_analyzer::TobRoleFeaturesAnalyzer::new(),
+        )),
+        "TobSplitsAnalyzer" => {
+            let config = config.ok_or(Error::Config(
+                "TobSplitsAnalyzer missing config options".into(),
+            ))?;
+            let config = parse_config(name, config)?;
+            Ok(wrap_analyzer(
+                name.into(),
+                tob_splits_analyzer::TobSplitsAnalyzer::new(&config)?,
+            ))
+        }
+        _ => {
+            #[cfg(feature = "plugins")]
+            if let Some(plugin) = plugin::get(implementation) {
+                return Ok(wrap_analyzer(
+                    name.into(),
+                    plugin::PluginAnalyzer::new(plugin, name, config),
+                ));
+            }
+
+            Err(Error::Config(format!(
+                r#"Analyzer "{name}" has unknown implementation "{implementation}""#
+            )))
+        }
+    }
+}
+
+/// Files read by analyzers, keyed by the type they are loaded as and their path.
+type SharedFiles = Mutex<HashMap<(TypeId, String), Arc<dyn Any + Send + Sync>>>;
+
+static SHARED_FILES: OnceLock<SharedFiles> = OnceLock::new();
+
+/// Returns a file used by analyzers, loading it with `load` the first time it is requested.
+///
+/// Every analyzer is initialized when its program is loaded, so files are read then and shared by
+/// the instances created for each run instead of being read again on the async runtime.
+pub(crate) fn load_shared<T: Any + Send + Sync>(
+    path: &str,
+    load: impl FnOnce(&str) -> Result<T>,
+) -> Result<Arc<T>> {
+    let files = SHARED_FILES.get_or_init(SharedFiles::default);
+    let key = (TypeId::of::<T>(), path.to_owned());
+    if let Some(file) = files.lock().unwrap().get(&key) {
+        return Ok(Arc::clone(file)
+            .downcast()
+            .expect("shared files are keyed by their type"));
+    }
+
+    let file = Arc::new(load(path)?);
+    files.lock().unwrap().insert(key, file.clone());
+    Ok(file)
+}
+
+/// Deserializes an analyzer's configuration options, reporting the path to the offending field
+/// if they are invalid.
+fn parse_config<T: DeserializeOwned>(name: &str, config: toml::Value) -> Result<T> {
+    serde_path_to_error::deserialize(config).map_err(|e| {
+        Error::Config(format!(
+            r#"Invalid config for analyzer "{name}" at `{}`: {}"#,
+            e.path(),
+            e.inner().message()
+        ))
+    })
+}
+
+/// Returns the JSON Schema of the output of every analyzer implementation, keyed by
+/// implementation name.
+pub fn output_schemas() -> Vec<(&'static str, RootSchema)> {
+    vec![
+        (
+            "DamageTakenAnalyzer",
+            schema_for!(PlayerOutputs<damage_taken_analyzer::DamageTaken>),
+        ),
+        (
+            "DataQualityAnalyzer",
+            schema_for!(data_quality_analyzer::DataQuality),
+        ),
+        ("GearAnalyzer", schema_for!(gear_analyzer::PlayerGear)),
+        (
+            "GearSwitchAnalyzer",
+            schema_for!(PlayerOutputs<gear_switch_analyzer::GearSwitches>),
+        ),
+        (
+            "MetricAnalyzer",
+            schema_for!(PlayerOutputs<metric_analyzer::Metrics>),
+        ),
+        (
+            "PersonalBestAnalyzer",
+            schema_for!(personal_best_analyzer::PersonalBests),
+        ),
+        (
+            "ScoreAnalyzer",
+            schema_for!(PlayerOutputs<score_analyzer::PlayerScore>),
+        ),
+        ("TestAnalyzer", schema_for!(u32)),
+        ("TestOffsetAnalyzer", schema_for!(u32)),
+        (
+            "TobBloatAnalyzer",
+            schema_for!(tob_bloat_analyzer::BloatDowns),
+        ),
+        (
+            "TobRoleAnalyzer",
+            schema_for!(HashMap<String, tob_role_analyzer::PlayerRoles>),
+        ),
+        (
+            "TobRoleFeaturesAnalyzer",
+            schema_for!(PlayerOutputs<tob_role_features_analyzer::RoleFeatures>),
+        ),
+        (
+            "TobSplitsAnalyzer",
+            schema_for!(tob_splits_analyzer::Splits),
+        ),
+    ]
+}
+
+/// Public description of an analyzer implementation which programs can use.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ImplementationInfo {
+    pub implementation: &'static str,
+    pub description: &'static str,
+    /// Challenge types the analyzer supports, by lowercase proto name. Empty if it supports any.
+    pub challenge_types: Vec<&'static str>,
+    /// Implementations which must run before the analyzer as its dependencies.
+    pub required_dependencies: Vec<&'static str>,
+    /// Implementations whose outputs the analyzer uses if they are among its dependencies.
+    pub optional_dependencies: Vec<&'static str>,
+    /// Whether the analyzer runs separately for each player in the analysis scope.
+    pub per_player: bool,
+    /// Whether the analyzer must be given a config block.
+    pub config_required: bool,
+    /// JSON Schema of the analyzer's config block, if it accepts one.
+    #[schema(value_type = Option<Object>)]
+    pub config_schema: Option<RootSchema>,
+}
+
+impl ImplementationInfo {
+    fn new(implementation: &'static str, description: &'static str) -> Self {
+        Self {
+            implementation,
+            description,
+            challenge_types: Vec::new(),
+            required_dependencies: Vec::new(),
+            optional_dependencies: Vec::new(),
+            per_player: false,
+            config_required: false,
+            config_schema: None,
+        }
+    }
+
+    fn per_player(mut self) -> Self {
+        self.per_player = true;
+        self
+    }
+
+    fn tob_only(mut self) -> Self {
+        self.challenge_types = vec!["tob"];
+        self
+    }
+
+    fn requires(mut self, implementation: &'static str) -> Self {
+        self.required_dependencies.push(implementation);
+        self
+    }
+
+    fn optional(mut self, implementation: &'static str) -> Self {
+        self.optional_dependencies.push(implementation);
+        self
+    }
+
+    fn config(mut self, schema: RootSchema, required: bool) -> Self {
+        self.config_schema = Some(schema);
+        self.config_required = required;
+        self
+    }
+}
+
+/// Returns a description of every analyzer implementation, ordered by implementation name, followed
+/// by any loaded plugins.
+pub fn catalog() -> Vec<ImplementationInfo> {
+    #[allow(unused_mut)]
+    let mut catalog = vec![
+        ImplementationInfo::new(
+            "DamageTakenAnalyzer",
+            "Attributes the damage taken by each player to the NPC attacks which dealt it.",
+        )
+        .per_player(),
+        ImplementationInfo::new(
+            "DataQualityAnalyzer",
+            "Scores how completely each stage of the challenge was recorded.",
+        ),
+        ImplementationInfo::new(
+            "GearAnalyzer",
+            "Determines the gear each player has in each stage of the challenge.",
+        )
+        .per_player(),
+        ImplementationInfo::new(
+            "GearSwitchAnalyzer",
+            "Measures each player's equipment switches and flags expected switches which never \
+             happened.",
+        )
+        .per_player()
+        .config(schema_for!(gear_switch_analyzer::Config), false),
+        ImplementationInfo::new(
+            "MetricAnalyzer",
+            "Counts player events matching declarative filters, such as attacks on specific NPCs.",
+        )
+        .per_player()
+        .config(schema_for!(metric_analyzer::Config), true),
+        ImplementationInfo::new(
+            "PersonalBestAnalyzer",
+            "Compares room and overall times against the personal bests of the party and each \
+             player, recording new bests.",
+        )
+        .config(schema_for!(personal_best_analyzer::Config), false),
+        ImplementationInfo::new(
+            "ScoreAnalyzer",
+            "Scores each player from 0 to 100 overall and per room from the findings and metrics \
+             of its dependencies.",
+        )
+        .per_player()
+        .optional("MetricAnalyzer")
+        .config(schema_for!(score_analyzer::Config), false),
+        ImplementationInfo::new("TestAnalyzer", "Returns a configured value.")
+            .config(schema_for!(test_analyzer::Config), true),
+        ImplementationInfo::new(
+            "TestOffsetAnalyzer",
+            "Offsets the output of a TestAnalyzer by a configured amount.",
+        )
+        .optional("TestAnalyzer")
+        .config(schema_for!(test_offset_analyzer::Config), true),
+        ImplementationInfo::new(
+            "TobBloatAnalyzer",
+            "Reports the attack ticks the party lost during each of Bloat's downs.",
+        )
+        .tob_only(),
+        ImplementationInfo::new(
+            "TobRoleAnalyzer",
+            "Determines the role of every player within a Theatre of Blood raid.",
+        )
+        .tob_only()
+        .requires("GearAnalyzer")
+        .config(schema_for!(tob_role_analyzer::Config), false),
+        ImplementationInfo::new(
+            "TobRoleFeaturesAnalyzer",
+            "Extracts the signals used to determine each player's role into a feature vector.",
+        )
+        .tob_only()
+        .per_player()
+        .requires("GearAnalyzer"),
+        ImplementationInfo::new(
+            "TobSplitsAnalyzer",
+            "Compares the time taken by each room against benchmarks for the raid's scale.",
+        )
+        .tob_only()
+        .config(schema_for!(tob_splits_analyzer::Config), true),
+    ];
+
+    #[cfg(feature = "plugins")]
+    catalog.extend(plugin::implementations().into_iter().map(|implementation| {
+        ImplementationInfo::new(implementation, "Analyzer loaded from a WebAssembly plugin.")
+    }));
+
+    catalog
+}
+
+/// Serializes a map keyed by stage using the stages' canonical names as keys, for use with
+/// `#[serde(serialize_with)]` in analyzer outputs.
+pub(crate) fn serialize_by_stage<V, S>(
+    map: &HashMap<blert::Stage, V>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error>
+where
+    V: Serialize,
+    S: Serializer,
+{
+    let mut state = serializer.serialize_map(Some(map.len()))?;
+    for (stage, value) in map {
+        state.serialize_entry(stage.as_str_name(), value)?;
+    }
+    state.end()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catalog_covers_every_implementation() {
+        let cataloged = catalog()
+            .iter()
+            .map(|info| info.implementation)
+            .collect::<Vec<_>>();
+        let with_schemas = output_schemas()
+            .into_iter()
+            .map(|(implementation, _)| implementation)
+            .collect::<Vec<_>>();
+        assert_eq!(cataloged, with_schemas);
+    }
+
+    #[test]
+    fn config_errors_include_field_path() {
+        let config = toml::from_str(
+            r#"expectations = [{ stage = "TOB_VERZIK", style = "magic" }, { stage = "TOB_MAIDEN", style = "mage" }]"#,
+        )
+        .unwrap();
+        let Err(Error::Config(message)) =
+            init_analyzer("Switches", "GearSwitchAnalyzer", Some(config))
+        else {
+            panic!("Expected invalid config to be rejected");
+        };
+        assert!(message.contains(r#""Switches" at `expectations[1].style`"#));
+    }
+
+    #[test]
+    fn shared_files_are_loaded_once() {
+        let path = "shared_files_are_loaded_once.json";
+        let first = load_shared(path, |_| Ok(1u32)).unwrap();
+        let second = load_shared(path, |_| -> Result<u32> { panic!("loaded twice") }).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+
+        // Failed loads are not cached.
+        assert!(load_shared(path, |_| -> Result<u64> { Err(Error::InvalidArgument) }).is_err());
+        assert_eq!(*load_shared(path, |_| Ok(2u64)).unwrap(), 2);
     }
 }
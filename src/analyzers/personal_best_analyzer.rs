@@ -0,0 +1,278 @@
+use std::collections::{BTreeMap, HashMap};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::{Analyzer, Context};
+use crate::blert;
+use crate::challenge::{Challenge, Status};
+use crate::error::Result;
+use crate::findings::{Finding, Severity};
+use crate::history::PersonalBest;
+use uuid::Uuid;
+
+/// The `PersonalBestAnalyzer` times each room of a challenge and compares the times against the
+/// personal bests of the party as a team and of each of its players, recording any new bests.
+///
+/// Bests are kept separately for each challenge type, mode, and scale. A room only counts if the
+/// party cleared it, and the overall time only counts if they completed the challenge. Times
+/// within a configured margin of a best are reported as near misses.
+///
+/// Bests are only compared when the engine stores results and the run is not ephemeral;
+/// otherwise, only the times themselves are reported. The analyzer does not record new bests
+/// itself: they are recorded from its output once the run has completed and been saved.
+pub struct PersonalBestAnalyzer {
+    near_miss_ticks: u32,
+}
+
+/// Configuration options for the `PersonalBestAnalyzer`.
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Maximum number of ticks slower than a best for a time to be reported as a near miss.
+    /// Defaults to 5.
+    near_miss_ticks: Option<u32>,
+}
+
+/// Name of the split covering the whole challenge.
+const OVERALL_SPLIT: &str = "OVERALL";
+
+impl PersonalBestAnalyzer {
+    const DEFAULT_NEAR_MISS_TICKS: u32 = 5;
+
+    pub fn new(config: &Config) -> Self {
+        Self {
+            near_miss_ticks: config
+                .near_miss_ticks
+                .unwrap_or(Self::DEFAULT_NEAR_MISS_TICKS),
+        }
+    }
+
+    /// Returns the holder under which a party's team bests are kept.
+    fn team_holder(party: &[String]) -> String {
+        let mut party = party
+            .iter()
+            .map(|username| username.to_lowercase())
+            .collect::<Vec<_>>();
+        party.sort_unstable();
+        format!("team:{}", party.join(","))
+    }
+
+    /// Returns the holder under which a player's bests are kept.
+    fn player_holder(username: &str) -> String {
+        format!("player:{}", username.to_lowercase())
+    }
+
+    /// Returns the category under which the challenge's bests are kept.
+    fn category(challenge: &Challenge) -> String {
+        format!(
+            "{}:{}:{}",
+            challenge.r#type().as_str_name(),
+            challenge.mode().as_str_name(),
+            challenge.scale()
+        )
+    }
+
+    /// Returns the time of each split the party cleared, keyed by split name.
+    fn split_times(challenge: &Challenge) -> BTreeMap<String, u32> {
+        let stages = challenge.stage_infos();
+        let completed = challenge.status() == Status::Completed;
+
+        // Unless the challenge was completed, its final stage ended in a wipe or reset.
+        let cleared = if completed {
+            stages.len()
+        } else {
+            stages.len().saturating_sub(1)
+        };
+
+        let mut times = stages[..cleared]
+            .iter()
+            .map(|stage| (stage.stage().as_str_name().to_owned(), stage.total_ticks()))
+            .collect::<BTreeMap<_, _>>();
+        if completed {
+            times.insert(OVERALL_SPLIT.to_owned(), challenge.total_ticks());
+        }
+        times
+    }
+
+    /// Compares a holder's split times against their existing bests. Without known bests, times
+    /// are reported on their own.
+    fn compare(
+        times: &BTreeMap<String, u32>,
+        holder: &str,
+        bests: Option<&HashMap<(String, String), PersonalBest>>,
+        current: Uuid,
+    ) -> BTreeMap<String, SplitResult> {
+        times
+            .iter()
+            .map(|(split, &ticks)| {
+                let Some(bests) = bests else {
+                    let result = SplitResult {
+                        ticks,
+                        previous_best: None,
+                        new_best: false,
+                    };
+                    return (split.clone(), result);
+                };
+
+                let result = match bests.get(&(holder.to_owned(), split.clone())) {
+                    // A best set by this challenge was recorded by a previous analysis of it.
+                    Some(best) if best.challenge_uuid == current => SplitResult {
+                        ticks,
+                        previous_best: None,
+                        new_best: true,
+                    },
+                    Some(best) => SplitResult {
+                        ticks,
+                        previous_best: Some(best.ticks),
+                        new_best: ticks < best.ticks,
+                    },
+                    None => SplitResult {
+                        ticks,
+                        previous_best: None,
+                        new_best: true,
+                    },
+                };
+                (split.clone(), result)
+            })
+            .collect()
+    }
+
+    fn emit_findings(
+        &self,
+        context: &Context,
+        player: Option<&str>,
+        results: &BTreeMap<String, SplitResult>,
+    ) {
+        let (best_category, near_category) = match player {
+            Some(_) => ("splits.personal_best", "splits.near_personal_best"),
+            None => ("splits.team_best", "splits.near_team_best"),
+        };
+
+        for (split, result) in results {
+            let (category, difference) = match result.previous_best {
+                Some(best) if result.new_best => (best_category, best - result.ticks),
+                Some(best) if result.ticks - best <= self.near_miss_ticks => {
+                    (near_category, result.ticks - best)
+                }
+                _ => continue,
+            };
+
+            let finding = Finding::new(Severity::Info, category).with_data(serde_json::json!({
+                "split": split,
+                "ticks": result.ticks,
+                "previousBest": result.previous_best,
+                "difference": difference,
+            }));
+            let finding = match blert::Stage::from_str_name(split) {
+                Some(stage) => finding.with_stage(stage),
+                None => finding,
+            };
+            context.emit_finding(match player {
+                Some(username) => finding.with_player(username),
+                None => finding,
+            });
+        }
+    }
+}
+
+/// A split time compared against its holder's best.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SplitResult {
+    pub ticks: u32,
+    /// The holder's best time before this challenge, if they had one.
+    pub previous_best: Option<u32>,
+    /// Whether the time is the holder's best.
+    pub new_best: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct PersonalBests {
+    /// Category of challenges in which the bests are kept.
+    pub category: String,
+    /// The party's times, keyed by split.
+    pub team: BTreeMap<String, SplitResult>,
+    /// Each player's times, keyed by username and split.
+    pub players: BTreeMap<String, BTreeMap<String, SplitResult>>,
+}
+
+impl PersonalBests {
+    /// Returns the new bests set by a party, as `(holder, split, ticks)`.
+    pub fn new_bests(&self, party: &[String]) -> Vec<(String, String, u32)> {
+        let team = (PersonalBestAnalyzer::team_holder(party), &self.team);
+        let players = self
+            .players
+            .iter()
+            .map(|(username, results)| (PersonalBestAnalyzer::player_holder(username), results));
+
+        std::iter::once(team)
+            .chain(players)
+            .flat_map(|(holder, results)| {
+                results
+                    .iter()
+                    .filter(|(_, result)| result.new_best)
+                    .map(move |(split, result)| (holder.clone(), split.clone(), result.ticks))
+            })
+            .collect()
+    }
+}
+
+impl Analyzer for PersonalBestAnalyzer {
+    type Output = PersonalBests;
+
+    fn name(&self) -> &str {
+        "PersonalBestAnalyzer"
+    }
+
+    fn player_fields(&self) -> &'static [&'static str] {
+        &["players"]
+    }
+
+    fn analyze(&self, context: &Context) -> Result<Self::Output> {
+        // Bests are only comparable between runs which cover the whole challenge.
+        context.require_all_stages()?;
+
+        let challenge = context.challenge();
+        let category = Self::category(challenge);
+        let times = Self::split_times(challenge);
+
+        let team_holder = Self::team_holder(challenge.party());
+
+        let players = challenge
+            .party()
+            .iter()
+            .filter(|username| context.is_player_in_scope(username))
+            .map(|username| (username.clone(), Self::player_holder(username)))
+            .collect::<Vec<_>>();
+
+        let holders = std::iter::once(team_holder.clone())
+            .chain(players.iter().map(|(_, holder)| holder.clone()))
+            .collect::<Vec<_>>();
+        let bests = context.history().and_then(|history| {
+            history
+                .personal_bests(&holders, &category)
+                .map_err(|e| tracing::warn!("Failed to load personal bests: {e}"))
+                .ok()
+        });
+
+        let current = challenge.uuid();
+        let team = Self::compare(&times, &team_holder, bests.as_ref(), current);
+        self.emit_findings(context, None, &team);
+
+        let players = players
+            .into_iter()
+            .map(|(username, holder)| {
+                let results = Self::compare(&times, &holder, bests.as_ref(), current);
+                self.emit_findings(context, Some(&username), &results);
+                (username, results)
+            })
+            .collect();
+
+        Ok(PersonalBests {
+            category,
+            team,
+            players,
+        })
+    }
+}
@@ -0,0 +1,475 @@
+//! Analyzers loaded at runtime from WebAssembly modules, allowing experimental analyzers to be
+//! deployed by dropping them into a plugins directory instead of recompiling the service.
+//!
+//! Each `<Implementation>.wasm` file in the directory provides the analyzer implementation named
+//! by its file stem. Plugins run in a fresh instance for every analysis, with no imports, a
+//! bounded amount of fuel and bounded memory, so they cannot affect the host or each other. A
+//! running plugin is also interrupted once its analyzer is killed or exceeds its time limit, as
+//! it never reaches the sandbox's checkpoints.
+//!
+//! # ABI (version 1)
+//!
+//! A plugin module exports its linear memory as `memory`, along with the functions:
+//!
+//! - `blert_abi_version() -> i32`, which must return [`ABI_VERSION`].
+//! - `blert_alloc(len: i32) -> i32`, which allocates `len` bytes and returns their address.
+//! - `blert_analyze(input: i32, input_len: i32, events: i32, events_len: i32) -> i64`, which
+//!   analyzes a challenge and returns the address of its result in the upper 32 bits and the
+//!   result's length in the lower 32.
+//!
+//! The `input` buffer is a JSON object with the analyzer's `name`, `config`, and the analysis
+//! `level`, a `challenge` summary (`uuid`, `type`, `mode`, `status`, `party`), and the JSON
+//! `outputs` of every analyzer which has completed so far. The `events` buffer contains each
+//! recorded stage as a little-endian `u32` length followed by an encoded `ChallengeEvents` proto.
+//!
+//! The result is a JSON object of the form `{"ok": <output>}` or `{"error": "<message>"}`. When
+//! the output is persisted, opted-out players are pseudonymized in its top-level keys, or in its
+//! elements if it is a list, so per-player outputs should be keyed by username.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use prost::Message;
+use serde::Deserialize;
+use serde_json::json;
+use wasmtime::{
+    Config, Engine, Instance, Module, Store, StoreLimits, StoreLimitsBuilder, UpdateDeadline,
+};
+
+use crate::analysis::{Analyzer, Context};
+use crate::blert;
+use crate::error::{Error, Result};
+use crate::sandbox::{Sandbox, Violation};
+
+/// Version of the plugin ABI implemented by the host.
+pub const ABI_VERSION: i32 = 1;
+
+/// Fuel given to each analysis, bounding the number of instructions a plugin can execute.
+const FUEL_PER_RUN: u64 = 10_000_000_000;
+
+/// Interval at which the engine's epoch advances, bounding how long a plugin keeps running after
+/// its analyzer is killed.
+const EPOCH_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Maximum size of a plugin's linear memory when its analyzer has no allocation limit.
+const DEFAULT_MAX_MEMORY_BYTES: u64 = 256 * 1024 * 1024;
+
+static PLUGINS: OnceLock<HashMap<String, Plugin>> = OnceLock::new();
+
+/// A compiled plugin module.
+#[derive(Clone)]
+pub struct Plugin {
+    implementation: &'static str,
+    engine: Engine,
+    module: Module,
+}
+
+/// Compiles every plugin in the directory at `path`, making them available to programs. Plugins
+/// are only loaded once; later calls have no effect. A missing directory is treated as empty.
+pub fn load_from_directory(path: impl AsRef<Path>) -> Result<()> {
+    if PLUGINS.get().is_some() {
+        return Ok(());
+    }
+
+    let engine = engine()?;
+    let mut plugins = HashMap::new();
+    let entries = match std::fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let _ = PLUGINS.set(plugins);
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().map_or(true, |ext| ext != "wasm") {
+            continue;
+        }
+        let Some(implementation) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+
+        let module = Module::from_file(&engine, &path)
+            .map_err(|e| Error::Config(format!("{}: {e}", path.display())))?;
+        for export in [
+            "memory",
+            "blert_abi_version",
+            "blert_alloc",
+            "blert_analyze",
+        ] {
+            if module.get_export(export).is_none() {
+                return Err(Error::Config(format!(
+                    r#"{}: plugin does not export "{export}""#,
+                    path.display()
+                )));
+            }
+        }
+
+        tracing::info!(r#"Loaded analyzer plugin "{implementation}""#);
+        let implementation: &'static str = Box::leak(implementation.to_owned().into_boxed_str());
+        plugins.insert(
+            implementation.to_owned(),
+            Plugin {
+                implementation,
+                engine: engine.clone(),
+                module,
+            },
+        );
+    }
+
+    if !plugins.is_empty() {
+        let engine = engine.clone();
+        thread::Builder::new()
+            .name("plugin-epochs".into())
+            .spawn(move || loop {
+                thread::sleep(EPOCH_INTERVAL);
+                engine.increment_epoch();
+            })?;
+    }
+
+    let _ = PLUGINS.set(plugins);
+    Ok(())
+}
+
+/// Creates an engine which meters the fuel of the plugins it runs and can interrupt them.
+fn engine() -> Result<Engine> {
+    let mut config = Config::new();
+    config.consume_fuel(true);
+    config.epoch_interruption(true);
+    Engine::new(&config).map_err(|e| Error::Config(e.to_string()))
+}
+
+/// Returns the plugin providing an analyzer implementation, if one is loaded.
+pub fn get(implementation: &str) -> Option<&'static Plugin> {
+    PLUGINS.get()?.get(implementation)
+}
+
+/// Returns the implementation names of every loaded plugin, sorted.
+pub fn implementations() -> Vec<&'static str> {
+    let mut implementations = PLUGINS
+        .get()
+        .into_iter()
+        .flat_map(HashMap::values)
+        .map(|plugin| plugin.implementation)
+        .collect::<Vec<_>>();
+    implementations.sort_unstable();
+    implementations
+}
+
+/// An analyzer instance backed by a plugin.
+pub struct PluginAnalyzer {
+    plugin: Plugin,
+    name: String,
+    config: Option<toml::Value>,
+}
+
+impl PluginAnalyzer {
+    pub fn new(plugin: &Plugin, name: &str, config: Option<toml::Value>) -> Self {
+        Self {
+            plugin: plugin.clone(),
+            name: name.to_owned(),
+            config,
+        }
+    }
+
+    fn input(&self, context: &Context) -> Result<Vec<u8>> {
+        let challenge = context.challenge();
+        let input = json!({
+            "name": self.name,
+            "config": self.config,
+            "level": context.level(),
+            "challenge": {
+                "uuid": challenge.uuid(),
+                "type": challenge.r#type().as_str_name(),
+                "mode": challenge.mode().as_str_name(),
+                "status": challenge.status(),
+                "party": challenge.party(),
+            },
+            "outputs": context.completed_outputs(),
+        });
+        serde_json::to_vec(&input).map_err(Error::from)
+    }
+
+    fn events(context: &Context) -> Vec<u8> {
+        let challenge = context.challenge();
+        let mut buffer = Vec::new();
+
+        for info in challenge.stage_infos() {
+            let mut events = blert::ChallengeEvents {
+                party_names: challenge.party().to_vec(),
+                events: info.all_events().cloned().collect(),
+                ..Default::default()
+            };
+            events.set_stage(info.stage());
+
+            let encoded = events.encode_to_vec();
+            buffer.extend_from_slice(&(encoded.len() as u32).to_le_bytes());
+            buffer.extend_from_slice(&encoded);
+        }
+
+        buffer
+    }
+
+    /// Returns the limits on the plugin's memory, which may grow up to the allocation limit of
+    /// the analyzer's sandbox.
+    fn store_limits() -> StoreLimits {
+        let max_memory = Sandbox::current()
+            .and_then(|sandbox| sandbox.limits().max_allocated_bytes)
+            .unwrap_or(DEFAULT_MAX_MEMORY_BYTES);
+        StoreLimitsBuilder::new()
+            .memory_size(usize::try_from(max_memory).unwrap_or(usize::MAX))
+            .instances(1)
+            .build()
+    }
+
+    /// Interrupts the plugin at the next epoch once its sandbox is killed or the analyzer's time
+    /// limit has passed. Otherwise, the plugin keeps running until it runs out of fuel.
+    fn interrupt_when_killed(store: &mut Store<StoreLimits>) {
+        let sandbox = Sandbox::current();
+        let deadline = sandbox
+            .as_ref()
+            .and_then(|sandbox| sandbox.limits().timeout())
+            .map(|timeout| Instant::now() + timeout);
+
+        store.set_epoch_deadline(1);
+        store.epoch_deadline_callback(move |_| {
+            let killed = sandbox.as_ref().is_some_and(|sandbox| sandbox.is_killed())
+                || deadline.is_some_and(|deadline| Instant::now() >= deadline);
+            if killed {
+                return Err(wasmtime::Error::msg(Violation::Killed.to_string()));
+            }
+            Ok(UpdateDeadline::Continue(1))
+        });
+    }
+
+    /// Instantiates the plugin and runs its analysis, returning its raw result.
+    fn call(&self, input: &[u8], events: &[u8]) -> wasmtime::Result<Vec<u8>> {
+        let mut store = Store::new(&self.plugin.engine, Self::store_limits());
+        store.limiter(|limits| limits);
+        store.set_fuel(FUEL_PER_RUN)?;
+        Self::interrupt_when_killed(&mut store);
+        let instance = Instance::new(&mut store, &self.plugin.module, &[])?;
+
+        let version = instance
+            .get_typed_func::<(), i32>(&mut store, "blert_abi_version")?
+            .call(&mut store, ())?;
+        if version != ABI_VERSION {
+            return Err(wasmtime::Error::msg(format!(
+                "plugin implements ABI version {version}, expected {ABI_VERSION}"
+            )));
+        }
+
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| wasmtime::Error::msg("plugin does not export memory"))?;
+        let alloc = instance.get_typed_func::<i32, i32>(&mut store, "blert_alloc")?;
+        let analyze =
+            instance.get_typed_func::<(i32, i32, i32, i32), i64>(&mut store, "blert_analyze")?;
+
+        let write =
+            |store: &mut Store<StoreLimits>, bytes: &[u8]| -> wasmtime::Result<(i32, i32)> {
+                let len = i32::try_from(bytes.len())?;
+                let address = alloc.call(&mut *store, len)?;
+                memory.write(&mut *store, address as u32 as usize, bytes)?;
+                Ok((address, len))
+            };
+        let (input_address, input_len) = write(&mut store, input)?;
+        let (events_address, events_len) = write(&mut store, events)?;
+
+        let packed = analyze.call(
+            &mut store,
+            (input_address, input_len, events_address, events_len),
+        )? as u64;
+        let address = (packed >> 32) as usize;
+        let len = (packed & 0xffff_ffff) as usize;
+
+        // Checked before allocating, as the plugin controls the length.
+        if address.saturating_add(len) > memory.data_size(&store) {
+            return Err(wasmtime::Error::msg(format!(
+                "plugin returned a result of {len} bytes at {address}, outside of its memory"
+            )));
+        }
+        let mut output = vec![0; len];
+        memory.read(&store, address, &mut output)?;
+        Ok(output)
+    }
+
+    /// Parses the raw result of a plugin's analysis into its output.
+    fn parse_result(&self, result: &[u8]) -> Result<serde_json::Value> {
+        match serde_json::from_slice(result) {
+            Ok(PluginResult::Ok(output)) => Ok(output),
+            Ok(PluginResult::Error(message)) => Err(Error::Plugin(format!(
+                "{}: {message}",
+                self.plugin.implementation
+            ))),
+            Err(e) => Err(Error::Plugin(format!(
+                "{}: malformed result: {e}",
+                self.plugin.implementation
+            ))),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum PluginResult {
+    Ok(serde_json::Value),
+    Error(String),
+}
+
+impl Analyzer for PluginAnalyzer {
+    type Output = serde_json::Value;
+
+    fn name(&self) -> &str {
+        self.plugin.implementation
+    }
+
+    /// Plugins receive the party's usernames and cannot declare where they appear in their
+    /// output, so it is treated as keyed by username as a whole.
+    fn player_fields(&self) -> &'static [&'static str] {
+        &[""]
+    }
+
+    fn analyze(&self, context: &Context) -> Result<Self::Output> {
+        let input = self.input(context)?;
+        let events = Self::events(context);
+
+        let result = self
+            .call(&input, &events)
+            .map_err(|e| Error::Plugin(format!("{}: {e}", self.plugin.implementation)))?;
+        self.parse_result(&result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    /// Returns a plugin module which returns `result` from its analysis, unless `analyze`
+    /// replaces the body of `blert_analyze`.
+    fn module(abi_version: i32, result: &str, analyze: Option<&str>) -> String {
+        let body = analyze.map_or_else(|| format!("(i64.const {})", result.len()), str::to_owned);
+        format!(
+            r#"
+            (module
+              (memory (export "memory") 1)
+              (global $next (mut i32) (i32.const 1024))
+              (data (i32.const 0) "{}")
+              (func (export "blert_abi_version") (result i32) (i32.const {abi_version}))
+              (func (export "blert_alloc") (param $len i32) (result i32)
+                (local $address i32)
+                (local.set $address (global.get $next))
+                (global.set $next (i32.add (global.get $next) (local.get $len)))
+                (local.get $address))
+              (func (export "blert_analyze") (param i32 i32 i32 i32) (result i64)
+                {body}))
+            "#,
+            result.replace('"', "\\\""),
+        )
+    }
+
+    fn analyzer(wat: &str) -> PluginAnalyzer {
+        let engine = engine().unwrap();
+        let module = Module::new(&engine, wat::parse_str(wat).unwrap()).unwrap();
+        let plugin = Plugin {
+            implementation: "TestPlugin",
+            engine,
+            module,
+        };
+        PluginAnalyzer::new(&plugin, "TestPlugin", None)
+    }
+
+    fn run(analyzer: &PluginAnalyzer) -> Result<serde_json::Value> {
+        let result = analyzer
+            .call(br#"{"name":"TestPlugin"}"#, &[0; 8])
+            .map_err(|e| Error::Plugin(format!("{e:#}")))?;
+        analyzer.parse_result(&result)
+    }
+
+    #[test]
+    fn plugin_results_are_parsed() {
+        let ok = analyzer(&module(ABI_VERSION, r#"{"ok":{"alice":1}}"#, None));
+        assert_eq!(run(&ok).unwrap(), json!({ "alice": 1 }));
+
+        let error = analyzer(&module(ABI_VERSION, r#"{"error":"no data"}"#, None));
+        assert!(matches!(
+            run(&error),
+            Err(Error::Plugin(message)) if message == "TestPlugin: no data"
+        ));
+
+        let malformed = analyzer(&module(ABI_VERSION, "not json", None));
+        assert!(matches!(
+            run(&malformed),
+            Err(Error::Plugin(message)) if message.contains("malformed result")
+        ));
+    }
+
+    #[test]
+    fn results_outside_of_plugin_memory_are_rejected() {
+        // A result ending one byte past the single page of memory.
+        let packed = (65_530_i64 << 32) | 7;
+        let plugin = analyzer(&module(
+            ABI_VERSION,
+            "",
+            Some(&format!("(i64.const {packed})")),
+        ));
+        assert!(matches!(
+            run(&plugin),
+            Err(Error::Plugin(message)) if message.contains("outside of its memory")
+        ));
+
+        // An address and length beyond any memory.
+        let plugin = analyzer(&module(ABI_VERSION, "", Some("(i64.const -1)")));
+        assert!(matches!(
+            run(&plugin),
+            Err(Error::Plugin(message)) if message.contains("outside of its memory")
+        ));
+    }
+
+    #[test]
+    fn mismatched_abi_versions_are_rejected() {
+        let plugin = analyzer(&module(ABI_VERSION + 1, r#"{"ok":null}"#, None));
+        assert!(matches!(
+            run(&plugin),
+            Err(Error::Plugin(message)) if message.contains("implements ABI version 2")
+        ));
+    }
+
+    #[test]
+    fn killed_plugins_are_interrupted() {
+        let plugin = analyzer(&module(
+            ABI_VERSION,
+            "",
+            Some("(loop $spin (br $spin)) (i64.const 0)"),
+        ));
+        let sandbox = Arc::new(Sandbox::default());
+        sandbox.kill();
+        let _guard = sandbox.enter();
+
+        let done = AtomicBool::new(false);
+        let result = thread::scope(|scope| {
+            scope.spawn(|| {
+                while !done.load(Ordering::Relaxed) {
+                    thread::sleep(Duration::from_millis(5));
+                    plugin.plugin.engine.increment_epoch();
+                }
+            });
+            let result = run(&plugin);
+            done.store(true, Ordering::Relaxed);
+            result
+        });
+
+        assert!(matches!(
+            result,
+            Err(Error::Plugin(message)) if message.contains(&Violation::Killed.to_string())
+        ));
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::challenge::Challenge;
+use crate::error::{Error, Result};
+
+use super::gear_analyzer::PlayerGear;
+use super::tob_role_analyzer::{Role, TobRoleAnalyzer};
+use super::tob_role_features_analyzer::RoleFeatures;
+
+/// A `RoleClassifier` assigns a primary role to every player in a Theatre of Blood raid.
+pub trait RoleClassifier: Send + Sync {
+    /// Returns a short name identifying the classifier.
+    fn name(&self) -> &str;
+
+    /// Assigns a role to every player in the party, or fails if it cannot do so with confidence.
+    fn classify(
+        &self,
+        challenge: &Challenge,
+        player_gear: &PlayerGear,
+    ) -> Result<HashMap<String, Role>>;
+}
+
+/// Weights of a single class in a logistic model.
+#[derive(Debug, Deserialize)]
+struct ClassWeights {
+    bias: f64,
+    weights: Vec<f64>,
+}
+
+/// A multinomial logistic regression model over [`RoleFeatures`], as stored on disk.
+#[derive(Debug, Deserialize)]
+struct LogisticModel {
+    /// Names of the features used by the model, in the order of each class's weights.
+    features: Vec<String>,
+    classes: HashMap<Role, ClassWeights>,
+}
+
+/// A [`RoleClassifier`] backed by a logistic regression model trained on exported role features.
+///
+/// The model scores every player against every role. Roles are then assigned to the party as a
+/// whole by choosing the assignment of the raid's required roles which maximizes the total
+/// log-probability.
+#[derive(Debug)]
+pub struct LogisticRoleClassifier {
+    model: LogisticModel,
+}
+
+impl LogisticRoleClassifier {
+    /// Minimum total probability of an assignment for it to be accepted.
+    const MIN_ASSIGNMENT_PROBABILITY: f64 = 0.05;
+
+    /// Loads a model from a JSON file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let model: LogisticModel = serde_json::from_slice(&fs::read(path)?)?;
+
+        let known_features = RoleFeatures::default()
+            .values()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        if let Some(unknown) = model
+            .features
+            .iter()
+            .find(|f| !known_features.contains(&f.as_str()))
+        {
+            return Err(Error::Config(format!(
+                r#"Role model {} uses unknown feature "{unknown}""#,
+                path.display()
+            )));
+        }
+        if let Some((role, _)) = model
+            .classes
+            .iter()
+            .find(|(_, class)| class.weights.len() != model.features.len())
+        {
+            return Err(Error::Config(format!(
+                "Role model {} has the wrong number of weights for {role:?}",
+                path.display()
+            )));
+        }
+
+        Ok(Self { model })
+    }
+
+    /// Returns the log-probability of each role in the model for a player.
+    fn log_probabilities(&self, features: &RoleFeatures) -> HashMap<Role, f64> {
+        let values = features.values();
+        let inputs = self
+            .model
+            .features
+            .iter()
+            .map(|name| {
+                values
+                    .iter()
+                    .find_map(|(n, v)| (n == name).then_some(*v))
+                    .unwrap_or_default()
+            })
+            .collect::<Vec<_>>();
+
+        let scores = self
+            .model
+            .classes
+            .iter()
+            .map(|(&role, class)| {
+                let score = class.bias
+                    + class
+                        .weights
+                        .iter()
+                        .zip(&inputs)
+                        .map(|(w, x)| w * x)
+                        .sum::<f64>();
+                (role, score)
+            })
+            .collect::<Vec<_>>();
+
+        // Log-softmax, shifted by the maximum score for numerical stability.
+        let max = scores
+            .iter()
+            .map(|(_, s)| *s)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let log_sum = scores
+            .iter()
+            .map(|(_, s)| (s - max).exp())
+            .sum::<f64>()
+            .ln();
+        scores
+            .into_iter()
+            .map(|(role, s)| (role, s - max - log_sum))
+            .collect()
+    }
+}
+
+impl RoleClassifier for LogisticRoleClassifier {
+    fn name(&self) -> &str {
+        "logistic"
+    }
+
+    fn classify(
+        &self,
+        challenge: &Challenge,
+        player_gear: &PlayerGear,
+    ) -> Result<HashMap<String, Role>> {
+        let roles = TobRoleAnalyzer::roles_for_scale(challenge.scale())?;
+
+        let scores = challenge
+            .party()
+            .iter()
+            .map(|player| {
+                let gear = player_gear.player(player).ok_or(Error::IncompleteData)?;
+                let features = RoleFeatures::extract(challenge, player, gear)?;
+                Ok(self.log_probabilities(&features))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let (log_probability, assignment) =
+            best_assignment(&scores, &roles).ok_or(Error::IncompleteData)?;
+        if log_probability.exp() < Self::MIN_ASSIGNMENT_PROBABILITY {
+            tracing::warn!(
+                "Challenge {}: most likely role assignment has probability {:.3}",
+                challenge.uuid(),
+                log_probability.exp(),
+            );
+            return Err(Error::IncompleteData);
+        }
+
+        Ok(challenge.party().iter().cloned().zip(assignment).collect())
+    }
+}
+
+/// Finds the assignment of `roles` to players which maximizes the total log-probability, where
+/// `scores[i]` holds the log-probability of each role for player `i`. Returns the total
+/// log-probability and the role assigned to each player, in order.
+fn best_assignment(scores: &[HashMap<Role, f64>], roles: &[Role]) -> Option<(f64, Vec<Role>)> {
+    let Some((player_scores, rest)) = scores.split_first() else {
+        return roles.is_empty().then_some((0.0, Vec::new()));
+    };
+
+    let mut best: Option<(f64, Vec<Role>)> = None;
+    for (i, &role) in roles.iter().enumerate() {
+        // Roles may appear multiple times; each distinct role only needs to be tried once.
+        if roles[..i].contains(&role) {
+            continue;
+        }
+        let Some(&score) = player_scores.get(&role) else {
+            continue;
+        };
+
+        let mut remaining = roles.to_vec();
+        remaining.remove(i);
+        if let Some((rest_score, mut assignment)) = best_assignment(rest, &remaining) {
+            let total = score + rest_score;
+            if best.as_ref().map_or(true, |(b, _)| total > *b) {
+                assignment.insert(0, role);
+                best = Some((total, assignment));
+            }
+        }
+    }
+
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn best_assignment_maximizes_total_probability() {
+        let scores = vec![
+            HashMap::from([
+                (Role::Mage, -0.1),
+                (Role::Ranger, -2.0),
+                (Role::Melee, -3.0),
+            ]),
+            HashMap::from([
+                (Role::Mage, -0.2),
+                (Role::Ranger, -3.0),
+                (Role::Melee, -1.0),
+            ]),
+            HashMap::from([
+                (Role::Mage, -4.0),
+                (Role::Ranger, -0.5),
+                (Role::Melee, -0.7),
+            ]),
+        ];
+
+        let (total, assignment) =
+            best_assignment(&scores, &[Role::Mage, Role::Ranger, Role::Melee]).unwrap();
+        assert_eq!(assignment, vec![Role::Mage, Role::Melee, Role::Ranger]);
+        assert!((total + 1.6).abs() < 1e-9);
+
+        assert!(best_assignment(&scores, &[Role::Mage, Role::Ranger]).is_none());
+    }
+}
@@ -0,0 +1,234 @@
+use std::collections::{BTreeMap, HashMap};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::{Context, PlayerAnalyzer};
+use crate::blert;
+use crate::error::{Error, Result};
+use crate::findings::{Finding, Severity};
+
+use super::data_quality_analyzer;
+use super::metric_analyzer::MetricAnalyzer;
+
+/// The `ScoreAnalyzer` grades each player's performance from 0 to 100, overall and in each room
+/// they took part in, as the basis for performance grades shown on the site.
+///
+/// Every player starts at a perfect score, from which points are deducted for each finding about
+/// them emitted by the analyzer's dependencies, weighted by severity or category. Room scores only
+/// count findings within the room, while the overall score counts all of the player's findings
+/// along with deductions for metrics computed by a `MetricAnalyzer` dependency.
+///
+/// Rooms which a `DataQualityAnalyzer` dependency found to be poorly recorded are not scored, and
+/// their findings are not deducted from the overall score, as they cannot be relied upon.
+pub struct ScoreAnalyzer {
+    severity_weights: BTreeMap<Severity, f64>,
+    category_weights: BTreeMap<String, f64>,
+    metric_weights: BTreeMap<String, f64>,
+}
+
+/// Configuration options for the `ScoreAnalyzer`.
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Points deducted for each finding of a severity. Severities which are not listed use their
+    /// default weights.
+    #[serde(default)]
+    severity_weights: BTreeMap<Severity, f64>,
+    /// Points deducted for each finding of a category, overriding the weight of its severity.
+    #[serde(default)]
+    category_weights: BTreeMap<String, f64>,
+    /// Points deducted from the overall score for each unit of a metric.
+    #[serde(default)]
+    metric_weights: BTreeMap<String, f64>,
+}
+
+impl ScoreAnalyzer {
+    const MAX_SCORE: f64 = 100.0;
+
+    pub fn new(config: &Config) -> Result<Self> {
+        let weights = config
+            .severity_weights
+            .values()
+            .chain(config.category_weights.values())
+            .chain(config.metric_weights.values());
+        if let Some(weight) = weights.find(|w| !w.is_finite() || **w < 0.0) {
+            return Err(Error::Config(format!(
+                "Score weights must be non-negative, got {weight}"
+            )));
+        }
+
+        let mut severity_weights = BTreeMap::from([
+            (Severity::Info, 0.0),
+            (Severity::Minor, 2.0),
+            (Severity::Major, 6.0),
+            (Severity::Critical, 15.0),
+        ]);
+        severity_weights.extend(config.severity_weights.iter().map(|(&s, &w)| (s, w)));
+
+        Ok(Self {
+            severity_weights,
+            category_weights: config.category_weights.clone(),
+            metric_weights: config.metric_weights.clone(),
+        })
+    }
+
+    fn deduction(&self, finding: &Finding) -> f64 {
+        self.category_weights
+            .get(&finding.category)
+            .or_else(|| self.severity_weights.get(&finding.severity))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Returns the points deducted for findings, ignoring those in stages which were found to be
+    /// poorly recorded.
+    fn deductions<'a>(
+        &self,
+        findings: impl IntoIterator<Item = &'a Finding>,
+        low_quality_stages: &[String],
+    ) -> f64 {
+        findings
+            .into_iter()
+            .filter(|finding| {
+                !finding
+                    .stage
+                    .as_ref()
+                    .is_some_and(|stage| low_quality_stages.contains(stage))
+            })
+            .map(|finding| self.deduction(finding))
+            .sum()
+    }
+
+    fn score(deductions: f64) -> u8 {
+        (Self::MAX_SCORE - deductions)
+            .clamp(0.0, Self::MAX_SCORE)
+            .round() as u8
+    }
+}
+
+/// A player's scores, from 0 to 100.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PlayerScore {
+    overall: u8,
+    #[serde(serialize_with = "super::serialize_by_stage")]
+    #[schemars(with = "HashMap<String, u8>")]
+    by_stage: HashMap<blert::Stage, u8>,
+}
+
+impl PlayerAnalyzer for ScoreAnalyzer {
+    type Output = PlayerScore;
+
+    fn name(&self) -> &str {
+        "ScoreAnalyzer"
+    }
+
+    fn analyze_player(&self, context: &Context, username: &str) -> Result<Self::Output> {
+        let findings = context.dependency_findings();
+        let player_findings = findings
+            .iter()
+            .filter(|finding| finding.player.as_deref() == Some(username))
+            .collect::<Vec<_>>();
+
+        let low_quality_stages = context
+            .blackboard()
+            .get::<Vec<String>>(data_quality_analyzer::LOW_QUALITY_STAGES)
+            .unwrap_or_default();
+
+        let by_stage = context
+            .challenge()
+            .stage_infos()
+            .iter()
+            .filter(|stage| stage.player_state(username).is_some())
+            .filter(|stage| {
+                !low_quality_stages
+                    .iter()
+                    .any(|name| name == stage.stage().as_str_name())
+            })
+            .map(|stage| {
+                let name = stage.stage().as_str_name();
+                let findings = player_findings
+                    .iter()
+                    .copied()
+                    .filter(|finding| finding.stage.as_deref() == Some(name));
+                (
+                    stage.stage(),
+                    Self::score(self.deductions(findings, &low_quality_stages)),
+                )
+            })
+            .collect();
+
+        let mut deductions = self.deductions(player_findings, &low_quality_stages);
+        let metric_outputs = context.get_player_dependency_output::<MetricAnalyzer>();
+        if let Some(metrics) = metric_outputs
+            .as_ref()
+            .and_then(|outputs| outputs.player(username))
+        {
+            deductions += self
+                .metric_weights
+                .iter()
+                .filter_map(|(name, weight)| Some(f64::from(metrics.get(name)?) * weight))
+                .sum::<f64>();
+        }
+
+        Ok(PlayerScore {
+            overall: Self::score(deductions),
+            by_stage,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn findings_are_weighted() {
+        let config: Config = toml::from_str(
+            r#"
+            severity_weights = { minor = 3 }
+            category_weights = { "gear.missing_style" = 20 }
+            "#,
+        )
+        .unwrap();
+        let analyzer = ScoreAnalyzer::new(&config).unwrap();
+
+        let late = Finding::new(Severity::Minor, "bloat.late_entry");
+        let missing = Finding::new(Severity::Major, "gear.missing_style");
+        let critical = Finding::new(Severity::Critical, "death");
+        assert!((analyzer.deduction(&late) - 3.0).abs() < f64::EPSILON);
+        assert!((analyzer.deduction(&missing) - 20.0).abs() < f64::EPSILON);
+        assert!((analyzer.deduction(&critical) - 15.0).abs() < f64::EPSILON);
+
+        assert_eq!(ScoreAnalyzer::score(12.4), 88);
+        assert_eq!(ScoreAnalyzer::score(250.0), 0);
+
+        let negative: Config = toml::from_str("metric_weights = { deaths = -1 }").unwrap();
+        assert!(matches!(
+            ScoreAnalyzer::new(&negative),
+            Err(Error::Config(_))
+        ));
+    }
+
+    #[test]
+    fn findings_in_low_quality_stages_are_not_deducted() {
+        let analyzer = ScoreAnalyzer::new(&Config::default()).unwrap();
+        let mut maiden = Finding::new(Severity::Major, "maiden.late_freeze");
+        maiden.stage = Some("TOB_MAIDEN".into());
+        let mut bloat = Finding::new(Severity::Minor, "bloat.late_entry");
+        bloat.stage = Some("TOB_BLOAT".into());
+        let death = Finding::new(Severity::Critical, "death");
+        let findings = [maiden, bloat, death];
+
+        let all = analyzer.deductions(&findings, &[]);
+        assert!((all - 23.0).abs() < f64::EPSILON);
+
+        let low_quality = ["TOB_MAIDEN".to_string()];
+        let reliable = analyzer.deductions(&findings, &low_quality);
+        assert!((reliable - 17.0).abs() < f64::EPSILON);
+        assert_eq!(
+            ScoreAnalyzer::score(reliable),
+            ScoreAnalyzer::score(analyzer.deductions(&findings[1..], &[]))
+        );
+    }
+}
@@ -10,7 +10,7 @@ pub struct TestOffsetAnalyzer {
     offset: u32,
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
 pub struct Config {
     offset: u32,
 }
@@ -34,7 +34,7 @@ impl Analyzer for TestOffsetAnalyzer {
         let value = context
             .get_dependency_output::<TestAnalyzer>()
             .map_or_else(|| self.offset, |v| *v + self.offset);
-        log::debug!("TestOffsetAnalyzer output {value}");
+        tracing::debug!("TestOffsetAnalyzer output {value}");
         Ok(value)
     }
 }
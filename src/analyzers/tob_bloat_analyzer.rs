@@ -0,0 +1,392 @@
+use std::collections::BTreeMap;
+
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::analysis::{Analyzer, Context};
+use crate::blert;
+use crate::challenge::{AttackState, DeathState, PlayerAttackExt, PlayerStates};
+use crate::error::{Error, Result};
+use crate::findings::{Finding, Severity, TickRange};
+use crate::npc::Role;
+use crate::tob::bloat::{self, Down};
+
+/// The `TobBloatAnalyzer` evaluates how well the party used each of Bloat's downs.
+///
+/// Bloat only takes meaningful damage while down, so every tick of a down on which a player could
+/// have attacked but did not prolongs the room. For each down and player, the analyzer reports
+/// ticks lost to entering the down late and to leaving it before Bloat stood up, whether they were
+/// still beneath Bloat when it stood up and were stunned by its stomp, along with special attacks
+/// used on Bloat while it was walking instead of saved for a down. Players who were dead or absent
+/// when Bloat went down are left out of the down, and players who died during it are only
+/// accountable for the ticks before their death.
+pub struct TobBloatAnalyzer {}
+
+impl TobBloatAnalyzer {
+    /// Number of ticks after Bloat goes down within which a player's first attack is considered
+    /// on time, accounting for the time taken to step in.
+    const ENTRY_GRACE_TICKS: u32 = 2;
+
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    /// Reports each player's late entry into, early exit from, and stomp at the end of a down as
+    /// findings.
+    fn emit_down_findings(context: &Context, down: &DownReport) {
+        for (username, player) in &down.players {
+            if let Some(range) = player.late_entry {
+                context.emit_finding(
+                    Finding::new(Severity::Minor, "bloat.late_entry")
+                        .with_player(username)
+                        .with_stage(blert::Stage::TobBloat)
+                        .with_ticks(range.start, range.end)
+                        .with_data(
+                            json!({ "down": down.number, "ticks": player.late_entry_ticks }),
+                        ),
+                );
+            }
+            if let Some(range) = player.early_exit {
+                context.emit_finding(
+                    Finding::new(Severity::Minor, "bloat.early_exit")
+                        .with_player(username)
+                        .with_stage(blert::Stage::TobBloat)
+                        .with_ticks(range.start, range.end)
+                        .with_data(
+                            json!({ "down": down.number, "ticks": player.early_exit_ticks }),
+                        ),
+                );
+            }
+            if player.stomped {
+                context.emit_finding(
+                    Finding::new(Severity::Minor, "bloat.stomped")
+                        .with_player(username)
+                        .with_stage(blert::Stage::TobBloat)
+                        .with_ticks(down.end_tick, down.end_tick)
+                        .with_data(json!({ "down": down.number })),
+                );
+            }
+        }
+    }
+
+    /// Evaluates a player's use of a down, or returns `None` if they could not take part in it.
+    /// `bloat` is the tile and size of Bloat while it was down, if its position was recorded.
+    fn analyze_player_down(
+        down: &Down,
+        states: &PlayerStates,
+        is_bloat: impl Fn(&blert::challenge_data::StageNpc) -> bool,
+        bloat: Option<(&blert::Coords, u32)>,
+    ) -> Option<PlayerDown> {
+        states.get_tick(down.start_tick as usize)?;
+        let death_tick = states
+            .iter()
+            .find(|state| state.death_state == DeathState::JustDied)
+            .map(|state| state.tick);
+        let end_tick = down.participation_end(death_tick)?;
+
+        let attacks = states
+            .attacks_on(is_bloat)
+            .filter(|(tick, _)| down.contains(*tick))
+            .collect::<Vec<_>>();
+
+        let first_attack_tick = attacks.first().map(|(tick, _)| *tick);
+        let last_attack_tick = attacks.last().map(|(tick, _)| *tick);
+
+        let late_entry = Self::late_entry(down.start_tick, end_tick, first_attack_tick);
+
+        // Bloat dying ends the down early, so only downs it stood up from can be left early.
+        let early_exit = match last_attack_tick {
+            Some(tick) if down.stood_up => {
+                Self::early_exit(Self::off_cooldown_tick(states, tick), end_tick)
+            }
+            _ => None,
+        };
+
+        // Only players who survived until Bloat stood up can be stomped by it.
+        let stomped = down.stood_up
+            && end_tick == down.end_tick
+            && Self::stomped(
+                bloat,
+                states
+                    .get_tick(down.end_tick as usize)
+                    .map(|state| &state.position),
+            );
+
+        Some(PlayerDown {
+            first_attack_tick,
+            last_attack_tick,
+            attacks: attacks.len(),
+            specs: attacks
+                .iter()
+                .filter(|(_, attack)| attack.attack.is_special())
+                .count(),
+            late_entry_ticks: late_entry.map_or(0, |range| range.end - range.start + 1),
+            early_exit_ticks: early_exit.map_or(0, |range| range.end - range.start + 1),
+            late_entry,
+            early_exit,
+            stomped,
+        })
+    }
+
+    /// Returns the ticks at the start of a down, which the player could take part in until
+    /// `end_tick` (exclusive), lost to entering it late. A player who never attacked during the
+    /// down lost all of it that they were alive for.
+    fn late_entry(
+        start_tick: u32,
+        end_tick: u32,
+        first_attack_tick: Option<u32>,
+    ) -> Option<TickRange> {
+        let (start, end) = match first_attack_tick {
+            Some(tick) => (start_tick + Self::ENTRY_GRACE_TICKS, tick),
+            None => (start_tick, end_tick),
+        };
+        (start < end).then(|| TickRange {
+            start,
+            end: end - 1,
+        })
+    }
+
+    /// Returns the ticks at the end of a down, which the player could take part in until
+    /// `end_tick` (exclusive), on which they were off cooldown after their last attack on
+    /// `resume_tick` but no longer attacking.
+    fn early_exit(resume_tick: u32, end_tick: u32) -> Option<TickRange> {
+        (resume_tick < end_tick).then(|| TickRange {
+            start: resume_tick,
+            end: end_tick - 1,
+        })
+    }
+
+    /// Returns whether a player standing on `position` when Bloat stood up was beneath it.
+    fn stomped(bloat: Option<(&blert::Coords, u32)>, position: Option<&blert::Coords>) -> bool {
+        match (bloat, position) {
+            (Some((tile, size)), Some(position)) => bloat::is_beneath(tile, size, position),
+            _ => false,
+        }
+    }
+
+    /// Returns the first tick after an attack on which the player could attack again.
+    fn off_cooldown_tick(states: &PlayerStates, attack_tick: u32) -> u32 {
+        let mut tick = attack_tick + 1;
+        while states
+            .get_tick(tick as usize)
+            .is_some_and(|state| matches!(state.attack_state, AttackState::OnCooldown(_)))
+        {
+            tick += 1;
+        }
+        tick
+    }
+}
+
+/// A player's use of a single down.
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerDown {
+    pub first_attack_tick: Option<u32>,
+    pub last_attack_tick: Option<u32>,
+    pub attacks: usize,
+    pub specs: usize,
+    /// Ticks at the start of the down on which the player could have been attacking.
+    pub late_entry_ticks: u32,
+    /// Ticks at the end of the down on which the player was off cooldown but no longer attacking.
+    pub early_exit_ticks: u32,
+    /// The ticks lost to entering the down late, if any.
+    pub late_entry: Option<TickRange>,
+    /// The ticks lost to leaving the down early, if any.
+    pub early_exit: Option<TickRange>,
+    /// Whether the player was still beneath Bloat when it stood up, and so was stomped and
+    /// stunned.
+    pub stomped: bool,
+}
+
+/// The party's use of a single down.
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DownReport {
+    pub number: u32,
+    pub walk_ticks: u32,
+    pub start_tick: u32,
+    pub end_tick: u32,
+    /// Total attack ticks lost by the party to late entries and early exits.
+    pub ticks_lost: u32,
+    pub players: BTreeMap<String, PlayerDown>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BloatDowns {
+    pub downs: Vec<DownReport>,
+    /// Special attacks each player used on Bloat while it was walking.
+    pub walking_specs: BTreeMap<String, usize>,
+}
+
+impl Analyzer for TobBloatAnalyzer {
+    type Output = BloatDowns;
+
+    fn name(&self) -> &str {
+        "TobBloatAnalyzer"
+    }
+
+    fn player_fields(&self) -> &'static [&'static str] {
+        &["downs/*/players", "walkingSpecs"]
+    }
+
+    fn analyze(&self, context: &Context) -> Result<Self::Output> {
+        let challenge = context.challenge();
+        let blert::Challenge::Tob = challenge.r#type() else {
+            return Err(Error::FailedPrecondition(
+                "TobBloatAnalyzer requires a TOB challenge".into(),
+            ));
+        };
+        context.require_stages(&[blert::Stage::TobBloat])?;
+
+        let Some(stage) = challenge.stage_info(blert::Stage::TobBloat) else {
+            return Ok(BloatDowns {
+                downs: Vec::new(),
+                walking_specs: BTreeMap::new(),
+            });
+        };
+
+        let is_bloat = |npc: &blert::challenge_data::StageNpc| {
+            context
+                .npc_registry()
+                .has_role(npc.spawn_npc_id, Role::Boss)
+        };
+
+        let downs = bloat::downs(stage);
+        let bloat_npc = stage.npcs().find(|npc| is_bloat(npc));
+        let bloat_size = bloat_npc
+            .and_then(|npc| context.npc_registry().get(npc.spawn_npc_id))
+            .map_or(bloat::SIZE, |npc| npc.size);
+        let party = challenge
+            .party()
+            .iter()
+            .filter(|username| context.is_player_in_scope(username))
+            .filter_map(|username| {
+                stage
+                    .player_state(username)
+                    .map(|states| (username.clone(), states))
+            })
+            .collect::<Vec<_>>();
+
+        let reports = downs
+            .iter()
+            .map(|down| {
+                let position = bloat_npc.and_then(|npc| bloat::position_during(stage, npc, down));
+                let bloat = position.as_ref().map(|tile| (tile, bloat_size));
+                let players = party
+                    .iter()
+                    .filter_map(|(username, states)| {
+                        Self::analyze_player_down(down, states, is_bloat, bloat)
+                            .map(|player| (username.clone(), player))
+                    })
+                    .collect::<BTreeMap<_, _>>();
+
+                DownReport {
+                    number: down.number,
+                    walk_ticks: down.walk_ticks,
+                    start_tick: down.start_tick,
+                    end_tick: down.end_tick,
+                    ticks_lost: players
+                        .values()
+                        .map(|player| player.late_entry_ticks + player.early_exit_ticks)
+                        .sum(),
+                    players,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        for down in &reports {
+            Self::emit_down_findings(context, down);
+        }
+
+        let walking_specs = party
+            .iter()
+            .map(|(username, states)| {
+                let specs = states
+                    .attacks_on(is_bloat)
+                    .filter(|(tick, attack)| {
+                        attack.attack.is_special() && !downs.iter().any(|d| d.contains(*tick))
+                    })
+                    .count();
+                (username.clone(), specs)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        for (username, &specs) in &walking_specs {
+            if specs > 0 {
+                context.emit_finding(
+                    Finding::new(Severity::Minor, "bloat.walking_spec")
+                        .with_player(username)
+                        .with_stage(blert::Stage::TobBloat)
+                        .with_data(json!({ "specs": specs })),
+                );
+            }
+        }
+
+        Ok(BloatDowns {
+            downs: reports,
+            walking_specs,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn late_entry_starts_after_the_grace_period() {
+        let late = |first_attack| TobBloatAnalyzer::late_entry(40, 72, first_attack);
+
+        // Attacking within the grace period is on time.
+        assert_eq!(late(Some(40)), None);
+        assert_eq!(late(Some(42)), None);
+
+        assert_eq!(late(Some(43)), Some(TickRange { start: 42, end: 42 }));
+        assert_eq!(late(Some(46)), Some(TickRange { start: 42, end: 45 }));
+
+        // A player who never attacked lost the whole down.
+        assert_eq!(late(None), Some(TickRange { start: 40, end: 71 }));
+    }
+
+    #[test]
+    fn early_exit_ends_with_participation() {
+        // Bloat stood up on tick 72, so the last tick of the down is 71.
+        assert_eq!(
+            TobBloatAnalyzer::early_exit(69, 72),
+            Some(TickRange { start: 69, end: 71 })
+        );
+        assert_eq!(TobBloatAnalyzer::early_exit(72, 72), None);
+
+        // A player who died on tick 60 could only attack until then.
+        let down = Down {
+            number: 1,
+            walk_ticks: 40,
+            start_tick: 40,
+            end_tick: 72,
+            stood_up: true,
+        };
+        let end_tick = down.participation_end(Some(60)).unwrap();
+        assert_eq!(
+            TobBloatAnalyzer::early_exit(55, end_tick),
+            Some(TickRange { start: 55, end: 59 })
+        );
+        assert_eq!(TobBloatAnalyzer::early_exit(60, end_tick), None);
+    }
+
+    #[test]
+    fn players_beneath_bloat_when_it_stands_up_are_stomped() {
+        let tile = blert::Coords { x: 3288, y: 4445 };
+        let bloat = Some((&tile, bloat::SIZE));
+        let beneath = blert::Coords { x: 3290, y: 4447 };
+        let beside = blert::Coords { x: 3294, y: 4447 };
+
+        assert!(TobBloatAnalyzer::stomped(bloat, Some(&beneath)));
+        assert!(!TobBloatAnalyzer::stomped(bloat, Some(&beside)));
+
+        // Without Bloat's position or the player's, no stomp can be detected.
+        assert!(!TobBloatAnalyzer::stomped(None, Some(&beneath)));
+        assert!(!TobBloatAnalyzer::stomped(bloat, None));
+    }
+}
@@ -1,8 +1,12 @@
 use std::{
     cmp::Reverse,
     collections::{HashMap, HashSet},
+    sync::Arc,
 };
 
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
 use crate::{
     analysis::Analyzer,
     blert,
@@ -10,12 +14,14 @@ use crate::{
     error::{Error, Result},
     item,
     npc::NpcExt,
+    tob::maiden,
 };
 
 use super::gear_analyzer::{self, GearAnalyzer};
+use super::role_classifier::{self, RoleClassifier};
 
 /// A well-defined meta role for a player in the Theatre of Blood.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
 pub enum Role {
     Solo,
     DuoMage,
@@ -33,7 +39,7 @@ impl Role {
 }
 
 /// A role responsibility within a Theatre of Blood room.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
 pub enum SubRole {
     MaidenSoloFreezer,
     MaidenNorthFreezer,
@@ -45,7 +51,7 @@ pub enum SubRole {
     NyloEastMelee,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, JsonSchema)]
 #[allow(dead_code)]
 pub struct PlayerRoles(Role, Vec<SubRole>);
 
@@ -105,6 +111,26 @@ impl AssignmentContext<'_> {
 #[derive(Debug)]
 struct PrimaryRole(String, Role);
 
+/// Backend used to assign primary roles to players.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ClassifierKind {
+    /// Hand-tuned heuristics over players' attacks and gear.
+    #[default]
+    Heuristic,
+
+    /// A statistical model trained on role features, loaded from `model_path`.
+    Model,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct Config {
+    #[serde(default)]
+    classifier: ClassifierKind,
+    /// Path to the trained model used by the `model` classifier.
+    model_path: Option<String>,
+}
+
 /// The `TobRoleAnalyzer` attempts to determine the role of every player within a Theatre of Blood
 /// raid.
 ///
@@ -119,7 +145,12 @@ struct PrimaryRole(String, Role);
 ///
 /// To simplify downstream usage, the analyzer takes an all-or-nothing approach: if it cannot
 /// assign roles to every player, it will fail outright.
-pub struct TobRoleAnalyzer {}
+///
+/// Primary roles are assigned by a configurable [`RoleClassifier`], defaulting to the built-in
+/// heuristics. Sub-roles are always determined heuristically.
+pub struct TobRoleAnalyzer {
+    classifier: Arc<dyn RoleClassifier>,
+}
 
 impl TobRoleAnalyzer {
     /// The threshold for the number of 4 tick melees a player must have to be considered a meleer
@@ -128,36 +159,113 @@ impl TobRoleAnalyzer {
     const MELEE_4T_THRESHOLD: u32 = 12;
 
     /// Weapons used by meleers in the Nylocas room.
-    const NYLO_MELEE_WEAPONS: &'static [i32] = &[
+    pub(super) const NYLO_MELEE_WEAPONS: &'static [i32] = &[
         item::Id::SWIFT_BLADE,
         item::Id::HAM_JOINT,
         item::Id::DUAL_MACUAHUITL,
     ];
 
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(config: &Config) -> Result<Self> {
+        let classifier: Arc<dyn RoleClassifier> = match config.classifier {
+            ClassifierKind::Heuristic => Arc::new(HeuristicRoleClassifier),
+            ClassifierKind::Model => {
+                let path = config.model_path.as_ref().ok_or_else(|| {
+                    Error::Config("TobRoleAnalyzer model classifier requires model_path".into())
+                })?;
+                super::load_shared(path, |path| {
+                    role_classifier::LogisticRoleClassifier::load(path)
+                })?
+            }
+        };
+
+        Ok(Self { classifier })
     }
 
-    /// Attempts to assign roles to all players based on room data. If every role is successfully
-    /// assigned, returns a map of player names to their roles. Otherwise, returns an error.
-    fn determine_roles(
-        challenge: &Challenge,
-        player_gear: &gear_analyzer::PlayerGear,
-    ) -> Result<HashMap<String, PlayerRoles>> {
-        let roles_to_assign = match challenge.scale() {
-            1 => unreachable!(),
-            2 => vec![Role::DuoMage, Role::DuoRanger],
-            3 => vec![Role::Mage, Role::Ranger, Role::Melee],
-            4 => vec![Role::Mage, Role::MeleeFreeze, Role::Ranger, Role::Melee],
-            5 => vec![
+    /// Returns the roles which must be filled in a raid of the given scale.
+    pub(super) fn roles_for_scale(scale: usize) -> Result<Vec<Role>> {
+        match scale {
+            1 => Ok(vec![Role::Solo]),
+            2 => Ok(vec![Role::DuoMage, Role::DuoRanger]),
+            3 => Ok(vec![Role::Mage, Role::Ranger, Role::Melee]),
+            4 => Ok(vec![
+                Role::Mage,
+                Role::MeleeFreeze,
+                Role::Ranger,
+                Role::Melee,
+            ]),
+            5 => Ok(vec![
                 Role::Mage,
                 Role::Mage,
                 Role::Ranger,
                 Role::Melee,
                 Role::Melee,
-            ],
-            _ => return Err(Error::FailedPrecondition("Invalid raid scale".into())),
-        };
+            ]),
+            _ => Err(Error::FailedPrecondition("Invalid raid scale".into())),
+        }
+    }
+
+    /// Assigns roles to all players using the configured classifier, then determines their
+    /// sub-roles based on room data. If every role is successfully assigned, returns a map of
+    /// player names to their roles. Otherwise, returns an error.
+    fn determine_roles(
+        &self,
+        challenge: &Challenge,
+        player_gear: &gear_analyzer::PlayerGear,
+    ) -> Result<HashMap<String, PlayerRoles>> {
+        let assigned_roles = self.classifier.classify(challenge, player_gear)?;
+        tracing::debug!(
+            "Challenge {}: {} classifier assigned roles {assigned_roles:?}",
+            challenge.uuid(),
+            self.classifier.name(),
+        );
+
+        let player_roles = assigned_roles
+            .into_iter()
+            .map(|(player, role)| {
+                let mut subroles = Vec::new();
+
+                if let Some(maiden_data) = challenge.stage_info(blert::Stage::TobMaiden) {
+                    let player_state = maiden_data
+                        .player_state(&player)
+                        .ok_or(Error::IncompleteData)?;
+                    subroles.extend(Self::determine_maiden_subroles(
+                        challenge,
+                        maiden_data,
+                        &player_state,
+                        role,
+                    ));
+                }
+                if let Some(nylo_data) = challenge.stage_info(blert::Stage::TobNylocas) {
+                    let player_state = nylo_data
+                        .player_state(&player)
+                        .ok_or(Error::IncompleteData)?;
+                    subroles.extend(Self::determine_nylo_subroles(
+                        challenge,
+                        nylo_data,
+                        &player_state,
+                        role,
+                    ));
+                }
+
+                Ok((player, PlayerRoles(role, subroles)))
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        if player_roles.len() == challenge.scale() {
+            Ok(player_roles)
+        } else {
+            tracing::error!("Failed to assign roles to all players");
+            Err(Error::IncompleteData)
+        }
+    }
+
+    /// Attempts to assign a primary role to all players using hand-tuned heuristics over room
+    /// data.
+    fn assign_primary_roles(
+        challenge: &Challenge,
+        player_gear: &gear_analyzer::PlayerGear,
+    ) -> Result<Vec<PrimaryRole>> {
+        let roles_to_assign = Self::roles_for_scale(challenge.scale())?;
 
         let mut ctx = AssignmentContext {
             challenge,
@@ -168,8 +276,6 @@ impl TobRoleAnalyzer {
             players_not_matching_any_role: Vec::new(),
         };
 
-        let mut player_roles = HashMap::new();
-
         // Do an initial pass counting how many roles each player could potentially match, and sort
         // the party in that order to maximize the chance of successful role assignment.
         // Keep track of the orb to correct the party's order after role assignment.
@@ -190,7 +296,7 @@ impl TobRoleAnalyzer {
         assigned_roles.extend(Self::try_guess_unmatched_roles(&mut ctx, player_gear));
 
         if ctx.players_not_matching_any_role.len() > 1 {
-            log::error!(
+            tracing::error!(
                 "Cannot assign roles to all players as multiple players do not match any role",
             );
             return Err(Error::IncompleteData);
@@ -206,45 +312,11 @@ impl TobRoleAnalyzer {
         )? {
             assigned_roles.extend(roles);
         } else {
-            log::error!("Failed to assign roles to all players");
+            tracing::error!("Failed to assign roles to all players");
             return Err(Error::IncompleteData);
         };
 
-        player_roles.extend(assigned_roles.into_iter().map(|PrimaryRole(player, role)| {
-            let mut subroles = Vec::new();
-
-            if let Some(maiden_data) = challenge.stage_info(blert::Stage::TobMaiden) {
-                let player_state = maiden_data
-                    .player_state(&player)
-                    .expect("Player state is known to exist");
-                subroles.extend(Self::determine_maiden_subroles(
-                    challenge,
-                    maiden_data,
-                    &player_state,
-                    role,
-                ));
-            }
-            if let Some(nylo_data) = challenge.stage_info(blert::Stage::TobNylocas) {
-                let player_state = nylo_data
-                    .player_state(&player)
-                    .expect("Player state is known to exist");
-                subroles.extend(Self::determine_nylo_subroles(
-                    challenge,
-                    nylo_data,
-                    &player_state,
-                    role,
-                ));
-            }
-
-            (player, PlayerRoles(role, subroles))
-        }));
-
-        if player_roles.len() == challenge.scale() {
-            Ok(player_roles)
-        } else {
-            log::error!("Failed to assign roles to all players");
-            Err(Error::IncompleteData)
-        }
+        Ok(assigned_roles)
     }
 
     fn find_role_matches(
@@ -253,7 +325,7 @@ impl TobRoleAnalyzer {
     ) -> Result<()> {
         let (stage_data, match_fn): (&StageInfo, MatchFn) =
             if ctx.challenge.stage() < blert::Stage::TobNylocas {
-                log::debug!(
+                tracing::debug!(
                     "Challenge {}: assigning roles based on Maiden data",
                     ctx.uuid(),
                 );
@@ -263,7 +335,7 @@ impl TobRoleAnalyzer {
                     .ok_or_else(|| Error::IncompleteData)?;
                 (maiden_data, Self::try_match_role_pre_nylo)
             } else {
-                log::debug!(
+                tracing::debug!(
                     "Challenge {}: assigning roles based on Nylocas data",
                     ctx.uuid(),
                 );
@@ -289,10 +361,10 @@ impl TobRoleAnalyzer {
                     ctx.challenge.mode(),
                     ctx.scale(),
                     &player_state,
-                    &gear,
+                    gear,
                 ) {
                     MatchCertainty::Strong => {
-                        log::debug!("Definitively matched {player} to {role:?}");
+                        tracing::debug!("Definitively matched {player} to {role:?}");
                         ctx.strong_matches.entry(*role).or_default().push(player);
                         strong_match_index = Some(i);
                         break;
@@ -345,7 +417,7 @@ impl TobRoleAnalyzer {
                     ctx.roles_to_assign
                         .retain(|role| *role != Role::MeleeFreeze);
                 } else {
-                    log::warn!(
+                    tracing::warn!(
                         "{}: Multiple weak matches for Mage alongside a strong match",
                         ctx.uuid(),
                     );
@@ -445,7 +517,7 @@ impl TobRoleAnalyzer {
 
         if roles_to_assign.len() == 1 {
             // If there's only one role left to assign, assume it belongs to the last player.
-            log::debug!("Assigning final role {:?} to {player}", roles_to_assign[0]);
+            tracing::debug!("Assigning final role {:?} to {player}", roles_to_assign[0]);
             roles_assigned.push(PrimaryRole(player.to_string(), roles_to_assign[0]));
             return Ok(Some(std::mem::take(roles_assigned)));
         }
@@ -458,11 +530,11 @@ impl TobRoleAnalyzer {
                 .map_or(false, |players| players.contains(&player));
 
             if !player_matches_role {
-                log::debug!("{player} does not match role {role:?}");
+                tracing::debug!("{player} does not match role {role:?}");
                 continue;
             }
 
-            log::debug!("Potentially assigning role {role:?} to {player}");
+            tracing::debug!("Potentially assigning role {role:?} to {player}");
 
             roles_assigned.push(PrimaryRole(player.to_string(), role));
             roles_to_assign.swap(0, i);
@@ -475,7 +547,7 @@ impl TobRoleAnalyzer {
             )? {
                 Some(assigned_roles) => return Ok(Some(assigned_roles)),
                 None => {
-                    log::debug!("Failed to assign role {role:?} to {player}");
+                    tracing::debug!("Failed to assign role {role:?} to {player}");
                 }
             }
 
@@ -498,8 +570,7 @@ impl TobRoleAnalyzer {
         let mut has_dinhs = false;
 
         player_state
-            .attacks()
-            .filter(|(_, atk)| atk.target.as_ref().is_some_and(|t| t.is_maiden_matomenos()))
+            .attacks_on(NpcExt::is_maiden_matomenos)
             .for_each(|(_, atk)| {
                 if atk.attack.is_barrage() {
                     has_barraged = true;
@@ -713,8 +784,10 @@ impl TobRoleAnalyzer {
             if num_freezers == 1 {
                 subroles.push(SubRole::MaidenSoloFreezer);
             } else {
-                let (north_freezes, south_freezes) =
-                    Self::count_north_and_south_freezes(player_state);
+                let (north_freezes, south_freezes) = Self::count_north_and_south_freezes(
+                    player_state,
+                    &maiden::spawn_sets(maiden_data),
+                );
 
                 if north_freezes > south_freezes {
                     subroles.push(SubRole::MaidenNorthFreezer);
@@ -724,9 +797,9 @@ impl TobRoleAnalyzer {
             }
         }
 
-        let has_chinned = player_state.attacks().any(|(_, atk)| {
-            atk.attack.is_chin() && atk.target.as_ref().is_some_and(|t| t.is_maiden_matomenos())
-        });
+        let has_chinned = player_state
+            .attacks_on(NpcExt::is_maiden_matomenos)
+            .any(|(_, atk)| atk.attack.is_chin());
         if has_chinned {
             subroles.push(SubRole::MaidenChinner);
         }
@@ -844,36 +917,32 @@ impl TobRoleAnalyzer {
     }
 
     /// Counts how many times a player barraged a north or south Maiden crab.
-    fn count_north_and_south_freezes(player_state: &PlayerStates) -> (u32, u32) {
-        use blert::challenge_data::stage_npc::Type;
-        use blert::event::npc::maiden_crab;
-
-        // On
-        player_state.attacks().fold((0, 0), |acc, (tick, atk)| {
-            match (atk.attack, atk.target.as_ref()) {
-                // Only count freezes occurring within 17 ticks of the crab spawning, as that is
-                // how long a scuffed 4 crab takes to walk into Maiden. Any freezes beyond that
-                // are considered DPS on the clump.
-                (attack, Some(target)) if attack.is_barrage() && tick - target.spawn_tick <= 17 => {
-                    if let Some(Type::MaidenCrab(crab)) = &target.r#type {
-                        match crab.position() {
-                            maiden_crab::Position::S1
-                            | maiden_crab::Position::S2
-                            | maiden_crab::Position::S3
-                            | maiden_crab::Position::S4Inner
-                            | maiden_crab::Position::S4Outer => (acc.0, acc.1 + 1),
-                            maiden_crab::Position::N1
-                            | maiden_crab::Position::N2
-                            | maiden_crab::Position::N3
-                            | maiden_crab::Position::N4Inner
-                            | maiden_crab::Position::N4Outer => (acc.0 + 1, acc.1),
-                        }
+    fn count_north_and_south_freezes(
+        player_state: &PlayerStates,
+        spawn_sets: &[maiden::SpawnSet<'_>],
+    ) -> (u32, u32) {
+        // Only count freezes occurring within 17 ticks of a set spawning, as that is how long a
+        // scuffed 4 crab takes to walk into Maiden. Any freezes beyond that are considered DPS on
+        // the clump.
+        const FREEZE_TICKS: u32 = 17;
+
+        spawn_sets.iter().fold((0, 0), |(north, south), set| {
+            let north_crabs = set.north().map(|crab| crab.room_id).collect::<HashSet<_>>();
+            let south_crabs = set.south().map(|crab| crab.room_id).collect::<HashSet<_>>();
+
+            player_state
+                .attacks_within(set.spawn_tick..=set.spawn_tick + FREEZE_TICKS)
+                .filter(|(_, atk)| atk.attack.is_barrage())
+                .filter_map(|(_, atk)| atk.target.as_ref())
+                .fold((north, south), |(north, south), target| {
+                    if north_crabs.contains(&target.room_id) {
+                        (north + 1, south)
+                    } else if south_crabs.contains(&target.room_id) {
+                        (north, south + 1)
                     } else {
-                        acc
+                        (north, south)
                     }
-                }
-                _ => acc,
-            }
+                })
         })
     }
 }
@@ -885,6 +954,10 @@ impl Analyzer for TobRoleAnalyzer {
         "TobRoleAnalyzer"
     }
 
+    fn player_fields(&self) -> &'static [&'static str] {
+        &[""]
+    }
+
     fn analyze(&self, context: &crate::analysis::Context) -> Result<Self::Output> {
         let challenge = context.challenge();
         let blert::Challenge::Tob = challenge.r#type() else {
@@ -892,9 +965,10 @@ impl Analyzer for TobRoleAnalyzer {
                 "TobRoleAnalyzer requires a TOB challenge".into(),
             ));
         };
+        context.require_stages(&[blert::Stage::TobMaiden, blert::Stage::TobNylocas])?;
 
         let gear = context
-            .get_dependency_output::<GearAnalyzer>()
+            .get_player_dependency_output::<GearAnalyzer>()
             .ok_or(Error::Dependency("GearAnalyzer".into()))?;
 
         if challenge.scale() == 1 {
@@ -906,6 +980,28 @@ impl Analyzer for TobRoleAnalyzer {
             return Ok(roles);
         }
 
-        Self::determine_roles(challenge, &gear)
+        self.determine_roles(challenge, &gear)
+    }
+}
+
+/// The default [`RoleClassifier`], which assigns roles using the `TobRoleAnalyzer`'s hand-tuned
+/// heuristics.
+pub struct HeuristicRoleClassifier;
+
+impl RoleClassifier for HeuristicRoleClassifier {
+    fn name(&self) -> &str {
+        "heuristic"
+    }
+
+    fn classify(
+        &self,
+        challenge: &Challenge,
+        player_gear: &gear_analyzer::PlayerGear,
+    ) -> Result<HashMap<String, Role>> {
+        let roles = TobRoleAnalyzer::assign_primary_roles(challenge, player_gear)?;
+        Ok(roles
+            .into_iter()
+            .map(|PrimaryRole(player, role)| (player, role))
+            .collect())
     }
 }
@@ -0,0 +1,222 @@
+use std::collections::HashSet;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    analysis::{Context, PlayerAnalyzer},
+    blert,
+    challenge::{Challenge, PlayerAttackExt, PlayerStates},
+    error::{Error, Result},
+    item,
+    npc::NpcExt,
+};
+
+use super::gear_analyzer::{self, GearAnalyzer};
+use super::tob_role_analyzer::TobRoleAnalyzer;
+
+/// The `TobRoleFeaturesAnalyzer` extracts the signals used to determine a player's role in a
+/// Theatre of Blood raid into a flat feature vector.
+///
+/// The features mirror those considered by the [`TobRoleAnalyzer`] heuristics. Alongside the
+/// roles it assigns, they form training data for a statistical role classifier.
+pub struct TobRoleFeaturesAnalyzer {}
+
+impl TobRoleFeaturesAnalyzer {
+    /// Maximum number of ticks after a Nylo spawns within which an attack on it is considered a
+    /// prefire.
+    const PREFIRE_TICKS: u32 = 9;
+
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    fn add_maiden_features(features: &mut RoleFeatures, player_state: &PlayerStates) {
+        for (_, atk) in player_state.attacks_on(NpcExt::is_maiden_matomenos) {
+            if atk.attack.is_barrage() {
+                features.maiden_barrages += 1;
+            } else if atk.attack.is_chin() {
+                features.maiden_chins += 1;
+            } else if matches!(
+                atk.attack,
+                blert::PlayerAttack::DinhsSpec | blert::PlayerAttack::DinhsBash
+            ) {
+                features.maiden_dinhs += 1;
+            }
+        }
+    }
+
+    fn add_nylo_features(features: &mut RoleFeatures, player_state: &PlayerStates) {
+        use blert::challenge_data::stage_npc::Type;
+        use blert::event::npc::nylo::SpawnType;
+        use blert::PlayerAttack;
+
+        let mut nylos_prefired = HashSet::new();
+
+        for (tick, atk) in player_state.attacks() {
+            match atk.attack {
+                PlayerAttack::SwiftBlade
+                | PlayerAttack::HamJoint
+                | PlayerAttack::DualMacuahuitl => {
+                    features.nylo_swifts += 1;
+                }
+                PlayerAttack::ClawScratch | PlayerAttack::TentWhip => features.nylo_4t_melees += 1,
+                PlayerAttack::Blowpipe | PlayerAttack::BlowpipeSpec => features.nylo_pipes += 1,
+                PlayerAttack::Scythe | PlayerAttack::ScytheUncharged => {
+                    features.nylo_scythes += 1;
+                }
+                attack if attack.is_barrage() => features.nylo_barrages += 1,
+                attack if attack.is_chin() => features.nylo_chins += 1,
+                _ => {}
+            }
+
+            let Some(target) = &atk.target else {
+                continue;
+            };
+            let Some(Type::Nylo(nylo)) = &target.r#type else {
+                continue;
+            };
+
+            let is_prefire = nylo.spawn_type() != SpawnType::Split
+                && tick
+                    .checked_sub(target.spawn_tick)
+                    .is_some_and(|ticks| ticks <= Self::PREFIRE_TICKS);
+            if !is_prefire || !nylos_prefired.insert(target.room_id) {
+                continue;
+            }
+
+            features.nylo_prefires += 1;
+            match nylo.spawn_type() {
+                SpawnType::West => features.nylo_west_prefires += 1,
+                SpawnType::East => features.nylo_east_prefires += 1,
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Features describing a single player's behavior and equipment during a raid.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct RoleFeatures {
+    pub scale: u32,
+    pub hard_mode: bool,
+    pub reached_nylocas: bool,
+
+    pub maiden_barrages: u32,
+    pub maiden_chins: u32,
+    pub maiden_dinhs: u32,
+
+    pub nylo_barrages: u32,
+    pub nylo_chins: u32,
+    pub nylo_swifts: u32,
+    pub nylo_4t_melees: u32,
+    pub nylo_pipes: u32,
+    pub nylo_scythes: u32,
+    pub nylo_prefires: u32,
+    pub nylo_west_prefires: u32,
+    pub nylo_east_prefires: u32,
+
+    pub has_void: bool,
+    pub has_non_void_ranged_gear: bool,
+    pub has_nylo_melee_weapon: bool,
+    pub has_dinhs: bool,
+    pub has_paint_cannon: bool,
+}
+
+impl PlayerAnalyzer for TobRoleFeaturesAnalyzer {
+    type Output = RoleFeatures;
+
+    fn name(&self) -> &str {
+        "TobRoleFeaturesAnalyzer"
+    }
+
+    fn analyze_player(&self, context: &Context, username: &str) -> Result<Self::Output> {
+        let challenge = context.challenge();
+        let blert::Challenge::Tob = challenge.r#type() else {
+            return Err(Error::FailedPrecondition(
+                "TobRoleFeaturesAnalyzer requires a TOB challenge".into(),
+            ));
+        };
+        context.require_stages(&[blert::Stage::TobMaiden, blert::Stage::TobNylocas])?;
+
+        let gear = context
+            .get_player_dependency_output::<GearAnalyzer>()
+            .ok_or(Error::Dependency("GearAnalyzer".into()))?;
+        let gear = gear.player(username).ok_or(Error::IncompleteData)?;
+
+        RoleFeatures::extract(challenge, username, gear)
+    }
+}
+
+impl RoleFeatures {
+    /// Extracts the role features of a player in a Theatre of Blood raid.
+    pub fn extract(
+        challenge: &Challenge,
+        username: &str,
+        gear: &gear_analyzer::Player,
+    ) -> Result<Self> {
+        let mut features = RoleFeatures {
+            scale: challenge.scale() as u32,
+            hard_mode: challenge.mode() == blert::ChallengeMode::TobHard,
+            has_void: gear.has_void(item::VoidStyle::Any),
+            has_non_void_ranged_gear: gear.has_any_in_challenge(&[
+                item::Id::ZARYTE_VAMBRACES,
+                item::Id::MASORI_MASK,
+                item::Id::MASORI_BODY,
+                item::Id::MASORI_CHAPS,
+                item::Id::MASORI_MASK_F,
+                item::Id::MASORI_BODY_F,
+                item::Id::MASORI_CHAPS_F,
+            ]),
+            has_nylo_melee_weapon: gear.has_any_in_challenge(TobRoleAnalyzer::NYLO_MELEE_WEAPONS),
+            has_dinhs: gear
+                .has_any_in_challenge(&[item::Id::DINHS_BULWARK, item::Id::DINHS_BLAZING_BULWARK]),
+            has_paint_cannon: gear.has(blert::Stage::TobNylocas, item::Id::GOBLIN_PAINT_CANNON),
+            ..RoleFeatures::default()
+        };
+
+        let maiden_state = challenge
+            .stage_info(blert::Stage::TobMaiden)
+            .and_then(|stage| stage.player_state(username))
+            .ok_or(Error::IncompleteData)?;
+        TobRoleFeaturesAnalyzer::add_maiden_features(&mut features, &maiden_state);
+
+        if let Some(nylo) = challenge.stage_info(blert::Stage::TobNylocas) {
+            let nylo_state = nylo.player_state(username).ok_or(Error::IncompleteData)?;
+            features.reached_nylocas = true;
+            TobRoleFeaturesAnalyzer::add_nylo_features(&mut features, &nylo_state);
+        }
+
+        Ok(features)
+    }
+
+    /// Returns the value of every feature as a number, keyed by feature name.
+    pub fn values(&self) -> Vec<(&'static str, f64)> {
+        let flag = |b: bool| if b { 1.0 } else { 0.0 };
+        vec![
+            ("scale", f64::from(self.scale)),
+            ("hard_mode", flag(self.hard_mode)),
+            ("reached_nylocas", flag(self.reached_nylocas)),
+            ("maiden_barrages", f64::from(self.maiden_barrages)),
+            ("maiden_chins", f64::from(self.maiden_chins)),
+            ("maiden_dinhs", f64::from(self.maiden_dinhs)),
+            ("nylo_barrages", f64::from(self.nylo_barrages)),
+            ("nylo_chins", f64::from(self.nylo_chins)),
+            ("nylo_swifts", f64::from(self.nylo_swifts)),
+            ("nylo_4t_melees", f64::from(self.nylo_4t_melees)),
+            ("nylo_pipes", f64::from(self.nylo_pipes)),
+            ("nylo_scythes", f64::from(self.nylo_scythes)),
+            ("nylo_prefires", f64::from(self.nylo_prefires)),
+            ("nylo_west_prefires", f64::from(self.nylo_west_prefires)),
+            ("nylo_east_prefires", f64::from(self.nylo_east_prefires)),
+            ("has_void", flag(self.has_void)),
+            (
+                "has_non_void_ranged_gear",
+                flag(self.has_non_void_ranged_gear),
+            ),
+            ("has_nylo_melee_weapon", flag(self.has_nylo_melee_weapon)),
+            ("has_dinhs", flag(self.has_dinhs)),
+            ("has_paint_cannon", flag(self.has_paint_cannon)),
+        ]
+    }
+}
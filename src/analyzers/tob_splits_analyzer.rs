@@ -0,0 +1,148 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::{Analyzer, Context};
+use crate::blert;
+use crate::error::{Error, Result};
+use crate::time;
+use crate::tob::benchmarks::Benchmarks;
+use crate::tob::nylo;
+
+/// The `TobSplitsAnalyzer` reports the time taken by each room of a Theatre of Blood raid and
+/// compares it against the benchmark for the raid's mode and scale.
+///
+/// Solo and duo raids play very differently from larger teams, so rooms are only compared against
+/// a profile for the exact scale of the raid. Raids without one are reported without benchmarks.
+///
+/// The Nylocas room's waves are additionally aligned against the canonical wave schedule to report
+/// how often the room stalled.
+pub struct TobSplitsAnalyzer {
+    benchmarks: Arc<Benchmarks>,
+}
+
+/// Configuration options for the `TobSplitsAnalyzer`.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct Config {
+    /// Path to the JSON file containing benchmark profiles.
+    benchmarks_path: String,
+}
+
+impl TobSplitsAnalyzer {
+    pub fn new(config: &Config) -> Result<Self> {
+        Ok(Self {
+            benchmarks: super::load_shared(&config.benchmarks_path, |path| Benchmarks::load(path))?,
+        })
+    }
+}
+
+/// The time taken by a single room.
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomSplit {
+    pub ticks: u32,
+    /// Real time taken by the room, formatted as `mm:ss.cs`.
+    pub time: String,
+    /// Benchmark time of the room for the raid's mode and scale, if one exists.
+    pub benchmark_ticks: Option<u32>,
+    /// Ticks slower (positive) or faster (negative) than the benchmark.
+    pub delta_ticks: Option<i64>,
+}
+
+/// How the Nylocas room's waves spawned relative to their schedule.
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NyloWaves {
+    /// Total number of times the waves stalled.
+    pub stalls: u32,
+    /// Number of times each stalled wave stalled, keyed by wave number.
+    pub stalled_waves: BTreeMap<u32, u32>,
+    /// Waves whose recorded nylos do not match the schedule, for which stalls may be miscounted.
+    pub mismatched_waves: Vec<u32>,
+}
+
+impl NyloWaves {
+    fn from_matches(matches: &[nylo::WaveMatch<'_>]) -> Self {
+        Self {
+            stalls: matches.iter().map(|m| m.stalls).sum(),
+            stalled_waves: matches
+                .iter()
+                .filter(|m| m.stalls > 0)
+                .map(|m| (m.wave.number, m.stalls))
+                .collect(),
+            mismatched_waves: matches
+                .iter()
+                .filter(|m| !m.is_exact())
+                .map(|m| m.wave.number)
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Splits {
+    /// Scale of the benchmark profile used, if the raid had one.
+    pub benchmark_scale: Option<usize>,
+    /// Wave stalls in the Nylocas room, if it was recorded.
+    pub nylo_waves: Option<NyloWaves>,
+    #[serde(serialize_with = "super::serialize_by_stage")]
+    #[schemars(with = "HashMap<String, RoomSplit>")]
+    rooms: HashMap<blert::Stage, RoomSplit>,
+}
+
+impl Splits {
+    /// Returns the split of a room, if it was recorded.
+    pub fn room(&self, stage: blert::Stage) -> Option<&RoomSplit> {
+        self.rooms.get(&stage)
+    }
+}
+
+impl Analyzer for TobSplitsAnalyzer {
+    type Output = Splits;
+
+    fn name(&self) -> &str {
+        "TobSplitsAnalyzer"
+    }
+
+    fn analyze(&self, context: &Context) -> Result<Self::Output> {
+        let challenge = context.challenge();
+        let blert::Challenge::Tob = challenge.r#type() else {
+            return Err(Error::FailedPrecondition(
+                "TobSplitsAnalyzer requires a TOB challenge".into(),
+            ));
+        };
+        context.require_all_stages()?;
+
+        let profile = self.benchmarks.profile(challenge.mode(), challenge.scale());
+
+        let rooms = challenge
+            .stage_infos()
+            .iter()
+            .map(|stage| {
+                let ticks = stage.total_ticks();
+                let benchmark_ticks = profile.and_then(|p| p.room_ticks(stage.stage()));
+                let split = RoomSplit {
+                    ticks,
+                    time: time::format_duration(stage.duration()),
+                    benchmark_ticks,
+                    delta_ticks: benchmark_ticks
+                        .map(|benchmark| i64::from(ticks) - i64::from(benchmark)),
+                };
+                (stage.stage(), split)
+            })
+            .collect();
+
+        let nylo_waves = challenge
+            .stage_info(blert::Stage::TobNylocas)
+            .map(|stage| NyloWaves::from_matches(&nylo::match_waves(stage)));
+
+        Ok(Splits {
+            benchmark_scale: profile.map(|p| p.scale),
+            nylo_waves,
+            rooms,
+        })
+    }
+}
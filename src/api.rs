@@ -1,35 +1,1372 @@
-use axum::extract::{Json, State};
-use axum::http::StatusCode;
-use serde::Deserialize;
+use axum::extract::{Extension, Json, Multipart, Path, Query, Request, State};
+use axum::http::{header, HeaderName, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::sse::{self, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use futures::stream::{self, Stream};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::convert::Infallible;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::broadcast::error::RecvError;
+use utoipa::{IntoParams, OpenApi, ToSchema};
 use uuid::Uuid;
 
-use crate::challenge::Challenge;
-use crate::{analysis, AppState};
+use crate::analysis::{
+    AnalyzerInfo, AnalyzerOutcome, AnalyzerStatus, DuplicatePolicy, Level, ProgramInfo,
+    ProgramResult, ProgramRunHandle, RunOptions,
+};
+use crate::analyzers::{self, ImplementationInfo};
+use crate::backfill::{BackfillProgress, BackfillRequest, BackfillStatus};
+use crate::blert;
+use crate::challenge::{self, Challenge};
+use crate::data_repository::{
+    ChallengeVerification, DataRepository, FileStatus, FileVerification, MemoryBackend,
+};
+use crate::dispatch::Priority;
+use crate::error::Error;
+use crate::export::{self, ExportRequest, TableFormat};
+use crate::findings::{Finding, Severity, TickRange};
+use crate::jobs::{self, CancelError, Job};
+use crate::load::LoadMetrics;
+use crate::ratings::RoleRating;
+use crate::report::{self, Mistake, PlayerReport};
+use crate::results::{ResultsFilter, RunStatus, StoredRun};
+use crate::schemas;
+use crate::search::{SearchFilter, SearchPage, SearchResult};
+use crate::sessions::Session;
+use crate::submission::{self, AnalysisRequest, Submission, SubmitError};
+use crate::trends::{self, Direction, PlayerTrends, RoleConsistency, Trend, TrendsRequest};
+use crate::usage::ResourceUsage;
+use crate::AppState;
 
-#[derive(Debug, Deserialize)]
+/// OpenAPI description of the HTTP API, served at `/openapi.json`.
+///
+/// Every response body from the versioned API is wrapped in an [`Envelope`], as documented for
+/// each endpoint's successful responses. Failed responses carry an [`EnvelopeError`] instead.
+#[derive(OpenApi)]
+#[openapi(
+    servers((url = "/v1")),
+    paths(
+        analyze,
+        analyze_raw,
+        get_job,
+        job_events,
+        cancel_job,
+        reanalyze_outdated,
+        start_backfill,
+        get_backfill,
+        cancel_backfill,
+        verify_challenge,
+        get_analysis,
+        get_player_reports,
+        get_session,
+        get_player_trends,
+        get_player_ratings,
+        search_challenges,
+        export_results,
+        export_player_states,
+        get_programs,
+        get_analyzers,
+        get_metrics
+    ),
+    components(schemas(
+        AnalyzeEnvelope,
+        ProgramResultEnvelope,
+        JobEnvelope,
+        StoredRunsEnvelope,
+        PlayerReportsEnvelope,
+        ReanalyzeEnvelope,
+        BackfillEnvelope,
+        VerificationEnvelope,
+        SessionEnvelope,
+        TrendsEnvelope,
+        RatingsEnvelope,
+        SearchEnvelope,
+        ProgramsEnvelope,
+        AnalyzersEnvelope,
+        MetricsEnvelope,
+        EnvelopeError,
+        EnvelopeMeta,
+        AnalyzeRequest,
+        AnalyzeResponse,
+        AnalyzerInfo,
+        AnalyzerOutcome,
+        AnalyzerStatus,
+        BackfillProgress,
+        BackfillRequest,
+        BackfillStatus,
+        ChallengeVerification,
+        FileStatus,
+        FileVerification,
+        ImplementationInfo,
+        DuplicatePolicy,
+        Finding,
+        Level,
+        LoadMetrics,
+        Mistake,
+        PlayerReport,
+        Direction,
+        Priority,
+        Job,
+        jobs::Status,
+        ProgramInfo,
+        PlayerTrends,
+        ProgramResult,
+        ReanalyzeRequest,
+        ReanalyzeResponse,
+        ResourceUsage,
+        RoleConsistency,
+        RoleRating,
+        RunStatus,
+        SearchPage,
+        SearchResult,
+        Session,
+        Severity,
+        StoredRun,
+        TickRange,
+        Trend
+    ))
+)]
+pub struct ApiDoc;
+
+/// Version of the API served under the `/v1` prefix.
+pub const API_VERSION: &str = "v1";
+
+/// Wrapper around every JSON response from the versioned API.
+#[derive(Debug, Serialize, ToSchema)]
+#[aliases(
+    AnalyzeEnvelope = Envelope<AnalyzeResponse>,
+    ProgramResultEnvelope = Envelope<ProgramResult>,
+    JobEnvelope = Envelope<Job>,
+    StoredRunsEnvelope = Envelope<Vec<StoredRun>>,
+    PlayerReportsEnvelope = Envelope<Vec<PlayerReport>>,
+    ReanalyzeEnvelope = Envelope<ReanalyzeResponse>,
+    BackfillEnvelope = Envelope<BackfillProgress>,
+    VerificationEnvelope = Envelope<ChallengeVerification>,
+    SessionEnvelope = Envelope<Session>,
+    TrendsEnvelope = Envelope<PlayerTrends>,
+    RatingsEnvelope = Envelope<Vec<RoleRating>>,
+    SearchEnvelope = Envelope<SearchPage>,
+    ProgramsEnvelope = Envelope<Vec<ProgramInfo>>,
+    AnalyzersEnvelope = Envelope<Vec<ImplementationInfo>>,
+    MetricsEnvelope = Envelope<LoadMetrics>
+)]
+pub struct Envelope<T> {
+    /// Body of a successful response.
+    data: Option<T>,
+    /// Description of why the request failed.
+    error: Option<EnvelopeError>,
+    meta: EnvelopeMeta,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct EnvelopeError {
+    status: u16,
+    message: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct EnvelopeMeta {
+    version: &'static str,
+    request_id: Option<String>,
+    /// Time taken to handle the request, in milliseconds.
+    duration_ms: f64,
+}
+
+/// Middleware wrapping the body of every JSON or empty response in an [`Envelope`]. Other
+/// responses, such as event streams, are passed through unchanged.
+pub async fn wrap_in_envelope(request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(|id| id.0.clone());
+
+    let response = next.run(request).await;
+
+    let content_type = response.headers().get(header::CONTENT_TYPE);
+    let is_json = content_type.is_some_and(|t| t.as_bytes().starts_with(b"application/json"));
+    if content_type.is_some() && !is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let body = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => {
+            tracing::error!("Failed to read response body: {e}");
+            parts.status = StatusCode::INTERNAL_SERVER_ERROR;
+            axum::body::Bytes::new()
+        }
+    };
+
+    let value = if body.is_empty() {
+        None
+    } else if is_json {
+        serde_json::from_slice(&body).ok()
+    } else {
+        Some(serde_json::Value::String(
+            String::from_utf8_lossy(&body).into_owned(),
+        ))
+    };
+
+    let (data, error) = if parts.status.is_success() {
+        (value, None)
+    } else {
+        let message = match value {
+            Some(serde_json::Value::String(message)) => message,
+            Some(value) => value.to_string(),
+            None => parts
+                .status
+                .canonical_reason()
+                .unwrap_or_default()
+                .to_owned(),
+        };
+        let error = EnvelopeError {
+            status: parts.status.as_u16(),
+            message,
+        };
+        (None, Some(error))
+    };
+
+    let envelope = Envelope::<serde_json::Value> {
+        data,
+        error,
+        meta: EnvelopeMeta {
+            version: API_VERSION,
+            request_id,
+            duration_ms: start.elapsed().as_secs_f64() * 1000.0,
+        },
+    };
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    (parts, Json(envelope)).into_response()
+}
+
+/// Header carrying the ID correlating a request with the logs it produces.
+pub static REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// ID correlating an API request with the log messages it produces.
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+/// Returns the request ID supplied by a caller if it is well-formed, or a newly generated ID.
+pub fn request_id_or_new(supplied: Option<&str>) -> String {
+    supplied
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= 64
+                && id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        })
+        .map_or_else(
+            || format!("{:016x}", rand::random::<u64>()),
+            ToOwned::to_owned,
+        )
+}
+
+/// Middleware which assigns every request an ID, made available to handlers as a [`RequestId`]
+/// extension and returned in the `X-Request-Id` response header. A well-formed ID supplied by
+/// the caller in the same header is used instead of generating a new one.
+pub async fn assign_request_id(mut request: Request, next: Next) -> Response {
+    let request_id = request_id_or_new(
+        request
+            .headers()
+            .get(&REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok()),
+    );
+
+    tracing::info!(
+        "{} {} (request {request_id})",
+        request.method(),
+        request.uri().path()
+    );
+    request
+        .extensions_mut()
+        .insert(RequestId(request_id.clone()));
+
+    let mut response = next.run(request).await;
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response
+            .headers_mut()
+            .insert(REQUEST_ID_HEADER.clone(), value);
+    }
+    response
+}
+
+/// Permissions which can be granted to an API key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Starting and cancelling program runs.
+    Analyze,
+
+    /// Reading jobs, stored results, sessions, and programs.
+    ReadResults,
+
+    /// Every operation, including administrative ones.
+    Admin,
+}
+
+impl Scope {
+    fn from_str(scope: &str) -> Option<Self> {
+        match scope {
+            "analyze" => Some(Scope::Analyze),
+            "read_results" => Some(Scope::ReadResults),
+            "admin" => Some(Scope::Admin),
+            _ => None,
+        }
+    }
+
+    /// Returns whether a key with this scope is permitted to perform operations requiring
+    /// `required`.
+    fn grants(self, required: Scope) -> bool {
+        self == Scope::Admin || self == required
+    }
+}
+
+/// Middleware requiring the [`Scope::Analyze`] scope.
+pub async fn require_analyze(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    authorize(&state, Scope::Analyze, request, next).await
+}
+
+/// Middleware requiring the [`Scope::ReadResults`] scope.
+pub async fn require_read_results(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    authorize(&state, Scope::ReadResults, request, next).await
+}
+
+/// Middleware requiring the [`Scope::Admin`] scope.
+pub async fn require_admin(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    authorize(&state, Scope::Admin, request, next).await
+}
+
+/// Validates the bearer token in a request's `Authorization` header against the API keys stored
+/// in the database, and only forwards the request if the key grants the `required` scope.
+async fn authorize(
+    state: &AppState,
+    required: Scope,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let authorization = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    check_api_key(state, authorization, required).await?;
+    Ok(next.run(request).await)
+}
+
+/// Checks that the bearer token in an `Authorization` header value belongs to an API key which
+/// grants the `required` scope.
+///
+/// Every request is allowed if authentication was explicitly disabled. Otherwise, keys live in
+/// the database, so every request is rejected when running without one.
+pub(crate) async fn check_api_key(
+    state: &AppState,
+    authorization: Option<&str>,
+    required: Scope,
+) -> Result<(), StatusCode> {
+    if state.auth_disabled {
+        return Ok(());
+    }
+    let Some(pool) = &state.database_pool else {
+        return Err(StatusCode::UNAUTHORIZED);
+    };
+
+    let key = authorization
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let key_hash = Sha256::digest(key.trim().as_bytes()).to_vec();
+
+    let scopes: Option<(Vec<String>,)> =
+        sqlx::query_as("SELECT scopes FROM api_keys WHERE key_hash = $1 AND revoked_at IS NULL")
+            .bind(key_hash)
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to look up API key: {e:?}");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+
+    let (scopes,) = scopes.ok_or(StatusCode::UNAUTHORIZED)?;
+    let authorized = scopes
+        .iter()
+        .filter_map(|scope| Scope::from_str(scope))
+        .any(|scope| scope.grants(required));
+
+    if authorized {
+        Ok(())
+    } else {
+        Err(StatusCode::FORBIDDEN)
+    }
+}
+
+/// Returns the OpenAPI description of the API.
+pub async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Index of the published output schemas.
+#[derive(Debug, Serialize)]
+pub struct SchemaIndex {
+    version: &'static str,
+    schemas: Vec<&'static str>,
+}
+
+/// Lists the names of the JSON Schemas describing the engine's outputs.
+pub async fn list_schemas() -> Json<SchemaIndex> {
+    Json(SchemaIndex {
+        version: API_VERSION,
+        schemas: schemas::all(API_VERSION).into_keys().collect(),
+    })
+}
+
+/// Returns a JSON Schema describing one of the engine's outputs.
+pub async fn get_schema(
+    Path(name): Path<String>,
+) -> Result<Json<schemars::schema::RootSchema>, StatusCode> {
+    schemas::all(API_VERSION)
+        .remove(name.as_str())
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct AnalyzeRequest {
-    program: String,
+    /// Program to run. If unset, the default program for the challenge's type is used.
+    program: Option<String>,
+    /// Additional programs to run on the same challenge, each as its own job.
+    #[serde(default)]
+    additional_programs: Vec<String>,
     uuid: String,
+    /// HTTPS URL to which a summary of the run is POSTed once it finishes and its results are
+    /// stored. Local and private addresses are rejected.
+    callback_url: Option<String>,
+    /// Whether to wait for the run to finish and return its results in the response.
+    #[serde(default)]
+    wait: bool,
+    /// Players to restrict the analysis to. If unset, the whole party is analyzed.
+    players: Option<Vec<String>>,
+    /// Stages to restrict the analysis to, e.g. `TOB_NYLOCAS`. If unset, every recorded stage is
+    /// analyzed. Analyzers which need stages outside the scope fail.
+    stages: Option<Vec<String>>,
+    /// Level of analysis to perform, basic by default. Deeper levels may be downgraded or
+    /// deferred while the engine is under load.
+    level: Option<Level>,
+    /// Whether to attach to or reject an identical run which is already in progress.
+    #[serde(default)]
+    on_duplicate: DuplicatePolicy,
+    /// Priority of the run. Bulk runs, such as backfills, only use workers not needed by
+    /// interactive runs.
+    #[serde(default)]
+    priority: Priority,
+    /// Whether to run the program even if the same version of it has already analyzed the
+    /// challenge. Otherwise, the stored results of that run are returned.
+    #[serde(default)]
+    force: bool,
+    /// Locale in which to write the messages of findings, e.g. `pt-BR`. Defaults to English.
+    locale: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalyzeResponse {
+    /// ID of the job running the program. Unset if stored results were returned instead.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    job_id: Option<u32>,
+    /// Jobs running the request's additional programs, in the order they were requested.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    additional_job_ids: Vec<u32>,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    result: Option<ProgramResult>,
+    /// Whether the results are those of an earlier run of the same program version on the
+    /// challenge, rather than of a new job.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    cached: bool,
+}
+
+impl AnalyzeResponse {
+    fn cached(result: ProgramResult) -> Self {
+        Self {
+            job_id: None,
+            additional_job_ids: Vec::new(),
+            result: Some(result),
+            cached: true,
+        }
+    }
 }
 
+#[utoipa::path(
+    post,
+    path = "/analyze",
+    request_body = AnalyzeRequest,
+    responses(
+        (status = 200, description = "Program run started", body = AnalyzeEnvelope),
+        (status = 400, description = "Invalid request or no applicable program"),
+        (status = 404, description = "Challenge not found"),
+        (status = 409, description = "An identical run is already in progress"),
+        (status = 429, description = "Too many program runs are in progress or queued"),
+        (status = 503, description = "Challenge data temporarily unavailable")
+    )
+)]
 pub async fn analyze(
     State(state): State<Arc<AppState>>,
+    request_id: Option<Extension<RequestId>>,
     Json(request): Json<AnalyzeRequest>,
-) -> Result<String, StatusCode> {
+) -> Result<Json<AnalyzeResponse>, StatusCode> {
     let uuid = Uuid::from_str(&request.uuid).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let stages = match &request.stages {
+        Some(stages) => Some(
+            stages
+                .iter()
+                .map(|stage| blert::Stage::from_str_name(stage))
+                .collect::<Option<Vec<_>>>()
+                .ok_or(StatusCode::BAD_REQUEST)?,
+        ),
+        None => None,
+    };
+
+    let submission = AnalysisRequest {
+        uuid,
+        program: request.program,
+        additional_programs: request.additional_programs,
+        callback_url: request.callback_url,
+        players: request.players,
+        stages,
+        level: request.level.unwrap_or(Level::Basic),
+        on_duplicate: request.on_duplicate,
+        priority: request.priority,
+        force: request.force,
+        locale: request.locale,
+        request_id: request_id.map(|Extension(RequestId(id))| id),
+    };
+    let mut handles = match submission::submit(&state, &submission).await {
+        Ok(Submission::Cached(result)) => return Ok(Json(AnalyzeResponse::cached(result))),
+        Ok(Submission::Started(handles)) => handles,
+        Err(SubmitError::Load(e)) if e.is_retryable() => {
+            return Err(StatusCode::SERVICE_UNAVAILABLE)
+        }
+        Err(SubmitError::Load(_)) => return Err(StatusCode::NOT_FOUND),
+        Err(SubmitError::NoDefaultProgram) => return Err(StatusCode::BAD_REQUEST),
+        Err(SubmitError::Run(Error::AlreadyRunning(_))) => return Err(StatusCode::CONFLICT),
+        Err(SubmitError::Run(Error::Busy)) => return Err(StatusCode::TOO_MANY_REQUESTS),
+        Err(SubmitError::Run(Error::Unavailable(_))) => {
+            return Err(StatusCode::SERVICE_UNAVAILABLE)
+        }
+        Err(SubmitError::Run(_)) => return Err(StatusCode::BAD_REQUEST),
+    };
+    let additional_job_ids = handles
+        .iter()
+        .skip(1)
+        .map(ProgramRunHandle::run_number)
+        .collect();
+    let handle = handles.swap_remove(0);
+    let job_id = handle.run_number();
+
+    let result = if request.wait {
+        let result = handle.wait().await.map_err(|e| {
+            tracing::error!("Failed to wait for job {job_id}: {e:?}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        Some(result)
+    } else {
+        None
+    };
+
+    Ok(Json(AnalyzeResponse {
+        job_id: Some(job_id),
+        additional_job_ids,
+        result,
+        cached: false,
+    }))
+}
+
+/// Maximum size of a challenge bundle uploaded to [`analyze_raw`].
+pub const MAX_RAW_BUNDLE_BYTES: usize = 64 * 1024 * 1024;
+
+/// Runs a program on challenge data uploaded in the request and returns its results, without
+/// reading from the database or data repository. Results are not stored.
+///
+/// The body is a multipart form with a `challenge` part containing an encoded `ChallengeData`
+/// proto, and a `stage` part containing an encoded `ChallengeEvents` proto for each recorded
+/// stage. Optional `program` and `level` text parts select what to run.
+#[utoipa::path(
+    post,
+    path = "/analyze/raw",
+    request_body(content_type = "multipart/form-data", description = "Challenge proto bundle"),
+    responses(
+        (status = 200, description = "Program completed", body = ProgramResultEnvelope),
+        (status = 400, description = "Malformed bundle or unknown program"),
+        (status = 413, description = "Bundle is too large"),
+        (status = 429, description = "Too many program runs in progress")
+    )
+)]
+pub async fn analyze_raw(
+    State(state): State<Arc<AppState>>,
+    request_id: Option<Extension<RequestId>>,
+    mut multipart: Multipart,
+) -> Result<Json<ProgramResult>, StatusCode> {
+    // Uploaded challenges do not exist elsewhere, so they are given a random ID.
+    let uuid = Uuid::new_v4();
+    let mut challenge = None;
+    let mut stages = Vec::new();
+    let mut program = None;
+    let mut level = Level::Basic;
+
+    while let Some(field) = multipart.next_field().await.map_err(|e| e.status())? {
+        let name = field.name().unwrap_or_default().to_owned();
+        let contents = field.bytes().await.map_err(|e| e.status())?;
+
+        match name.as_str() {
+            "challenge" => {
+                challenge = Some(
+                    blert::ChallengeData::decode(contents).map_err(|_| StatusCode::BAD_REQUEST)?,
+                );
+            }
+            "stage" => {
+                stages.push(
+                    blert::ChallengeEvents::decode(contents)
+                        .map_err(|_| StatusCode::BAD_REQUEST)?,
+                );
+            }
+            "program" => {
+                program = Some(
+                    String::from_utf8(contents.to_vec()).map_err(|_| StatusCode::BAD_REQUEST)?,
+                );
+            }
+            "level" => {
+                level = serde_json::from_value(serde_json::Value::String(
+                    String::from_utf8_lossy(&contents).into_owned(),
+                ))
+                .map_err(|_| StatusCode::BAD_REQUEST)?;
+            }
+            _ => return Err(StatusCode::BAD_REQUEST),
+        }
+    }
+
+    // Uploads are only stored once they are known to be of a supported type of challenge and to
+    // contain only its stages.
+    let challenge_data = challenge.ok_or(StatusCode::BAD_REQUEST)?;
+    let supported =
+        challenge::supported_stages(challenge_data.r#type()).ok_or(StatusCode::BAD_REQUEST)?;
+    if !supported.contains(&challenge_data.stage())
+        || stages
+            .iter()
+            .any(|events| !supported.contains(&events.stage()))
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let backend = MemoryBackend::new();
+    backend.insert_challenge(uuid, &challenge_data);
+    for events in &stages {
+        backend.insert_stage_events(uuid, events);
+    }
+
+    let repository = DataRepository::new(Box::new(backend));
+    let challenge = Challenge::load_from_repository(&repository, uuid)
+        .await
+        .map_err(|e| {
+            tracing::debug!("Failed to load uploaded challenge: {e}");
+            StatusCode::BAD_REQUEST
+        })?;
+
+    let engine = &state.analysis_engine;
+    let program = match program {
+        Some(program) => program,
+        None => engine
+            .default_program(challenge.r#type(), challenge.mode())
+            .ok_or(StatusCode::BAD_REQUEST)?
+            .to_owned(),
+    };
+
+    let options = RunOptions {
+        level,
+        request_id: request_id.map(|Extension(RequestId(id))| id),
+        ephemeral: true,
+        ..RunOptions::default()
+    };
+    let handle = engine
+        .run_program(&program, Arc::new(challenge), options)
+        .map_err(|e| match e {
+            Error::Busy => StatusCode::TOO_MANY_REQUESTS,
+            Error::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::BAD_REQUEST,
+        })?;
+
+    let job_id = handle.run_number();
+    let result = handle.wait().await.map_err(|e| {
+        tracing::error!("Failed to wait for job {job_id}: {e:?}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(result))
+}
+
+/// Returns the status of a program run started through [`analyze`].
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}",
+    params(("id" = u32, Path, description = "ID of the job")),
+    responses(
+        (status = 200, description = "Current status of the job", body = JobEnvelope),
+        (status = 404, description = "Unknown job")
+    )
+)]
+pub async fn get_job(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<u32>,
+) -> Result<Json<Job>, StatusCode> {
+    state.jobs.get(id).map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Streams the progress of a program run as server-sent events, ending once the run finishes.
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}/events",
+    params(("id" = u32, Path, description = "ID of the job")),
+    responses(
+        (status = 200, description = "Stream of job progress events", content_type = "text/event-stream"),
+        (status = 404, description = "Unknown job")
+    )
+)]
+pub async fn job_events(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<u32>,
+) -> Result<Sse<impl Stream<Item = Result<sse::Event, Infallible>>>, StatusCode> {
+    let receiver = state.jobs.subscribe(id).ok_or(StatusCode::NOT_FOUND)?;
+
+    let events = stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    let sse_event = sse::Event::default()
+                        .event(event.name())
+                        .json_data(&event)
+                        .ok()?;
+                    return Some((Ok(sse_event), receiver));
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Subscriber to job {id} missed {skipped} events");
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct AnalysisQuery {
+    program: Option<String>,
+    analyzer: Option<String>,
+}
+
+/// Returns the stored results of every analysis program run on a challenge, optionally filtered
+/// to a specific program or analyzer.
+#[utoipa::path(
+    get,
+    path = "/analysis/{uuid}",
+    params(("uuid" = Uuid, Path, description = "UUID of the challenge"), AnalysisQuery),
+    responses(
+        (status = 200, description = "Stored program runs, most recent first", body = StoredRunsEnvelope),
+        (status = 400, description = "Invalid challenge UUID"),
+        (status = 404, description = "No stored results"),
+        (status = 503, description = "Results persistence is not configured")
+    )
+)]
+pub async fn get_analysis(
+    State(state): State<Arc<AppState>>,
+    Path(uuid): Path<String>,
+    Query(query): Query<AnalysisQuery>,
+) -> Result<Json<Vec<StoredRun>>, StatusCode> {
+    let uuid = Uuid::from_str(&uuid).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let filter = ResultsFilter {
+        program: query.program.as_deref(),
+        analyzer: query.analyzer.as_deref(),
+    };
+
+    let results = state
+        .results
+        .as_ref()
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+
+    let runs = results.load_results(uuid, &filter).await.map_err(|e| {
+        tracing::error!("Failed to load results for challenge {uuid}: {e:?}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if runs.is_empty() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(Json(runs))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ReportQuery {
+    /// Program whose latest run to report on. Defaults to the latest run of any program.
+    program: Option<String>,
+    /// Level of detail of the reports. Defaults to the level of the run.
+    #[param(inline)]
+    level: Option<Level>,
+    /// Only report on this player.
+    player: Option<String>,
+    /// Locale in which to write the reports, e.g. `pt-BR`. Defaults to English.
+    locale: Option<String>,
+}
+
+/// Returns human-readable reports on each player's performance, built from the latest completed
+/// or partial program run on a challenge.
+#[utoipa::path(
+    get,
+    path = "/analysis/{uuid}/report",
+    params(("uuid" = Uuid, Path, description = "UUID of the challenge"), ReportQuery),
+    responses(
+        (status = 200, description = "Reports ordered by username", body = PlayerReportsEnvelope),
+        (status = 400, description = "Invalid challenge UUID"),
+        (status = 404, description = "No stored results or unknown player"),
+        (status = 503, description = "Results persistence is not configured")
+    )
+)]
+pub async fn get_player_reports(
+    State(state): State<Arc<AppState>>,
+    Path(uuid): Path<String>,
+    Query(query): Query<ReportQuery>,
+) -> Result<Json<Vec<PlayerReport>>, StatusCode> {
+    let uuid = Uuid::from_str(&uuid).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let results = state
+        .results
+        .as_ref()
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+
+    let filter = ResultsFilter {
+        program: query.program.as_deref(),
+        analyzer: None,
+    };
+    let runs = results.load_results(uuid, &filter).await.map_err(|e| {
+        tracing::error!("Failed to load results for challenge {uuid}: {e:?}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let run = runs
+        .iter()
+        .find(|run| {
+            run.status == RunStatus::Completed.as_str() || run.status == RunStatus::Partial.as_str()
+        })
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let level = query
+        .level
+        .or_else(|| serde_json::from_value(serde_json::Value::String(run.level.clone())).ok())
+        .unwrap_or(Level::Basic);
+
+    // Outputs are stored under the analyzer's name within the program, which usually matches its
+    // implementation.
+    let outputs = run
+        .outputs
+        .iter()
+        .map(|(name, output)| {
+            let implementation = state
+                .analysis_engine
+                .analyzer_implementation(&run.program, name)
+                .unwrap_or(name.as_str());
+            (implementation, output)
+        })
+        .collect();
+
+    let challenge_type = results.load_challenge_type(uuid).await.map_err(|e| {
+        tracing::error!("Failed to load challenge {uuid}: {e:?}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let links = challenge_type.map(|challenge_type| {
+        state
+            .analysis_engine
+            .replay_links()
+            .challenge(challenge_type, uuid)
+    });
+
+    let localizer = state
+        .analysis_engine
+        .message_catalog()
+        .localizer(query.locale.as_deref());
+    let mut reports = report::build_reports(&outputs, level, &localizer, links.as_ref());
+    if let Some(player) = &query.player {
+        reports.retain(|report| report.username.eq_ignore_ascii_case(player));
+        if reports.is_empty() {
+            return Err(StatusCode::NOT_FOUND);
+        }
+    }
+
+    Ok(Json(reports))
+}
 
-    let challenge = Challenge::load(&state.database_pool, &state.data_repository, uuid)
+/// Requests cancellation of a running program.
+#[utoipa::path(
+    post,
+    path = "/jobs/{id}/cancel",
+    params(("id" = u32, Path, description = "ID of the job")),
+    responses(
+        (status = 202, description = "Cancellation requested"),
+        (status = 404, description = "Unknown job"),
+        (status = 409, description = "Job has already finished")
+    )
+)]
+pub async fn cancel_job(State(state): State<Arc<AppState>>, Path(id): Path<u32>) -> StatusCode {
+    match state.jobs.cancel(id) {
+        Ok(()) => StatusCode::ACCEPTED,
+        Err(CancelError::NotFound) => StatusCode::NOT_FOUND,
+        Err(CancelError::AlreadyFinished) => StatusCode::CONFLICT,
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ReanalyzeRequest {
+    /// Maximum number of challenges to reanalyze.
+    #[serde(default = "ReanalyzeRequest::default_limit")]
+    limit: usize,
+}
+
+impl ReanalyzeRequest {
+    fn default_limit() -> usize {
+        100
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReanalyzeResponse {
+    /// Jobs started to reanalyze challenges.
+    job_ids: Vec<u32>,
+}
+
+/// Starts bulk runs reanalyzing challenges whose stored results were produced by outdated
+/// analyzer versions.
+#[utoipa::path(
+    post,
+    path = "/maintenance/reanalyze-outdated",
+    request_body = ReanalyzeRequest,
+    responses(
+        (status = 200, description = "Reanalysis runs started", body = ReanalyzeEnvelope),
+        (status = 503, description = "Database or results persistence is not configured")
+    )
+)]
+pub async fn reanalyze_outdated(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<ReanalyzeRequest>,
+) -> Result<Json<ReanalyzeResponse>, StatusCode> {
+    let pool = state
+        .database_pool
+        .as_ref()
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+
+    let job_ids = state
+        .analysis_engine
+        .requeue_outdated(pool, &state.data_repository, request.limit)
         .await
-        .map_err(|_| StatusCode::NOT_FOUND)?;
+        .map_err(|e| match e {
+            Error::FailedPrecondition(_) | Error::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            e => {
+                tracing::error!("Failed to requeue outdated analyses: {e:?}");
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
 
+    Ok(Json(ReanalyzeResponse { job_ids }))
+}
+
+/// Starts reanalyzing historical challenges matching the request's filters in the background.
+#[utoipa::path(
+    post,
+    path = "/maintenance/backfill",
+    request_body = BackfillRequest,
+    responses(
+        (status = 202, description = "Backfill started", body = BackfillEnvelope),
+        (status = 400, description = "Invalid filter, program, or concurrency"),
+        (status = 503, description = "Database is not configured")
+    )
+)]
+pub async fn start_backfill(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<BackfillRequest>,
+) -> Result<(StatusCode, Json<BackfillProgress>), StatusCode> {
+    let progress = state
+        .backfills
+        .start(state.clone(), request)
+        .map_err(|e| match e {
+            Error::FailedPrecondition(_) => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::BAD_REQUEST,
+        })?;
+    Ok((StatusCode::ACCEPTED, Json(progress)))
+}
+
+/// Returns the progress of a backfill.
+#[utoipa::path(
+    get,
+    path = "/maintenance/backfill/{id}",
+    params(("id" = u32, Path, description = "ID of the backfill")),
+    responses(
+        (status = 200, description = "Backfill progress", body = BackfillEnvelope),
+        (status = 404, description = "Unknown backfill")
+    )
+)]
+pub async fn get_backfill(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<u32>,
+) -> Result<Json<BackfillProgress>, StatusCode> {
     state
+        .backfills
+        .get(id)
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Stops a backfill from starting further program runs.
+#[utoipa::path(
+    post,
+    path = "/maintenance/backfill/{id}/cancel",
+    params(("id" = u32, Path, description = "ID of the backfill")),
+    responses(
+        (status = 202, description = "Cancellation requested"),
+        (status = 404, description = "Unknown backfill")
+    )
+)]
+pub async fn cancel_backfill(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<u32>,
+) -> StatusCode {
+    if state.backfills.cancel(id) {
+        StatusCode::ACCEPTED
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+/// Checks that every file of a challenge in the data repository exists, matches its stored
+/// checksum, and decodes.
+#[utoipa::path(
+    get,
+    path = "/maintenance/verify/{uuid}",
+    params(("uuid" = Uuid, Path, description = "UUID of the challenge")),
+    responses(
+        (status = 200, description = "Status of each of the challenge's files", body = VerificationEnvelope),
+        (status = 400, description = "Invalid challenge UUID")
+    )
+)]
+pub async fn verify_challenge(
+    State(state): State<Arc<AppState>>,
+    Path(uuid): Path<String>,
+) -> Result<Json<ChallengeVerification>, StatusCode> {
+    let uuid = Uuid::from_str(&uuid).map_err(|_| StatusCode::BAD_REQUEST)?;
+    Ok(Json(state.data_repository.verify_challenge(uuid).await))
+}
+
+/// Returns the practice session to which a challenge belongs.
+#[utoipa::path(
+    get,
+    path = "/sessions/{uuid}",
+    params(("uuid" = Uuid, Path, description = "UUID of the challenge")),
+    responses(
+        (status = 200, description = "Session containing the challenge", body = SessionEnvelope),
+        (status = 400, description = "Invalid challenge UUID"),
+        (status = 404, description = "Challenge is not part of a session"),
+        (status = 503, description = "Results persistence is not configured")
+    )
+)]
+pub async fn get_session(
+    State(state): State<Arc<AppState>>,
+    Path(uuid): Path<String>,
+) -> Result<Json<Session>, StatusCode> {
+    let uuid = Uuid::from_str(&uuid).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let results = state
+        .results
+        .as_ref()
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+
+    let session = results.load_session(uuid).await.map_err(|e| {
+        tracing::error!("Failed to load session for challenge {uuid}: {e:?}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    session.map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Returns trends in a player's performance across their recent analyzed challenges.
+#[utoipa::path(
+    get,
+    path = "/players/{name}/trends",
+    params(("name" = String, Path, description = "Username of the player"), TrendsRequest),
+    responses(
+        (status = 200, description = "Trends over the player's recent challenges", body = TrendsEnvelope),
+        (status = 404, description = "No analyzed challenges for the player"),
+        (status = 503, description = "Results persistence is not configured")
+    )
+)]
+pub async fn get_player_trends(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(request): Query<TrendsRequest>,
+) -> Result<Json<PlayerTrends>, StatusCode> {
+    let results = state
+        .results
+        .as_ref()
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+
+    let trends = trends::player_trends(results, &state.analysis_engine, &name, &request)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to compute trends for player {name}: {e:?}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    if trends.challenges == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(Json(trends))
+}
+
+/// Returns a player's current skill rating in each role they have played.
+#[utoipa::path(
+    get,
+    path = "/players/{name}/ratings",
+    params(("name" = String, Path, description = "Username of the player")),
+    responses(
+        (status = 200, description = "Ratings by role, highest first", body = RatingsEnvelope),
+        (status = 404, description = "Player has not been rated"),
+        (status = 503, description = "Results persistence is not configured")
+    )
+)]
+pub async fn get_player_ratings(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<RoleRating>>, StatusCode> {
+    let results = state
+        .results
+        .as_ref()
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+
+    let ratings = results.load_ratings(&name).await.map_err(|e| {
+        tracing::error!("Failed to load ratings for player {name}: {e:?}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    if ratings.is_empty() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(Json(ratings))
+}
+
+/// Searches for challenges by their properties and by metrics derived from their analysis.
+#[utoipa::path(
+    get,
+    path = "/challenges/search",
+    params(SearchFilter),
+    responses(
+        (status = 200, description = "Matching challenges, most recent first", body = SearchEnvelope),
+        (status = 400, description = "Invalid filter"),
+        (status = 503, description = "Results persistence is not configured")
+    )
+)]
+pub async fn search_challenges(
+    State(state): State<Arc<AppState>>,
+    Query(filter): Query<SearchFilter>,
+) -> Result<Json<SearchPage>, StatusCode> {
+    let results = state
+        .results
+        .as_ref()
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+
+    let role_analyzers = state
         .analysis_engine
-        .lock()
-        .unwrap()
-        .run_program(&request.program, analysis::Level::Basic, challenge)
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
+        .analyzers_implementing("TobRoleAnalyzer");
+    let page = results
+        .search_challenges(&filter, &role_analyzers)
+        .await
+        .map_err(|e| match e {
+            Error::InvalidField(_) => StatusCode::BAD_REQUEST,
+            e => {
+                tracing::error!("Failed to search challenges: {e:?}");
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    Ok(Json(page))
+}
+
+/// Exports the outputs of a program's runs as a table with one row per player per challenge.
+#[utoipa::path(
+    get,
+    path = "/export/results",
+    params(ExportRequest),
+    responses(
+        (status = 200, description = "CSV or Parquet file of results", content_type = "text/csv"),
+        (status = 400, description = "Invalid filter"),
+        (status = 503, description = "Results persistence is not configured")
+    )
+)]
+pub async fn export_results(
+    State(state): State<Arc<AppState>>,
+    Query(request): Query<ExportRequest>,
+) -> Result<Response, StatusCode> {
+    let results = state
+        .results
+        .as_ref()
+        .ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+
+    let mut file = Vec::new();
+    export::export_results(results, &request, &mut file)
+        .await
+        .map_err(|e| match e {
+            Error::InvalidField(_) => StatusCode::BAD_REQUEST,
+            e => {
+                tracing::error!("Failed to export results of {}: {e:?}", request.program);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    let disposition = format!(
+        r#"attachment; filename="{}.{}""#,
+        request.program,
+        request.format.extension()
+    );
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                request.format.content_type().to_owned(),
+            ),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        file,
+    )
+        .into_response())
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct StatesQuery {
+    /// File format of the export. Defaults to Parquet.
+    #[param(inline)]
+    format: Option<TableFormat>,
+}
+
+/// Exports the reconstructed state of every player on every tick of a challenge as a table.
+#[utoipa::path(
+    get,
+    path = "/challenges/{uuid}/states",
+    params(("uuid" = Uuid, Path, description = "UUID of the challenge"), StatesQuery),
+    responses(
+        (
+            status = 200,
+            description = "Parquet or CSV file with one row per player per tick",
+            content_type = "application/vnd.apache.parquet"
+        ),
+        (status = 400, description = "Invalid challenge UUID"),
+        (status = 404, description = "Challenge not found")
+    )
+)]
+pub async fn export_player_states(
+    State(state): State<Arc<AppState>>,
+    Path(uuid): Path<String>,
+    Query(query): Query<StatesQuery>,
+) -> Result<Response, StatusCode> {
+    let uuid = Uuid::from_str(&uuid).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let format = query.format.unwrap_or(TableFormat::Parquet);
+
+    let challenge = match &state.database_pool {
+        Some(pool) => Challenge::load(pool, &state.data_repository, uuid).await,
+        None => Challenge::load_from_repository(&state.data_repository, uuid).await,
+    }
+    .map_err(|e| {
+        if e.is_retryable() {
+            tracing::warn!("Failed to load challenge {uuid}: {e}");
+            StatusCode::SERVICE_UNAVAILABLE
+        } else {
+            StatusCode::NOT_FOUND
+        }
+    })?;
+
+    let pseudonymizer = match &state.results {
+        Some(results) => Some(results.load_pseudonymizer().await.map_err(|e| {
+            tracing::error!("Failed to load privacy opt-outs: {e:?}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?),
+        None => None,
+    };
+    let name = |username: &str| match &pseudonymizer {
+        Some(pseudonymizer) => pseudonymizer.name(username),
+        None => username.to_owned(),
+    };
+
+    let mut file = Vec::new();
+    export::export_player_states(&challenge, name, format, &mut file).map_err(|e| {
+        tracing::error!("Failed to export player states of {uuid}: {e:?}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let disposition = format!(
+        r#"attachment; filename="{uuid}-states.{}""#,
+        format.extension()
+    );
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_owned()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        file,
+    )
+        .into_response())
+}
+
+/// Returns every analysis program loaded by the engine.
+#[utoipa::path(
+    get,
+    path = "/programs",
+    responses((status = 200, description = "Loaded programs, ordered by name", body = ProgramsEnvelope))
+)]
+pub async fn get_programs(State(state): State<Arc<AppState>>) -> Json<Vec<ProgramInfo>> {
+    Json(state.analysis_engine.programs())
+}
+
+/// Returns every analyzer implementation available to programs.
+#[utoipa::path(
+    get,
+    path = "/analyzers",
+    responses((
+        status = 200,
+        description = "Analyzer implementations, ordered by name",
+        body = AnalyzersEnvelope
+    ))
+)]
+pub async fn get_analyzers() -> Json<Vec<ImplementationInfo>> {
+    Json(analyzers::catalog())
+}
 
-    Ok("ok".into())
+/// Returns the current load on the analysis engine and counts of runs shed due to load.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses((status = 200, description = "Current engine load", body = MetricsEnvelope))
+)]
+pub async fn get_metrics(State(state): State<Arc<AppState>>) -> Json<LoadMetrics> {
+    Json(state.analysis_engine.load_metrics())
 }
@@ -0,0 +1,488 @@
+//! Bulk reanalysis of historical challenges, used to apply analyzer changes to past raids.
+//!
+//! A backfill pages through the `challenges` table in chronological order, loading each matching
+//! challenge and running a program on it at bulk priority. A bounded number of challenges are
+//! analyzed at once so that a backfill does not crowd out interactive requests.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use sqlx::types::time::OffsetDateTime;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::analysis::{DuplicatePolicy, Level, ProgramResult, RunOptions};
+use crate::challenge::{Challenge, Status};
+use crate::dispatch::Priority;
+use crate::error::{Error, Result};
+use crate::jobs::CancellationToken;
+use crate::results::RunStatus;
+use crate::{search, AppState};
+
+/// How long to wait before retrying a run which the engine was too busy to accept.
+const BUSY_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Challenges to reanalyze and how to analyze them.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BackfillRequest {
+    /// Program to run. If unset, the default program for each challenge's type is used.
+    pub program: Option<String>,
+    /// Level of analysis to perform.
+    #[serde(default = "BackfillRequest::default_level")]
+    pub level: Level,
+    /// Challenge type to reanalyze, e.g. `tob`.
+    pub r#type: Option<String>,
+    /// Challenge mode to reanalyze, e.g. `tob_hard`.
+    pub mode: Option<String>,
+    /// Only reanalyze challenges which started at or after this Unix timestamp.
+    pub since: Option<i64>,
+    /// Only reanalyze challenges which started before this Unix timestamp.
+    pub until: Option<i64>,
+    /// Maximum number of challenges to analyze at once.
+    #[serde(default = "BackfillRequest::default_concurrency")]
+    pub concurrency: usize,
+    /// Maximum number of challenges to reanalyze. If unset, every matching challenge is.
+    pub limit: Option<u64>,
+}
+
+impl BackfillRequest {
+    const PAGE_SIZE: i64 = 100;
+    const MAX_CONCURRENCY: usize = 32;
+
+    fn default_level() -> Level {
+        Level::Basic
+    }
+
+    fn default_concurrency() -> usize {
+        4
+    }
+
+    /// Converts the request's filters to their database representations, rejecting any which
+    /// are invalid.
+    fn filters(&self) -> Result<Filters> {
+        let timestamp = |field: &str, value: Option<i64>| {
+            value
+                .map(|v| {
+                    OffsetDateTime::from_unix_timestamp(v)
+                        .map_err(|_| Error::InvalidField(field.into()))
+                })
+                .transpose()
+        };
+
+        Ok(Filters {
+            r#type: self.r#type.as_deref().map(search::parse_type).transpose()?,
+            mode: self.mode.as_deref().map(search::parse_mode).transpose()?,
+            since: timestamp("since", self.since)?,
+            until: timestamp("until", self.until)?,
+        })
+    }
+}
+
+struct Filters {
+    r#type: Option<i16>,
+    mode: Option<i16>,
+    since: Option<OffsetDateTime>,
+    until: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BackfillStatus {
+    Running,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+/// Progress of a backfill.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BackfillProgress {
+    pub id: u32,
+    pub status: BackfillStatus,
+    /// Matching challenges found so far.
+    pub scanned: u64,
+    /// Challenges whose program run finished successfully.
+    pub succeeded: u64,
+    /// Challenges which failed to load or whose program run failed.
+    pub failed: u64,
+    /// Challenges without a program to run or without data in the repository.
+    pub skipped: u64,
+    /// Why the backfill stopped early, if it failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+struct Backfill {
+    request: BackfillRequest,
+    progress: Mutex<BackfillProgress>,
+    cancellation: CancellationToken,
+}
+
+impl Backfill {
+    fn update(&self, f: impl FnOnce(&mut BackfillProgress)) {
+        f(&mut self.progress.lock().unwrap());
+    }
+}
+
+/// Tracks the backfills started since the service began.
+#[derive(Default)]
+pub struct Registry {
+    next_id: AtomicU32,
+    backfills: Mutex<HashMap<u32, Arc<Backfill>>>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a backfill in the background, returning its initial progress.
+    pub fn start(
+        &self,
+        state: Arc<AppState>,
+        request: BackfillRequest,
+    ) -> Result<BackfillProgress> {
+        if state.database_pool.is_none() {
+            return Err(Error::FailedPrecondition(
+                "Backfills require a database".into(),
+            ));
+        }
+        if request.concurrency == 0 || request.concurrency > BackfillRequest::MAX_CONCURRENCY {
+            return Err(Error::InvalidField("concurrency".into()));
+        }
+        if let Some(program) = &request.program {
+            if state.analysis_engine.program_version(program).is_none() {
+                return Err(Error::InvalidField("program".into()));
+            }
+        }
+        let filters = request.filters()?;
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let progress = BackfillProgress {
+            id,
+            status: BackfillStatus::Running,
+            scanned: 0,
+            succeeded: 0,
+            failed: 0,
+            skipped: 0,
+            error: None,
+        };
+        let backfill = Arc::new(Backfill {
+            request,
+            progress: Mutex::new(progress.clone()),
+            cancellation: CancellationToken::default(),
+        });
+        self.backfills.lock().unwrap().insert(id, backfill.clone());
+
+        tokio::spawn(async move {
+            let status = match run(&state, &backfill, &filters).await {
+                Ok(()) if backfill.cancellation.is_cancelled() => BackfillStatus::Cancelled,
+                Ok(()) => BackfillStatus::Completed,
+                Err(e) => {
+                    tracing::error!("Backfill {id} failed: {e:?}");
+                    backfill.update(|p| p.error = Some(e.to_string()));
+                    BackfillStatus::Failed
+                }
+            };
+            backfill.update(|p| p.status = status);
+
+            let progress = backfill.progress.lock().unwrap().clone();
+            tracing::info!(
+                "Backfill {id} finished: {} succeeded, {} failed, {} skipped",
+                progress.succeeded,
+                progress.failed,
+                progress.skipped,
+            );
+        });
+
+        tracing::info!("Started backfill {id}");
+        Ok(progress)
+    }
+
+    /// Returns the progress of a backfill.
+    pub fn get(&self, id: u32) -> Option<BackfillProgress> {
+        let backfills = self.backfills.lock().unwrap();
+        let backfill = backfills.get(&id)?;
+        let progress = backfill.progress.lock().unwrap().clone();
+        Some(progress)
+    }
+
+    /// Requests that a backfill stop. Program runs already started are allowed to finish.
+    /// Returns `false` if the backfill does not exist.
+    pub fn cancel(&self, id: u32) -> bool {
+        match self.backfills.lock().unwrap().get(&id) {
+            Some(backfill) => {
+                backfill.cancellation.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Pages through every challenge matching the backfill's filters, analyzing each page with
+/// bounded concurrency.
+async fn run(state: &AppState, backfill: &Backfill, filters: &Filters) -> Result<()> {
+    let pool = state
+        .database_pool
+        .as_ref()
+        .ok_or_else(|| Error::FailedPrecondition("Backfills require a database".into()))?;
+    let finished = [Status::Completed, Status::Wiped, Status::Reset].map(i16::from);
+
+    let mut remaining = backfill.request.limit.unwrap_or(u64::MAX);
+    let mut cursor: Option<(OffsetDateTime, i32)> = None;
+
+    while remaining > 0 && !backfill.cancellation.is_cancelled() {
+        let page_size = i64::try_from(remaining).map_or(BackfillRequest::PAGE_SIZE, |r| {
+            r.min(BackfillRequest::PAGE_SIZE)
+        });
+
+        let page: Vec<(i32, Uuid, OffsetDateTime)> = sqlx::query_as(
+            "
+            SELECT c.id, c.uuid, c.start_time
+            FROM challenges c
+            WHERE c.status = ANY($1)
+              AND ($2::SMALLINT IS NULL OR c.type = $2)
+              AND ($3::SMALLINT IS NULL OR c.mode = $3)
+              AND ($4::TIMESTAMPTZ IS NULL OR c.start_time >= $4)
+              AND ($5::TIMESTAMPTZ IS NULL OR c.start_time < $5)
+              AND ($6::TIMESTAMPTZ IS NULL OR (c.start_time, c.id) > ($6, $7))
+            ORDER BY c.start_time, c.id
+            LIMIT $8
+            ",
+        )
+        .bind(&finished[..])
+        .bind(filters.r#type)
+        .bind(filters.mode)
+        .bind(filters.since)
+        .bind(filters.until)
+        .bind(cursor.map(|(time, _)| time))
+        .bind(cursor.map(|(_, id)| id))
+        .bind(page_size)
+        .fetch_all(pool)
+        .await?;
+
+        let Some(&(last_id, _, last_time)) = page.last() else {
+            break;
+        };
+        cursor = Some((last_time, last_id));
+        remaining -= page.len() as u64;
+        backfill.update(|p| p.scanned += page.len() as u64);
+
+        futures::stream::iter(page)
+            .for_each_concurrent(backfill.request.concurrency, |(_, uuid, _)| {
+                analyze(state, backfill, uuid)
+            })
+            .await;
+    }
+
+    Ok(())
+}
+
+/// Loads and analyzes a single challenge, recording the outcome in the backfill's progress.
+async fn analyze(state: &AppState, backfill: &Backfill, uuid: Uuid) {
+    if backfill.cancellation.is_cancelled() {
+        return;
+    }
+
+    let Some(pool) = &state.database_pool else {
+        return;
+    };
+
+    match state.data_repository.has_challenge(uuid).await {
+        Ok(true) => {}
+        Ok(false) => {
+            backfill.update(|p| p.skipped += 1);
+            return;
+        }
+        Err(e) => {
+            tracing::warn!("Backfill failed to look up challenge {uuid}: {e}");
+            backfill.update(|p| p.failed += 1);
+            return;
+        }
+    }
+
+    let challenge = match Challenge::load(pool, &state.data_repository, uuid).await {
+        Ok(challenge) => Arc::new(challenge),
+        Err(e) => {
+            tracing::warn!("Backfill failed to load challenge {uuid}: {e}");
+            backfill.update(|p| p.failed += 1);
+            return;
+        }
+    };
+
+    let program = match &backfill.request.program {
+        Some(program) => program.as_str(),
+        None => match state
+            .analysis_engine
+            .default_program(challenge.r#type(), challenge.mode())
+        {
+            Some(program) => program,
+            None => {
+                backfill.update(|p| p.skipped += 1);
+                return;
+            }
+        },
+    };
+
+    let options = RunOptions {
+        level: backfill.request.level,
+        priority: Priority::Bulk,
+        on_duplicate: DuplicatePolicy::Attach,
+        ..RunOptions::default()
+    };
+
+    let handle = loop {
+        match state
+            .analysis_engine
+            .run_program(program, challenge.clone(), options.clone())
+        {
+            Err(Error::Busy) if !backfill.cancellation.is_cancelled() => {
+                tokio::time::sleep(BUSY_RETRY_INTERVAL).await;
+            }
+            result => break result,
+        }
+    };
+
+    match handle {
+        Ok(handle) => record_result(backfill, uuid, handle.wait().await),
+        Err(e) => {
+            tracing::warn!("Backfill failed to start run on challenge {uuid}: {e}");
+            backfill.update(|p| p.failed += 1);
+        }
+    }
+}
+
+/// Records the outcome of a challenge's program run in the backfill's progress. Only runs in
+/// which every analyzer completed count as successes.
+fn record_result(backfill: &Backfill, uuid: Uuid, result: Result<ProgramResult>) {
+    match result {
+        Ok(result) if result.status == RunStatus::Completed => {
+            backfill.update(|p| p.succeeded += 1);
+        }
+        Ok(result) => {
+            tracing::warn!(
+                "Backfill run on challenge {uuid} finished as {}",
+                result.status.as_str()
+            );
+            backfill.update(|p| p.failed += 1);
+        }
+        Err(e) => {
+            tracing::warn!("Backfill run on challenge {uuid} failed: {e}");
+            backfill.update(|p| p.failed += 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::Engine;
+    use crate::data_repository::{DataRepository, MemoryBackend};
+    use crate::synthetic::{Generator, SyntheticConfig};
+    use crate::{item, npc};
+
+    #[test]
+    fn requests_are_validated() {
+        let request: BackfillRequest =
+            serde_json::from_str(r#"{"type": "tob", "since": 1700000000}"#).unwrap();
+        assert_eq!(request.level, Level::Basic);
+        assert_eq!(request.concurrency, 4);
+
+        let filters = request.filters().unwrap();
+        assert!(filters.r#type.is_some());
+        assert!(filters.mode.is_none());
+        assert_eq!(
+            filters.since.map(OffsetDateTime::unix_timestamp),
+            Some(1_700_000_000)
+        );
+
+        let request: BackfillRequest = serde_json::from_str(r#"{"mode": "raid"}"#).unwrap();
+        assert!(matches!(request.filters(), Err(Error::InvalidField(f)) if f == "mode"));
+    }
+
+    #[tokio::test]
+    async fn failed_runs_are_not_counted_as_successes() {
+        let dir = std::env::temp_dir().join(format!("blert-backfill-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("passing.toml"),
+            r#"
+            [program]
+            name = "passing"
+
+            [analyzers.TestAnalyzer]
+            implementation = "TestAnalyzer"
+            config = { value = 1 }
+            "#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("failing.toml"),
+            r#"
+            [program]
+            name = "failing"
+
+            [analyzers.TobBloatAnalyzer]
+            implementation = "TobBloatAnalyzer"
+            "#,
+        )
+        .unwrap();
+        let engine = Engine::load_from_directory(
+            &dir,
+            item::Registry::load_from_file("resources/runescape_items.json").unwrap(),
+            npc::Registry::shared().unwrap(),
+        )
+        .await;
+        std::fs::remove_dir_all(&dir).unwrap();
+        let engine = engine.unwrap();
+
+        // The challenge's Bloat events are missing, so the Bloat analyzer fails.
+        let mut synthetic = Generator::new(SyntheticConfig {
+            stages: 2,
+            ticks_per_stage: 20,
+            ..SyntheticConfig::default()
+        })
+        .generate();
+        synthetic.stages.remove(1);
+        let backend = MemoryBackend::new();
+        synthetic.store(&backend);
+        let repository = DataRepository::new(Box::new(backend));
+        let challenge = Arc::new(
+            Challenge::load_from_repository(&repository, synthetic.uuid)
+                .await
+                .unwrap(),
+        );
+
+        let backfill = Backfill {
+            request: serde_json::from_str("{}").unwrap(),
+            progress: Mutex::new(BackfillProgress {
+                id: 1,
+                status: BackfillStatus::Running,
+                scanned: 2,
+                succeeded: 0,
+                failed: 0,
+                skipped: 0,
+                error: None,
+            }),
+            cancellation: CancellationToken::default(),
+        };
+
+        for program in ["passing", "failing"] {
+            let result = engine
+                .run_program_inline(program, challenge.clone(), Level::Basic)
+                .await;
+            record_result(&backfill, synthetic.uuid, result);
+        }
+
+        let progress = backfill.progress.lock().unwrap();
+        assert_eq!(progress.succeeded, 1);
+        assert_eq!(progress.failed, 1);
+    }
+}
@@ -0,0 +1,133 @@
+//! Auxiliary values shared between the analyzers of a program run.
+
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+
+struct Entry {
+    value: Arc<dyn Any + Send + Sync>,
+    json: serde_json::Value,
+    player_fields: &'static [&'static str],
+}
+
+/// A `Blackboard` is a map of keyed values published by analyzers during a program run.
+///
+/// It is intended for small pieces of auxiliary data which do not warrant being part of an
+/// analyzer's output, such as flags set by a precondition check. Values are read back with their
+/// original type, and their serialized form is persisted alongside the run's results, with the
+/// names of opted-out players pseudonymized like in analyzer outputs.
+#[derive(Default)]
+pub struct Blackboard {
+    entries: RwLock<HashMap<String, Entry>>,
+}
+
+impl Blackboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Publishes a value under `key`. Each key may only be published once per run.
+    ///
+    /// `player_fields` are the fields of the serialized value which hold player usernames, as
+    /// declared by [`Analyzer::player_fields`](crate::analysis::Analyzer::player_fields).
+    pub fn publish<T>(
+        &self,
+        key: &str,
+        value: T,
+        player_fields: &'static [&'static str],
+    ) -> Result<()>
+    where
+        T: Serialize + Send + Sync + 'static,
+    {
+        let json = serde_json::to_value(&value)?;
+
+        let mut entries = self.entries.write().unwrap();
+        if entries.contains_key(key) {
+            return Err(Error::FailedPrecondition(format!(
+                r#"Blackboard key "{key}" is already set"#
+            )));
+        }
+        entries.insert(
+            key.to_owned(),
+            Entry {
+                value: Arc::new(value),
+                json,
+                player_fields,
+            },
+        );
+        Ok(())
+    }
+
+    /// Returns the value published under `key`, if one exists and is of type `T`.
+    pub fn get<T: Send + Sync + 'static>(&self, key: &str) -> Option<Arc<T>> {
+        let entries = self.entries.read().unwrap();
+        entries.get(key)?.value.clone().downcast().ok()
+    }
+
+    /// Returns whether a value has been published under `key`.
+    pub fn contains(&self, key: &str) -> bool {
+        self.entries.read().unwrap().contains_key(key)
+    }
+
+    /// Returns the fields holding player usernames in every published value.
+    pub fn player_fields(&self) -> HashMap<String, &'static [&'static str]> {
+        self.entries
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(key, entry)| (key.clone(), entry.player_fields))
+            .collect()
+    }
+
+    /// Returns the serialized form of every published value.
+    pub fn to_json(&self) -> BTreeMap<String, serde_json::Value> {
+        self.entries
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(key, entry)| (key.clone(), entry.json.clone()))
+            .collect()
+    }
+}
+
+impl std::fmt::Debug for Blackboard {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_map().entries(self.to_json()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typed_values() {
+        let blackboard = Blackboard::new();
+        blackboard.publish("complete_data", true, &[]).unwrap();
+        blackboard.publish("deaths", vec![3u32, 7], &[]).unwrap();
+        blackboard
+            .publish("leader", "Alice".to_string(), &[""])
+            .unwrap();
+
+        assert_eq!(
+            blackboard.get::<bool>("complete_data").as_deref(),
+            Some(&true)
+        );
+        assert_eq!(
+            blackboard.get::<Vec<u32>>("deaths").as_deref(),
+            Some(&vec![3, 7])
+        );
+        assert!(blackboard.get::<u32>("complete_data").is_none());
+        assert!(blackboard.get::<bool>("missing").is_none());
+        assert!(blackboard.publish("complete_data", false, &[]).is_err());
+
+        let json = blackboard.to_json();
+        assert_eq!(json["complete_data"], serde_json::json!(true));
+        assert_eq!(json["deaths"], serde_json::json!([3, 7]));
+        assert_eq!(blackboard.player_fields()["leader"], &[""]);
+    }
+}
@@ -1,16 +1,27 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    ops::{RangeBounds, RangeInclusive},
+    panic,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use futures::future::{self, FutureExt};
+use futures::future::{self, TryFutureExt};
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::{
     blert,
-    data_repository::DataRepository,
+    data_repository::{self, DataRepository},
     error::{Error, Result},
     item::{self, EquipmentSlot},
+    sandbox, time,
+    tob::phases::{self, Phase, PhaseInterval},
+    usage::ResourceUsage,
 };
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Status {
     InProgress = 0,
     Completed = 1,
@@ -43,6 +54,38 @@ impl TryFrom<i16> for Status {
     }
 }
 
+impl From<Status> for i16 {
+    fn from(status: Status) -> Self {
+        match status {
+            Status::InProgress => 0,
+            Status::Completed => 1,
+            Status::Wiped => 2,
+            Status::Reset => 3,
+        }
+    }
+}
+
+/// Challenge metadata which is normally read from the database. It can be provided in a JSON
+/// sidecar file to analyze a challenge offline without inferring it from the recorded stages.
+#[derive(Debug, Default, Deserialize)]
+pub struct ChallengeMetadata {
+    pub status: Option<Status>,
+    /// Players in the challenge, in orb order.
+    pub party: Option<Vec<String>>,
+}
+
+/// Returns the stages of a type of challenge, in order, or `None` if challenges of the type cannot
+/// be analyzed.
+pub fn supported_stages(r#type: blert::Challenge) -> Option<RangeInclusive<blert::Stage>> {
+    match r#type {
+        blert::Challenge::Tob => Some(blert::Stage::TobMaiden..=blert::Stage::TobVerzik),
+        blert::Challenge::Colosseum => {
+            Some(blert::Stage::ColosseumWave1..=blert::Stage::ColosseumWave12)
+        }
+        _ => None,
+    }
+}
+
 #[derive(Debug)]
 pub struct Challenge {
     uuid: Uuid,
@@ -54,6 +97,12 @@ pub struct Challenge {
 
     data: blert::ChallengeData,
     stages: Vec<StageInfo>,
+    /// Stages to which loading was restricted. If `None`, every recorded stage was loaded.
+    stage_scope: Option<Vec<blert::Stage>>,
+    /// Stages within the scope which were recorded but whose events are missing from the
+    /// repository, e.g. because their upload failed.
+    missing_stages: Vec<blert::Stage>,
+    usage: ResourceUsage,
 }
 
 impl Challenge {
@@ -63,6 +112,17 @@ impl Challenge {
         pool: &sqlx::PgPool,
         repository: &DataRepository,
         uuid: Uuid,
+    ) -> Result<Self> {
+        Self::load_with_scope(pool, repository, uuid, None).await
+    }
+
+    /// Loads a challenge like [`Challenge::load`], but only the events of the stages in
+    /// `stage_scope`, if it is set.
+    pub async fn load_with_scope(
+        pool: &sqlx::PgPool,
+        repository: &DataRepository,
+        uuid: Uuid,
+        stage_scope: Option<&[blert::Stage]>,
     ) -> Result<Self> {
         let challenge = sqlx::query!("SELECT * FROM challenges WHERE uuid = $1", uuid)
             .fetch_one(pool)
@@ -80,7 +140,7 @@ impl Challenge {
         .fetch_all(pool)
         .await?;
 
-        let challenge_data = repository.load_challenge(uuid).await?;
+        let (challenge_data, fetch_stats) = repository.load_challenge(uuid).await?;
 
         let r#type = blert::Challenge::try_from(i32::from(challenge.r#type))
             .map_err(|_| Error::InvalidField("type".to_string()))?;
@@ -92,13 +152,6 @@ impl Challenge {
         let mode = blert::ChallengeMode::try_from(mode)
             .map_err(|_| Error::InvalidField("mode".to_string()))?;
 
-        let first_stage = match blert::Challenge::try_from(i32::from(challenge.r#type)) {
-            Ok(blert::Challenge::Tob) => blert::Stage::TobMaiden as i16,
-            Ok(blert::Challenge::Colosseum) => blert::Stage::ColosseumWave1 as i16,
-            Ok(_) => unimplemented!(),
-            Err(_) => return Err(Error::InvalidField("type".to_string())),
-        };
-
         let challenge_stage = challenge
             .stage
             .ok_or(Error::InvalidField("stage".to_string()))
@@ -107,15 +160,16 @@ impl Challenge {
                     .map_err(|_| Error::InvalidField("stage".to_string()))
             })?;
 
-        let stages = future::try_join_all((first_stage..=challenge_stage as i16).map(|stage| {
-            let stage =
-                blert::Stage::try_from(i32::from(stage)).expect("Stage is within the valid range");
-            repository.load_stage_events(uuid, stage).map(|res| {
-                res.map_err(Error::from)
-                    .and_then(|s| StageInfo::new(&challenge_data, s))
-            })
-        }))
+        let (stages, missing_stages, mut usage) = Self::load_stages(
+            repository,
+            &challenge_data,
+            uuid,
+            r#type,
+            challenge_stage,
+            stage_scope,
+        )
         .await?;
+        usage.add_fetch(&fetch_stats);
 
         Ok(Challenge {
             uuid,
@@ -129,9 +183,182 @@ impl Challenge {
             party: challenge_players.into_iter().map(|p| p.username).collect(),
             data: challenge_data,
             stages,
+            stage_scope: stage_scope.map(<[_]>::to_vec),
+            missing_stages,
+            usage,
         })
     }
 
+    /// Loads information about the challenge identified by `uuid` solely from a Blert data
+    /// repository, for use when no database is available.
+    ///
+    /// As the repository does not record the challenge's final status, it is inferred from the
+    /// recorded stages: a challenge which reached its final stage without the whole party dying
+    /// is considered completed, one in which the whole party died in its last stage is considered
+    /// wiped, and any other challenge is considered reset.
+    pub async fn load_from_repository(repository: &DataRepository, uuid: Uuid) -> Result<Self> {
+        Self::load_from_repository_with_scope(repository, uuid, None).await
+    }
+
+    /// Loads a challenge like [`Challenge::load_from_repository`], but only the events of the
+    /// stages in `stage_scope`, if it is set. A challenge whose final recorded stage is outside
+    /// the scope cannot be known to have wiped, and is considered reset.
+    pub async fn load_from_repository_with_scope(
+        repository: &DataRepository,
+        uuid: Uuid,
+        stage_scope: Option<&[blert::Stage]>,
+    ) -> Result<Self> {
+        let (challenge_data, fetch_stats) = repository.load_challenge(uuid).await?;
+
+        let r#type = challenge_data.r#type();
+        let mode = challenge_data.mode();
+        let challenge_stage = challenge_data.stage();
+        let party = challenge_data.party.clone();
+
+        let (stages, missing_stages, mut usage) = Self::load_stages(
+            repository,
+            &challenge_data,
+            uuid,
+            r#type,
+            challenge_stage,
+            stage_scope,
+        )
+        .await?;
+        usage.add_fetch(&fetch_stats);
+
+        let party_wiped = !party.is_empty()
+            && stages.last().is_some_and(|stage| {
+                stage.stage == challenge_stage
+                    && stage
+                        .events_for_type(blert::event::Type::PlayerDeath)
+                        .count()
+                        >= party.len()
+            });
+        let final_stage = match r#type {
+            blert::Challenge::Tob => blert::Stage::TobVerzik,
+            blert::Challenge::Colosseum => blert::Stage::ColosseumWave12,
+            _ => return Err(Error::InvalidField("type".to_string())),
+        };
+
+        let status = if party_wiped {
+            Status::Wiped
+        } else if challenge_stage == final_stage {
+            Status::Completed
+        } else {
+            Status::Reset
+        };
+
+        Ok(Challenge {
+            uuid,
+            r#type,
+            mode,
+            status,
+            stage: challenge_stage,
+            party,
+            data: challenge_data,
+            stages,
+            stage_scope: stage_scope.map(<[_]>::to_vec),
+            missing_stages,
+            usage,
+        })
+    }
+
+    /// Loads the events of every stage of a challenge from its first stage up to and including
+    /// `last_stage`, along with the resources consumed doing so. Stages outside `stage_scope`, if
+    /// it is set, are skipped, as are stages without events in the repository, which are returned
+    /// as missing.
+    async fn load_stages(
+        repository: &DataRepository,
+        challenge_data: &blert::ChallengeData,
+        uuid: Uuid,
+        r#type: blert::Challenge,
+        last_stage: blert::Stage,
+        stage_scope: Option<&[blert::Stage]>,
+    ) -> Result<(Vec<StageInfo>, Vec<blert::Stage>, ResourceUsage)> {
+        let first_stage = match supported_stages(r#type) {
+            Some(stages) => *stages.start() as i16,
+            None => return Err(Error::InvalidField("type".to_string()).with_challenge(uuid)),
+        };
+
+        let available = repository
+            .stages_available(uuid)
+            .await
+            .map_err(|e| Error::from(e).with_challenge(uuid))?;
+        let (recorded, mut missing): (Vec<_>, Vec<_>) = (first_stage..=last_stage as i16)
+            .map(|stage| {
+                blert::Stage::try_from(i32::from(stage)).expect("Stage is within the valid range")
+            })
+            .filter(|stage| stage_scope.map_or(true, |scope| scope.contains(stage)))
+            .partition(|stage| available.contains(stage));
+
+        // Stages are loaded concurrently, up to the repository's limit on files loaded at once.
+        let budget = &repository.challenge_budget();
+
+        let stages = future::try_join_all(recorded.into_iter().map(|stage| {
+            async move {
+                // The file may have been removed since the stages were listed.
+                let (events, fetch_stats) =
+                    match repository.load_stage_events(uuid, stage, budget).await {
+                        Ok(loaded) => loaded,
+                        Err(data_repository::Error::NotFound(_)) => return Ok(Err(stage)),
+                        Err(e) => return Err(e.into()),
+                    };
+
+                // Building a stage's state walks all of its events, which can take a while for
+                // long rooms, so it is done off the async runtime.
+                let challenge_data = challenge_data.clone();
+                let (info, build_time) = tokio::task::spawn_blocking(move || {
+                    let start = Instant::now();
+                    // The events are shared with concurrent loads of the same stage, if any.
+                    let events = Arc::try_unwrap(events).unwrap_or_else(|events| (*events).clone());
+                    StageInfo::new(&challenge_data, events).map(|info| (info, start.elapsed()))
+                })
+                .await
+                .map_err(|e| match e.try_into_panic() {
+                    Ok(payload) => panic::resume_unwind(payload),
+                    Err(_) => Error::Cancelled,
+                })??;
+
+                Ok::<_, Error>(Ok((info, fetch_stats, build_time)))
+            }
+            .map_err(move |e| e.with_stage(stage).with_challenge(uuid))
+        }))
+        .await?;
+
+        let mut usage = ResourceUsage::default();
+        let stages = stages
+            .into_iter()
+            .filter_map(|loaded| match loaded {
+                Ok((info, fetch_stats, build_time)) => {
+                    usage.add_fetch(&fetch_stats);
+                    usage.add_state_build_time(build_time);
+                    Some(info)
+                }
+                Err(stage) => {
+                    missing.push(stage);
+                    None
+                }
+            })
+            .collect();
+
+        missing.sort_by_key(|&stage| stage as i32);
+        for stage in &missing {
+            tracing::warn!("Challenge {uuid} has no events for stage {stage:?}");
+        }
+
+        Ok((stages, missing, usage))
+    }
+
+    /// Replaces the challenge's inferred metadata with any fields set in `metadata`.
+    pub fn apply_metadata(&mut self, metadata: ChallengeMetadata) {
+        if let Some(status) = metadata.status {
+            self.status = status;
+        }
+        if let Some(party) = metadata.party {
+            self.party = party;
+        }
+    }
+
     /// Returns the ID of the challenge.
     pub fn uuid(&self) -> Uuid {
         self.uuid
@@ -171,6 +398,18 @@ impl Challenge {
         self.stages.iter().map(|info| info.stage)
     }
 
+    /// Returns the stages to which the challenge's loading was restricted, or `None` if every
+    /// recorded stage was loaded.
+    pub fn stage_scope(&self) -> Option<&[blert::Stage]> {
+        self.stage_scope.as_deref()
+    }
+
+    /// Returns the stages whose events are missing from the repository, in order. The challenge
+    /// is analyzed without them.
+    pub fn missing_stages(&self) -> &[blert::Stage] {
+        &self.missing_stages
+    }
+
     /// Returns all stage data for the challenge.
     pub fn stage_infos(&self) -> &[StageInfo] {
         self.stages.as_slice()
@@ -180,6 +419,25 @@ impl Challenge {
     pub fn stage_info(&self, stage: blert::Stage) -> Option<&StageInfo> {
         self.stages.iter().find(|&info| info.stage == stage)
     }
+
+    /// Returns the total number of ticks recorded across all stages of the challenge.
+    pub fn total_ticks(&self) -> u32 {
+        self.stages.iter().map(StageInfo::total_ticks).sum()
+    }
+
+    /// Returns the resources consumed loading the challenge.
+    pub fn resource_usage(&self) -> &ResourceUsage {
+        &self.usage
+    }
+
+    /// Returns the real-time duration of the challenge, or `None` if some of its recorded stages
+    /// were not loaded, as their time would be missing from it.
+    pub fn duration(&self) -> Option<Duration> {
+        if self.stage_scope.is_some() || !self.missing_stages.is_empty() {
+            return None;
+        }
+        Some(time::ticks_to_duration(self.total_ticks()))
+    }
 }
 
 fn is_player_event(event: &blert::Event) -> bool {
@@ -201,17 +459,19 @@ struct StageEvents {
 
 impl StageEvents {
     pub fn for_tick(&self, tick: u32) -> &[blert::Event] {
-        let start_index = self.tick_indices[tick as usize];
+        let Some(&start_index) = self.tick_indices.get(tick as usize) else {
+            return &[];
+        };
         if start_index < 0 {
             return &[];
         }
         let start_index = start_index as usize;
 
-        let end_index = if tick > self.total_ticks {
-            self.all.len()
-        } else {
-            self.tick_indices[tick as usize + 1] as usize
-        };
+        // The next tick may have no events, so find the start of the next tick which does.
+        let end_index = self.tick_indices[tick as usize + 1..]
+            .iter()
+            .find(|&&i| i >= 0)
+            .map_or(self.all.len(), |&i| i as usize);
 
         &self.all[start_index..end_index]
     }
@@ -222,7 +482,9 @@ pub struct StageInfo {
     stage: blert::Stage,
     events: StageEvents,
     player_state: HashMap<String, Vec<Option<PlayerState>>>,
+    first_update_ticks: HashMap<String, u32>,
     npcs: HashMap<u64, Arc<blert::challenge_data::StageNpc>>,
+    phases: Vec<PhaseInterval>,
 }
 
 impl StageInfo {
@@ -283,12 +545,36 @@ impl StageInfo {
 
         let player_state = Self::build_player_state(&stage_data.party_names, &events, &npcs)?;
 
-        Ok(Self {
+        let mut first_update_ticks = HashMap::new();
+        for event in events
+            .by_type
+            .get(&blert::event::Type::PlayerUpdate)
+            .into_iter()
+            .flatten()
+            .map(|&i| &events.all[i])
+        {
+            let username = event
+                .player
+                .as_ref()
+                .and_then(|p| stage_data.party_names.get(p.party_index as usize));
+            if let Some(username) = username {
+                first_update_ticks
+                    .entry(username.clone())
+                    .or_insert(event.tick);
+            }
+        }
+
+        let mut info = Self {
             stage,
             events,
             player_state,
+            first_update_ticks,
             npcs,
-        })
+            phases: Vec::new(),
+        };
+        info.phases = phases::compute(&info);
+
+        Ok(info)
     }
 
     fn build_player_state(
@@ -325,7 +611,7 @@ impl StageInfo {
                             Some((e, player))
                         }
                         (true, None) => {
-                            log::error!("Player event without player data: {e:?}");
+                            tracing::error!("Player event without player data: {e:?}");
                             None
                         }
                         _ => None,
@@ -371,7 +657,7 @@ impl StageInfo {
                                         Ok(())
                                     }
                                     Err(e) => {
-                                        log::error!("Error parsing item delta: {e}");
+                                        tracing::error!("Error parsing item delta: {e}");
                                         Err(Error::InvalidField(format!("PlayerUpdateEvent({username}:{tick}): equipment_deltas")))
                                     }
                                 })
@@ -396,7 +682,10 @@ impl StageInfo {
 
     /// Returns an iterator over every event in the stage.
     pub fn all_events(&self) -> impl Iterator<Item = &blert::Event> {
-        self.events.all.iter()
+        self.events
+            .all
+            .iter()
+            .inspect(|_| sandbox::charge_events(1))
     }
 
     /// Returns the total number of recorded events in the stage.
@@ -404,6 +693,26 @@ impl StageInfo {
         self.events.all.len()
     }
 
+    /// Returns the number of ticks recorded in the stage.
+    pub fn total_ticks(&self) -> u32 {
+        self.events.total_ticks
+    }
+
+    /// Returns the real-time duration of the stage.
+    pub fn duration(&self) -> Duration {
+        time::ticks_to_duration(self.total_ticks())
+    }
+
+    /// Returns the stage NPC with the given room ID, if it is known.
+    pub fn npc(&self, room_id: u64) -> Option<&Arc<blert::challenge_data::StageNpc>> {
+        self.npcs.get(&room_id)
+    }
+
+    /// Returns an iterator over every NPC which spawned during the stage, in no particular order.
+    pub fn npcs(&self) -> impl Iterator<Item = &Arc<blert::challenge_data::StageNpc>> {
+        self.npcs.values()
+    }
+
     /// Returns an iterator over all events with the specified type.
     pub fn events_for_type(
         &self,
@@ -414,6 +723,21 @@ impl StageInfo {
             .get(&event_type)
             .into_iter()
             .flat_map(move |indices| indices.iter().map(|&i| &self.events.all[i]))
+            .inspect(|_| sandbox::charge_events(1))
+    }
+
+    /// Returns the boss phases reached during the stage in chronological order. Stages without
+    /// tracked phases have none.
+    pub fn phases(&self) -> &[PhaseInterval] {
+        &self.phases
+    }
+
+    /// Returns the boss phase which was ongoing on a tick, if any.
+    pub fn phase_at(&self, tick: u32) -> Option<Phase> {
+        self.phases
+            .iter()
+            .find(|interval| interval.contains(tick))
+            .map(|interval| interval.phase)
     }
 
     /// Returns information about a specific player in the stage.
@@ -422,6 +746,126 @@ impl StageInfo {
             .get(username)
             .map(|states| PlayerStates { states })
     }
+
+    /// Returns an iterator which replays the stage tick by tick, from its first tick to its
+    /// last, providing a combined view of the stage's state on each tick.
+    pub fn replay(&self) -> Replay<'_> {
+        Replay {
+            stage: self,
+            tick: 0,
+        }
+    }
+
+    /// Returns the full equipment loadout of a player on the first tick of the stage on which
+    /// their state was recorded.
+    pub fn starting_equipment(&self, username: &str) -> Option<Loadout> {
+        let tick = *self.first_update_ticks.get(username)?;
+        let state = self
+            .player_state
+            .get(username)?
+            .get(tick as usize)?
+            .as_ref()?;
+
+        Some(Loadout {
+            equipment: &state.equipment,
+        })
+    }
+}
+
+/// Iterator over the ticks of a stage. See [`StageInfo::replay`].
+#[derive(Debug, Clone)]
+pub struct Replay<'a> {
+    stage: &'a StageInfo,
+    tick: u32,
+}
+
+impl<'a> Iterator for Replay<'a> {
+    type Item = TickView<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.tick > self.stage.total_ticks() {
+            return None;
+        }
+
+        let events = self.stage.events.for_tick(self.tick);
+        sandbox::charge_events(events.len() as u64);
+
+        let view = TickView {
+            stage: self.stage,
+            tick: self.tick,
+            events,
+        };
+        self.tick += 1;
+        Some(view)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.stage.total_ticks() + 1).saturating_sub(self.tick) as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for Replay<'_> {}
+
+/// The combined state of a stage on a single tick.
+#[derive(Debug, Clone, Copy)]
+pub struct TickView<'a> {
+    stage: &'a StageInfo,
+    tick: u32,
+    events: &'a [blert::Event],
+}
+
+impl<'a> TickView<'a> {
+    /// Returns the tick being viewed.
+    pub fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    /// Returns every event which occurred on the tick.
+    pub fn events(&self) -> &'a [blert::Event] {
+        self.events
+    }
+
+    /// Returns the events of a specific type which occurred on the tick.
+    pub fn events_of_type(
+        &self,
+        event_type: blert::event::Type,
+    ) -> impl Iterator<Item = &'a blert::Event> {
+        self.events
+            .iter()
+            .filter(move |event| event.r#type() == event_type)
+    }
+
+    /// Returns the state of a player on the tick, if it was recorded.
+    pub fn player(&self, username: &str) -> Option<&'a PlayerState> {
+        self.stage
+            .player_state
+            .get(username)?
+            .get(self.tick as usize)?
+            .as_ref()
+    }
+
+    /// Returns the recorded state of every player on the tick, keyed by username.
+    pub fn players(&self) -> impl Iterator<Item = (&'a str, &'a PlayerState)> {
+        let tick = self.tick as usize;
+        self.stage
+            .player_state
+            .iter()
+            .filter_map(move |(username, states)| {
+                states
+                    .get(tick)
+                    .and_then(Option::as_ref)
+                    .map(|state| (username.as_str(), state))
+            })
+    }
+
+    /// Returns every NPC which is alive on the tick.
+    pub fn npcs(&self) -> impl Iterator<Item = &'a Arc<blert::challenge_data::StageNpc>> {
+        let tick = self.tick;
+        self.stage.npcs().filter(move |npc| {
+            npc.spawn_tick <= tick && (npc.death_tick == 0 || tick <= npc.death_tick)
+        })
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -455,6 +899,43 @@ pub struct PlayerStats {
     magic: Option<SkillLevel>,
 }
 
+impl PlayerStats {
+    /// Returns the player's Attack level, if it was recorded on the tick.
+    pub fn attack(&self) -> Option<&SkillLevel> {
+        self.attack.as_ref()
+    }
+
+    /// Returns the player's Defence level, if it was recorded on the tick.
+    pub fn defence(&self) -> Option<&SkillLevel> {
+        self.defence.as_ref()
+    }
+
+    /// Returns the player's Strength level, if it was recorded on the tick.
+    pub fn strength(&self) -> Option<&SkillLevel> {
+        self.strength.as_ref()
+    }
+
+    /// Returns the player's Hitpoints level, if it was recorded on the tick.
+    pub fn hitpoints(&self) -> Option<&SkillLevel> {
+        self.hitpoints.as_ref()
+    }
+
+    /// Returns the player's Ranged level, if it was recorded on the tick.
+    pub fn ranged(&self) -> Option<&SkillLevel> {
+        self.ranged.as_ref()
+    }
+
+    /// Returns the player's Prayer level, if it was recorded on the tick.
+    pub fn prayer(&self) -> Option<&SkillLevel> {
+        self.prayer.as_ref()
+    }
+
+    /// Returns the player's Magic level, if it was recorded on the tick.
+    pub fn magic(&self) -> Option<&SkillLevel> {
+        self.magic.as_ref()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ItemQuantity(i32, i32);
 
@@ -468,6 +949,19 @@ impl ItemQuantity {
     }
 }
 
+/// A snapshot of the items equipped by a player on a specific tick.
+#[derive(Debug, Clone, Copy)]
+pub struct Loadout<'a> {
+    equipment: &'a [Option<ItemQuantity>; 11],
+}
+
+impl<'a> Loadout<'a> {
+    /// Returns the item equipped in a slot, if any.
+    pub fn get(&self, slot: EquipmentSlot) -> Option<&'a ItemQuantity> {
+        self.equipment.get(slot as usize).and_then(Option::as_ref)
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct PlayerStates<'a> {
     states: &'a [Option<PlayerState>],
@@ -488,12 +982,51 @@ impl PlayerStates<'_> {
         })
     }
 
+    /// Returns every attack done by the player on an NPC matching `filter`, with their attack
+    /// ticks. Attacks without a known target are excluded.
+    pub fn attacks_on<F>(&self, filter: F) -> impl Iterator<Item = (u32, &PlayerAttacked)>
+    where
+        F: Fn(&blert::challenge_data::StageNpc) -> bool,
+    {
+        self.attacks()
+            .filter(move |(_, attack)| attack.target.as_deref().is_some_and(&filter))
+    }
+
+    /// Returns every attack done by the player within a range of ticks, with their attack ticks.
+    pub fn attacks_within<R>(&self, ticks: R) -> impl Iterator<Item = (u32, &PlayerAttacked)>
+    where
+        R: RangeBounds<u32>,
+    {
+        self.attacks().filter(move |(tick, _)| ticks.contains(tick))
+    }
+
+    /// Returns the tick of the player's first attack, if they attacked.
+    pub fn first_attack_tick(&self) -> Option<u32> {
+        self.attacks().next().map(|(tick, _)| tick)
+    }
+
+    /// Returns the tick of the player's last attack, if they attacked.
+    pub fn last_attack_tick(&self) -> Option<u32> {
+        self.attacks().last().map(|(tick, _)| tick)
+    }
+
     /// Returns the player state for a specific tick, if it exists.
     pub fn get_tick(&self, tick: usize) -> Option<&PlayerState> {
         self.states.get(tick).and_then(Option::as_ref)
     }
 }
 
+impl<'a> IntoIterator for PlayerStates<'a> {
+    type Item = &'a PlayerState;
+    type IntoIter = std::iter::Flatten<std::slice::Iter<'a, Option<PlayerState>>>;
+
+    /// Converts the states into an iterator over every known player state which borrows the
+    /// underlying stage rather than the `PlayerStates`.
+    fn into_iter(self) -> Self::IntoIter {
+        self.states.iter().flatten()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct PlayerState {
     pub tick: u32,
@@ -551,8 +1084,8 @@ impl PlayerState {
                 _ => AttackState::Idle,
             },
             death_state: match self.death_state {
-                DeathState::JustDied => DeathState::Dead,
-                _ => DeathState::Alive,
+                DeathState::Alive => DeathState::Alive,
+                DeathState::JustDied | DeathState::Dead => DeathState::Dead,
             },
             position: self.position.clone(),
             stats: PlayerStats::default(),
@@ -740,6 +1273,11 @@ impl PrayerSet {
     pub fn is_active(self, prayer: Prayer) -> bool {
         self.prayers & (1 << prayer as u64) != 0
     }
+
+    /// Returns the set as a bitmask, with the bit at each active `Prayer`'s value set.
+    pub fn raw(self) -> u64 {
+        self.prayers
+    }
 }
 
 impl From<u64> for PrayerSet {
@@ -751,6 +1289,7 @@ impl From<u64> for PrayerSet {
 pub trait PlayerAttackExt {
     fn is_barrage(&self) -> bool;
     fn is_chin(&self) -> bool;
+    fn is_special(&self) -> bool;
 }
 
 impl PlayerAttackExt for blert::PlayerAttack {
@@ -778,6 +1317,10 @@ impl PlayerAttackExt for blert::PlayerAttack {
                 | blert::PlayerAttack::ChinRed
         )
     }
+
+    fn is_special(&self) -> bool {
+        self.as_str_name().ends_with("_SPEC")
+    }
 }
 
 #[cfg(test)]
@@ -805,6 +1348,68 @@ mod tests {
         assert!(Status::try_from(i16::MIN).is_err());
     }
 
+    #[test]
+    fn player_states_attack_queries() {
+        use super::{
+            AttackState, DeathState, PlayerAttacked, PlayerState, PlayerStates, PlayerStats,
+            PrayerSet,
+        };
+        use crate::blert;
+        use std::sync::Arc;
+
+        let crab = Arc::new(blert::challenge_data::StageNpc {
+            room_id: 1,
+            ..Default::default()
+        });
+        let boss = Arc::new(blert::challenge_data::StageNpc {
+            room_id: 2,
+            ..Default::default()
+        });
+
+        let state = |tick, target: Option<&Arc<blert::challenge_data::StageNpc>>| {
+            Some(PlayerState {
+                tick,
+                attack_state: match target {
+                    Some(npc) => AttackState::Attacked(PlayerAttacked {
+                        attack: blert::PlayerAttack::SwiftBlade,
+                        target: Some(npc.clone()),
+                    }),
+                    None => AttackState::Idle,
+                },
+                death_state: DeathState::Alive,
+                position: blert::Coords::default(),
+                stats: PlayerStats::default(),
+                prayers: PrayerSet::empty(),
+                equipment: Default::default(),
+            })
+        };
+
+        let states = [
+            state(0, None),
+            state(1, Some(&crab)),
+            None,
+            state(3, Some(&boss)),
+            state(4, Some(&crab)),
+            state(5, None),
+        ];
+        let states = PlayerStates { states: &states };
+
+        let on_crab = states
+            .attacks_on(|npc| npc.room_id == 1)
+            .map(|(tick, _)| tick)
+            .collect::<Vec<_>>();
+        assert_eq!(on_crab, [1, 4]);
+
+        let within = states
+            .attacks_within(2..=3)
+            .map(|(tick, _)| tick)
+            .collect::<Vec<_>>();
+        assert_eq!(within, [3]);
+
+        assert_eq!(states.first_attack_tick(), Some(1));
+        assert_eq!(states.last_attack_tick(), Some(4));
+    }
+
     #[test]
     fn item_delta_from_raw() {
         use super::{EquipmentSlot, ItemDelta};
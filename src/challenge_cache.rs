@@ -0,0 +1,173 @@
+//! Cache of recently loaded challenges. The website often starts several programs on a challenge
+//! shortly after it finishes, and the cache lets them share a single load from the data
+//! repository.
+
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use uuid::Uuid;
+
+use crate::challenge::{Challenge, Status};
+use crate::error::{Error, Result};
+
+/// Least recently used cache of fully loaded challenges, each kept for a limited time.
+///
+/// Challenges whose data may still change, i.e. those in progress or missing the events of some
+/// stages, are never cached, nor are challenges loaded for only some of their stages.
+pub struct ChallengeCache {
+    capacity: usize,
+    ttl: Duration,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    by_uuid: HashMap<Uuid, Entry>,
+    /// UUIDs of the cached challenges, keyed by the clock value of their last use.
+    by_use: BTreeMap<u64, Uuid>,
+    clock: u64,
+}
+
+struct Entry {
+    challenge: Arc<Challenge>,
+    loaded_at: Instant,
+    last_use: u64,
+}
+
+impl ChallengeCache {
+    const DEFAULT_CAPACITY: usize = 64;
+    const DEFAULT_TTL: Duration = Duration::from_secs(300);
+
+    /// Creates a cache of up to `capacity` challenges, each kept for at most `ttl` after it was
+    /// loaded. A cache with no capacity caches nothing.
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity,
+            ttl,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// Reads the cache's capacity from `BLERT_CHALLENGE_CACHE_SIZE` and the time for which each
+    /// challenge is kept from `BLERT_CHALLENGE_CACHE_TTL_SECS`. Setting the size to 0 disables
+    /// the cache.
+    pub fn from_env() -> Result<Self> {
+        let capacity = match env::var("BLERT_CHALLENGE_CACHE_SIZE") {
+            Ok(size) => size
+                .parse()
+                .map_err(|_| Error::Environment("BLERT_CHALLENGE_CACHE_SIZE"))?,
+            Err(_) => Self::DEFAULT_CAPACITY,
+        };
+        let ttl = match env::var("BLERT_CHALLENGE_CACHE_TTL_SECS") {
+            Ok(secs) => Duration::from_secs(
+                secs.parse()
+                    .map_err(|_| Error::Environment("BLERT_CHALLENGE_CACHE_TTL_SECS"))?,
+            ),
+            Err(_) => Self::DEFAULT_TTL,
+        };
+        Ok(Self::new(capacity, ttl))
+    }
+
+    /// Returns the cached challenge with the given UUID, if it has not expired.
+    pub fn get(&self, uuid: Uuid) -> Option<Arc<Challenge>> {
+        let mut entries = self.entries.lock().unwrap();
+        let entries = &mut *entries;
+
+        let entry = entries.by_uuid.get_mut(&uuid)?;
+        if entry.loaded_at.elapsed() > self.ttl {
+            entries.by_use.remove(&entry.last_use);
+            entries.by_uuid.remove(&uuid);
+            return None;
+        }
+
+        entries.by_use.remove(&entry.last_use);
+        entries.clock += 1;
+        entry.last_use = entries.clock;
+        entries.by_use.insert(entries.clock, uuid);
+        Some(entry.challenge.clone())
+    }
+
+    /// Caches a freshly loaded challenge, replacing any cached copy of it and evicting the least
+    /// recently used challenge if the cache is full.
+    pub fn insert(&self, challenge: Arc<Challenge>) {
+        if self.capacity == 0
+            || challenge.status() == Status::InProgress
+            || !challenge.missing_stages().is_empty()
+            || challenge.stage_scope().is_some()
+        {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        let uuid = challenge.uuid();
+        if let Some(previous) = entries.by_uuid.remove(&uuid) {
+            entries.by_use.remove(&previous.last_use);
+        }
+
+        while entries.by_uuid.len() >= self.capacity {
+            let Some((_, evicted)) = entries.by_use.pop_first() else {
+                break;
+            };
+            entries.by_uuid.remove(&evicted);
+        }
+
+        entries.clock += 1;
+        let last_use = entries.clock;
+        entries.by_use.insert(last_use, uuid);
+        entries.by_uuid.insert(
+            uuid,
+            Entry {
+                challenge,
+                loaded_at: Instant::now(),
+                last_use,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_repository::{DataRepository, MemoryBackend};
+    use crate::synthetic::{Generator, SyntheticConfig};
+
+    #[tokio::test]
+    async fn least_recently_used_challenges_are_evicted() {
+        let mut generator = Generator::new(SyntheticConfig {
+            scale: 2,
+            stages: 1,
+            ticks_per_stage: 10,
+            ..SyntheticConfig::default()
+        });
+        let backend = MemoryBackend::new();
+        let uuids = (0..3)
+            .map(|_| {
+                let synthetic = generator.generate();
+                synthetic.store(&backend);
+                synthetic.uuid
+            })
+            .collect::<Vec<_>>();
+        let repository = DataRepository::new(Box::new(backend));
+
+        let cache = ChallengeCache::new(2, Duration::from_secs(60));
+        for &uuid in &uuids {
+            let challenge = Challenge::load_from_repository(&repository, uuid)
+                .await
+                .unwrap();
+            cache.insert(Arc::new(challenge));
+            if uuid == uuids[1] {
+                assert!(cache.get(uuids[0]).is_some());
+            }
+        }
+
+        assert!(cache.get(uuids[0]).is_some());
+        assert!(cache.get(uuids[1]).is_none());
+        assert!(cache.get(uuids[2]).is_some());
+
+        let expiring = ChallengeCache::new(2, Duration::ZERO);
+        expiring.insert(cache.get(uuids[2]).unwrap());
+        assert!(expiring.get(uuids[2]).is_none());
+    }
+}
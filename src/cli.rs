@@ -0,0 +1,347 @@
+//! Command-line subcommands run in place of the analysis server.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use uuid::Uuid;
+
+use crate::analysis::{self, Level};
+use crate::challenge::{Challenge, ChallengeMetadata};
+use crate::data_repository::{DataRepository, FilesystemBackend, MemoryBackend};
+use crate::error::{Error, Result};
+use crate::synthetic::{Generator, SyntheticConfig};
+use crate::{diff, export, item, npc, results};
+
+/// Runs the subcommand named by the first argument, if any. Returns `Ok(false)` if no subcommand
+/// was specified, in which case the server should be started.
+pub async fn run(args: &[String]) -> Result<bool> {
+    match args.first().map(String::as_str) {
+        None => Ok(false),
+        Some("diff") => diff_command(&args[1..]).map(|()| true),
+        Some("export-roles") => export_roles_command(&args[1..]).await.map(|()| true),
+        Some("export-results") => export_results_command(&args[1..]).await.map(|()| true),
+        Some("export-states") => export_states_command(&args[1..]).await.map(|()| true),
+        Some("run") => run_command(&args[1..]).await.map(|()| true),
+        Some("analyze") => analyze_command(&args[1..]).await.map(|()| true),
+        Some("bench") => bench_command(&args[1..]).await.map(|()| true),
+        Some(command) => {
+            eprintln!("Unknown command: {command}");
+            eprintln!(
+                "Usage: raid-analyzer [diff <before.json> <after.json> | \
+                 export-roles <out.parquet> | export-results <program> <out.csv|out.parquet> \
+                 [options] | export-states --data <dir> --uuid <uuid> --out <file> | \
+                 run <program> <uuid> [level] | \
+                 analyze --data <dir> --uuid <uuid> [options] | bench <program> [challenges] [scale]]"
+            );
+            Err(Error::InvalidArgument)
+        }
+    }
+}
+
+fn read_report(path: impl AsRef<Path>) -> Result<serde_json::Value> {
+    let report = fs::read(path)?;
+    serde_json::from_slice(&report).map_err(Error::from)
+}
+
+/// Prints the differences between two run reports of the same challenge.
+fn diff_command(args: &[String]) -> Result<()> {
+    let [before, after] = args else {
+        eprintln!("Usage: raid-analyzer diff <before.json> <after.json>");
+        return Err(Error::InvalidArgument);
+    };
+
+    let diff = diff::diff_reports(&read_report(before)?, &read_report(after)?)?;
+    if diff.is_empty() {
+        println!("Reports are identical");
+    } else {
+        print!("{diff}");
+        println!("{} change(s)", diff.changes().len());
+    }
+
+    Ok(())
+}
+
+/// Runs a program on a single challenge in the current process and prints its result. Analyzers
+/// run inline in a fixed order, so the output of two invocations can be compared with `diff`.
+async fn run_command(args: &[String]) -> Result<()> {
+    let usage = || {
+        eprintln!("Usage: raid-analyzer run <program> <uuid> [basic|learner|casual|maxeff]");
+        Error::InvalidArgument
+    };
+
+    let (program, uuid, level) = match args {
+        [program, uuid] => (program, uuid, Level::Basic),
+        [program, uuid, level] => (program, uuid, parse_level(level).ok_or_else(usage)?),
+        _ => return Err(usage()),
+    };
+    let uuid = Uuid::parse_str(uuid).map_err(|_| usage())?;
+
+    let repository = crate::initialize_data_repository().await?;
+    let challenge = Challenge::load_from_repository(&repository, uuid).await?;
+
+    let item_registry = item::Registry::load_from_file("resources/runescape_items.json")?;
+    let npc_registry = npc::Registry::shared()?;
+    let engine =
+        analysis::Engine::load_from_directory("./programs", item_registry, npc_registry).await?;
+
+    let result = engine
+        .run_program_inline(program, Arc::new(challenge), level)
+        .await?;
+    println!("{}", serde_json::to_string_pretty(&result)?);
+
+    Ok(())
+}
+
+fn parse_level(level: &str) -> Option<Level> {
+    serde_json::from_value(serde_json::Value::String(level.to_owned())).ok()
+}
+
+/// Parses arguments of the form `--name value` into a map of names to values, accepting only the
+/// names in `allowed`.
+fn parse_flags<'a>(args: &'a [String], allowed: &[&str]) -> Option<HashMap<&'a str, &'a str>> {
+    let mut flags = HashMap::new();
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let name = flag
+            .strip_prefix("--")
+            .filter(|name| allowed.contains(name))?;
+        flags.insert(name, args.next()?.as_str());
+    }
+    Some(flags)
+}
+
+/// Analyzes a challenge stored in a local directory laid out like a filesystem data repository,
+/// without a database. Metadata which would otherwise be read from the database can be provided
+/// in a JSON sidecar file; if it is not, it is inferred from the recorded stages.
+async fn analyze_command(args: &[String]) -> Result<()> {
+    let usage = || {
+        eprintln!(
+            "Usage: raid-analyzer analyze --data <dir> --uuid <uuid> [--program <name>] \
+             [--level <level>] [--metadata <sidecar.json>] [--out <result.json>]"
+        );
+        Error::InvalidArgument
+    };
+
+    let flags = parse_flags(
+        args,
+        &["data", "uuid", "program", "level", "metadata", "out"],
+    )
+    .ok_or_else(usage)?;
+    let data = flags.get("data").ok_or_else(usage)?;
+    let uuid = flags
+        .get("uuid")
+        .and_then(|uuid| Uuid::parse_str(uuid).ok())
+        .ok_or_else(usage)?;
+    let level = match flags.get("level") {
+        Some(level) => parse_level(level).ok_or_else(usage)?,
+        None => Level::Basic,
+    };
+
+    let repository = DataRepository::new(Box::new(FilesystemBackend::new(Path::new(data))));
+    let mut challenge = Challenge::load_from_repository(&repository, uuid).await?;
+    if let Some(path) = flags.get("metadata") {
+        let metadata: ChallengeMetadata = serde_json::from_slice(&fs::read(path)?)?;
+        challenge.apply_metadata(metadata);
+    }
+
+    let item_registry = item::Registry::load_from_file("resources/runescape_items.json")?;
+    let npc_registry = npc::Registry::shared()?;
+    let engine =
+        analysis::Engine::load_from_directory("./programs", item_registry, npc_registry).await?;
+
+    let program = match flags.get("program") {
+        Some(program) => *program,
+        None => engine
+            .default_program(challenge.r#type(), challenge.mode())
+            .ok_or_else(|| {
+                Error::FailedPrecondition("No default program for the challenge".into())
+            })?,
+    };
+
+    let result = engine
+        .run_program_inline(program, Arc::new(challenge), level)
+        .await?;
+    let result = serde_json::to_string_pretty(&result)?;
+
+    match flags.get("out") {
+        Some(path) => {
+            fs::write(path, result)?;
+            eprintln!("Wrote {program} results for {uuid} to {path}");
+        }
+        None => println!("{result}"),
+    }
+
+    Ok(())
+}
+
+/// Exports the reconstructed state of every player on every tick of a challenge stored in a local
+/// directory to a Parquet or CSV file.
+async fn export_states_command(args: &[String]) -> Result<()> {
+    let usage = || {
+        eprintln!(
+            "Usage: raid-analyzer export-states --data <dir> --uuid <uuid> \
+             --out <states.parquet|states.csv>"
+        );
+        Error::InvalidArgument
+    };
+
+    let flags = parse_flags(args, &["data", "uuid", "out"]).ok_or_else(usage)?;
+    let data = flags.get("data").ok_or_else(usage)?;
+    let uuid = flags
+        .get("uuid")
+        .and_then(|uuid| Uuid::parse_str(uuid).ok())
+        .ok_or_else(usage)?;
+    let output = flags.get("out").ok_or_else(usage)?;
+    let format = export::TableFormat::from_path(Path::new(output)).ok_or_else(usage)?;
+
+    let repository = DataRepository::new(Box::new(FilesystemBackend::new(Path::new(data))));
+    let challenge = Challenge::load_from_repository(&repository, uuid).await?;
+
+    let file = fs::File::create(output)?;
+    let rows = export::export_player_states(&challenge, str::to_owned, format, file)?;
+    println!("Exported {rows} player state(s) to {output}");
+
+    Ok(())
+}
+
+/// Number of workers used by the benchmark, matching the server.
+const BENCH_WORKERS: u32 = 8;
+
+/// Generates synthetic challenges and runs a program on all of them concurrently, reporting how
+/// long loading and analyzing them took.
+async fn bench_command(args: &[String]) -> Result<()> {
+    let usage = || {
+        eprintln!("Usage: raid-analyzer bench <program> [challenges] [scale]");
+        Error::InvalidArgument
+    };
+
+    let (program, count, scale) = match args {
+        [program] => (program, 100, 5),
+        [program, count] => (program, count.parse().map_err(|_| usage())?, 5),
+        [program, count, scale] => (
+            program,
+            count.parse().map_err(|_| usage())?,
+            scale.parse().map_err(|_| usage())?,
+        ),
+        _ => return Err(usage()),
+    };
+
+    let backend = MemoryBackend::new();
+    let mut generator = Generator::new(SyntheticConfig {
+        scale,
+        ..SyntheticConfig::default()
+    });
+    let mut uuids = Vec::with_capacity(count);
+    let mut total_events = 0;
+    for _ in 0..count {
+        let synthetic = generator.generate();
+        synthetic.store(&backend);
+        total_events += synthetic.total_events();
+        uuids.push(synthetic.uuid);
+    }
+    let repository = DataRepository::new(Box::new(backend));
+
+    let start = Instant::now();
+    let mut challenges = Vec::with_capacity(count);
+    let mut state_build_time_us = 0;
+    for uuid in uuids {
+        let challenge = Challenge::load_from_repository(&repository, uuid).await?;
+        state_build_time_us += challenge.resource_usage().state_build_time_us;
+        challenges.push(Arc::new(challenge));
+    }
+    println!(
+        "Loaded {count} challenge(s) with {total_events} event(s) in {:.2?} \
+         ({:.2?} building state)",
+        start.elapsed(),
+        Duration::from_micros(state_build_time_us),
+    );
+
+    let item_registry = item::Registry::load_from_file("resources/runescape_items.json")?;
+    let npc_registry = npc::Registry::shared()?;
+    let mut engine =
+        analysis::Engine::load_from_directory("./programs", item_registry, npc_registry).await?;
+    engine.start(BENCH_WORKERS);
+
+    let start = Instant::now();
+    let handles = challenges
+        .into_iter()
+        .map(|challenge| engine.run_program(program, challenge, analysis::RunOptions::default()))
+        .collect::<Result<Vec<_>>>()?;
+    let mut analyzer_time_us = 0;
+    for handle in handles {
+        analyzer_time_us += handle.wait().await?.usage.analyzer_time_us;
+    }
+    let elapsed = start.elapsed();
+
+    println!(
+        "Analyzed {count} challenge(s) in {elapsed:.2?} ({:.1} per second, {:.2?} in analyzers)",
+        count as f64 / elapsed.as_secs_f64(),
+        Duration::from_micros(analyzer_time_us),
+    );
+
+    engine.shutdown(Duration::from_secs(5)).await;
+    Ok(())
+}
+
+/// Exports role-assignment training data from the results database to a Parquet file.
+async fn export_roles_command(args: &[String]) -> Result<()> {
+    let [output] = args else {
+        eprintln!("Usage: raid-analyzer export-roles <out.parquet>");
+        return Err(Error::InvalidArgument);
+    };
+
+    let uri =
+        env::var("BLERT_DATABASE_URI").map_err(|_| Error::Environment("BLERT_DATABASE_URI"))?;
+    let pool = sqlx::postgres::PgPoolOptions::new().connect(&uri).await?;
+    let store = results::Store::new(pool);
+
+    let rows = export::export_role_training_data(&store, output).await?;
+    println!("Exported {rows} player(s) to {output}");
+
+    Ok(())
+}
+
+/// Exports the outputs of a program's runs from the results database to a CSV or Parquet file,
+/// with one row per player per challenge.
+async fn export_results_command(args: &[String]) -> Result<()> {
+    let usage = || {
+        eprintln!(
+            "Usage: raid-analyzer export-results <program> <out.csv|out.parquet> \
+             [--since <timestamp>] [--until <timestamp>]"
+        );
+        Error::InvalidArgument
+    };
+
+    let [program, output, options @ ..] = args else {
+        return Err(usage());
+    };
+    let format = export::TableFormat::from_path(Path::new(output)).ok_or_else(usage)?;
+    let flags = parse_flags(options, &["since", "until"]).ok_or_else(usage)?;
+    let timestamp = |flag: &str| {
+        flags
+            .get(flag)
+            .map(|value| value.parse().map_err(|_| Error::InvalidField(flag.into())))
+            .transpose()
+    };
+    let request = export::ExportRequest {
+        program: program.clone(),
+        since: timestamp("since")?,
+        until: timestamp("until")?,
+        format,
+    };
+
+    let uri =
+        env::var("BLERT_DATABASE_URI").map_err(|_| Error::Environment("BLERT_DATABASE_URI"))?;
+    let pool = sqlx::postgres::PgPoolOptions::new().connect(&uri).await?;
+    let store = results::Store::new(pool);
+
+    let file = fs::File::create(output)?;
+    let rows = export::export_results(&store, &request, file).await?;
+    println!("Exported {rows} row(s) to {output}");
+
+    Ok(())
+}
@@ -1,43 +1,695 @@
+use futures::future;
 use prost::Message;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
 use std::{
-    fs,
-    io::Cursor,
+    collections::{BTreeMap, HashMap},
+    env, fmt, fs,
+    future::Future,
+    io::{self, Cursor, Read, Seek, SeekFrom, Write},
+    ops::Range,
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant, SystemTime},
 };
+use tokio::sync::{oneshot, Semaphore};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
 use crate::blert;
 
 pub struct DataRepository {
     backend: Box<dyn Backend + Sync + Send>,
+    limits: LoadLimits,
+    /// Permits to load a file, bounding the number of files loaded at once across all callers.
+    load_permits: Semaphore,
+    /// Callers waiting on each fetch in progress, keyed by relative path. Concurrent reads of the
+    /// same file share a single fetch from the backend.
+    in_flight: InFlightMap<Arc<Vec<u8>>>,
+    /// Callers waiting on each streamed load of a stage's events in progress, keyed by relative
+    /// path. Concurrent loads of the same stage share a single stream and its decoded events.
+    streams_in_flight: InFlightMap<(Arc<blert::ChallengeEvents>, FetchStats)>,
+}
+
+type FetchResult = Result<Arc<Vec<u8>>, Error>;
+
+/// Callers waiting on each load in progress, keyed by the relative path of the file loaded.
+type InFlightMap<T> = Mutex<HashMap<String, Vec<oneshot::Sender<Result<T, Error>>>>>;
+
+/// Statistics about a single fetch from a data repository.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FetchStats {
+    /// Size of the file as stored, before decompression.
+    pub bytes: u64,
+    pub fetch_time: Duration,
+    /// Time taken to decompress and decode the file.
+    pub decode_time: Duration,
+}
+
+/// Limits on the resources used loading challenges from a repository.
+#[derive(Debug, Clone, Copy)]
+pub struct LoadLimits {
+    /// Maximum number of files loaded at once, across every challenge being loaded.
+    pub concurrency: usize,
+    /// Maximum size of a single file, in bytes, both as stored and once decompressed.
+    pub max_file_bytes: u64,
+    /// Maximum total size of the stage event files of a single challenge as stored, in bytes.
+    /// Checked as each file is read, before it is decoded.
+    pub max_challenge_bytes: u64,
+}
+
+impl Default for LoadLimits {
+    fn default() -> Self {
+        Self {
+            concurrency: 32,
+            max_file_bytes: 256 * 1024 * 1024,
+            max_challenge_bytes: 1024 * 1024 * 1024,
+        }
+    }
+}
+
+impl LoadLimits {
+    /// Reads the limits from `BLERT_LOAD_CONCURRENCY`, `BLERT_MAX_FILE_MB`, and
+    /// `BLERT_MAX_CHALLENGE_MB`. Unset limits keep their defaults.
+    pub fn from_env() -> crate::error::Result<Self> {
+        const MEGABYTE: u64 = 1024 * 1024;
+        let defaults = Self::default();
+
+        let concurrency = env_or("BLERT_LOAD_CONCURRENCY", defaults.concurrency)?;
+        if concurrency == 0 {
+            return Err(crate::error::Error::Environment("BLERT_LOAD_CONCURRENCY"));
+        }
+
+        Ok(Self {
+            concurrency,
+            max_file_bytes: env_or("BLERT_MAX_FILE_MB", defaults.max_file_bytes / MEGABYTE)?
+                * MEGABYTE,
+            max_challenge_bytes: env_or(
+                "BLERT_MAX_CHALLENGE_MB",
+                defaults.max_challenge_bytes / MEGABYTE,
+            )? * MEGABYTE,
+        })
+    }
+}
+
+/// Running total of the bytes of the stage event files loaded for a single challenge, checked
+/// against [`LoadLimits::max_challenge_bytes`].
+#[derive(Debug)]
+pub struct ChallengeBudget {
+    loaded_bytes: AtomicU64,
+    max_bytes: u64,
+}
+
+impl ChallengeBudget {
+    /// Adds a file of `bytes` to the total, failing if the challenge is then over its limit.
+    fn charge(&self, relative_path: &str, bytes: u64) -> Result<(), Error> {
+        let loaded = self.loaded_bytes.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        if loaded > self.max_bytes {
+            return Err(Error::LimitExceeded(format!(
+                "challenge events exceed the limit of {} bytes at {relative_path}",
+                self.max_bytes
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// A load from the backend on which other callers may be waiting. If the load is dropped before
+/// it completes, e.g. because its caller was cancelled, its waiters are released to load the file
+/// themselves.
+struct InFlightFetch<'a, T> {
+    in_flight: &'a InFlightMap<T>,
+    relative_path: &'a str,
+    completed: bool,
+}
+
+impl<T: Clone> InFlightFetch<'_, T> {
+    fn complete(mut self, result: &Result<T, Error>) {
+        self.completed = true;
+        let waiters = self
+            .in_flight
+            .lock()
+            .unwrap()
+            .remove(self.relative_path)
+            .unwrap_or_default();
+        for waiter in waiters {
+            let _ = waiter.send(result.clone());
+        }
+    }
+}
+
+impl<T> Drop for InFlightFetch<'_, T> {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.in_flight.lock().unwrap().remove(self.relative_path);
+        }
+    }
+}
+
+/// Runs `load`, or waits for the result of a load of the same file which is already in progress in
+/// `in_flight`. If the load being waited on is cancelled, `load` is run instead, or the result of
+/// another waiter which took its place is waited on.
+async fn coalesce<T: Clone>(
+    in_flight: &InFlightMap<T>,
+    relative_path: &str,
+    load: impl Future<Output = Result<T, Error>>,
+) -> Result<T, Error> {
+    loop {
+        let waiter = {
+            let mut in_flight = in_flight.lock().unwrap();
+            if let Some(waiters) = in_flight.get_mut(relative_path) {
+                let (tx, rx) = oneshot::channel();
+                waiters.push(tx);
+                Some(rx)
+            } else {
+                in_flight.insert(relative_path.to_owned(), Vec::new());
+                None
+            }
+        };
+
+        let Some(rx) = waiter else {
+            break;
+        };
+        if let Ok(result) = rx.await {
+            return result;
+        }
+    }
+
+    let fetch = InFlightFetch {
+        in_flight,
+        relative_path,
+        completed: false,
+    };
+    let result = load.await;
+    fetch.complete(&result);
+    result
+}
+
+/// Compression formats in which repository files may be stored, recognized by their magic bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+    const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+    fn detect(contents: &[u8]) -> Option<Self> {
+        if contents.starts_with(&Self::GZIP_MAGIC) {
+            Some(Self::Gzip)
+        } else if contents.starts_with(&Self::ZSTD_MAGIC) {
+            Some(Self::Zstd)
+        } else {
+            None
+        }
+    }
+
+    /// Decompresses a file, reading at most one byte past `max_bytes` so that a file which
+    /// decompresses to more than the limit is detected without decompressing it whole.
+    fn decompress(self, contents: &[u8], max_bytes: u64) -> io::Result<Vec<u8>> {
+        let limit = max_bytes.saturating_add(1);
+        let mut decompressed = Vec::new();
+        match self {
+            Self::Gzip => {
+                flate2::read::GzDecoder::new(contents)
+                    .take(limit)
+                    .read_to_end(&mut decompressed)?;
+            }
+            Self::Zstd => {
+                zstd::stream::read::Decoder::new(contents)?
+                    .take(limit)
+                    .read_to_end(&mut decompressed)?;
+            }
+        }
+        if decompressed.len() as u64 > max_bytes {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                DecompressedSizeExceeded(max_bytes),
+            ));
+        }
+        Ok(decompressed)
+    }
+}
+
+/// Error raised when a file decompresses to more than the size limit of a file, passed through
+/// decompressors as an I/O error.
+#[derive(Debug)]
+struct DecompressedSizeExceeded(u64);
+
+impl fmt::Display for DecompressedSizeExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "decompresses to over the limit of {} bytes", self.0)
+    }
+}
+
+impl std::error::Error for DecompressedSizeExceeded {}
+
+/// Converts an error from decompressing and decoding a file into a repository error.
+fn decompress_error(relative_path: &str, compression: Option<Compression>, e: &io::Error) -> Error {
+    let inner = e.get_ref();
+    if let Some(e) = inner.and_then(|e| e.downcast_ref::<prost::DecodeError>()) {
+        Error::Decode(e.clone())
+    } else if let Some(e) = inner.and_then(|e| e.downcast_ref::<DecompressedSizeExceeded>()) {
+        Error::LimitExceeded(format!("{relative_path} {e}"))
+    } else {
+        Error::Decompress(format!("{compression:?} file {relative_path}: {e}"))
+    }
+}
+
+/// Decodes a protobuf message incrementally from bytes written to it, holding at most one
+/// top-level field's worth of undecoded bytes at a time. This keeps the peak memory of decoding
+/// a message with a large repeated field close to the size of the decoded message itself.
+struct MessageDecoder<M> {
+    message: M,
+    pending: Vec<u8>,
+    written: u64,
+    /// Maximum number of bytes which may be written, i.e. the size limit of the decoded file.
+    max_bytes: u64,
+}
+
+impl<M: Message + Default> MessageDecoder<M> {
+    fn new(max_bytes: u64) -> Self {
+        Self {
+            message: M::default(),
+            pending: Vec::new(),
+            written: 0,
+            max_bytes,
+        }
+    }
+
+    /// Merges every complete field in the pending bytes into the message.
+    fn decode_fields(&mut self) -> Result<(), prost::DecodeError> {
+        let mut consumed = 0;
+        while let Some(length) = field_length(&self.pending[consumed..])? {
+            let mut field = &self.pending[consumed..consumed + length];
+            let (tag, wire_type) = prost::encoding::decode_key(&mut field)?;
+            self.message.merge_field(
+                tag,
+                wire_type,
+                &mut field,
+                prost::encoding::DecodeContext::default(),
+            )?;
+            consumed += length;
+        }
+        self.pending.drain(..consumed);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<M, prost::DecodeError> {
+        if self.pending.is_empty() {
+            Ok(self.message)
+        } else {
+            Err(prost::DecodeError::new(
+                "message ends partway through a field",
+            ))
+        }
+    }
+}
+
+impl<M: Message + Default> Write for MessageDecoder<M> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written += buf.len() as u64;
+        if self.written > self.max_bytes {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                DecompressedSizeExceeded(self.max_bytes),
+            ));
+        }
+        self.pending.extend_from_slice(buf);
+        self.decode_fields()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns the encoded length of the field at the start of `buf`, including its key, or `None` if
+/// `buf` does not yet hold the whole field.
+fn field_length(buf: &[u8]) -> Result<Option<usize>, prost::DecodeError> {
+    let Some((key, key_length)) = read_varint(buf)? else {
+        return Ok(None);
+    };
+    let value_length = match key & 0x7 {
+        0 => match read_varint(&buf[key_length..])? {
+            Some((_, length)) => length,
+            None => return Ok(None),
+        },
+        1 => 8,
+        2 => match read_varint(&buf[key_length..])? {
+            Some((length, prefix_length)) => usize::try_from(length)
+                .ok()
+                .and_then(|length| length.checked_add(prefix_length))
+                .ok_or_else(|| prost::DecodeError::new("field length overflows"))?,
+            None => return Ok(None),
+        },
+        5 => 4,
+        wire_type => {
+            return Err(prost::DecodeError::new(format!(
+                "unsupported wire type {wire_type}"
+            )))
+        }
+    };
+
+    let length = key_length + value_length;
+    Ok((buf.len() >= length).then_some(length))
+}
+
+/// Reads a varint from the start of `buf`, returning its value and encoded length, or `None` if
+/// `buf` ends before the varint does.
+fn read_varint(buf: &[u8]) -> Result<Option<(u64, usize)>, prost::DecodeError> {
+    let mut value = 0;
+    for (i, &byte) in buf.iter().enumerate().take(10) {
+        value |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(Some((value, i + 1)));
+        }
+    }
+    if buf.len() >= 10 {
+        Err(prost::DecodeError::new("invalid varint"))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Decompresses and decodes a message from a file written to it in chunks.
+enum StreamDecoder<M: Message + Default> {
+    Uncompressed(MessageDecoder<M>),
+    Gzip(flate2::write::GzDecoder<MessageDecoder<M>>),
+    Zstd(zstd::stream::write::Decoder<'static, MessageDecoder<M>>),
+}
+
+impl<M: Message + Default> StreamDecoder<M> {
+    /// Creates a decoder whose decompressed output is limited to `max_bytes`.
+    fn new(compression: Option<Compression>, max_bytes: u64) -> io::Result<Self> {
+        let decoder = MessageDecoder::new(max_bytes);
+        Ok(match compression {
+            None => Self::Uncompressed(decoder),
+            Some(Compression::Gzip) => Self::Gzip(flate2::write::GzDecoder::new(decoder)),
+            Some(Compression::Zstd) => Self::Zstd(zstd::stream::write::Decoder::new(decoder)?),
+        })
+    }
+
+    fn write(&mut self, chunk: &[u8]) -> io::Result<()> {
+        match self {
+            Self::Uncompressed(decoder) => decoder.write_all(chunk),
+            Self::Gzip(decoder) => decoder.write_all(chunk),
+            Self::Zstd(decoder) => decoder.write_all(chunk),
+        }
+    }
+
+    fn finish(self) -> io::Result<MessageDecoder<M>> {
+        match self {
+            Self::Uncompressed(decoder) => Ok(decoder),
+            Self::Gzip(decoder) => decoder.finish(),
+            Self::Zstd(mut decoder) => {
+                decoder.flush()?;
+                Ok(decoder.into_inner())
+            }
+        }
+    }
 }
 
 impl DataRepository {
     const CHALLENGE_FILE_NAME: &'static str = "challenge";
 
+    /// Size of each ranged read of a file which is streamed from the backend.
+    const STREAM_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
     pub fn new(backend: Box<dyn Backend + Sync + Send>) -> Self {
-        Self { backend }
+        let limits = LoadLimits::default();
+        Self {
+            backend,
+            limits,
+            load_permits: Semaphore::new(limits.concurrency),
+            in_flight: Mutex::new(HashMap::new()),
+            streams_in_flight: Mutex::new(HashMap::new()),
+        }
     }
 
-    pub async fn load_challenge(&self, uuid: Uuid) -> Result<blert::ChallengeData, Error> {
-        let data = self
-            .backend
-            .read_file(Self::relative_path(uuid, Self::CHALLENGE_FILE_NAME))
-            .await?;
-        blert::ChallengeData::decode(&mut Cursor::new(&data)).map_err(Error::from)
+    /// Replaces the repository's default load limits.
+    pub fn with_limits(mut self, limits: LoadLimits) -> Self {
+        self.limits = limits;
+        self.load_permits = Semaphore::new(limits.concurrency);
+        self
+    }
+
+    /// Returns the limits on the resources used loading challenges.
+    pub fn limits(&self) -> &LoadLimits {
+        &self.limits
+    }
+
+    /// Returns an empty budget for the stage events of a challenge, to be shared by every load
+    /// of its stages.
+    pub fn challenge_budget(&self) -> ChallengeBudget {
+        ChallengeBudget {
+            loaded_bytes: AtomicU64::new(0),
+            max_bytes: self.limits.max_challenge_bytes,
+        }
+    }
+
+    pub async fn load_challenge(
+        &self,
+        uuid: Uuid,
+    ) -> Result<(blert::ChallengeData, FetchStats), Error> {
+        let _permit = self.load_permits.acquire().await;
+        self.load_message(Self::relative_path(uuid, Self::CHALLENGE_FILE_NAME), None)
+            .await
     }
 
+    /// Loads the events of a stage of a challenge. Event files can be tens of megabytes, so they
+    /// are streamed from backends which support ranged reads rather than buffered whole.
+    /// Concurrent loads of the same stage share a single stream and its decoded events.
+    ///
+    /// The size of the file as stored is charged to `budget` before it is decoded.
     pub async fn load_stage_events(
         &self,
         uuid: Uuid,
         stage: blert::Stage,
-    ) -> Result<blert::ChallengeEvents, Error> {
-        let file_name = self.stage_file_name(stage);
-        let raw = self
+        budget: &ChallengeBudget,
+    ) -> Result<(Arc<blert::ChallengeEvents>, FetchStats), Error> {
+        let relative_path = Self::relative_path(uuid, Self::stage_file_name(stage));
+        let charged = AtomicBool::new(false);
+        let (events, stats) = coalesce(&self.streams_in_flight, &relative_path, async {
+            let _permit = self.load_permits.acquire().await;
+            charged.store(true, Ordering::Relaxed);
+            let (events, stats) = self.stream_message(relative_path.clone(), budget).await?;
+            Ok((Arc::new(events), stats))
+        })
+        .await?;
+
+        // Callers which shared another's load did not decode the file, but it still counts
+        // towards their challenge's total.
+        if !charged.load(Ordering::Relaxed) {
+            budget.charge(&relative_path, stats.bytes)?;
+        }
+        Ok((events, stats))
+    }
+
+    /// Returns whether the repository has data for a challenge.
+    pub async fn has_challenge(&self, uuid: Uuid) -> Result<bool, Error> {
+        self.backend
+            .exists(Self::relative_path(uuid, Self::CHALLENGE_FILE_NAME))
+            .await
+    }
+
+    /// Returns the stages of a challenge whose events are in the repository, in order.
+    pub async fn stages_available(&self, uuid: Uuid) -> Result<Vec<blert::Stage>, Error> {
+        let directory = Self::relative_path(uuid, "");
+        let files = self.backend.list(directory.clone()).await?;
+
+        let mut stages = files
+            .iter()
+            .filter_map(|path| Self::stage_from_file_name(path.strip_prefix(&directory)?))
+            .collect::<Vec<_>>();
+        stages.sort_by_key(|&stage| stage as i32);
+        stages.dedup();
+        Ok(stages)
+    }
+
+    /// Reads every file of a challenge, checking that each exists, matches its stored checksum,
+    /// and decodes. The stages checked are those up to the last stage the challenge reached.
+    pub async fn verify_challenge(&self, uuid: Uuid) -> ChallengeVerification {
+        let path = Self::relative_path(uuid, Self::CHALLENGE_FILE_NAME);
+        let challenge = self
+            .load_message::<blert::ChallengeData>(path.clone(), None)
+            .await;
+        let stages = match &challenge {
+            Ok((challenge_data, _)) => Self::recorded_stages(challenge_data),
+            Err(_) => Vec::new(),
+        };
+
+        let mut files = vec![FileVerification::new(path, challenge.map(|_| ()))];
+        files.extend(
+            future::join_all(stages.into_iter().map(|stage| async move {
+                let path = Self::relative_path(uuid, Self::stage_file_name(stage));
+                let result = self
+                    .load_message::<blert::ChallengeEvents>(path.clone(), None)
+                    .await;
+                FileVerification::new(path, result.map(|_| ()))
+            }))
+            .await,
+        );
+
+        ChallengeVerification {
+            valid: files.iter().all(|file| file.status == FileStatus::Ok),
+            uuid,
+            files,
+        }
+    }
+
+    /// Returns the stages from the first stage of a challenge's type up to the last stage it
+    /// reached. Challenge types without stage files have none.
+    fn recorded_stages(challenge_data: &blert::ChallengeData) -> Vec<blert::Stage> {
+        let first_stage = match challenge_data.r#type() {
+            blert::Challenge::Tob => blert::Stage::TobMaiden,
+            blert::Challenge::Colosseum => blert::Stage::ColosseumWave1,
+            _ => return Vec::new(),
+        };
+
+        (first_stage as i32..=challenge_data.stage() as i32)
+            .filter_map(|stage| blert::Stage::try_from(stage).ok())
+            .collect()
+    }
+
+    /// Reads and decodes a protobuf message from a file in the repository. Files compressed with
+    /// gzip or zstd are transparently decompressed. If a `budget` is given, the file is charged to
+    /// it before it is decoded.
+    async fn load_message<M: Message + Default>(
+        &self,
+        relative_path: String,
+        budget: Option<&ChallengeBudget>,
+    ) -> Result<(M, FetchStats), Error> {
+        let start = Instant::now();
+        let raw = self.fetch(&relative_path).await?;
+        let fetch_time = start.elapsed();
+        if let Some(budget) = budget {
+            budget.charge(&relative_path, raw.len() as u64)?;
+        }
+
+        let start = Instant::now();
+        let message = match Compression::detect(&raw) {
+            Some(compression) => {
+                let decompressed = compression
+                    .decompress(&raw, self.limits.max_file_bytes)
+                    .map_err(|e| decompress_error(&relative_path, Some(compression), &e))?;
+                M::decode(&mut Cursor::new(&decompressed))?
+            }
+            None => M::decode(&mut Cursor::new(raw.as_slice()))?,
+        };
+
+        Ok((
+            message,
+            FetchStats {
+                bytes: raw.len() as u64,
+                fetch_time,
+                decode_time: start.elapsed(),
+            },
+        ))
+    }
+
+    /// Reads and decodes a protobuf message from a file in chunks, decompressing and decoding each
+    /// chunk as it arrives. Falls back to reading the whole file if the backend cannot read it in
+    /// parts.
+    async fn stream_message<M: Message + Default>(
+        &self,
+        relative_path: String,
+        budget: &ChallengeBudget,
+    ) -> Result<(M, FetchStats), Error> {
+        let mut stats = FetchStats::default();
+
+        let start = Instant::now();
+        let Some(first) = self
             .backend
-            .read_file(Self::relative_path(uuid, file_name))
-            .await?;
-        blert::ChallengeEvents::decode(&mut Cursor::new(&raw)).map_err(Error::from)
+            .read_range(relative_path.clone(), 0..Self::STREAM_CHUNK_SIZE)
+            .await?
+        else {
+            return self.load_message(relative_path, Some(budget)).await;
+        };
+        stats.fetch_time += start.elapsed();
+        check_file_size(&relative_path, first.file_size, self.limits.max_file_bytes)?;
+        budget.charge(&relative_path, first.file_size)?;
+
+        let compression = Compression::detect(&first.contents);
+        // Decode errors are passed through the decompressor as I/O errors.
+        let stream_error = |e: io::Error| decompress_error(&relative_path, compression, &e);
+
+        let mut decoder = StreamDecoder::<M>::new(compression, self.limits.max_file_bytes)
+            .map_err(stream_error)?;
+        let mut hasher = first.checksum.as_ref().map(|_| Sha256::new());
+        // A file which fails to decode partway through is still read to its end, so that a file
+        // which is corrupt is reported as such rather than as undecodable.
+        let mut decode_error = None;
+        let mut chunk = first.contents;
+
+        loop {
+            if let Some(hasher) = &mut hasher {
+                hasher.update(&chunk);
+            }
+            stats.bytes += chunk.len() as u64;
+
+            if decode_error.is_none() {
+                let start = Instant::now();
+                decode_error = decoder.write(&chunk).err();
+                stats.decode_time += start.elapsed();
+            }
+
+            if stats.bytes >= first.file_size {
+                break;
+            }
+            if chunk.is_empty() {
+                return Err(Error::Backend(format!(
+                    "{relative_path} ended after {} of {} bytes",
+                    stats.bytes, first.file_size
+                )));
+            }
+
+            let start = Instant::now();
+            let range = stats.bytes..stats.bytes + Self::STREAM_CHUNK_SIZE;
+            chunk = self
+                .backend
+                .read_range(relative_path.clone(), range)
+                .await?
+                .ok_or_else(|| {
+                    Error::Backend(format!("{relative_path} can no longer be read in parts"))
+                })?
+                .contents;
+            stats.fetch_time += start.elapsed();
+        }
+
+        if let (Some(hasher), Some(expected)) = (hasher, &first.checksum) {
+            verify_digest(&relative_path, &hasher.finalize(), expected)?;
+        }
+        if let Some(e) = decode_error {
+            return Err(stream_error(e));
+        }
+
+        let start = Instant::now();
+        let message = decoder.finish().map_err(stream_error)?.finish()?;
+        stats.decode_time += start.elapsed();
+
+        Ok((message, stats))
+    }
+
+    /// Reads a file from the backend, or waits for the result of a read of the same file which is
+    /// already in progress.
+    async fn fetch(&self, relative_path: &str) -> FetchResult {
+        coalesce(&self.in_flight, relative_path, async {
+            let (contents, checksum) = self
+                .backend
+                .read_file_with_checksum(relative_path.to_owned(), self.limits.max_file_bytes)
+                .await?;
+            verify(relative_path, &contents, checksum.as_deref())?;
+            Ok(Arc::new(contents))
+        })
+        .await
     }
 
     /// Returns the relative path to a file from the root of the repository.
@@ -46,7 +698,16 @@ impl DataRepository {
         format!("{}/{}/{}", &uuid[0..2], uuid.replace('-', ""), file_name)
     }
 
-    fn stage_file_name(&self, stage: blert::Stage) -> &str {
+    /// Returns the stage whose events are stored in the file with the given name, if any.
+    fn stage_from_file_name(file_name: &str) -> Option<blert::Stage> {
+        let tob = blert::Stage::TobMaiden as i32..=blert::Stage::TobVerzik as i32;
+        let colosseum = blert::Stage::ColosseumWave1 as i32..=blert::Stage::ColosseumWave12 as i32;
+        tob.chain(colosseum)
+            .filter_map(|stage| blert::Stage::try_from(stage).ok())
+            .find(|&stage| Self::stage_file_name(stage) == file_name)
+    }
+
+    fn stage_file_name(stage: blert::Stage) -> &'static str {
         match stage {
             blert::Stage::UnknownStage => todo!(),
             blert::Stage::TobMaiden => "maiden",
@@ -93,22 +754,167 @@ impl DataRepository {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, thiserror::Error)]
 pub enum Error {
+    #[error("file not found: {0}")]
     NotFound(String),
+    #[error("backend error: {0}")]
     Backend(String),
-    Decode(prost::DecodeError),
+    #[error("failed to decode protobuf: {0}")]
+    Decode(#[from] prost::DecodeError),
+    #[error("failed to decompress {0}")]
+    Decompress(String),
+    #[error("corrupt file: {0}")]
+    Corrupt(String),
+    #[error("load limit exceeded: {0}")]
+    LimitExceeded(String),
 }
 
-impl From<prost::DecodeError> for Error {
-    fn from(err: prost::DecodeError) -> Self {
-        Error::Decode(err)
+impl Error {
+    /// Returns whether the operation which produced the error may succeed if retried.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Error::Backend(_))
     }
 }
 
 #[async_trait::async_trait]
 pub trait Backend {
     async fn read_file(&self, relative_path: String) -> Result<Vec<u8>, Error>;
+
+    /// Returns whether a file exists, without reading it.
+    async fn exists(&self, relative_path: String) -> Result<bool, Error>;
+
+    /// Returns the relative paths of every file whose path starts with `prefix`.
+    async fn list(&self, prefix: String) -> Result<Vec<String>, Error>;
+
+    /// Reads a file along with the hex-encoded SHA-256 checksum stored with it when it was
+    /// uploaded, if the backend has one. Files larger than `max_bytes` are rejected with
+    /// [`Error::LimitExceeded`], without being read whole if the backend can avoid it.
+    async fn read_file_with_checksum(
+        &self,
+        relative_path: String,
+        max_bytes: u64,
+    ) -> Result<(Vec<u8>, Option<String>), Error> {
+        let contents = self.read_file(relative_path.clone()).await?;
+        check_file_size(&relative_path, contents.len() as u64, max_bytes)?;
+        Ok((contents, None))
+    }
+
+    /// Reads the bytes of a file within `range`, which may extend past its end. Returns `None` if
+    /// the backend cannot read part of the file, in which case it must be read whole.
+    async fn read_range(
+        &self,
+        _relative_path: String,
+        _range: Range<u64>,
+    ) -> Result<Option<FileRange>, Error> {
+        Ok(None)
+    }
+}
+
+/// Part of a file returned by a ranged read.
+pub struct FileRange {
+    pub contents: Vec<u8>,
+    /// Size of the whole file.
+    pub file_size: u64,
+    /// Hex-encoded SHA-256 checksum of the whole file, if the backend has one.
+    pub checksum: Option<String>,
+}
+
+/// Checks that a file of `size` bytes is within the size limit of a file.
+fn check_file_size(relative_path: &str, size: u64, max_bytes: u64) -> Result<(), Error> {
+    if size > max_bytes {
+        return Err(Error::LimitExceeded(format!(
+            "{relative_path} is {size} bytes, over the limit of {max_bytes}"
+        )));
+    }
+    Ok(())
+}
+
+/// Reads the file at `path`, stopping one byte past `max_bytes` so that a file which is over the
+/// limit is not read whole.
+fn read_file_capped(path: &Path, max_bytes: u64) -> io::Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    fs::File::open(path)?
+        .take(max_bytes.saturating_add(1))
+        .read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+/// Checks a file's contents against its stored checksum, if it has one.
+fn verify(relative_path: &str, contents: &[u8], checksum: Option<&str>) -> Result<(), Error> {
+    match checksum {
+        Some(expected) => verify_digest(relative_path, &Sha256::digest(contents), expected),
+        None => Ok(()),
+    }
+}
+
+/// Checks the SHA-256 digest of a file's contents against its stored checksum.
+fn verify_digest(relative_path: &str, digest: &[u8], expected: &str) -> Result<(), Error> {
+    let actual = digest
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>();
+    if actual.eq_ignore_ascii_case(expected.trim()) {
+        Ok(())
+    } else {
+        Err(Error::Corrupt(format!(
+            "{relative_path}: SHA-256 is {actual}, expected {expected}"
+        )))
+    }
+}
+
+/// Result of checking every file of a challenge in the repository.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ChallengeVerification {
+    pub uuid: Uuid,
+    /// Whether every file was read and decoded successfully.
+    pub valid: bool,
+    pub files: Vec<FileVerification>,
+}
+
+/// Result of checking a single repository file.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FileVerification {
+    /// Path of the file relative to the root of the repository.
+    pub path: String,
+    pub status: FileStatus,
+    /// Description of what is wrong with the file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl FileVerification {
+    fn new(path: String, result: Result<(), Error>) -> Self {
+        let status = match &result {
+            Ok(()) => FileStatus::Ok,
+            Err(Error::NotFound(_)) => FileStatus::Missing,
+            Err(Error::Corrupt(_)) => FileStatus::Corrupt,
+            Err(Error::Decode(_) | Error::Decompress(_)) => FileStatus::Undecodable,
+            Err(Error::Backend(_)) => FileStatus::Unavailable,
+            Err(Error::LimitExceeded(_)) => FileStatus::TooLarge,
+        };
+        Self {
+            path,
+            status,
+            error: result.err().map(|e| e.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FileStatus {
+    Ok,
+    /// The file does not exist.
+    Missing,
+    /// The file does not match its stored checksum, e.g. because its upload was truncated.
+    Corrupt,
+    /// The file matches its checksum but could not be decompressed or decoded.
+    Undecodable,
+    /// The backend could not be reached.
+    Unavailable,
+    /// The file is larger than the repository allows.
+    TooLarge,
 }
 
 #[derive(Debug)]
@@ -122,16 +928,618 @@ impl FilesystemBackend {
             root: root.to_owned(),
         }
     }
+
+    /// Reads the checksum of a file from the `.sha256` file beside it, if there is one.
+    fn checksum(&self, relative_path: &str) -> Option<String> {
+        let mut checksum_path = self.root.join(relative_path).into_os_string();
+        checksum_path.push(".sha256");
+        let checksum = fs::read_to_string(checksum_path).ok()?;
+        checksum.split_whitespace().next().map(str::to_owned)
+    }
 }
 
 #[async_trait::async_trait]
 impl Backend for FilesystemBackend {
     async fn read_file(&self, relative_path: String) -> Result<Vec<u8>, Error> {
         let full_path = self.root.join(relative_path);
-        fs::read(&full_path).map_err(|_| Error::NotFound(full_path.to_string_lossy().into()))
+        fs::read(&full_path).map_err(|e| io_error(&full_path, e))
+    }
+
+    async fn exists(&self, relative_path: String) -> Result<bool, Error> {
+        Ok(self.root.join(relative_path).is_file())
     }
+
+    async fn list(&self, prefix: String) -> Result<Vec<String>, Error> {
+        // Only the directory containing the prefix's final component needs to be walked.
+        let directory = prefix
+            .rsplit_once('/')
+            .map_or("", |(directory, _)| directory);
+        let mut files = Vec::new();
+        match list_files(&self.root, &self.root.join(directory), &mut files) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(Error::Backend(format!("{prefix}: {e}"))),
+        }
+        files.retain(|path| path.starts_with(&prefix));
+        Ok(files)
+    }
+
+    /// Reads the file's checksum from a `.sha256` file beside it, in the format written by
+    /// `sha256sum`.
+    async fn read_file_with_checksum(
+        &self,
+        relative_path: String,
+        max_bytes: u64,
+    ) -> Result<(Vec<u8>, Option<String>), Error> {
+        let full_path = self.root.join(&relative_path);
+        let contents =
+            read_file_capped(&full_path, max_bytes).map_err(|e| io_error(&full_path, e))?;
+        check_file_size(&relative_path, contents.len() as u64, max_bytes)?;
+        Ok((contents, self.checksum(&relative_path)))
+    }
+
+    async fn read_range(
+        &self,
+        relative_path: String,
+        range: Range<u64>,
+    ) -> Result<Option<FileRange>, Error> {
+        let full_path = self.root.join(&relative_path);
+        let (contents, file_size) =
+            read_file_range(&full_path, &range).map_err(|e| io_error(&full_path, e))?;
+        Ok(Some(FileRange {
+            contents,
+            file_size,
+            checksum: self.checksum(&relative_path),
+        }))
+    }
+}
+
+/// Converts an error reading the file at `path`. Only a missing file is reported as not found;
+/// other failures, such as a permission error, are backend errors which may not persist.
+fn io_error(path: &Path, e: io::Error) -> Error {
+    if e.kind() == io::ErrorKind::NotFound {
+        Error::NotFound(path.to_string_lossy().into())
+    } else {
+        Error::Backend(format!("{}: {e}", path.display()))
+    }
+}
+
+/// Reads the bytes of the file at `path` within `range`, along with the size of the whole file.
+fn read_file_range(path: &Path, range: &Range<u64>) -> io::Result<(Vec<u8>, u64)> {
+    let mut file = fs::File::open(path)?;
+    let file_size = file.metadata()?.len();
+    file.seek(SeekFrom::Start(range.start))?;
+    let mut contents = Vec::new();
+    file.take(range.end.saturating_sub(range.start))
+        .read_to_end(&mut contents)?;
+    Ok((contents, file_size))
 }
 
+/// Collects the path relative to `root` of every file under `dir`.
+fn list_files(root: &Path, dir: &Path, files: &mut Vec<String>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            list_files(root, &path, files)?;
+        } else if let Ok(relative_path) = path.strip_prefix(root) {
+            files.push(
+                relative_path
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/"),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Backend serving files from memory, for tests and local development without S3 or a populated
+/// filesystem tree.
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    files: RwLock<HashMap<String, Vec<u8>>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores the challenge data for a challenge.
+    pub fn insert_challenge(&self, uuid: Uuid, challenge: &blert::ChallengeData) {
+        self.insert(
+            DataRepository::relative_path(uuid, DataRepository::CHALLENGE_FILE_NAME),
+            challenge.encode_to_vec(),
+        );
+    }
+
+    /// Stores the events of a single stage of a challenge.
+    pub fn insert_stage_events(&self, uuid: Uuid, events: &blert::ChallengeEvents) {
+        self.insert(
+            DataRepository::relative_path(uuid, DataRepository::stage_file_name(events.stage())),
+            events.encode_to_vec(),
+        );
+    }
+
+    fn insert(&self, relative_path: String, contents: Vec<u8>) {
+        self.files.write().unwrap().insert(relative_path, contents);
+    }
+
+    /// Writes every stored file under `root` in the layout read by a [`FilesystemBackend`], e.g.
+    /// to record test fixtures.
+    pub fn write_to(&self, root: &Path) -> io::Result<()> {
+        for (relative_path, contents) in self.files.read().unwrap().iter() {
+            let path = root.join(relative_path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, contents)?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Backend for MemoryBackend {
+    async fn read_file(&self, relative_path: String) -> Result<Vec<u8>, Error> {
+        self.files
+            .read()
+            .unwrap()
+            .get(&relative_path)
+            .cloned()
+            .ok_or(Error::NotFound(relative_path))
+    }
+
+    async fn exists(&self, relative_path: String) -> Result<bool, Error> {
+        Ok(self.files.read().unwrap().contains_key(&relative_path))
+    }
+
+    async fn list(&self, prefix: String) -> Result<Vec<String>, Error> {
+        Ok(self
+            .files
+            .read()
+            .unwrap()
+            .keys()
+            .filter(|path| path.starts_with(&prefix))
+            .cloned()
+            .collect())
+    }
+}
+
+/// Backend which keeps the files fetched from another, typically remote, backend in a local
+/// directory, so that challenges which are analyzed again are not downloaded again. Once the
+/// cached files exceed the size limit, the least recently used ones are evicted.
+///
+/// The cache directory may be reused across restarts: files already in it are indexed on startup,
+/// oldest first by modification time.
+///
+/// A challenge's `challenge` file is rewritten as the challenge progresses, so it is never cached
+/// and is always read from the inner backend. Stage files do not change once written.
+pub struct CachingBackend {
+    inner: Box<dyn Backend + Sync + Send>,
+    root: PathBuf,
+    max_bytes: u64,
+    index: Mutex<CacheIndex>,
+}
+
+/// Suffix of files which are still being written to the cache.
+const PARTIAL_SUFFIX: &str = ".partial";
+
+impl CachingBackend {
+    /// Creates a cache of at most `max_bytes` in the directory at `root`, creating it if needed.
+    pub fn new(
+        inner: Box<dyn Backend + Sync + Send>,
+        root: &Path,
+        max_bytes: u64,
+    ) -> io::Result<Self> {
+        fs::create_dir_all(root)?;
+
+        let mut files = Vec::new();
+        Self::scan(root, root, &mut files)?;
+        files.sort_by_key(|&(_, _, modified)| modified);
+
+        let mut index = CacheIndex::default();
+        for (relative_path, size, _) in files {
+            index.insert(relative_path, size);
+        }
+
+        let backend = Self {
+            inner,
+            root: root.to_owned(),
+            max_bytes,
+            index: Mutex::new(index),
+        };
+        backend.evict();
+        Ok(backend)
+    }
+
+    /// Collects the relative path, size, and modification time of every cached file under `dir`,
+    /// removing partially-written files left behind by a previous process.
+    fn scan(root: &Path, dir: &Path, files: &mut Vec<(String, u64, SystemTime)>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                Self::scan(root, &path, files)?;
+                continue;
+            }
+
+            let Ok(relative_path) = path.strip_prefix(root) else {
+                continue;
+            };
+            let relative_path = relative_path
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            if relative_path.ends_with(PARTIAL_SUFFIX) || !Self::is_cacheable(&relative_path) {
+                fs::remove_file(&path)?;
+                continue;
+            }
+
+            files.push((relative_path, metadata.len(), metadata.modified()?));
+        }
+        Ok(())
+    }
+
+    /// Returns whether a file's contents are final, and can therefore be cached.
+    fn is_cacheable(relative_path: &str) -> bool {
+        relative_path
+            .rsplit('/')
+            .next()
+            .is_some_and(|file_name| file_name != DataRepository::CHALLENGE_FILE_NAME)
+    }
+
+    /// Writes a fetched file to the cache, evicting other files to make room for it. Files larger
+    /// than the whole cache are not stored.
+    async fn store(&self, relative_path: &str, contents: &[u8]) -> io::Result<()> {
+        let size = contents.len() as u64;
+        if size > self.max_bytes {
+            return Ok(());
+        }
+
+        let path = self.root.join(relative_path);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        // Concurrent fetches of the same file each write their own copy, and the last to finish
+        // replaces the others.
+        let mut partial = path.clone().into_os_string();
+        partial.push(format!(".{}{PARTIAL_SUFFIX}", Uuid::new_v4().simple()));
+        tokio::fs::write(&partial, contents).await?;
+        tokio::fs::rename(&partial, &path).await?;
+
+        let evicted = {
+            let mut index = self.index.lock().unwrap();
+            index.insert(relative_path.to_owned(), size);
+            index.evict(self.max_bytes)
+        };
+        for relative_path in evicted {
+            let path = self.root.join(&relative_path);
+            if let Err(e) = tokio::fs::remove_file(&path).await {
+                tracing::warn!("Failed to evict {} from cache: {e}", path.display());
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the least recently used files until the cache fits within its size limit.
+    fn evict(&self) {
+        let evicted = self.index.lock().unwrap().evict(self.max_bytes);
+        for relative_path in evicted {
+            let path = self.root.join(&relative_path);
+            if let Err(e) = fs::remove_file(&path) {
+                tracing::warn!("Failed to evict {} from cache: {e}", path.display());
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Backend for CachingBackend {
+    async fn read_file(&self, relative_path: String) -> Result<Vec<u8>, Error> {
+        self.read_file_with_checksum(relative_path, u64::MAX)
+            .await
+            .map(|(contents, _)| contents)
+    }
+
+    async fn exists(&self, relative_path: String) -> Result<bool, Error> {
+        if self
+            .index
+            .lock()
+            .unwrap()
+            .entries
+            .contains_key(&relative_path)
+        {
+            return Ok(true);
+        }
+        self.inner.exists(relative_path).await
+    }
+
+    /// Lists files in the inner backend, as the cache holds only some of them.
+    async fn list(&self, prefix: String) -> Result<Vec<String>, Error> {
+        self.inner.list(prefix).await
+    }
+
+    /// Only cached files are read in parts, so that files which are not yet cached are read whole
+    /// and cached.
+    ///
+    /// A file may be evicted after its first part has been read. The rest of it is then read from
+    /// the inner backend, in parts if it supports them or otherwise by fetching and caching the
+    /// file again, so that a read in progress is never abandoned.
+    async fn read_range(
+        &self,
+        relative_path: String,
+        range: Range<u64>,
+    ) -> Result<Option<FileRange>, Error> {
+        if self.index.lock().unwrap().touch(&relative_path) {
+            let path = self.root.join(&relative_path);
+            match read_file_range(&path, &range) {
+                Ok((contents, file_size)) => {
+                    return Ok(Some(FileRange {
+                        contents,
+                        file_size,
+                        checksum: None,
+                    }));
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to read cached file {}: {e}", path.display());
+                    self.index.lock().unwrap().remove(&relative_path);
+                }
+            }
+        }
+
+        if range.start == 0 {
+            return Ok(None);
+        }
+        if let Some(part) = self
+            .inner
+            .read_range(relative_path.clone(), range.clone())
+            .await?
+        {
+            return Ok(Some(part));
+        }
+
+        let contents = self.read_file(relative_path).await?;
+        let file_size = contents.len() as u64;
+        let start = range.start.min(file_size);
+        let end = range.end.clamp(start, file_size);
+        Ok(Some(FileRange {
+            contents: contents[start as usize..end as usize].to_vec(),
+            file_size,
+            checksum: None,
+        }))
+    }
+
+    /// Files fetched from the inner backend are verified before they are cached, so cached files
+    /// are returned without a checksum.
+    async fn read_file_with_checksum(
+        &self,
+        relative_path: String,
+        max_bytes: u64,
+    ) -> Result<(Vec<u8>, Option<String>), Error> {
+        if !Self::is_cacheable(&relative_path) {
+            return self
+                .inner
+                .read_file_with_checksum(relative_path, max_bytes)
+                .await;
+        }
+
+        if self.index.lock().unwrap().touch(&relative_path) {
+            let path = self.root.join(&relative_path);
+            match read_file_capped(&path, max_bytes) {
+                Ok(contents) => {
+                    check_file_size(&relative_path, contents.len() as u64, max_bytes)?;
+                    return Ok((contents, None));
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to read cached file {}: {e}", path.display());
+                    self.index.lock().unwrap().remove(&relative_path);
+                }
+            }
+        }
+
+        let (contents, checksum) = self
+            .inner
+            .read_file_with_checksum(relative_path.clone(), max_bytes)
+            .await?;
+        verify(&relative_path, &contents, checksum.as_deref())?;
+        if let Err(e) = self.store(&relative_path, &contents).await {
+            tracing::warn!("Failed to cache {relative_path}: {e}");
+        }
+        Ok((contents, None))
+    }
+}
+
+/// Sizes and recency of use of the files in a [`CachingBackend`].
+#[derive(Debug, Default)]
+struct CacheIndex {
+    /// Size and last use of each cached file, keyed by relative path.
+    entries: HashMap<String, (u64, u64)>,
+    /// Cached files keyed by their last use, least recent first.
+    by_use: BTreeMap<u64, String>,
+    total_bytes: u64,
+    /// Incremented on every use of a file.
+    clock: u64,
+}
+
+impl CacheIndex {
+    /// Marks a file as just used. Returns `false` if it is not cached.
+    fn touch(&mut self, relative_path: &str) -> bool {
+        let Some((_, last_use)) = self.entries.get_mut(relative_path) else {
+            return false;
+        };
+        self.by_use.remove(last_use);
+        self.clock += 1;
+        *last_use = self.clock;
+        self.by_use.insert(self.clock, relative_path.to_owned());
+        true
+    }
+
+    fn insert(&mut self, relative_path: String, size: u64) {
+        self.remove(&relative_path);
+        self.clock += 1;
+        self.entries
+            .insert(relative_path.clone(), (size, self.clock));
+        self.by_use.insert(self.clock, relative_path);
+        self.total_bytes += size;
+    }
+
+    fn remove(&mut self, relative_path: &str) {
+        if let Some((size, last_use)) = self.entries.remove(relative_path) {
+            self.by_use.remove(&last_use);
+            self.total_bytes -= size;
+        }
+    }
+
+    /// Removes the least recently used files until the total size is at most `max_bytes`,
+    /// returning their paths.
+    fn evict(&mut self, max_bytes: u64) -> Vec<String> {
+        let mut evicted = Vec::new();
+        while self.total_bytes > max_bytes {
+            let Some((_, relative_path)) = self.by_use.pop_first() else {
+                break;
+            };
+            if let Some((size, _)) = self.entries.remove(&relative_path) {
+                self.total_bytes -= size;
+            }
+            evicted.push(relative_path);
+        }
+        evicted
+    }
+}
+
+/// Credentials with which to access S3.
+#[derive(Debug, Clone, Default)]
+pub enum S3Credentials {
+    /// Credentials resolved by the default AWS provider chain, e.g. from the standard AWS
+    /// environment variables or an instance profile.
+    #[default]
+    Default,
+    /// An explicit access key.
+    AccessKey {
+        access_key_id: String,
+        secret_access_key: String,
+        session_token: Option<String>,
+    },
+    /// A role assumed using the default credentials.
+    AssumeRole {
+        role_arn: String,
+        external_id: Option<String>,
+    },
+}
+
+/// Options for connecting to S3.
+#[derive(Debug, Clone)]
+pub struct S3Options {
+    /// Endpoint of an S3-compatible service. If unset, AWS S3 is used.
+    pub endpoint: Option<String>,
+    /// Maximum number of attempts of each request, including the first.
+    pub max_attempts: u32,
+    /// Delay before the first retry of a failed request, doubled with each further retry.
+    pub initial_backoff: Duration,
+    /// Time allowed for a single attempt of a request.
+    pub attempt_timeout: Duration,
+    /// Time allowed for a request, across all of its attempts.
+    pub operation_timeout: Duration,
+    pub credentials: S3Credentials,
+}
+
+impl Default for S3Options {
+    fn default() -> Self {
+        Self {
+            endpoint: None,
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(200),
+            attempt_timeout: Duration::from_secs(10),
+            operation_timeout: Duration::from_secs(30),
+            credentials: S3Credentials::Default,
+        }
+    }
+}
+
+impl S3Options {
+    /// Reads the options from the environment:
+    ///
+    /// - `BLERT_S3_ENDPOINT`: endpoint of an S3-compatible service.
+    /// - `BLERT_S3_MAX_ATTEMPTS`, `BLERT_S3_INITIAL_BACKOFF_MS`, `BLERT_S3_ATTEMPT_TIMEOUT_MS`,
+    ///   and `BLERT_S3_TIMEOUT_MS`: retry and timeout settings.
+    /// - `BLERT_S3_ACCESS_KEY_ID` and `BLERT_S3_SECRET_ACCESS_KEY`, with an optional
+    ///   `BLERT_S3_SESSION_TOKEN`: an explicit access key.
+    /// - `BLERT_S3_ROLE_ARN`, with an optional `BLERT_S3_EXTERNAL_ID`: a role to assume.
+    ///
+    /// Unset options keep their defaults. An access key and a role cannot both be set.
+    pub fn from_env() -> crate::error::Result<Self> {
+        let defaults = Self::default();
+
+        let access_key = match (
+            env::var("BLERT_S3_ACCESS_KEY_ID"),
+            env::var("BLERT_S3_SECRET_ACCESS_KEY"),
+        ) {
+            (Ok(access_key_id), Ok(secret_access_key)) => Some(S3Credentials::AccessKey {
+                access_key_id,
+                secret_access_key,
+                session_token: env::var("BLERT_S3_SESSION_TOKEN").ok(),
+            }),
+            (Ok(_), Err(_)) => {
+                return Err(crate::error::Error::Environment(
+                    "BLERT_S3_SECRET_ACCESS_KEY",
+                ))
+            }
+            (Err(_), Ok(_)) => {
+                return Err(crate::error::Error::Environment("BLERT_S3_ACCESS_KEY_ID"))
+            }
+            (Err(_), Err(_)) => None,
+        };
+        let role = env::var("BLERT_S3_ROLE_ARN")
+            .ok()
+            .map(|role_arn| S3Credentials::AssumeRole {
+                role_arn,
+                external_id: env::var("BLERT_S3_EXTERNAL_ID").ok(),
+            });
+        let credentials = match (access_key, role) {
+            (Some(_), Some(_)) => {
+                return Err(crate::error::Error::Environment("BLERT_S3_ROLE_ARN"));
+            }
+            (Some(credentials), None) | (None, Some(credentials)) => credentials,
+            (None, None) => S3Credentials::Default,
+        };
+
+        Ok(Self {
+            endpoint: env::var("BLERT_S3_ENDPOINT").ok(),
+            max_attempts: env_or("BLERT_S3_MAX_ATTEMPTS", defaults.max_attempts)?,
+            initial_backoff: Duration::from_millis(env_or(
+                "BLERT_S3_INITIAL_BACKOFF_MS",
+                defaults.initial_backoff.as_millis() as u64,
+            )?),
+            attempt_timeout: Duration::from_millis(env_or(
+                "BLERT_S3_ATTEMPT_TIMEOUT_MS",
+                defaults.attempt_timeout.as_millis() as u64,
+            )?),
+            operation_timeout: Duration::from_millis(env_or(
+                "BLERT_S3_TIMEOUT_MS",
+                defaults.operation_timeout.as_millis() as u64,
+            )?),
+            credentials,
+        })
+    }
+}
+
+/// Parses an environment variable, returning `default` if it is unset.
+fn env_or<T: std::str::FromStr>(name: &'static str, default: T) -> crate::error::Result<T> {
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map_err(|_| crate::error::Error::Environment(name)),
+        Err(_) => Ok(default),
+    }
+}
+
+/// Backend reading files from an S3 bucket. Failed requests are retried with exponential backoff
+/// when S3 reports a transient error, such as a 5xx response or throttling.
 #[derive(Debug)]
 pub struct S3Backend {
     bucket: String,
@@ -139,13 +1547,141 @@ pub struct S3Backend {
 }
 
 impl S3Backend {
-    pub async fn init(endpoint: &str, bucket: &str) -> Self {
-        let config = aws_config::defaults(aws_config::BehaviorVersion::latest())
-            .endpoint_url(endpoint)
-            .load()
-            .await;
-        let client = aws_sdk_s3::Client::new(&config);
+    /// User metadata key under which uploaders store the hex-encoded SHA-256 of each object.
+    const CHECKSUM_METADATA_KEY: &'static str = "sha256";
+
+    /// Reads an object, or the bytes of it within `range`. A range starting past the end of the
+    /// object reads nothing. Reads of more than `max_bytes` are rejected, before the object's
+    /// body is read if S3 reports its length.
+    async fn get_object(
+        &self,
+        relative_path: String,
+        range: Option<Range<u64>>,
+        max_bytes: u64,
+    ) -> Result<FileRange, Error> {
+        use aws_sdk_s3::error::{DisplayErrorContext, SdkError};
+
+        let object = match self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(&relative_path)
+            .set_range(
+                range
+                    .as_ref()
+                    .map(|range| format!("bytes={}-{}", range.start, range.end - 1)),
+            )
+            .send()
+            .await
+        {
+            Ok(object) => object,
+            Err(SdkError::ServiceError(e))
+                if e.err().is_no_such_key() || e.raw().status().as_u16() == 404 =>
+            {
+                return Err(Error::NotFound(relative_path));
+            }
+            Err(SdkError::ServiceError(e)) if e.raw().status().as_u16() == 416 => {
+                return Ok(FileRange {
+                    contents: Vec::new(),
+                    file_size: range.map_or(0, |range| range.start),
+                    checksum: None,
+                });
+            }
+            Err(e) => {
+                return Err(Error::Backend(format!(
+                    "{relative_path}: {}",
+                    DisplayErrorContext(&e)
+                )));
+            }
+        };
 
+        let checksum = object
+            .metadata()
+            .and_then(|metadata| metadata.get(Self::CHECKSUM_METADATA_KEY))
+            .cloned();
+        // A ranged read reports the size of the whole object in its `Content-Range`, e.g.
+        // `bytes 0-99/1234`.
+        let file_size = object
+            .content_range()
+            .and_then(|content_range| content_range.rsplit_once('/'))
+            .and_then(|(_, size)| size.parse().ok());
+        if let Some(length) = object
+            .content_length()
+            .and_then(|length| u64::try_from(length).ok())
+        {
+            check_file_size(&relative_path, length, max_bytes)?;
+        }
+
+        let mut body = object.body;
+        let mut contents = Vec::new();
+        while let Some(bytes) = body
+            .try_next()
+            .await
+            .map_err(|e| Error::Backend(format!("{relative_path}: {e}")))?
+        {
+            check_file_size(
+                &relative_path,
+                (contents.len() + bytes.len()) as u64,
+                max_bytes,
+            )?;
+            contents.extend_from_slice(&bytes);
+        }
+
+        Ok(FileRange {
+            file_size: file_size
+                .unwrap_or_else(|| range.map_or(0, |range| range.start) + contents.len() as u64),
+            contents,
+            checksum,
+        })
+    }
+
+    pub async fn init(bucket: &str, options: &S3Options) -> Self {
+        let retry = aws_config::retry::RetryConfig::standard()
+            .with_max_attempts(options.max_attempts.max(1))
+            .with_initial_backoff(options.initial_backoff);
+        let timeout = aws_config::timeout::TimeoutConfig::builder()
+            .operation_attempt_timeout(options.attempt_timeout)
+            .operation_timeout(options.operation_timeout)
+            .build();
+
+        let mut loader = aws_config::defaults(aws_config::BehaviorVersion::latest())
+            .retry_config(retry)
+            .timeout_config(timeout);
+        if let Some(endpoint) = &options.endpoint {
+            loader = loader.endpoint_url(endpoint);
+        }
+
+        loader = match &options.credentials {
+            S3Credentials::Default => loader,
+            S3Credentials::AccessKey {
+                access_key_id,
+                secret_access_key,
+                session_token,
+            } => loader.credentials_provider(aws_sdk_s3::config::Credentials::new(
+                access_key_id,
+                secret_access_key,
+                session_token.clone(),
+                None,
+                "blert",
+            )),
+            S3Credentials::AssumeRole {
+                role_arn,
+                external_id,
+            } => {
+                let base = aws_config::defaults(aws_config::BehaviorVersion::latest())
+                    .load()
+                    .await;
+                let mut provider = aws_config::sts::AssumeRoleProvider::builder(role_arn)
+                    .session_name("raid-analyzer")
+                    .configure(&base);
+                if let Some(external_id) = external_id {
+                    provider = provider.external_id(external_id);
+                }
+                loader.credentials_provider(provider.build().await)
+            }
+        };
+
+        let client = aws_sdk_s3::Client::new(&loader.load().await);
         Self {
             bucket: bucket.to_owned(),
             client,
@@ -156,20 +1692,557 @@ impl S3Backend {
 #[async_trait::async_trait]
 impl Backend for S3Backend {
     async fn read_file(&self, relative_path: String) -> Result<Vec<u8>, Error> {
-        let object = self
+        self.read_file_with_checksum(relative_path, u64::MAX)
+            .await
+            .map(|(contents, _)| contents)
+    }
+
+    async fn exists(&self, relative_path: String) -> Result<bool, Error> {
+        use aws_sdk_s3::error::{DisplayErrorContext, SdkError};
+
+        match self
             .client
-            .get_object()
+            .head_object()
             .bucket(&self.bucket)
             .key(&relative_path)
             .send()
             .await
-            .map_err(|_| Error::NotFound(relative_path))?;
+        {
+            Ok(_) => Ok(true),
+            Err(SdkError::ServiceError(e))
+                if e.err().is_not_found() || e.raw().status().as_u16() == 404 =>
+            {
+                Ok(false)
+            }
+            Err(e) => Err(Error::Backend(format!(
+                "{relative_path}: {}",
+                DisplayErrorContext(&e)
+            ))),
+        }
+    }
 
-        let object = object
-            .body
-            .collect()
+    async fn list(&self, prefix: String) -> Result<Vec<String>, Error> {
+        use aws_sdk_s3::error::DisplayErrorContext;
+
+        let mut pages = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(&prefix)
+            .into_paginator()
+            .send();
+
+        let mut files = Vec::new();
+        while let Some(page) = pages.next().await {
+            let page =
+                page.map_err(|e| Error::Backend(format!("{prefix}: {}", DisplayErrorContext(&e))))?;
+            files.extend(
+                page.contents()
+                    .iter()
+                    .filter_map(|object| object.key().map(str::to_owned)),
+            );
+        }
+        Ok(files)
+    }
+
+    /// Reads the file's checksum from the `sha256` user metadata set by the uploader
+    /// (`x-amz-meta-sha256`).
+    async fn read_file_with_checksum(
+        &self,
+        relative_path: String,
+        max_bytes: u64,
+    ) -> Result<(Vec<u8>, Option<String>), Error> {
+        let object = self.get_object(relative_path, None, max_bytes).await?;
+        Ok((object.contents, object.checksum))
+    }
+
+    async fn read_range(
+        &self,
+        relative_path: String,
+        range: Range<u64>,
+    ) -> Result<Option<FileRange>, Error> {
+        let max_bytes = range.end.saturating_sub(range.start);
+        self.get_object(relative_path, Some(range), max_bytes)
+            .await
+            .map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn memory_backend_serves_stored_messages() {
+        let uuid = Uuid::new_v4();
+        let backend = MemoryBackend::new();
+
+        let challenge = blert::ChallengeData {
+            party: vec!["player".to_owned()],
+            ..Default::default()
+        };
+        backend.insert_challenge(uuid, &challenge);
+
+        let mut events = blert::ChallengeEvents::default();
+        events.set_stage(blert::Stage::TobBloat);
+        backend.insert_stage_events(uuid, &events);
+
+        let repository = DataRepository::new(Box::new(backend));
+        let (loaded, stats) = repository.load_challenge(uuid).await.unwrap();
+        assert_eq!(loaded, challenge);
+        assert_eq!(stats.bytes, challenge.encoded_len() as u64);
+
+        let (loaded, _) = repository
+            .load_stage_events(uuid, blert::Stage::TobBloat, &repository.challenge_budget())
             .await
-            .map_err(|e| Error::Backend(e.to_string()))?;
-        Ok(object.to_vec())
+            .unwrap();
+        assert_eq!(loaded.stage(), blert::Stage::TobBloat);
+
+        assert!(matches!(
+            repository
+                .load_stage_events(
+                    uuid,
+                    blert::Stage::TobMaiden,
+                    &repository.challenge_budget()
+                )
+                .await,
+            Err(Error::NotFound(_))
+        ));
+
+        assert!(repository.has_challenge(uuid).await.unwrap());
+        assert!(!repository.has_challenge(Uuid::new_v4()).await.unwrap());
+        assert_eq!(
+            repository.stages_available(uuid).await.unwrap(),
+            [blert::Stage::TobBloat]
+        );
+    }
+
+    /// Backend which counts its reads, each taking a little while.
+    struct CountingBackend {
+        inner: MemoryBackend,
+        reads: Arc<AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl Backend for CountingBackend {
+        async fn read_file(&self, relative_path: String) -> Result<Vec<u8>, Error> {
+            self.reads.fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.inner.read_file(relative_path).await
+        }
+
+        async fn exists(&self, relative_path: String) -> Result<bool, Error> {
+            self.inner.exists(relative_path).await
+        }
+
+        async fn list(&self, prefix: String) -> Result<Vec<String>, Error> {
+            self.inner.list(prefix).await
+        }
+
+        async fn read_range(
+            &self,
+            relative_path: String,
+            range: Range<u64>,
+        ) -> Result<Option<FileRange>, Error> {
+            let contents = self.read_file(relative_path).await?;
+            let file_size = contents.len() as u64;
+            let end = range.end.min(file_size);
+            Ok(Some(FileRange {
+                contents: contents[range.start.min(end) as usize..end as usize].to_vec(),
+                file_size,
+                checksum: None,
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrent_reads_share_a_fetch() {
+        let uuid = Uuid::new_v4();
+        let inner = MemoryBackend::new();
+        inner.insert_challenge(uuid, &blert::ChallengeData::default());
+        let mut events = blert::ChallengeEvents::default();
+        events.set_stage(blert::Stage::TobBloat);
+        inner.insert_stage_events(uuid, &events);
+        let reads = Arc::new(AtomicUsize::new(0));
+        let repository = DataRepository::new(Box::new(CountingBackend {
+            inner,
+            reads: reads.clone(),
+        }));
+
+        let (first, second) = tokio::join!(
+            repository.load_challenge(uuid),
+            repository.load_challenge(uuid)
+        );
+        assert!(first.is_ok() && second.is_ok());
+        assert_eq!(reads.load(Ordering::Relaxed), 1);
+        assert!(repository.in_flight.lock().unwrap().is_empty());
+
+        // Reads which do not overlap each fetch the file.
+        repository.load_challenge(uuid).await.unwrap();
+        assert_eq!(reads.load(Ordering::Relaxed), 2);
+
+        // Streamed stage loads are shared in the same way.
+        let (first, second) = tokio::join!(
+            repository.load_stage_events(
+                uuid,
+                blert::Stage::TobBloat,
+                &repository.challenge_budget()
+            ),
+            repository.load_stage_events(
+                uuid,
+                blert::Stage::TobBloat,
+                &repository.challenge_budget()
+            )
+        );
+        assert!(Arc::ptr_eq(&first.unwrap().0, &second.unwrap().0));
+        assert_eq!(reads.load(Ordering::Relaxed), 3);
+        assert!(repository.streams_in_flight.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn waiters_take_over_a_cancelled_fetch() {
+        let uuid = Uuid::new_v4();
+        let inner = MemoryBackend::new();
+        inner.insert_challenge(uuid, &blert::ChallengeData::default());
+        let reads = Arc::new(AtomicUsize::new(0));
+        let repository = DataRepository::new(Box::new(CountingBackend {
+            inner,
+            reads: reads.clone(),
+        }));
+
+        let mut leader = Box::pin(repository.load_challenge(uuid));
+        assert!(futures::poll!(&mut leader).is_pending());
+        let mut waiter = Box::pin(repository.load_challenge(uuid));
+        assert!(futures::poll!(&mut waiter).is_pending());
+        assert_eq!(reads.load(Ordering::Relaxed), 1);
+
+        // The leader's caller goes away partway through its read.
+        drop(leader);
+        waiter.await.unwrap();
+        assert_eq!(reads.load(Ordering::Relaxed), 2);
+        assert!(repository.in_flight.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn compressed_files_are_decompressed() {
+        let uuid = Uuid::new_v4();
+        let challenge = blert::ChallengeData {
+            party: vec!["player".to_owned()],
+            ..Default::default()
+        };
+        let mut events = blert::ChallengeEvents::default();
+        events.set_stage(blert::Stage::TobBloat);
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(&challenge.encode_to_vec()).unwrap();
+        let backend = MemoryBackend::new();
+        backend.insert(
+            DataRepository::relative_path(uuid, DataRepository::CHALLENGE_FILE_NAME),
+            gzip.finish().unwrap(),
+        );
+        backend.insert(
+            DataRepository::relative_path(uuid, "bloat"),
+            zstd::encode_all(events.encode_to_vec().as_slice(), 0).unwrap(),
+        );
+        backend.insert(
+            DataRepository::relative_path(uuid, "maiden"),
+            Compression::ZSTD_MAGIC.to_vec(),
+        );
+
+        let repository = DataRepository::new(Box::new(backend));
+        let (loaded, _) = repository.load_challenge(uuid).await.unwrap();
+        assert_eq!(loaded, challenge);
+        let (loaded, _) = repository
+            .load_stage_events(uuid, blert::Stage::TobBloat, &repository.challenge_budget())
+            .await
+            .unwrap();
+        assert_eq!(loaded.stage(), blert::Stage::TobBloat);
+        assert!(matches!(
+            repository
+                .load_stage_events(
+                    uuid,
+                    blert::Stage::TobMaiden,
+                    &repository.challenge_budget()
+                )
+                .await,
+            Err(Error::Decompress(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn caching_backend_evicts_least_recently_used() {
+        let root = std::env::temp_dir().join(format!("blert-cache-{}", Uuid::new_v4()));
+        let remote = MemoryBackend::new();
+        remote.insert("ab/first".into(), vec![1; 40]);
+        remote.insert("ab/second".into(), vec![2; 40]);
+        remote.insert("cd/third".into(), vec![3; 40]);
+
+        let cache = CachingBackend::new(Box::new(remote), &root, 100).unwrap();
+        cache.read_file("ab/first".into()).await.unwrap();
+        cache.read_file("ab/second".into()).await.unwrap();
+        cache.read_file("ab/first".into()).await.unwrap();
+        cache.read_file("cd/third".into()).await.unwrap();
+        assert!(root.join("ab/first").exists());
+        assert!(!root.join("ab/second").exists());
+        assert!(root.join("cd/third").exists());
+
+        // Cached files are served without the remote backend after a restart.
+        let cache = CachingBackend::new(Box::new(MemoryBackend::new()), &root, 100).unwrap();
+        assert_eq!(cache.read_file("cd/third".into()).await.unwrap(), [3; 40]);
+        assert!(matches!(
+            cache.read_file("ab/second".into()).await,
+            Err(Error::NotFound(_))
+        ));
+
+        // Challenge files may still change, so they are always read from the remote backend.
+        let remote = MemoryBackend::new();
+        remote.insert("ab/challenge".into(), vec![4; 10]);
+        let cache = CachingBackend::new(Box::new(remote), &root, 100).unwrap();
+        cache.read_file("ab/challenge".into()).await.unwrap();
+        assert!(!root.join("ab/challenge").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn caching_backend_reads_evicted_files_in_parts() {
+        let root = std::env::temp_dir().join(format!("blert-cache-{}", Uuid::new_v4()));
+        let remote = MemoryBackend::new();
+        remote.insert("ab/first".into(), (0..40).collect());
+        remote.insert("ab/second".into(), vec![2; 40]);
+        remote.insert("cd/third".into(), vec![3; 40]);
+
+        let cache = CachingBackend::new(Box::new(remote), &root, 100).unwrap();
+        cache.read_file("ab/first".into()).await.unwrap();
+        let first = cache
+            .read_range("ab/first".into(), 0..10)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(first.contents, (0..10).collect::<Vec<u8>>());
+
+        // The file is evicted partway through the read, which continues from the remote backend.
+        cache.read_file("ab/second".into()).await.unwrap();
+        cache.read_file("cd/third".into()).await.unwrap();
+        assert!(!root.join("ab/first").exists());
+
+        let rest = cache
+            .read_range("ab/first".into(), 10..50)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(rest.contents, (10..40).collect::<Vec<u8>>());
+        assert_eq!(rest.file_size, 40);
+
+        // Reads which have not started are still left to read the file whole.
+        assert!(cache
+            .read_range("ab/second".into(), 0..10)
+            .await
+            .unwrap()
+            .is_none());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn messages_decode_incrementally() {
+        let mut events = blert::ChallengeEvents {
+            party_names: vec!["player".to_owned(); 5],
+            events: (0..200)
+                .map(|tick| blert::Event {
+                    tick,
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        events.set_stage(blert::Stage::TobVerzik);
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(&events.encode_to_vec()).unwrap();
+        let compressed = gzip.finish().unwrap();
+
+        // Every chunk boundary splits a field somewhere.
+        let mut decoder =
+            StreamDecoder::<blert::ChallengeEvents>::new(Some(Compression::Gzip), u64::MAX)
+                .unwrap();
+        for chunk in compressed.chunks(7) {
+            decoder.write(chunk).unwrap();
+        }
+        assert_eq!(decoder.finish().unwrap().finish().unwrap(), events);
+
+        let encoded = events.encode_to_vec();
+        let mut decoder = MessageDecoder::<blert::ChallengeEvents>::new(u64::MAX);
+        decoder.write_all(&encoded[..encoded.len() - 1]).unwrap();
+        assert!(decoder.finish().is_err());
+    }
+
+    #[tokio::test]
+    async fn files_over_the_size_limit_are_rejected() {
+        let uuid = Uuid::new_v4();
+        let backend = MemoryBackend::new();
+        backend.insert_challenge(
+            uuid,
+            &blert::ChallengeData {
+                party: vec!["player".to_owned(); 100],
+                ..Default::default()
+            },
+        );
+
+        let mut events = blert::ChallengeEvents {
+            party_names: vec!["player".to_owned(); 5],
+            ..Default::default()
+        };
+        events.set_stage(blert::Stage::TobMaiden);
+        backend.insert_stage_events(uuid, &events);
+        events.set_stage(blert::Stage::TobBloat);
+        backend.insert_stage_events(uuid, &events);
+
+        let repository = DataRepository::new(Box::new(backend)).with_limits(LoadLimits {
+            max_file_bytes: 64,
+            max_challenge_bytes: events.encoded_len() as u64 + 1,
+            ..LoadLimits::default()
+        });
+        assert!(matches!(
+            repository.load_challenge(uuid).await,
+            Err(Error::LimitExceeded(_))
+        ));
+
+        // Stage files within the file limit still count towards their challenge's limit.
+        let budget = repository.challenge_budget();
+        repository
+            .load_stage_events(uuid, blert::Stage::TobMaiden, &budget)
+            .await
+            .unwrap();
+        assert!(matches!(
+            repository
+                .load_stage_events(uuid, blert::Stage::TobBloat, &budget)
+                .await,
+            Err(Error::LimitExceeded(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn files_decompressing_over_the_size_limit_are_rejected() {
+        let uuid = Uuid::new_v4();
+        let mut events = blert::ChallengeEvents {
+            party_names: vec!["player".to_owned(); 10_000],
+            ..Default::default()
+        };
+        events.set_stage(blert::Stage::TobMaiden);
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        gzip.write_all(&events.encode_to_vec()).unwrap();
+        let compressed = gzip.finish().unwrap();
+
+        let limits = LoadLimits {
+            max_file_bytes: 1024,
+            ..LoadLimits::default()
+        };
+        assert!((compressed.len() as u64) < limits.max_file_bytes);
+
+        // Files are decompressed whole from backends which cannot read them in parts, and
+        // streamed from those which can.
+        let backend = MemoryBackend::new();
+        backend.insert(
+            DataRepository::relative_path(uuid, "maiden"),
+            compressed.clone(),
+        );
+        let whole = DataRepository::new(Box::new(backend)).with_limits(limits);
+
+        let inner = MemoryBackend::new();
+        inner.insert(DataRepository::relative_path(uuid, "maiden"), compressed);
+        let streamed = DataRepository::new(Box::new(CountingBackend {
+            inner,
+            reads: Arc::new(AtomicUsize::new(0)),
+        }))
+        .with_limits(limits);
+
+        for repository in [whole, streamed] {
+            assert!(matches!(
+                repository
+                    .load_stage_events(
+                        uuid,
+                        blert::Stage::TobMaiden,
+                        &repository.challenge_budget()
+                    )
+                    .await,
+                Err(Error::LimitExceeded(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn verification_distinguishes_corrupt_and_missing_files() {
+        let root = std::env::temp_dir().join(format!("blert-verify-{}", Uuid::new_v4()));
+        let uuid = Uuid::new_v4();
+        let write = |file_name: &str, contents: &[u8]| {
+            let path = root.join(DataRepository::relative_path(uuid, file_name));
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        };
+
+        let mut challenge = blert::ChallengeData::default();
+        challenge.set_type(blert::Challenge::Tob);
+        challenge.set_stage(blert::Stage::TobBloat);
+        write("challenge", &challenge.encode_to_vec());
+
+        // A truncated upload whose checksum was computed over the complete file.
+        let events = blert::ChallengeEvents::default().encode_to_vec();
+        write("maiden", &events[..events.len().saturating_sub(1)]);
+        write(
+            "maiden.sha256",
+            format!("{}  maiden\n", "0".repeat(64)).as_bytes(),
+        );
+
+        let repository = DataRepository::new(Box::new(FilesystemBackend::new(&root)));
+        let verification = repository.verify_challenge(uuid).await;
+        let statuses = verification
+            .files
+            .iter()
+            .map(|file| file.status)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            statuses,
+            [FileStatus::Ok, FileStatus::Corrupt, FileStatus::Missing]
+        );
+        assert!(!verification.valid);
+        assert!(matches!(
+            repository
+                .load_stage_events(
+                    uuid,
+                    blert::Stage::TobMaiden,
+                    &repository.challenge_budget()
+                )
+                .await,
+            Err(Error::Corrupt(_))
+        ));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn filesystem_read_failures_other_than_missing_files_are_backend_errors() {
+        let root = std::env::temp_dir().join(format!("blert-read-{}", Uuid::new_v4()));
+        fs::create_dir_all(root.join("directory")).unwrap();
+        let backend = FilesystemBackend::new(&root);
+
+        assert!(matches!(
+            backend.read_file("missing".into()).await,
+            Err(Error::NotFound(_))
+        ));
+        assert!(matches!(
+            backend.read_file("directory".into()).await,
+            Err(Error::Backend(_))
+        ));
+        assert!(matches!(
+            backend
+                .read_file_with_checksum("directory".into(), u64::MAX)
+                .await,
+            Err(Error::Backend(_))
+        ));
+
+        fs::remove_dir_all(&root).unwrap();
     }
 }
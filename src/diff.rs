@@ -0,0 +1,308 @@
+//! Structural diffing of serialized run reports, used to evaluate analyzer changes by comparing
+//! the reports produced by two versions of a program for the same challenge.
+//!
+//! Values are compared by position, except for the report's findings, which are matched by their
+//! analyzer, category, player, and tick range so that an added or removed finding does not shift
+//! every finding after it.
+
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::error::{Error, Result};
+
+/// A single difference between two reports.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// A value exists only in the new report.
+    Added { path: String, value: Value },
+
+    /// A value exists only in the old report.
+    Removed { path: String, value: Value },
+
+    /// A value differs between the two reports.
+    Changed {
+        path: String,
+        before: Value,
+        after: Value,
+    },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added { path, value } => write!(f, "+ {path}: {value}"),
+            Change::Removed { path, value } => write!(f, "- {path}: {value}"),
+            Change::Changed {
+                path,
+                before,
+                after,
+            } => match (before.as_f64(), after.as_f64()) {
+                (Some(b), Some(a)) => write!(f, "~ {path}: {before} -> {after} ({:+})", a - b),
+                _ => write!(f, "~ {path}: {before} -> {after}"),
+            },
+        }
+    }
+}
+
+/// The differences between two run reports.
+#[derive(Debug, Default)]
+pub struct ReportDiff {
+    changes: Vec<Change>,
+}
+
+impl ReportDiff {
+    /// Returns whether the reports are identical.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Returns every difference between the reports, ordered by path.
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+}
+
+impl fmt::Display for ReportDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{change}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Key identifying the challenge a report belongs to. Reports for different challenges cannot be
+/// meaningfully compared.
+const CHALLENGE_KEY: &str = "challengeUuid";
+
+/// Key of a report's findings.
+const FINDINGS_KEY: &str = "findings";
+
+/// Compares two serialized run reports of the same challenge.
+///
+/// Returns an error if the reports belong to different challenges.
+pub fn diff_reports(before: &Value, after: &Value) -> Result<ReportDiff> {
+    if let (Some(b), Some(a)) = (before.get(CHALLENGE_KEY), after.get(CHALLENGE_KEY)) {
+        if b != a {
+            return Err(Error::FailedPrecondition(format!(
+                "Reports belong to different challenges: {b} and {a}"
+            )));
+        }
+    }
+
+    let mut diff = ReportDiff::default();
+    diff_values(String::new(), before, after, &mut diff.changes);
+    Ok(diff)
+}
+
+fn join_key(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+fn diff_values(path: String, before: &Value, after: &Value, changes: &mut Vec<Change>) {
+    match (before, after) {
+        (Value::Object(b), Value::Object(a)) => {
+            let mut keys = b.keys().chain(a.keys()).collect::<Vec<_>>();
+            keys.sort();
+            keys.dedup();
+
+            for key in keys {
+                let is_findings = path.is_empty() && key == FINDINGS_KEY;
+                let path = join_key(&path, key);
+                match (b.get(key), a.get(key)) {
+                    (Some(Value::Array(b)), Some(Value::Array(a))) if is_findings => {
+                        diff_findings(&path, b, a, changes);
+                    }
+                    (Some(b), Some(a)) => diff_values(path, b, a, changes),
+                    (Some(b), None) => changes.push(Change::Removed {
+                        path,
+                        value: b.clone(),
+                    }),
+                    (None, Some(a)) => changes.push(Change::Added {
+                        path,
+                        value: a.clone(),
+                    }),
+                    (None, None) => unreachable!(),
+                }
+            }
+        }
+        (Value::Array(b), Value::Array(a)) => {
+            for i in 0..b.len().max(a.len()) {
+                let path = format!("{path}[{i}]");
+                match (b.get(i), a.get(i)) {
+                    (Some(b), Some(a)) => diff_values(path, b, a, changes),
+                    (Some(b), None) => changes.push(Change::Removed {
+                        path,
+                        value: b.clone(),
+                    }),
+                    (None, Some(a)) => changes.push(Change::Added {
+                        path,
+                        value: a.clone(),
+                    }),
+                    (None, None) => unreachable!(),
+                }
+            }
+        }
+        (b, a) if b != a => changes.push(Change::Changed {
+            path,
+            before: b.clone(),
+            after: a.clone(),
+        }),
+        _ => {}
+    }
+}
+
+/// Returns the identity by which a finding is matched between reports.
+fn finding_identity(finding: &Value) -> String {
+    let field = |key: &str| finding.get(key).and_then(Value::as_str).unwrap_or_default();
+    let mut identity = format!(
+        "{}:{}:{}",
+        field("analyzer"),
+        field("category"),
+        field("player")
+    );
+    if let Some(range) = finding.get("tickRange").filter(|range| !range.is_null()) {
+        identity.push_str(&format!("@{}-{}", range["start"], range["end"]));
+    }
+    identity
+}
+
+/// Compares the findings of two reports, pairing findings with the same identity in the order in
+/// which they appear.
+fn diff_findings(path: &str, before: &[Value], after: &[Value], changes: &mut Vec<Change>) {
+    let mut unmatched = after
+        .iter()
+        .map(|finding| Some((finding_identity(finding), finding)))
+        .collect::<Vec<_>>();
+
+    for b in before {
+        let identity = finding_identity(b);
+        let path = format!("{path}[{identity}]");
+        let matched = unmatched
+            .iter_mut()
+            .find(|entry| entry.as_ref().is_some_and(|(id, _)| *id == identity))
+            .and_then(Option::take);
+
+        match matched {
+            Some((_, a)) => diff_values(path, b, a, changes),
+            None => changes.push(Change::Removed {
+                path,
+                value: b.clone(),
+            }),
+        }
+    }
+
+    for (identity, a) in unmatched.into_iter().flatten() {
+        changes.push(Change::Added {
+            path: format!("{path}[{identity}]"),
+            value: a.clone(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn identical_reports() {
+        let report = json!({ "challengeUuid": "a", "outputs": { "TestAnalyzer": 5 } });
+        assert!(diff_reports(&report, &report).unwrap().is_empty());
+    }
+
+    #[test]
+    fn different_challenges() {
+        let a = json!({ "challengeUuid": "a" });
+        let b = json!({ "challengeUuid": "b" });
+        assert!(diff_reports(&a, &b).is_err());
+    }
+
+    #[test]
+    fn nested_changes() {
+        let a = json!({
+            "challengeUuid": "a",
+            "outputs": { "TestAnalyzer": 5, "Removed": true, "Roles": ["Mage", "Ranger"] },
+        });
+        let b = json!({
+            "challengeUuid": "a",
+            "outputs": { "TestAnalyzer": 9, "Added": 1, "Roles": ["Mage"] },
+        });
+
+        let diff = diff_reports(&a, &b).unwrap();
+        assert_eq!(
+            diff.changes(),
+            &[
+                Change::Added {
+                    path: "outputs.Added".into(),
+                    value: json!(1),
+                },
+                Change::Removed {
+                    path: "outputs.Removed".into(),
+                    value: json!(true),
+                },
+                Change::Removed {
+                    path: "outputs.Roles[1]".into(),
+                    value: json!("Ranger"),
+                },
+                Change::Changed {
+                    path: "outputs.TestAnalyzer".into(),
+                    before: json!(5),
+                    after: json!(9),
+                },
+            ]
+        );
+        assert_eq!(
+            diff.changes()[3].to_string(),
+            "~ outputs.TestAnalyzer: 5 -> 9 (+4)"
+        );
+    }
+
+    #[test]
+    fn findings_are_matched_by_identity() {
+        let finding = |category: &str, player: &str, start: u32, message: &str| {
+            json!({
+                "analyzer": "TobBloatAnalyzer",
+                "category": category,
+                "player": player,
+                "tickRange": { "start": start, "end": start + 10 },
+                "message": message,
+            })
+        };
+        let a = json!({
+            "findings": [
+                finding("bloat.late_entry", "Alice", 5, "late"),
+                finding("bloat.late_entry", "Bob", 5, "late"),
+            ],
+        });
+        let b = json!({
+            "findings": [
+                finding("bloat.missed_down", "Alice", 1, "missed"),
+                finding("bloat.late_entry", "Alice", 5, "late"),
+                finding("bloat.late_entry", "Bob", 5, "very late"),
+            ],
+        });
+
+        let diff = diff_reports(&a, &b).unwrap();
+        assert_eq!(
+            diff.changes(),
+            &[
+                Change::Changed {
+                    path: "findings[TobBloatAnalyzer:bloat.late_entry:Bob@5-15].message".into(),
+                    before: json!("late"),
+                    after: json!("very late"),
+                },
+                Change::Added {
+                    path: "findings[TobBloatAnalyzer:bloat.missed_down:Alice@1-11]".into(),
+                    value: finding("bloat.missed_down", "Alice", 1, "missed"),
+                },
+            ]
+        );
+    }
+}
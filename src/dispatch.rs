@@ -0,0 +1,133 @@
+//! Priority-aware dispatch of work to the engine's workers.
+//!
+//! Each priority has its own queue. Workers always take from the interactive queue while it has
+//! work, so analyses requested by users are not stuck behind bulk backfills.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Priority of a program run's work relative to other runs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    /// Work on behalf of a user waiting for its results.
+    #[default]
+    Interactive,
+    /// Batch work, such as backfilling analyses of past challenges.
+    Bulk,
+}
+
+/// Creates an unbounded dispatch queue, returning its sending and receiving halves. Like an
+/// `async_channel`, both halves may be cloned.
+pub(crate) fn queue<T>() -> (Sender<T>, Receiver<T>) {
+    let (interactive_tx, interactive_rx) = async_channel::unbounded();
+    let (bulk_tx, bulk_rx) = async_channel::unbounded();
+    (
+        Sender {
+            interactive: interactive_tx,
+            bulk: bulk_tx,
+        },
+        Receiver {
+            interactive: interactive_rx,
+            bulk: bulk_rx,
+        },
+    )
+}
+
+/// Error returned when sending to a closed queue.
+#[derive(Debug)]
+pub(crate) struct Closed;
+
+pub(crate) struct Sender<T> {
+    interactive: async_channel::Sender<T>,
+    bulk: async_channel::Sender<T>,
+}
+
+impl<T> Sender<T> {
+    /// Queues an item at the given priority.
+    pub fn send(&self, item: T, priority: Priority) -> Result<(), Closed> {
+        let queue = match priority {
+            Priority::Interactive => &self.interactive,
+            Priority::Bulk => &self.bulk,
+        };
+        queue.try_send(item).map_err(|_| Closed)
+    }
+
+    /// Returns the number of items waiting in the queue across all priorities.
+    pub fn len(&self) -> usize {
+        self.interactive.len() + self.bulk.len()
+    }
+
+    /// Closes the queue. Items already queued can still be received.
+    pub fn close(&self) {
+        self.interactive.close();
+        self.bulk.close();
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            interactive: self.interactive.clone(),
+            bulk: self.bulk.clone(),
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sender")
+            .field("interactive", &self.interactive.len())
+            .field("bulk", &self.bulk.len())
+            .finish()
+    }
+}
+
+pub(crate) struct Receiver<T> {
+    interactive: async_channel::Receiver<T>,
+    bulk: async_channel::Receiver<T>,
+}
+
+impl<T> Receiver<T> {
+    /// Waits for the next item, taking interactive items before bulk ones. Returns `None` once
+    /// the queue is closed and empty.
+    pub async fn recv(&self) -> Option<T> {
+        tokio::select! {
+            biased;
+            Ok(item) = self.interactive.recv() => Some(item),
+            Ok(item) = self.bulk.recv() => Some(item),
+            else => None,
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        Self {
+            interactive: self.interactive.clone(),
+            bulk: self.bulk.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+
+    #[test]
+    fn interactive_items_are_received_first() {
+        let (tx, rx) = queue();
+        tx.send(1, Priority::Bulk).unwrap();
+        tx.send(2, Priority::Interactive).unwrap();
+        tx.send(3, Priority::Bulk).unwrap();
+        tx.send(4, Priority::Interactive).unwrap();
+        assert_eq!(tx.len(), 4);
+        tx.close();
+
+        let received = std::iter::from_fn(|| block_on(rx.recv())).collect::<Vec<_>>();
+        assert_eq!(received, vec![2, 4, 1, 3]);
+        assert!(tx.send(5, Priority::Interactive).is_err());
+    }
+}
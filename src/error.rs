@@ -1,41 +1,187 @@
-use crate::data_repository;
+use std::fmt;
+
+use uuid::Uuid;
+
+use crate::{blert, data_repository};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 pub enum Error {
+    #[error("missing environment variable {0}")]
     Environment(&'static str),
+    #[error("invalid field: {0}")]
     InvalidField(String),
+    #[error("incomplete challenge data")]
     IncompleteData,
+    #[error("invalid argument")]
     InvalidArgument,
+    #[error("failed precondition: {0}")]
     FailedPrecondition(String),
+    /// The service cannot currently perform the operation, e.g. because it is shutting down,
+    /// though another attempt or another instance of the service may.
+    #[error("service unavailable: {0}")]
+    Unavailable(String),
+    #[error("dependency error: {0}")]
     Dependency(String),
-    DataRepository(data_repository::Error),
-    Io(std::io::Error),
-    Sql(sqlx::Error),
+    #[error("data repository error: {0}")]
+    DataRepository(#[from] data_repository::Error),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("database error: {0}")]
+    Sql(#[from] sqlx::Error),
+    #[error("configuration error: {0}")]
     Config(String),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("program run cancelled")]
+    Cancelled,
+    #[error("program already running as job {0}")]
+    AlreadyRunning(u32),
+    #[error("too many program runs in progress")]
+    Busy,
+    #[error("analyzer panicked: {0}")]
+    AnalyzerPanic(String),
+    #[error("analyzer {0}")]
+    LimitExceeded(String),
+    #[cfg(feature = "plugins")]
+    #[error("analyzer plugin failed: {0}")]
+    Plugin(String),
+
+    /// An error annotated with information about where it occurred.
+    #[error("{context}: {source}")]
+    Context {
+        context: ErrorContext,
+        #[source]
+        source: Box<Error>,
+    },
 }
 
-impl From<data_repository::Error> for Error {
-    fn from(e: data_repository::Error) -> Self {
-        Self::DataRepository(e)
-    }
+/// Information about where in an analysis an error occurred.
+#[derive(Debug, Clone, Default)]
+pub struct ErrorContext {
+    pub challenge: Option<Uuid>,
+    pub stage: Option<blert::Stage>,
+    pub analyzer: Option<String>,
 }
 
-impl From<std::io::Error> for Error {
-    fn from(e: std::io::Error) -> Self {
-        Self::Io(e)
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::with_capacity(3);
+        if let Some(challenge) = self.challenge {
+            parts.push(format!("challenge {challenge}"));
+        }
+        if let Some(stage) = self.stage {
+            parts.push(format!("stage {}", stage.as_str_name()));
+        }
+        if let Some(analyzer) = &self.analyzer {
+            parts.push(format!(r#"analyzer "{analyzer}""#));
+        }
+        write!(f, "{}", parts.join(", "))
     }
 }
 
-impl From<sqlx::Error> for Error {
-    fn from(e: sqlx::Error) -> Self {
-        Self::Sql(e)
+impl Error {
+    /// Returns whether the operation which produced the error may succeed if retried, i.e. the
+    /// error was caused by a transient condition rather than by the data or configuration.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::DataRepository(e) => e.is_retryable(),
+            Error::Io(e) => matches!(
+                e.kind(),
+                std::io::ErrorKind::Interrupted
+                    | std::io::ErrorKind::TimedOut
+                    | std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+                    | std::io::ErrorKind::ConnectionRefused
+            ),
+            Error::Sql(e) => matches!(
+                e,
+                sqlx::Error::Io(_)
+                    | sqlx::Error::PoolTimedOut
+                    | sqlx::Error::PoolClosed
+                    | sqlx::Error::WorkerCrashed
+            ),
+            Error::Busy | Error::Unavailable(_) => true,
+            Error::Context { source, .. } => source.is_retryable(),
+            _ => false,
+        }
+    }
+
+    /// Annotates the error with the challenge in which it occurred.
+    #[must_use]
+    pub fn with_challenge(self, uuid: Uuid) -> Self {
+        self.with_context(|c| c.challenge = c.challenge.or(Some(uuid)))
+    }
+
+    /// Annotates the error with the stage in which it occurred.
+    #[must_use]
+    pub fn with_stage(self, stage: blert::Stage) -> Self {
+        self.with_context(|c| c.stage = c.stage.or(Some(stage)))
+    }
+
+    /// Annotates the error with the analyzer in which it occurred.
+    #[must_use]
+    pub fn with_analyzer(self, name: &str) -> Self {
+        self.with_context(|c| {
+            c.analyzer.get_or_insert_with(|| name.to_owned());
+        })
+    }
+
+    fn with_context(self, f: impl FnOnce(&mut ErrorContext)) -> Self {
+        match self {
+            Error::Context {
+                mut context,
+                source,
+            } => {
+                f(&mut context);
+                Error::Context { context, source }
+            }
+            e => {
+                let mut context = ErrorContext::default();
+                f(&mut context);
+                Error::Context {
+                    context,
+                    source: Box::new(e),
+                }
+            }
+        }
     }
 }
 
-impl From<toml::de::Error> for Error {
-    fn from(e: toml::de::Error) -> Self {
-        Self::Config(e.message().to_owned())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn context_is_merged() {
+        let uuid = Uuid::from_u128(1);
+        let e = Error::IncompleteData
+            .with_stage(blert::Stage::TobMaiden)
+            .with_analyzer("GearAnalyzer")
+            .with_challenge(uuid);
+
+        let Error::Context { context, source } = &e else {
+            panic!("Expected error with context");
+        };
+        assert_eq!(context.challenge, Some(uuid));
+        assert_eq!(context.stage, Some(blert::Stage::TobMaiden));
+        assert_eq!(context.analyzer.as_deref(), Some("GearAnalyzer"));
+        assert!(matches!(**source, Error::IncompleteData));
+        assert!(!e.is_retryable());
+    }
+
+    #[test]
+    fn retryable_errors() {
+        assert!(Error::Sql(sqlx::Error::PoolTimedOut).is_retryable());
+        assert!(!Error::Sql(sqlx::Error::RowNotFound).is_retryable());
+        assert!(
+            Error::DataRepository(data_repository::Error::Backend("timeout".into()))
+                .with_analyzer("GearAnalyzer")
+                .is_retryable()
+        );
+        assert!(!Error::Config("bad".into()).is_retryable());
+        assert!(Error::Unavailable("shutting down".into()).is_retryable());
+        assert!(!Error::FailedPrecondition("bad".into()).is_retryable());
     }
 }
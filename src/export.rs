@@ -0,0 +1,845 @@
+//! Export of persisted analysis results as training data for statistical models and as tables
+//! for spreadsheet and data-science use.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow::array::{
+    ArrayRef, BooleanArray, Float64Array, Int16Array, Int32Array, Int64Array, StringArray,
+    UInt32Array, UInt64Array,
+};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use serde::Deserialize;
+use serde_json::Value;
+use sqlx::types::time::OffsetDateTime;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::analyzers::tob_role_features_analyzer::RoleFeatures;
+use crate::challenge::{AttackState, Challenge, DeathState, PlayerState, SkillLevel, StageInfo};
+use crate::error::{Error, Result};
+use crate::item::EquipmentSlot;
+use crate::privacy::Pseudonymizer;
+use crate::results;
+
+/// A single player's role features and assigned role within a raid.
+#[derive(Debug)]
+struct RoleExample {
+    challenge_uuid: Uuid,
+    username: String,
+    features: RoleFeatures,
+    role: String,
+    sub_roles: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct RoleRunRow {
+    challenge_uuid: Uuid,
+    features: serde_json::Value,
+    roles: serde_json::Value,
+}
+
+impl results::Store {
+    /// Loads the role features and assigned roles of every player from each successful run
+    /// which produced both.
+    async fn load_role_examples(&self) -> Result<Vec<RoleExample>> {
+        let rows: Vec<RoleRunRow> = sqlx::query_as(
+            "
+            SELECT r.challenge_uuid, f.output AS features, l.output AS roles
+            FROM analysis_runs r
+            JOIN analyzer_outputs f
+              ON f.run_id = r.id AND f.analyzer = 'TobRoleFeaturesAnalyzer'
+            JOIN analyzer_outputs l
+              ON l.run_id = r.id AND l.analyzer = 'TobRoleAnalyzer'
+            WHERE r.status = 'completed'
+            ORDER BY r.id
+            ",
+        )
+        .fetch_all(self.pool())
+        .await?;
+
+        let mut examples = Vec::new();
+        for row in rows {
+            let features: HashMap<String, RoleFeatures> = serde_json::from_value(row.features)?;
+
+            for (username, features) in features {
+                // Roles are serialized as a `[role, [sub_roles...]]` pair.
+                let Some(roles) = row.roles.get(&username) else {
+                    continue;
+                };
+                let role = roles.get(0).and_then(serde_json::Value::as_str);
+                let Some(role) = role else {
+                    return Err(Error::InvalidField(format!(
+                        "role of {username} in {}",
+                        row.challenge_uuid
+                    )));
+                };
+                let sub_roles = roles
+                    .get(1)
+                    .and_then(serde_json::Value::as_array)
+                    .map(|s| {
+                        s.iter()
+                            .filter_map(serde_json::Value::as_str)
+                            .collect::<Vec<_>>()
+                            .join(",")
+                    })
+                    .unwrap_or_default();
+
+                examples.push(RoleExample {
+                    challenge_uuid: row.challenge_uuid,
+                    username,
+                    features,
+                    role: role.to_owned(),
+                    sub_roles,
+                });
+            }
+        }
+
+        Ok(examples)
+    }
+}
+
+/// Writes the role features and assigned role of every player in every analyzed raid to a
+/// Parquet file at `path`, returning the number of rows written. Players who have opted out of
+/// publication are pseudonymized.
+pub async fn export_role_training_data(
+    store: &results::Store,
+    path: impl AsRef<Path>,
+) -> Result<usize> {
+    let pseudonymizer = store.load_pseudonymizer().await?;
+    let mut examples = store.load_role_examples().await?;
+    for example in &mut examples {
+        example.username = pseudonymizer.name(&example.username);
+    }
+
+    let batch = role_examples_to_batch(&examples)?;
+
+    let file = File::create(path)?;
+    let mut writer = ArrowWriter::try_new(file, batch.schema(), None).map_err(parquet_error)?;
+    writer.write(&batch).map_err(parquet_error)?;
+    writer.close().map_err(parquet_error)?;
+
+    Ok(examples.len())
+}
+
+fn role_examples_to_batch(examples: &[RoleExample]) -> Result<RecordBatch> {
+    fn strings(examples: &[RoleExample], f: impl Fn(&RoleExample) -> String) -> ArrayRef {
+        Arc::new(examples.iter().map(|e| Some(f(e))).collect::<StringArray>())
+    }
+    fn counts(examples: &[RoleExample], f: impl Fn(&RoleFeatures) -> u32) -> ArrayRef {
+        Arc::new(
+            examples
+                .iter()
+                .map(|e| Some(f(&e.features)))
+                .collect::<UInt32Array>(),
+        )
+    }
+    fn flags(examples: &[RoleExample], f: impl Fn(&RoleFeatures) -> bool) -> ArrayRef {
+        Arc::new(
+            examples
+                .iter()
+                .map(|e| Some(f(&e.features)))
+                .collect::<BooleanArray>(),
+        )
+    }
+
+    let columns: Vec<(&str, ArrayRef)> = vec![
+        (
+            "challenge_uuid",
+            strings(examples, |e| e.challenge_uuid.to_string()),
+        ),
+        ("username", strings(examples, |e| e.username.clone())),
+        ("scale", counts(examples, |f| f.scale)),
+        ("hard_mode", flags(examples, |f| f.hard_mode)),
+        ("reached_nylocas", flags(examples, |f| f.reached_nylocas)),
+        ("maiden_barrages", counts(examples, |f| f.maiden_barrages)),
+        ("maiden_chins", counts(examples, |f| f.maiden_chins)),
+        ("maiden_dinhs", counts(examples, |f| f.maiden_dinhs)),
+        ("nylo_barrages", counts(examples, |f| f.nylo_barrages)),
+        ("nylo_chins", counts(examples, |f| f.nylo_chins)),
+        ("nylo_swifts", counts(examples, |f| f.nylo_swifts)),
+        ("nylo_4t_melees", counts(examples, |f| f.nylo_4t_melees)),
+        ("nylo_pipes", counts(examples, |f| f.nylo_pipes)),
+        ("nylo_scythes", counts(examples, |f| f.nylo_scythes)),
+        ("nylo_prefires", counts(examples, |f| f.nylo_prefires)),
+        (
+            "nylo_west_prefires",
+            counts(examples, |f| f.nylo_west_prefires),
+        ),
+        (
+            "nylo_east_prefires",
+            counts(examples, |f| f.nylo_east_prefires),
+        ),
+        ("has_void", flags(examples, |f| f.has_void)),
+        (
+            "has_non_void_ranged_gear",
+            flags(examples, |f| f.has_non_void_ranged_gear),
+        ),
+        (
+            "has_nylo_melee_weapon",
+            flags(examples, |f| f.has_nylo_melee_weapon),
+        ),
+        ("has_dinhs", flags(examples, |f| f.has_dinhs)),
+        ("has_paint_cannon", flags(examples, |f| f.has_paint_cannon)),
+        ("role", strings(examples, |e| e.role.clone())),
+        ("sub_roles", strings(examples, |e| e.sub_roles.clone())),
+    ];
+
+    let schema = Schema::new(
+        columns
+            .iter()
+            .map(|(name, array)| Field::new(*name, array.data_type().clone(), false))
+            .collect::<Vec<_>>(),
+    );
+
+    RecordBatch::try_new(
+        Arc::new(schema),
+        columns.into_iter().map(|(_, array)| array).collect(),
+    )
+    .map_err(|e| Error::Config(format!("Invalid training data schema: {e}")))
+}
+
+fn parquet_error(e: parquet::errors::ParquetError) -> Error {
+    Error::Io(std::io::Error::new(std::io::ErrorKind::Other, e))
+}
+
+fn arrow_error(e: arrow::error::ArrowError) -> Error {
+    Error::Io(std::io::Error::new(std::io::ErrorKind::Other, e))
+}
+
+/// File format of a results table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TableFormat {
+    #[default]
+    Csv,
+    Parquet,
+}
+
+impl TableFormat {
+    /// Determines the format of a file from its extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "csv" => Some(Self::Csv),
+            "parquet" => Some(Self::Parquet),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv",
+            Self::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Parquet => "parquet",
+        }
+    }
+}
+
+/// Selects the program runs whose outputs are exported, and how. Only the latest successful run
+/// of the program on each challenge is exported.
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportRequest {
+    /// Program whose outputs to export.
+    pub program: String,
+    /// Only export challenges which started at or after this Unix timestamp.
+    pub since: Option<i64>,
+    /// Only export challenges which started before this Unix timestamp.
+    pub until: Option<i64>,
+    /// File format of the export. Defaults to CSV.
+    #[serde(default)]
+    #[param(inline)]
+    pub format: TableFormat,
+}
+
+/// Columns which begin every row of a results table, in order.
+const KEY_COLUMNS: [&str; 5] = [
+    "challenge_uuid",
+    "start_time",
+    "level",
+    "status",
+    "username",
+];
+
+#[derive(sqlx::FromRow)]
+struct ExportRunRow {
+    id: i64,
+    challenge_uuid: Uuid,
+    level: String,
+    status: String,
+    start_time: OffsetDateTime,
+    party: Vec<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct ExportOutputRow {
+    run_id: i64,
+    analyzer: String,
+    output: Value,
+}
+
+/// A row of a results table, keyed by column name.
+type TableRow = BTreeMap<String, Value>;
+
+impl results::Store {
+    /// Loads the latest successful run of a program on each challenge matching `request`, with
+    /// the party of the challenge and the outputs of the run.
+    async fn load_export_runs(
+        &self,
+        request: &ExportRequest,
+    ) -> Result<Vec<(ExportRunRow, Vec<(String, Value)>)>> {
+        let timestamp = |field: &str, value: Option<i64>| {
+            value
+                .map(|v| {
+                    OffsetDateTime::from_unix_timestamp(v)
+                        .map_err(|_| Error::InvalidField(field.into()))
+                })
+                .transpose()
+        };
+        let since = timestamp("since", request.since)?;
+        let until = timestamp("until", request.until)?;
+
+        let runs: Vec<ExportRunRow> = sqlx::query_as(
+            "
+            SELECT r.id, r.challenge_uuid, r.level, r.status, c.start_time, ARRAY(
+                       SELECT cp.username FROM challenge_players cp
+                       WHERE cp.challenge_id = c.id
+                       ORDER BY cp.orb
+                   ) AS party
+            FROM analysis_runs r
+            JOIN challenges c ON c.uuid = r.challenge_uuid
+            WHERE r.program = $1
+              AND r.status IN ('completed', 'partial')
+              AND ($2::TIMESTAMPTZ IS NULL OR c.start_time >= $2)
+              AND ($3::TIMESTAMPTZ IS NULL OR c.start_time < $3)
+              AND r.id = (
+                  SELECT MAX(latest.id)
+                  FROM analysis_runs latest
+                  WHERE latest.challenge_uuid = r.challenge_uuid
+                    AND latest.program = r.program
+                    AND latest.status IN ('completed', 'partial')
+              )
+            ORDER BY c.start_time, r.id
+            ",
+        )
+        .bind(&request.program)
+        .bind(since)
+        .bind(until)
+        .fetch_all(self.pool())
+        .await?;
+
+        let run_ids = runs.iter().map(|run| run.id).collect::<Vec<_>>();
+        let rows: Vec<ExportOutputRow> = sqlx::query_as(
+            "
+            SELECT run_id, analyzer, output
+            FROM analyzer_outputs
+            WHERE run_id = ANY($1)
+            ORDER BY analyzer
+            ",
+        )
+        .bind(&run_ids)
+        .fetch_all(self.pool())
+        .await?;
+
+        let mut outputs: HashMap<i64, Vec<(String, Value)>> = HashMap::new();
+        for row in rows {
+            outputs
+                .entry(row.run_id)
+                .or_default()
+                .push((row.analyzer, row.output));
+        }
+
+        Ok(runs
+            .into_iter()
+            .map(|run| {
+                let outputs = outputs.remove(&run.id).unwrap_or_default();
+                (run, outputs)
+            })
+            .collect())
+    }
+}
+
+/// Writes the outputs of a program's runs as a table with one row per player per challenge,
+/// returning the number of rows written.
+///
+/// Outputs keyed by the usernames of the party are split across each player's row; all other
+/// outputs are repeated in every row of the challenge. Nested objects are flattened into
+/// dot-separated columns, e.g. `TobSplitsAnalyzer.rooms.TOB_MAIDEN`, and arrays are written as
+/// JSON. Players who have opted out of publication are pseudonymized.
+pub async fn export_results(
+    store: &results::Store,
+    request: &ExportRequest,
+    writer: impl Write + Send,
+) -> Result<usize> {
+    let pseudonymizer = store.load_pseudonymizer().await?;
+    let runs = store.load_export_runs(request).await?;
+
+    let rows = runs
+        .iter()
+        .flat_map(|(run, outputs)| player_rows(run, outputs, &pseudonymizer))
+        .collect::<Vec<_>>();
+    let batch = rows_to_batch(&rows)?;
+    write_batch(&batch, request.format, writer)?;
+    Ok(rows.len())
+}
+
+/// Builds the table rows for each member of a run's party.
+fn player_rows(
+    run: &ExportRunRow,
+    outputs: &[(String, Value)],
+    pseudonymizer: &Pseudonymizer,
+) -> Vec<TableRow> {
+    // Stored outputs are keyed by the pseudonymized names of opted-out players.
+    let party = run
+        .party
+        .iter()
+        .map(|username| pseudonymizer.name(username))
+        .collect::<Vec<_>>();
+    let members = party.iter().map(String::as_str).collect::<HashSet<_>>();
+
+    let is_per_player = |output: &Value| {
+        output.as_object().is_some_and(|entries| {
+            !entries.is_empty() && entries.keys().all(|key| members.contains(key.as_str()))
+        })
+    };
+
+    let mut challenge_row = TableRow::new();
+    challenge_row.insert(
+        "challenge_uuid".into(),
+        Value::String(run.challenge_uuid.to_string()),
+    );
+    challenge_row.insert(
+        "start_time".into(),
+        Value::from(run.start_time.unix_timestamp()),
+    );
+    challenge_row.insert("level".into(), Value::String(run.level.clone()));
+    challenge_row.insert("status".into(), Value::String(run.status.clone()));
+    for (analyzer, output) in outputs {
+        if !is_per_player(output) {
+            flatten_into(&mut challenge_row, analyzer, output);
+        }
+    }
+
+    party
+        .iter()
+        .map(|username| {
+            let mut row = challenge_row.clone();
+            row.insert("username".into(), Value::String(username.clone()));
+            for (analyzer, output) in outputs {
+                if is_per_player(output) {
+                    if let Some(value) = output.get(username) {
+                        flatten_into(&mut row, analyzer, value);
+                    }
+                }
+            }
+            row
+        })
+        .collect()
+}
+
+/// Inserts `value` into `row`, flattening nested objects into columns prefixed by `prefix`.
+fn flatten_into(row: &mut TableRow, prefix: &str, value: &Value) {
+    match value {
+        Value::Object(entries) => {
+            for (key, value) in entries {
+                flatten_into(row, &format!("{prefix}.{key}"), value);
+            }
+        }
+        Value::Array(_) => {
+            row.insert(prefix.to_owned(), Value::String(value.to_string()));
+        }
+        value => {
+            row.insert(prefix.to_owned(), value.clone());
+        }
+    }
+}
+
+/// Converts table rows to a record batch, typing each column by the values it holds. Columns
+/// with values of mixed types are written as strings.
+fn rows_to_batch(rows: &[TableRow]) -> Result<RecordBatch> {
+    let value_columns = rows
+        .iter()
+        .flat_map(BTreeMap::keys)
+        .filter(|column| !KEY_COLUMNS.contains(&column.as_str()))
+        .collect::<BTreeSet<_>>();
+    let columns = KEY_COLUMNS
+        .iter()
+        .copied()
+        .chain(value_columns.into_iter().map(String::as_str))
+        .collect::<Vec<_>>();
+
+    let (fields, arrays): (Vec<Field>, Vec<ArrayRef>) = columns
+        .into_iter()
+        .map(|column| {
+            let values = rows
+                .iter()
+                .map(|row| row.get(column).filter(|v| !v.is_null()))
+                .collect::<Vec<_>>();
+            let array = column_array(&values);
+            let field = Field::new(column, array.data_type().clone(), true);
+            (field, array)
+        })
+        .unzip();
+
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)
+        .map_err(|e| Error::Config(format!("Invalid results table schema: {e}")))
+}
+
+/// Writes a record batch to `writer` in the specified format.
+fn write_batch(batch: &RecordBatch, format: TableFormat, writer: impl Write + Send) -> Result<()> {
+    match format {
+        TableFormat::Csv => {
+            let mut writer = arrow::csv::Writer::new(writer);
+            writer.write(batch).map_err(arrow_error)?;
+        }
+        TableFormat::Parquet => {
+            let mut writer =
+                ArrowWriter::try_new(writer, batch.schema(), None).map_err(parquet_error)?;
+            writer.write(batch).map_err(parquet_error)?;
+            writer.close().map_err(parquet_error)?;
+        }
+    }
+    Ok(())
+}
+
+/// A player's reconstructed state on a single tick of a stage.
+struct StateRow<'a> {
+    stage: &'a StageInfo,
+    username: String,
+    state: &'a PlayerState,
+}
+
+/// Writes the reconstructed state of every player on every tick of a challenge as a table, with
+/// one row per player per tick of each stage, returning the number of rows written.
+///
+/// Rows include the player's position, attack, death state, skill levels, active prayers (as a
+/// bitmask of prayer IDs), and the ID of the item in each equipment slot. `name` maps each
+/// username to the name written to the table, e.g. to pseudonymize opted-out players.
+pub fn export_player_states(
+    challenge: &Challenge,
+    name: impl Fn(&str) -> String,
+    format: TableFormat,
+    writer: impl Write + Send,
+) -> Result<usize> {
+    let mut rows = Vec::new();
+    for stage in challenge.stage_infos() {
+        for username in challenge.party() {
+            let Some(states) = stage.player_state(username) else {
+                continue;
+            };
+            let username = name(username);
+            rows.extend(states.into_iter().map(|state| StateRow {
+                stage,
+                username: username.clone(),
+                state,
+            }));
+        }
+    }
+
+    let batch = player_states_to_batch(&rows)?;
+    write_batch(&batch, format, writer)?;
+    Ok(rows.len())
+}
+
+fn player_states_to_batch(rows: &[StateRow]) -> Result<RecordBatch> {
+    fn strings(rows: &[StateRow], f: impl Fn(&StateRow) -> Option<String>) -> ArrayRef {
+        Arc::new(rows.iter().map(f).collect::<StringArray>())
+    }
+    fn ticks(rows: &[StateRow], f: impl Fn(&PlayerState) -> Option<u32>) -> ArrayRef {
+        Arc::new(rows.iter().map(|r| f(r.state)).collect::<UInt32Array>())
+    }
+    fn coords(rows: &[StateRow], f: impl Fn(&PlayerState) -> i32) -> ArrayRef {
+        Arc::new(
+            rows.iter()
+                .map(|r| Some(f(r.state)))
+                .collect::<Int32Array>(),
+        )
+    }
+    fn flags(rows: &[StateRow], f: impl Fn(&PlayerState) -> bool) -> ArrayRef {
+        Arc::new(
+            rows.iter()
+                .map(|r| Some(f(r.state)))
+                .collect::<BooleanArray>(),
+        )
+    }
+    fn levels(rows: &[StateRow], f: impl Fn(&PlayerState) -> Option<&SkillLevel>) -> ArrayRef {
+        Arc::new(
+            rows.iter()
+                .map(|r| f(r.state).map(|level| level.current))
+                .collect::<Int16Array>(),
+        )
+    }
+
+    let attack = |state: &PlayerState| match &state.attack_state {
+        AttackState::Attacked(attacked) => Some(attacked),
+        _ => None,
+    };
+
+    let mut columns: Vec<(String, ArrayRef)> = vec![
+        (
+            "stage".into(),
+            strings(rows, |r| Some(r.stage.stage().as_str_name().to_owned())),
+        ),
+        ("tick".into(), ticks(rows, |s| Some(s.tick))),
+        (
+            "username".into(),
+            strings(rows, |r| Some(r.username.clone())),
+        ),
+        (
+            "phase".into(),
+            strings(rows, |r| {
+                let phase = r.stage.phase_at(r.state.tick)?;
+                serde_json::to_value(phase)
+                    .ok()
+                    .and_then(|phase| phase.as_str().map(str::to_owned))
+            }),
+        ),
+        ("x".into(), coords(rows, |s| s.position.x)),
+        ("y".into(), coords(rows, |s| s.position.y)),
+        (
+            "attack".into(),
+            strings(rows, |r| {
+                attack(r.state).map(|a| a.attack.as_str_name().to_owned())
+            }),
+        ),
+        (
+            "attack_target_id".into(),
+            ticks(rows, |s| {
+                attack(s).and_then(|a| a.target.as_ref().map(|npc| npc.spawn_npc_id))
+            }),
+        ),
+        (
+            "cooldown".into(),
+            ticks(rows, |s| match s.attack_state {
+                AttackState::OnCooldown(ticks) => Some(ticks),
+                _ => None,
+            }),
+        ),
+        (
+            "died".into(),
+            flags(rows, |s| s.death_state == DeathState::JustDied),
+        ),
+        (
+            "dead".into(),
+            flags(rows, |s| s.death_state != DeathState::Alive),
+        ),
+        ("attack_level".into(), levels(rows, |s| s.stats.attack())),
+        (
+            "strength_level".into(),
+            levels(rows, |s| s.stats.strength()),
+        ),
+        ("defence_level".into(), levels(rows, |s| s.stats.defence())),
+        ("ranged_level".into(), levels(rows, |s| s.stats.ranged())),
+        ("magic_level".into(), levels(rows, |s| s.stats.magic())),
+        ("hitpoints".into(), levels(rows, |s| s.stats.hitpoints())),
+        ("prayer".into(), levels(rows, |s| s.stats.prayer())),
+        (
+            "prayers".into(),
+            Arc::new(
+                rows.iter()
+                    .map(|r| Some(r.state.prayers.raw()))
+                    .collect::<UInt64Array>(),
+            ),
+        ),
+    ];
+
+    for slot in EquipmentSlot::iter() {
+        let items = rows
+            .iter()
+            .map(|r| r.state.equipped_item(slot).map(|item| item.id()))
+            .collect::<Int32Array>();
+        columns.push((
+            format!("{}_id", format!("{slot:?}").to_lowercase()),
+            Arc::new(items),
+        ));
+    }
+
+    let schema = Schema::new(
+        columns
+            .iter()
+            .map(|(name, array)| Field::new(name.as_str(), array.data_type().clone(), true))
+            .collect::<Vec<_>>(),
+    );
+
+    RecordBatch::try_new(
+        Arc::new(schema),
+        columns.into_iter().map(|(_, array)| array).collect(),
+    )
+    .map_err(|e| Error::Config(format!("Invalid player state schema: {e}")))
+}
+
+fn column_array(values: &[Option<&Value>]) -> ArrayRef {
+    let present = || values.iter().flatten();
+
+    let data_type = if present().all(|v| v.is_i64()) {
+        DataType::Int64
+    } else if present().all(|v| v.is_number()) {
+        DataType::Float64
+    } else if present().all(|v| v.is_boolean()) {
+        DataType::Boolean
+    } else {
+        DataType::Utf8
+    };
+
+    match data_type {
+        DataType::Int64 => Arc::new(
+            values
+                .iter()
+                .map(|v| v.and_then(Value::as_i64))
+                .collect::<Int64Array>(),
+        ),
+        DataType::Float64 => Arc::new(
+            values
+                .iter()
+                .map(|v| v.and_then(Value::as_f64))
+                .collect::<Float64Array>(),
+        ),
+        DataType::Boolean => Arc::new(
+            values
+                .iter()
+                .map(|v| v.and_then(Value::as_bool))
+                .collect::<BooleanArray>(),
+        ),
+        _ => Arc::new(
+            values
+                .iter()
+                .map(|v| {
+                    v.map(|v| match v {
+                        Value::String(s) => s.clone(),
+                        v => v.to_string(),
+                    })
+                })
+                .collect::<StringArray>(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::array::Array;
+    use serde_json::json;
+
+    use super::*;
+    use crate::blert;
+    use crate::data_repository::{DataRepository, MemoryBackend};
+    use crate::synthetic::{Generator, SyntheticConfig};
+
+    #[test]
+    fn outputs_are_flattened_into_player_rows() {
+        let run = ExportRunRow {
+            id: 1,
+            challenge_uuid: Uuid::nil(),
+            level: "basic".into(),
+            status: "completed".into(),
+            start_time: OffsetDateTime::UNIX_EPOCH,
+            party: vec!["alice".into(), "bob".into()],
+        };
+        let outputs = vec![
+            (
+                "MetricAnalyzer".to_owned(),
+                json!({"alice": {"deaths": 1}, "bob": {"deaths": 0}}),
+            ),
+            (
+                "TobSplitsAnalyzer".to_owned(),
+                json!({"rooms": {"TOB_MAIDEN": 120}, "notes": ["fast"]}),
+            ),
+        ];
+        let pseudonymizer = Pseudonymizer::new(String::new(), Vec::new());
+
+        let rows = player_rows(&run, &outputs, &pseudonymizer);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["username"], "alice");
+        assert_eq!(rows[0]["MetricAnalyzer.deaths"], 1);
+        assert_eq!(rows[1]["MetricAnalyzer.deaths"], 0);
+        assert_eq!(rows[1]["TobSplitsAnalyzer.rooms.TOB_MAIDEN"], 120);
+        assert_eq!(rows[1]["TobSplitsAnalyzer.notes"], r#"["fast"]"#);
+
+        let batch = rows_to_batch(&rows).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        let schema = batch.schema();
+        assert_eq!(schema.field(0).name(), "challenge_uuid");
+        assert_eq!(
+            schema
+                .field_with_name("MetricAnalyzer.deaths")
+                .unwrap()
+                .data_type(),
+            &DataType::Int64
+        );
+    }
+
+    #[tokio::test]
+    async fn players_stay_dead_after_their_death() {
+        const DEATH_TICK: u32 = 10;
+
+        let mut synthetic = Generator::new(SyntheticConfig {
+            scale: 2,
+            stages: 1,
+            ticks_per_stage: 40,
+            npcs_per_tick: 0,
+            seed: 5,
+        })
+        .generate();
+
+        let events = &mut synthetic.stages[0].events;
+        events.retain(|event| {
+            event.tick <= DEATH_TICK || event.player.as_ref().is_some_and(|p| p.party_index != 0)
+        });
+        let mut death = blert::Event {
+            tick: DEATH_TICK,
+            player: Some(blert::event::Player::default()),
+            ..Default::default()
+        };
+        death.set_type(blert::event::Type::PlayerDeath);
+        events.push(death);
+        events.sort_by_key(|event| event.tick);
+
+        let backend = MemoryBackend::new();
+        synthetic.store(&backend);
+        let repository = DataRepository::new(Box::new(backend));
+        let challenge = Challenge::load_from_repository(&repository, synthetic.uuid)
+            .await
+            .unwrap();
+        let stage = &challenge.stage_infos()[0];
+
+        let rows = stage
+            .player_state("synthetic 0")
+            .unwrap()
+            .into_iter()
+            .map(|state| StateRow {
+                stage,
+                username: "synthetic 0".into(),
+                state,
+            })
+            .collect::<Vec<_>>();
+        let batch = player_states_to_batch(&rows).unwrap();
+        let column = |name: &str| {
+            batch
+                .column_by_name(name)
+                .unwrap()
+                .as_any()
+                .downcast_ref::<BooleanArray>()
+                .unwrap()
+                .clone()
+        };
+        let (died, dead) = (column("died"), column("dead"));
+
+        let row = |tick: u32| rows.iter().position(|r| r.state.tick == tick).unwrap();
+        assert!(!dead.value(row(DEATH_TICK - 1)));
+        assert!(died.value(row(DEATH_TICK)) && dead.value(row(DEATH_TICK)));
+        for tick in [DEATH_TICK + 1, DEATH_TICK + 5, DEATH_TICK + 20] {
+            assert!(!died.value(row(tick)));
+            assert!(dead.value(row(tick)));
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Issues reported by analyzers in a uniform form.
+
+use std::sync::Mutex;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::blert;
+
+/// How much a finding affected the challenge.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    ToSchema,
+    JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Noteworthy, but not a mistake.
+    Info,
+    Minor,
+    Major,
+    /// Caused or nearly caused the challenge to fail.
+    Critical,
+}
+
+/// An inclusive range of ticks within a stage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema, JsonSchema)]
+pub struct TickRange {
+    pub start: u32,
+    pub end: u32,
+}
+
+/// A single issue or observation reported by an analyzer.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Finding {
+    /// Name of the analyzer which emitted the finding within its program.
+    pub analyzer: String,
+    /// Player the finding concerns, if it is not about the party as a whole.
+    pub player: Option<String>,
+    /// Canonical name of the stage in which the finding occurred, e.g. `TOB_BLOAT`.
+    pub stage: Option<String>,
+    pub tick_range: Option<TickRange>,
+    pub severity: Severity,
+    /// Machine-readable kind of the finding, e.g. `bloat.late_entry`.
+    pub category: String,
+    /// Human-readable description of the finding in the locale of the run, formatted from the
+    /// message for its category once the run finishes.
+    pub message: String,
+    /// Additional analyzer-specific details, including the arguments of the finding's message.
+    #[schema(value_type = Option<Object>)]
+    pub data: Option<serde_json::Value>,
+    /// URL of the replay of the challenge at the moment the finding describes.
+    pub link: Option<String>,
+}
+
+impl Finding {
+    pub fn new(severity: Severity, category: &str) -> Self {
+        Self {
+            analyzer: String::new(),
+            player: None,
+            stage: None,
+            tick_range: None,
+            severity,
+            category: category.to_owned(),
+            message: String::new(),
+            data: None,
+            link: None,
+        }
+    }
+
+    pub fn with_player(mut self, username: &str) -> Self {
+        self.player = Some(username.to_owned());
+        self
+    }
+
+    pub fn with_stage(mut self, stage: blert::Stage) -> Self {
+        self.stage = Some(stage.as_str_name().to_owned());
+        self
+    }
+
+    pub fn with_ticks(mut self, start: u32, end: u32) -> Self {
+        self.tick_range = Some(TickRange { start, end });
+        self
+    }
+
+    pub fn with_data(mut self, data: serde_json::Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+}
+
+/// The findings emitted by every analyzer in a program run.
+#[derive(Debug, Default)]
+pub struct Findings {
+    findings: Mutex<Vec<Finding>>,
+}
+
+impl Findings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&self, analyzer: &str, mut finding: Finding) {
+        finding.analyzer = analyzer.to_owned();
+        self.findings.lock().unwrap().push(finding);
+    }
+
+    /// Returns the findings emitted by the analyzers for which `include` returns true, ordered
+    /// by analyzer and player, and then by the order in which they were emitted.
+    pub fn collect(&self, include: impl Fn(&str) -> bool) -> Vec<Finding> {
+        let mut findings = self
+            .findings
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|finding| include(&finding.analyzer))
+            .cloned()
+            .collect::<Vec<_>>();
+        findings.sort_by(|a, b| (&a.analyzer, &a.player).cmp(&(&b.analyzer, &b.player)));
+        findings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn findings_are_grouped_by_analyzer() {
+        let findings = Findings::new();
+        findings.add("B", Finding::new(Severity::Minor, "b.first"));
+        findings.add("A", Finding::new(Severity::Major, "a.only"));
+        findings.add(
+            "B",
+            Finding::new(Severity::Info, "b.second")
+                .with_player("alice")
+                .with_ticks(3, 5),
+        );
+        findings.add("C", Finding::new(Severity::Info, "c.skipped"));
+
+        let collected = findings.collect(|analyzer| analyzer != "C");
+        let categories = collected
+            .iter()
+            .map(|finding| finding.category.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(categories, ["a.only", "b.first", "b.second"]);
+        assert_eq!(collected[2].analyzer, "B");
+        assert_eq!(collected[2].player.as_deref(), Some("alice"));
+        assert_eq!(
+            collected[2].tick_range,
+            Some(TickRange { start: 3, end: 5 })
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! Golden-output regression tests for analysis programs.
+//!
+//! Fixture challenges live under `testdata/challenges` in the same layout as a filesystem data
+//! repository. Each directory under `testdata/golden` is named after a program, and holds the
+//! expected result of running that program on every fixture as `<uuid>.json`. Programs run inline,
+//! so their outputs are deterministic.
+//!
+//! After an intentional change to an analyzer, regenerate the golden files with
+//!
+//! ```text
+//! UPDATE_GOLDEN=1 cargo test golden
+//! ```
+//!
+//! and review the diff before committing them. If there are no fixtures yet, updating also records
+//! a small seeded synthetic challenge as the first one.
+//!
+//! The test fails if there are no fixtures, if a program has no golden outputs, or if either
+//! directory cannot be read, so that it never passes without comparing anything.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::analysis::{Engine, Level, ProgramResult};
+use crate::challenge::Challenge;
+use crate::data_repository::{DataRepository, FilesystemBackend, MemoryBackend};
+use crate::synthetic::{Generator, SyntheticConfig};
+use crate::{diff, item, npc};
+
+const FIXTURES_DIR: &str = "testdata/challenges";
+const GOLDEN_DIR: &str = "testdata/golden";
+const UPDATE_VARIABLE: &str = "UPDATE_GOLDEN";
+
+/// Returns the UUIDs of every fixture challenge, sorted.
+fn fixture_uuids() -> Vec<Uuid> {
+    let mut uuids = subdirectories(Path::new(FIXTURES_DIR))
+        .into_iter()
+        .flat_map(|prefix| subdirectories(&prefix))
+        .filter_map(|dir| Uuid::parse_str(&dir.file_name()?.to_string_lossy()).ok())
+        .collect::<Vec<_>>();
+    uuids.sort();
+    uuids
+}
+
+/// Returns the names of every program with golden outputs, sorted.
+fn golden_programs() -> Vec<String> {
+    let mut programs = subdirectories(Path::new(GOLDEN_DIR))
+        .into_iter()
+        .filter_map(|dir| Some(dir.file_name()?.to_string_lossy().into_owned()))
+        .collect::<Vec<_>>();
+    programs.sort();
+    programs
+}
+
+/// Returns the number of golden outputs recorded for a program.
+fn golden_file_count(program: &str) -> usize {
+    read_dir(&Path::new(GOLDEN_DIR).join(program))
+        .into_iter()
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .count()
+}
+
+fn subdirectories(path: &Path) -> Vec<PathBuf> {
+    read_dir(path)
+        .into_iter()
+        .filter(|path| path.is_dir())
+        .collect()
+}
+
+/// Returns the paths of every entry in a directory, failing the test if it cannot be read.
+fn read_dir(path: &Path) -> Vec<PathBuf> {
+    fs::read_dir(path)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect()
+        })
+        .unwrap_or_else(|e| panic!("Failed to read {}: {e}", path.display()))
+}
+
+/// Records a small synthetic challenge as a fixture.
+fn write_synthetic_fixture() {
+    let synthetic = Generator::new(SyntheticConfig {
+        scale: 2,
+        stages: 2,
+        ticks_per_stage: 60,
+        npcs_per_tick: 1,
+        seed: 1,
+    })
+    .generate();
+
+    let backend = MemoryBackend::new();
+    synthetic.store(&backend);
+    backend
+        .write_to(Path::new(FIXTURES_DIR))
+        .unwrap_or_else(|e| panic!("Failed to write fixture {}: {e}", synthetic.uuid));
+}
+
+/// Strips the parts of a program result which vary between runs, such as resource usage.
+fn golden_value(result: &ProgramResult) -> Value {
+    json!({
+        "status": result.status,
+        "analyzers": result.analyzers,
+        "outputs": result.outputs,
+    })
+}
+
+#[tokio::test]
+async fn program_outputs_match_golden_files() {
+    let update = std::env::var_os(UPDATE_VARIABLE).is_some();
+
+    let repository = DataRepository::new(Box::new(FilesystemBackend::new(Path::new(FIXTURES_DIR))));
+    let engine = Engine::load_from_directory(
+        "./programs",
+        item::Registry::load_from_file("resources/runescape_items.json").unwrap(),
+        npc::Registry::shared().unwrap(),
+    )
+    .await
+    .unwrap();
+
+    if update && fixture_uuids().is_empty() {
+        write_synthetic_fixture();
+    }
+    let uuids = fixture_uuids();
+    assert!(
+        !uuids.is_empty(),
+        "No fixture challenges in {FIXTURES_DIR} (run with {UPDATE_VARIABLE}=1 to record one)"
+    );
+    let programs = golden_programs();
+    assert!(
+        !programs.is_empty(),
+        "No programs with golden outputs in {GOLDEN_DIR}"
+    );
+
+    let mut failures = Vec::new();
+
+    for uuid in &uuids {
+        let challenge = Arc::new(
+            Challenge::load_from_repository(&repository, *uuid)
+                .await
+                .unwrap_or_else(|e| panic!("Failed to load fixture {uuid}: {e}")),
+        );
+
+        for program in &programs {
+            let result = engine
+                .run_program_inline(program, challenge.clone(), Level::Basic)
+                .await
+                .unwrap_or_else(|e| panic!("Program {program} failed on {uuid}: {e}"));
+            let actual = golden_value(&result);
+
+            let path = Path::new(GOLDEN_DIR)
+                .join(program)
+                .join(format!("{uuid}.json"));
+            if update {
+                let mut contents = serde_json::to_string_pretty(&actual).unwrap();
+                contents.push('\n');
+                fs::write(&path, contents).unwrap();
+                continue;
+            }
+
+            let Ok(expected) = fs::read(&path) else {
+                failures.push(format!(
+                    "{}: missing golden file; run with {UPDATE_VARIABLE}=1 to create it",
+                    path.display(),
+                ));
+                continue;
+            };
+            let expected: Value = serde_json::from_slice(&expected).unwrap();
+
+            let diff = diff::diff_reports(&expected, &actual).unwrap();
+            if !diff.is_empty() {
+                failures.push(format!("{}:\n{diff}", path.display()));
+            }
+        }
+    }
+
+    if !update {
+        for program in &programs {
+            let goldens = golden_file_count(program);
+            if goldens != uuids.len() {
+                failures.push(format!(
+                    "{GOLDEN_DIR}/{program}: {goldens} golden files for {} fixtures",
+                    uuids.len(),
+                ));
+            }
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "Program outputs differ from golden files (run with {UPDATE_VARIABLE}=1 to update):\n\n{}",
+        failures.join("\n"),
+    );
+}
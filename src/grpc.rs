@@ -0,0 +1,286 @@
+//! gRPC interface to the engine for internal services which already speak protobuf. It exposes
+//! the same analyze, submit, and results operations as the HTTP API, authenticated with the same
+//! API keys passed as `authorization: Bearer <key>` metadata.
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+
+use crate::analysis::{DuplicatePolicy, Level, ProgramResult, ProgramRunHandle};
+use crate::api::{self, Scope};
+use crate::blert;
+use crate::dispatch::Priority;
+use crate::error::{self, Error};
+use crate::results::ResultsFilter;
+use crate::submission::{self, AnalysisRequest, Submission, SubmitError};
+use crate::AppState;
+
+mod proto {
+    #![allow(clippy::all, clippy::pedantic)]
+    tonic::include_proto!("analyzer.v1");
+}
+
+use proto::analyzer_server::{Analyzer, AnalyzerServer};
+
+/// Serves the gRPC API on `port` until `shutdown` resolves.
+pub async fn serve(
+    state: Arc<AppState>,
+    port: u16,
+    shutdown: impl std::future::Future<Output = ()>,
+) -> error::Result<()> {
+    let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    tracing::info!("gRPC server listening on port {port}");
+
+    tonic::transport::Server::builder()
+        .add_service(AnalyzerServer::new(AnalyzerService { state }))
+        .serve_with_shutdown(address, shutdown)
+        .await
+        .map_err(|e| Error::Dependency(format!("gRPC server failed: {e}")))
+}
+
+struct AnalyzerService {
+    state: Arc<AppState>,
+}
+
+impl AnalyzerService {
+    /// Checks that the request carries an API key granting the `required` scope.
+    async fn authorize<T>(&self, request: &Request<T>, required: Scope) -> Result<(), Status> {
+        let authorization = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok());
+
+        api::check_api_key(&self.state, authorization, required)
+            .await
+            .map_err(|status| match status {
+                StatusCode::UNAUTHORIZED => Status::unauthenticated("Missing or invalid API key"),
+                StatusCode::FORBIDDEN => Status::permission_denied("API key lacks required scope"),
+                _ => Status::internal("Failed to authorize request"),
+            })
+    }
+
+    /// Submits an analysis request in the same way as the HTTP API, returning stored results or
+    /// the handles of the started runs.
+    async fn submit_request(
+        &self,
+        request: Request<proto::AnalyzeRequest>,
+    ) -> Result<Submission, Status> {
+        let request_id = api::request_id_or_new(
+            request
+                .metadata()
+                .get(api::REQUEST_ID_HEADER.as_str())
+                .and_then(|value| value.to_str().ok()),
+        );
+        let request = request.into_inner();
+
+        let uuid = parse_uuid(&request.uuid)?;
+        let stages = (!request.stages.is_empty())
+            .then(|| {
+                request
+                    .stages
+                    .iter()
+                    .map(|stage| blert::Stage::from_str_name(stage))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| Status::invalid_argument("Invalid stage"))
+            })
+            .transpose()?;
+
+        let submission = AnalysisRequest {
+            uuid,
+            level: level_from_proto(request.level()),
+            on_duplicate: match request.on_duplicate() {
+                proto::DuplicatePolicy::Attach => DuplicatePolicy::Attach,
+                proto::DuplicatePolicy::Reject => DuplicatePolicy::Reject,
+            },
+            priority: match request.priority() {
+                proto::Priority::Interactive => Priority::Interactive,
+                proto::Priority::Bulk => Priority::Bulk,
+            },
+            program: request.program,
+            additional_programs: request.additional_programs,
+            callback_url: request.callback_url,
+            players: (!request.players.is_empty()).then_some(request.players),
+            stages,
+            force: request.force,
+            locale: request.locale,
+            request_id: Some(request_id),
+        };
+
+        submission::submit(&self.state, &submission)
+            .await
+            .map_err(|e| match e {
+                SubmitError::Load(e) if e.is_retryable() => Status::unavailable(e.to_string()),
+                SubmitError::Load(_) => Status::not_found(format!("Challenge {uuid} not found")),
+                SubmitError::NoDefaultProgram => {
+                    Status::invalid_argument("No default program for challenge")
+                }
+                SubmitError::Run(e) => status_from_error(e),
+            })
+    }
+}
+
+#[tonic::async_trait]
+impl Analyzer for AnalyzerService {
+    async fn analyze(
+        &self,
+        request: Request<proto::AnalyzeRequest>,
+    ) -> Result<Response<proto::AnalyzeResponse>, Status> {
+        self.authorize(&request, Scope::Analyze).await?;
+
+        let mut handles = match self.submit_request(request).await? {
+            Submission::Cached(result) => {
+                return Ok(Response::new(analyze_response(None, Vec::new(), result)))
+            }
+            Submission::Started(handles) => handles,
+        };
+        let additional_job_ids = handles
+            .iter()
+            .skip(1)
+            .map(ProgramRunHandle::run_number)
+            .collect();
+        let handle = handles.swap_remove(0);
+        let job_id = handle.run_number();
+        let result = handle.wait().await.map_err(|e| {
+            tracing::error!("Failed to wait for job {job_id}: {e:?}");
+            status_from_error(e)
+        })?;
+
+        Ok(Response::new(analyze_response(
+            Some(job_id),
+            additional_job_ids,
+            result,
+        )))
+    }
+
+    async fn submit(
+        &self,
+        request: Request<proto::AnalyzeRequest>,
+    ) -> Result<Response<proto::SubmitResponse>, Status> {
+        self.authorize(&request, Scope::Analyze).await?;
+
+        let response = match self.submit_request(request).await? {
+            Submission::Cached(_) => proto::SubmitResponse {
+                job_id: None,
+                additional_job_ids: Vec::new(),
+                cached: true,
+            },
+            Submission::Started(handles) => proto::SubmitResponse {
+                job_id: handles.first().map(ProgramRunHandle::run_number),
+                additional_job_ids: handles
+                    .iter()
+                    .skip(1)
+                    .map(ProgramRunHandle::run_number)
+                    .collect(),
+                cached: false,
+            },
+        };
+        Ok(Response::new(response))
+    }
+
+    async fn get_results(
+        &self,
+        request: Request<proto::GetResultsRequest>,
+    ) -> Result<Response<proto::GetResultsResponse>, Status> {
+        self.authorize(&request, Scope::ReadResults).await?;
+
+        let request = request.into_inner();
+        let uuid = parse_uuid(&request.uuid)?;
+        let results = self
+            .state
+            .results
+            .as_ref()
+            .ok_or_else(|| Status::unavailable("Results persistence is not configured"))?;
+
+        let filter = ResultsFilter {
+            program: request.program.as_deref(),
+            analyzer: request.analyzer.as_deref(),
+        };
+        let runs = results.load_results(uuid, &filter).await.map_err(|e| {
+            tracing::error!("Failed to load results for challenge {uuid}: {e:?}");
+            Status::internal("Failed to load results")
+        })?;
+
+        Ok(Response::new(proto::GetResultsResponse {
+            runs: runs
+                .into_iter()
+                .map(|run| proto::StoredRun {
+                    id: run.id,
+                    program: run.program,
+                    program_version: run.program_version,
+                    level: run.level,
+                    status: run.status,
+                    started_at: run.started_at,
+                    finished_at: run.finished_at,
+                    outputs: encode_outputs(run.outputs),
+                })
+                .collect(),
+        }))
+    }
+}
+
+fn parse_uuid(uuid: &str) -> Result<Uuid, Status> {
+    Uuid::from_str(uuid).map_err(|_| Status::invalid_argument("Invalid challenge UUID"))
+}
+
+fn level_from_proto(level: proto::Level) -> Level {
+    match level {
+        proto::Level::Basic => Level::Basic,
+        proto::Level::Learner => Level::Learner,
+        proto::Level::Casual => Level::Casual,
+        proto::Level::MaxEff => Level::MaxEff,
+    }
+}
+
+/// Builds the response to an analysis from the result of its job, or from stored results if
+/// `job_id` is unset.
+fn analyze_response(
+    job_id: Option<u32>,
+    additional_job_ids: Vec<u32>,
+    result: ProgramResult,
+) -> proto::AnalyzeResponse {
+    proto::AnalyzeResponse {
+        job_id,
+        status: result.status.as_str().to_owned(),
+        analyzers: result
+            .analyzers
+            .into_iter()
+            .map(|(name, outcome)| {
+                let outcome = proto::AnalyzerOutcome {
+                    status: outcome.status.as_str().to_owned(),
+                    error: outcome.error,
+                };
+                (name, outcome)
+            })
+            .collect(),
+        outputs: encode_outputs(result.outputs),
+        additional_job_ids,
+        cached: job_id.is_none(),
+    }
+}
+
+fn encode_outputs(outputs: BTreeMap<String, serde_json::Value>) -> HashMap<String, String> {
+    outputs
+        .into_iter()
+        .map(|(name, output)| (name, output.to_string()))
+        .collect()
+}
+
+fn status_from_error(error: Error) -> Status {
+    match error {
+        Error::InvalidArgument => Status::invalid_argument("Unknown program"),
+        Error::InvalidField(field) => Status::invalid_argument(format!("Invalid {field}")),
+        Error::AlreadyRunning(job) => {
+            Status::already_exists(format!("Program already running as job {job}"))
+        }
+        Error::Busy => Status::resource_exhausted("Too many program runs in progress"),
+        Error::FailedPrecondition(message) => Status::failed_precondition(message),
+        Error::Unavailable(message) => Status::unavailable(message),
+        Error::Cancelled => Status::cancelled("Program run cancelled"),
+        e => Status::internal(e.to_string()),
+    }
+}
@@ -0,0 +1,207 @@
+//! Access to the results of previous analyses.
+//!
+//! Analyzers run synchronously on blocking threads, so the `HistoryProvider` runs its queries to
+//! completion on the engine's runtime before returning.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tokio::runtime::Handle;
+use uuid::Uuid;
+
+use crate::error::Result;
+use crate::results;
+
+/// A `HistoryProvider` answers queries about a player's past raids from the stored outputs of
+/// previous program runs.
+///
+/// Only completed runs are considered. When a challenge has been analyzed more than once, its most
+/// recent run is used.
+pub struct HistoryProvider {
+    store: Arc<results::Store>,
+    runtime: Handle,
+    /// The challenge being analyzed, which is excluded from its own history.
+    current_challenge: Uuid,
+    /// Analyzers of the loaded programs as `(program, analyzer)` pairs, keyed by implementation.
+    /// Outputs are stored under a program's name for an analyzer, not its implementation.
+    analyzers: HashMap<String, Vec<(String, String)>>,
+}
+
+/// The fastest recorded time of a split by a player or team.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PersonalBest {
+    pub ticks: u32,
+    /// The challenge in which the time was set.
+    pub challenge_uuid: Uuid,
+}
+
+/// Selects a numeric metric from a player's output of a `PlayerAnalyzer`.
+#[derive(Debug, Clone, Copy)]
+pub struct PlayerMetric<'a> {
+    /// Implementation of the analyzer whose outputs contain the metric.
+    pub analyzer: &'a str,
+    /// Path to the metric within a single player's output, e.g. `["by_stage", "TOB_NYLOCAS",
+    /// "ENVIRONMENTAL"]`.
+    pub path: &'a [&'a str],
+}
+
+impl HistoryProvider {
+    pub(crate) fn new(
+        store: Arc<results::Store>,
+        runtime: Handle,
+        current_challenge: Uuid,
+        analyzers: HashMap<String, Vec<(String, String)>>,
+    ) -> Self {
+        Self {
+            store,
+            runtime,
+            current_challenge,
+            analyzers,
+        }
+    }
+
+    /// Returns the median value of `metric` for a player over their last `raids` analyzed
+    /// challenges in which the metric was recorded, or `None` if it never was.
+    pub fn player_median(
+        &self,
+        username: &str,
+        metric: PlayerMetric<'_>,
+        raids: u32,
+    ) -> Result<Option<f64>> {
+        self.runtime
+            .block_on(self.query_player_median(username, metric, raids))
+    }
+
+    async fn query_player_median(
+        &self,
+        username: &str,
+        metric: PlayerMetric<'_>,
+        raids: u32,
+    ) -> Result<Option<f64>> {
+        let Some(analyzers) = self.analyzers.get(metric.analyzer) else {
+            return Ok(None);
+        };
+        let (programs, names): (Vec<_>, Vec<_>) = analyzers.iter().cloned().unzip();
+
+        // Outputs of opted-out players are stored under their pseudonym.
+        let pseudonymizer = self.store.load_pseudonymizer().await?;
+        let name = pseudonymizer.name(username);
+
+        // Only the most recent run of each challenge counts towards the median.
+        let (median,): (Option<f64>,) = sqlx::query_as(
+            "
+            SELECT percentile_cont(0.5) WITHIN GROUP (ORDER BY value)
+            FROM (
+                SELECT value FROM (
+                    SELECT DISTINCT ON (r.challenge_uuid)
+                        r.started_at,
+                        (o.output #>> $2)::DOUBLE PRECISION AS value
+                    FROM analyzer_outputs o
+                    JOIN analysis_runs r ON r.id = o.run_id
+                    JOIN UNNEST($1::TEXT[], $5::TEXT[]) AS a (program, analyzer)
+                        ON a.program = r.program AND a.analyzer = o.analyzer
+                    WHERE r.status IN ('completed', 'partial')
+                        AND r.challenge_uuid <> $3
+                        AND jsonb_typeof(o.output #> $2) = 'number'
+                    ORDER BY r.challenge_uuid, r.started_at DESC
+                ) latest
+                ORDER BY started_at DESC
+                LIMIT $4
+            ) recent
+            ",
+        )
+        .bind(&programs)
+        .bind(Self::json_path(&name, metric))
+        .bind(self.current_challenge)
+        .bind(i64::from(raids))
+        .bind(&names)
+        .fetch_one(self.store.pool())
+        .await?;
+
+        Ok(median)
+    }
+
+    /// Returns the personal bests of each holder in a category of challenges, keyed by holder and
+    /// split name. Holders are identified by an opaque key, such as a username or a party.
+    pub fn personal_bests(
+        &self,
+        holders: &[String],
+        category: &str,
+    ) -> Result<HashMap<(String, String), PersonalBest>> {
+        self.runtime
+            .block_on(self.query_personal_bests(holders, category))
+    }
+
+    async fn query_personal_bests(
+        &self,
+        holders: &[String],
+        category: &str,
+    ) -> Result<HashMap<(String, String), PersonalBest>> {
+        let rows: Vec<(String, String, i32, Uuid)> = sqlx::query_as(
+            "
+            SELECT holder, split, ticks, challenge_uuid
+            FROM personal_bests
+            WHERE holder = ANY($1) AND category = $2
+            ",
+        )
+        .bind(holders)
+        .bind(category)
+        .fetch_all(self.store.pool())
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(holder, split, ticks, challenge_uuid)| {
+                let best = PersonalBest {
+                    ticks: ticks.max(0) as u32,
+                    challenge_uuid,
+                };
+                ((holder, split), best)
+            })
+            .collect())
+    }
+
+    /// Per-player outputs are stored keyed by the name under which each player's data is
+    /// published, so that name prefixes the path.
+    fn json_path(username: &str, metric: PlayerMetric<'_>) -> Vec<String> {
+        std::iter::once(username)
+            .chain(metric.path.iter().copied())
+            .map(str::to_owned)
+            .collect()
+    }
+}
+
+impl results::Store {
+    /// Records times set in a challenge as personal bests of their holders, given as
+    /// `(holder, split, ticks)`. Times slower than a holder's existing best are ignored.
+    pub async fn record_personal_bests(
+        &self,
+        challenge_uuid: Uuid,
+        category: &str,
+        times: &[(String, String, u32)],
+    ) -> Result<()> {
+        let mut tx = self.pool().begin().await?;
+        for (holder, split, ticks) in times {
+            sqlx::query(
+                "
+                INSERT INTO personal_bests (holder, category, split, ticks, challenge_uuid)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (holder, category, split) DO UPDATE
+                SET ticks = EXCLUDED.ticks,
+                    challenge_uuid = EXCLUDED.challenge_uuid,
+                    achieved_at = NOW()
+                WHERE personal_bests.ticks > EXCLUDED.ticks
+                ",
+            )
+            .bind(holder)
+            .bind(category)
+            .bind(split)
+            .bind(*ticks as i32)
+            .bind(challenge_uuid)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+}
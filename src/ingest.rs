@@ -0,0 +1,250 @@
+//! Consumption of analysis requests from a NATS JetStream stream, allowing the recording pipeline
+//! to trigger analysis without depending on the availability of the HTTP API.
+//!
+//! Each message is a JSON object of the form `{"uuid": ..., "program": ..., "level": ...}`, where
+//! only `uuid` is required. A message is acknowledged once its program run finishes, so requests
+//! in flight when the service stops are redelivered. Until then, the consumer periodically reports
+//! progress on the message, so that runs which wait for capacity or take a long time are not
+//! redelivered while still in progress. Requests which fail for transient reasons
+//! are negatively acknowledged to be retried after a delay; malformed requests and requests for
+//! unknown challenges or programs are terminated, as they will never succeed.
+
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_nats::jetstream::{self, consumer::pull, AckKind};
+use futures::StreamExt;
+use serde::Deserialize;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::analysis::{DuplicatePolicy, Level, RunOptions};
+use crate::challenge::Challenge;
+use crate::error::{Error, Result};
+use crate::AppState;
+
+/// How long JetStream waits before redelivering a request which failed transiently.
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Maximum number of requests processed at once.
+const MAX_IN_FLIGHT: usize = 8;
+
+/// How long JetStream waits for an acknowledgement or progress report before redelivering a
+/// request.
+const ACK_WAIT: Duration = Duration::from_secs(60);
+
+/// Interval at which progress is reported on a request being processed. Kept well below
+/// `ACK_WAIT` so that a delayed report does not cause a redelivery.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(20);
+
+/// Number of times a request is delivered before JetStream gives up on it.
+const MAX_DELIVER: i64 = 5;
+
+/// Connection settings for the request stream.
+#[derive(Debug, Clone)]
+pub struct Config {
+    url: String,
+    stream: String,
+    consumer: String,
+}
+
+impl Config {
+    const DEFAULT_STREAM: &'static str = "analysis-requests";
+    const DEFAULT_CONSUMER: &'static str = "raid-analyzer";
+
+    /// Reads the configuration from the environment. Returns `None` if `BLERT_NATS_URL` is not
+    /// set, in which case requests are only accepted over HTTP.
+    pub fn from_env() -> Option<Self> {
+        let url = env::var("BLERT_NATS_URL").ok()?;
+        Some(Self {
+            url,
+            stream: env::var("BLERT_NATS_STREAM").unwrap_or_else(|_| Self::DEFAULT_STREAM.into()),
+            consumer: env::var("BLERT_NATS_CONSUMER")
+                .unwrap_or_else(|_| Self::DEFAULT_CONSUMER.into()),
+        })
+    }
+}
+
+/// A request to analyze a challenge.
+#[derive(Debug, Deserialize)]
+struct AnalyzeMessage {
+    uuid: Uuid,
+    /// Program to run. Defaults to the program configured for the challenge's type.
+    program: Option<String>,
+    #[serde(default = "AnalyzeMessage::default_level")]
+    level: Level,
+}
+
+impl AnalyzeMessage {
+    fn default_level() -> Level {
+        Level::Basic
+    }
+}
+
+/// Connects to the request stream and spawns a task consuming requests from it.
+pub async fn spawn_consumer(state: Arc<AppState>, config: Config) -> Result<JoinHandle<()>> {
+    let client = async_nats::connect(&config.url)
+        .await
+        .map_err(|e| Error::Dependency(format!("Failed to connect to NATS: {e}")))?;
+    let stream = jetstream::new(client)
+        .get_stream(&config.stream)
+        .await
+        .map_err(|e| {
+            Error::Dependency(format!(r#"Failed to get stream "{}": {e}"#, config.stream))
+        })?;
+    let consumer: pull::PullConsumer = stream
+        .get_or_create_consumer(
+            &config.consumer,
+            pull::Config {
+                durable_name: Some(config.consumer.clone()),
+                ack_wait: ACK_WAIT,
+                max_deliver: MAX_DELIVER,
+                ..Default::default()
+            },
+        )
+        .await
+        .map_err(|e| Error::Dependency(format!("Failed to create NATS consumer: {e}")))?;
+    let messages = consumer
+        .messages()
+        .await
+        .map_err(|e| Error::Dependency(format!("Failed to consume NATS messages: {e}")))?;
+
+    tracing::info!(
+        r#"Consuming analysis requests from stream "{}""#,
+        config.stream
+    );
+
+    Ok(tokio::spawn(async move {
+        messages
+            .for_each_concurrent(MAX_IN_FLIGHT, |message| {
+                let state = state.clone();
+                async move {
+                    match message {
+                        Ok(message) => handle_message(&state, message).await,
+                        Err(e) => tracing::error!("Failed to receive NATS message: {e}"),
+                    }
+                }
+            })
+            .await;
+        tracing::warn!("Analysis request stream closed");
+    }))
+}
+
+async fn handle_message(state: &AppState, message: jetstream::Message) {
+    let ack = match serde_json::from_slice::<AnalyzeMessage>(&message.payload) {
+        Ok(request) => match process_with_progress(state, &message, &request).await {
+            Ok(()) => AckKind::Ack,
+            Err(e) if is_retryable(&e) => {
+                tracing::warn!(
+                    "Analysis request for {} failed; retrying: {e}",
+                    request.uuid
+                );
+                AckKind::Nak(Some(RETRY_DELAY))
+            }
+            Err(e) => {
+                tracing::error!("Analysis request for {} failed: {e}", request.uuid);
+                AckKind::Term
+            }
+        },
+        Err(e) => {
+            tracing::error!("Malformed analysis request: {e}");
+            AckKind::Term
+        }
+    };
+
+    if let Err(e) = message.ack_with(ack).await {
+        tracing::error!("Failed to acknowledge analysis request: {e}");
+    }
+}
+
+/// Returns whether a request which failed with `error` may succeed if redelivered. Runs are
+/// rejected while the engine is shutting down, after which another instance may process the
+/// request, but failures of the run itself, such as a panic, would recur.
+fn is_retryable(error: &Error) -> bool {
+    error.is_retryable()
+}
+
+/// Processes a request, reporting progress on its message until it finishes.
+async fn process_with_progress(
+    state: &AppState,
+    message: &jetstream::Message,
+    request: &AnalyzeMessage,
+) -> Result<()> {
+    let processing = process(state, request);
+    tokio::pin!(processing);
+
+    let mut progress = tokio::time::interval(PROGRESS_INTERVAL);
+    // The first tick completes immediately; there is no progress to report yet.
+    progress.tick().await;
+
+    loop {
+        tokio::select! {
+            result = &mut processing => return result,
+            _ = progress.tick() => {
+                if let Err(e) = message.ack_with(AckKind::Progress).await {
+                    tracing::warn!(
+                        "Failed to report progress on analysis request for {}: {e}",
+                        request.uuid
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Loads a challenge and runs the requested program on it to completion.
+async fn process(state: &AppState, request: &AnalyzeMessage) -> Result<()> {
+    let challenge = match &state.database_pool {
+        Some(pool) => Challenge::load(pool, &state.data_repository, request.uuid).await,
+        None => Challenge::load_from_repository(&state.data_repository, request.uuid).await,
+    }?;
+
+    let engine = &state.analysis_engine;
+    let program = match &request.program {
+        Some(program) => program.as_str(),
+        None => engine
+            .default_program(challenge.r#type(), challenge.mode())
+            .ok_or(Error::InvalidArgument)?,
+    };
+
+    let options = RunOptions {
+        level: request.level,
+        on_duplicate: DuplicatePolicy::Attach,
+        ..RunOptions::default()
+    };
+    let handle = engine.run_program(program, Arc::new(challenge), options)?;
+    tracing::debug!(
+        "Started job {} for queued request on {}",
+        handle.run_number(),
+        request.uuid
+    );
+
+    handle.wait().await.map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_transient_failures_are_retried() {
+        assert!(is_retryable(&Error::Unavailable(
+            "Engine is shutting down".into()
+        )));
+        assert!(is_retryable(&Error::Busy));
+        assert!(is_retryable(
+            &Error::Unavailable("Engine is shutting down".into()).with_challenge(Uuid::nil())
+        ));
+
+        assert!(!is_retryable(&Error::FailedPrecondition(
+            "Program run terminated: task panicked".into()
+        )));
+        assert!(!is_retryable(
+            &Error::FailedPrecondition("Invalid raid scale".into())
+                .with_analyzer("TobRoleAnalyzer")
+                .with_challenge(Uuid::nil())
+        ));
+        assert!(!is_retryable(&Error::InvalidArgument));
+    }
+}
@@ -170,7 +170,7 @@ impl Registry {
     pub fn load_from_file(path: impl AsRef<Path>) -> Result<Self> {
         let reader = fs::File::open(path)?;
         let items: Vec<Item> = serde_json::from_reader(reader).map_err(|e| {
-            log::error!("Failed to parse items file: {}", e);
+            tracing::error!("Failed to parse items file: {}", e);
             Error::IncompleteData
         })?;
 
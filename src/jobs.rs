@@ -0,0 +1,239 @@
+//! Tracking of the status of analysis program runs requested through the API.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Status of a job, or of an individual analyzer within a job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// Waiting to run.
+    Queued,
+
+    /// Dispatched to a worker.
+    Running,
+
+    /// Finished successfully.
+    Completed,
+
+    /// Finished with an error.
+    Failed,
+
+    /// Will not run, e.g. an unsampled shadow analyzer.
+    Skipped,
+
+    /// Stopped before finishing at the request of a user.
+    Cancelled,
+}
+
+impl Status {
+    fn is_finished(self) -> bool {
+        matches!(
+            self,
+            Status::Completed | Status::Failed | Status::Skipped | Status::Cancelled
+        )
+    }
+}
+
+/// A flag shared between a job and the program run executing it, used to request that the run
+/// stop early.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Requests cancellation of the job.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    /// Returns whether cancellation of the job has been requested.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
+
+/// Reasons a job cannot be cancelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancelError {
+    NotFound,
+    AlreadyFinished,
+}
+
+/// A single analysis program run on a challenge.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Job {
+    pub id: u32,
+    pub program: String,
+    pub challenge_uuid: Uuid,
+    pub status: Status,
+    pub analyzers: BTreeMap<String, Status>,
+    pub error: Option<String>,
+}
+
+/// A progress update published to subscribers of a job as it runs.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Event {
+    /// An analyzer was dispatched to a worker.
+    Scheduled { analyzer: String },
+
+    /// An analyzer finished successfully.
+    Completed { analyzer: String },
+
+    /// An analyzer finished with an error.
+    Failed { analyzer: String, error: String },
+
+    /// The job finished. No further events are published after this one.
+    Finished { status: Status },
+}
+
+impl Event {
+    /// Returns the name of the event's type.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::Scheduled { .. } => "scheduled",
+            Event::Completed { .. } => "completed",
+            Event::Failed { .. } => "failed",
+            Event::Finished { .. } => "finished",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Jobs {
+    jobs: HashMap<u32, Job>,
+    tokens: HashMap<u32, CancellationToken>,
+    events: HashMap<u32, broadcast::Sender<Event>>,
+    finished: VecDeque<u32>,
+}
+
+/// A `Registry` keeps track of the status of every active program run, as well as a bounded
+/// history of finished runs.
+#[derive(Debug, Default)]
+pub struct Registry {
+    jobs: RwLock<Jobs>,
+}
+
+impl Registry {
+    /// Maximum number of finished jobs retained before the oldest are evicted.
+    const MAX_FINISHED_JOBS: usize = 1024;
+
+    /// Number of unread events buffered for each subscriber before older events are dropped.
+    const EVENT_BUFFER_SIZE: usize = 64;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a new queued job, returning the token through which it can be cancelled.
+    pub fn create(&self, id: u32, program: &str, challenge_uuid: Uuid) -> CancellationToken {
+        let job = Job {
+            id,
+            program: program.to_owned(),
+            challenge_uuid,
+            status: Status::Queued,
+            analyzers: BTreeMap::new(),
+            error: None,
+        };
+        let token = CancellationToken::default();
+
+        let mut jobs = self.jobs.write().unwrap();
+        jobs.jobs.insert(id, job);
+        jobs.tokens.insert(id, token.clone());
+        jobs.events
+            .insert(id, broadcast::channel(Self::EVENT_BUFFER_SIZE).0);
+        token
+    }
+
+    /// Requests cancellation of an unfinished job.
+    pub fn cancel(&self, id: u32) -> Result<(), CancelError> {
+        let jobs = self.jobs.read().unwrap();
+        let job = jobs.jobs.get(&id).ok_or(CancelError::NotFound)?;
+        if job.status.is_finished() {
+            return Err(CancelError::AlreadyFinished);
+        }
+
+        if let Some(token) = jobs.tokens.get(&id) {
+            token.cancel();
+        }
+        Ok(())
+    }
+
+    /// Returns a snapshot of the job with the given ID, if it is known.
+    pub fn get(&self, id: u32) -> Option<Job> {
+        self.jobs.read().unwrap().jobs.get(&id).cloned()
+    }
+
+    /// Subscribes to the progress events of a job. The returned receiver is closed once the job
+    /// finishes; if it has already finished, only its final event is received.
+    pub fn subscribe(&self, id: u32) -> Option<broadcast::Receiver<Event>> {
+        let jobs = self.jobs.read().unwrap();
+        if let Some(sender) = jobs.events.get(&id) {
+            return Some(sender.subscribe());
+        }
+
+        let job = jobs.jobs.get(&id)?;
+        let (sender, receiver) = broadcast::channel(1);
+        let _ = sender.send(Event::Finished { status: job.status });
+        Some(receiver)
+    }
+
+    /// Publishes a progress event to the subscribers of a job.
+    pub fn publish(&self, id: u32, event: Event) {
+        if let Some(sender) = self.jobs.read().unwrap().events.get(&id) {
+            // Sending only fails if there are no subscribers, which is fine.
+            let _ = sender.send(event);
+        }
+    }
+
+    /// Updates the overall status of a job.
+    pub fn set_status(&self, id: u32, status: Status) {
+        self.update(id, |job| job.status = status);
+    }
+
+    /// Marks a job as failed with the given error.
+    pub fn set_failed(&self, id: u32, error: String) {
+        self.update(id, |job| {
+            job.status = Status::Failed;
+            job.error = Some(error);
+        });
+    }
+
+    /// Updates the status of a single analyzer within a job.
+    pub fn set_analyzer_status(&self, id: u32, analyzer: &str, status: Status) {
+        self.update(id, |job| {
+            job.analyzers.insert(analyzer.to_owned(), status);
+        });
+    }
+
+    fn update(&self, id: u32, f: impl FnOnce(&mut Job)) {
+        let mut jobs = self.jobs.write().unwrap();
+        let Some(job) = jobs.jobs.get_mut(&id) else {
+            return;
+        };
+
+        let was_finished = job.status.is_finished();
+        f(job);
+
+        if !was_finished && job.status.is_finished() {
+            let status = job.status;
+            jobs.tokens.remove(&id);
+            if let Some(sender) = jobs.events.remove(&id) {
+                let _ = sender.send(Event::Finished { status });
+            }
+            jobs.finished.push_back(id);
+            while jobs.finished.len() > Self::MAX_FINISHED_JOBS {
+                if let Some(evicted) = jobs.finished.pop_front() {
+                    jobs.jobs.remove(&evicted);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,179 @@
+//! Links to the moments in a challenge described by findings and reports, opening the blert
+//! website's replay viewer at the relevant stage and tick.
+
+use std::env;
+
+use uuid::Uuid;
+
+use crate::blert;
+use crate::error::{Error, Result};
+use crate::findings::Finding;
+use crate::results;
+
+/// Environment variable holding the base URL of the blert website.
+const BASE_URL_VAR: &str = "BLERT_WEB_URL";
+
+const DEFAULT_BASE_URL: &str = "https://blert.io";
+
+/// Builds links to challenges on a blert website.
+#[derive(Debug, Clone)]
+pub struct ReplayLinks {
+    base_url: String,
+}
+
+impl Default for ReplayLinks {
+    fn default() -> Self {
+        Self {
+            base_url: DEFAULT_BASE_URL.into(),
+        }
+    }
+}
+
+impl ReplayLinks {
+    /// Creates links to the website at `base_url`, which must be an HTTP(S) URL.
+    pub fn new(base_url: &str) -> Option<Self> {
+        if !base_url.starts_with("https://") && !base_url.starts_with("http://") {
+            return None;
+        }
+        Some(Self {
+            base_url: base_url.trim_end_matches('/').to_owned(),
+        })
+    }
+
+    /// Reads the website's base URL from `BLERT_WEB_URL`, defaulting to the public blert website.
+    pub fn from_env() -> Result<Self> {
+        match env::var(BASE_URL_VAR) {
+            Ok(url) => Self::new(&url).ok_or(Error::Environment(BASE_URL_VAR)),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    /// Returns links to a single challenge.
+    pub fn challenge(&self, challenge_type: blert::Challenge, uuid: Uuid) -> ChallengeLinks<'_> {
+        ChallengeLinks {
+            base_url: &self.base_url,
+            challenge_type,
+            uuid,
+        }
+    }
+}
+
+/// Builds links to the stages and ticks of a challenge.
+#[derive(Debug, Clone, Copy)]
+pub struct ChallengeLinks<'a> {
+    base_url: &'a str,
+    challenge_type: blert::Challenge,
+    uuid: Uuid,
+}
+
+impl ChallengeLinks<'_> {
+    /// Returns the URL of the challenge's overview page, if the website supports its type.
+    pub fn overview(&self) -> Option<String> {
+        let challenge = challenge_path(self.challenge_type)?;
+        Some(format!(
+            "{}/{challenge}/{}/overview",
+            self.base_url, self.uuid
+        ))
+    }
+
+    /// Returns the URL of the replay of a stage, given by its canonical name, opened at `tick` if
+    /// one is given.
+    pub fn stage(&self, stage: &str, tick: Option<u32>) -> Option<String> {
+        let challenge = challenge_path(self.challenge_type)?;
+        let stage = stage_path(blert::Stage::from_str_name(stage)?)?;
+        let url = format!("{}/{challenge}/{}/{stage}", self.base_url, self.uuid);
+        Some(match tick {
+            Some(tick) => format!("{url}?tick={tick}"),
+            None => url,
+        })
+    }
+
+    /// Returns the URL of the moment a finding describes: the first tick of its range within its
+    /// stage, or the challenge's overview if it is not specific to a stage.
+    pub fn finding(&self, finding: &Finding) -> Option<String> {
+        match &finding.stage {
+            Some(stage) => self.stage(stage, finding.tick_range.map(|ticks| ticks.start)),
+            None => self.overview(),
+        }
+    }
+}
+
+fn challenge_path(challenge_type: blert::Challenge) -> Option<&'static str> {
+    match challenge_type {
+        blert::Challenge::Tob => Some("raids/tob"),
+        blert::Challenge::Colosseum => Some("challenges/colosseum"),
+        _ => None,
+    }
+}
+
+fn stage_path(stage: blert::Stage) -> Option<&'static str> {
+    let path = match stage {
+        blert::Stage::TobMaiden => "maiden",
+        blert::Stage::TobBloat => "bloat",
+        blert::Stage::TobNylocas => "nylocas",
+        blert::Stage::TobSotetseg => "sotetseg",
+        blert::Stage::TobXarpus => "xarpus",
+        blert::Stage::TobVerzik => "verzik",
+        blert::Stage::ColosseumWave1 => "waves/1",
+        blert::Stage::ColosseumWave2 => "waves/2",
+        blert::Stage::ColosseumWave3 => "waves/3",
+        blert::Stage::ColosseumWave4 => "waves/4",
+        blert::Stage::ColosseumWave5 => "waves/5",
+        blert::Stage::ColosseumWave6 => "waves/6",
+        blert::Stage::ColosseumWave7 => "waves/7",
+        blert::Stage::ColosseumWave8 => "waves/8",
+        blert::Stage::ColosseumWave9 => "waves/9",
+        blert::Stage::ColosseumWave10 => "waves/10",
+        blert::Stage::ColosseumWave11 => "waves/11",
+        blert::Stage::ColosseumWave12 => "waves/12",
+        _ => return None,
+    };
+    Some(path)
+}
+
+impl results::Store {
+    /// Loads the type of a stored challenge. Returns `None` if the challenge does not exist.
+    pub async fn load_challenge_type(&self, uuid: Uuid) -> Result<Option<blert::Challenge>> {
+        let challenge_type: Option<i16> =
+            sqlx::query_scalar("SELECT type FROM challenges WHERE uuid = $1")
+                .bind(uuid)
+                .fetch_optional(self.pool())
+                .await?;
+
+        Ok(challenge_type.and_then(|t| blert::Challenge::try_from(i32::from(t)).ok()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::findings::Severity;
+
+    #[test]
+    fn findings_link_to_their_first_tick() {
+        let links = ReplayLinks::new("https://blert.example/").unwrap();
+        let uuid = Uuid::nil();
+        let tob = links.challenge(blert::Challenge::Tob, uuid);
+
+        let finding = Finding::new(Severity::Minor, "bloat.late_entry")
+            .with_stage(blert::Stage::TobBloat)
+            .with_ticks(42, 50);
+        assert_eq!(
+            tob.finding(&finding).as_deref(),
+            Some("https://blert.example/raids/tob/00000000-0000-0000-0000-000000000000/bloat?tick=42"),
+        );
+        assert_eq!(
+            tob.finding(&Finding::new(Severity::Info, "death"))
+                .as_deref(),
+            Some("https://blert.example/raids/tob/00000000-0000-0000-0000-000000000000/overview"),
+        );
+
+        let colosseum = links.challenge(blert::Challenge::Colosseum, uuid);
+        assert_eq!(
+            colosseum.stage("COLOSSEUM_WAVE_12", None).as_deref(),
+            Some("https://blert.example/challenges/colosseum/00000000-0000-0000-0000-000000000000/waves/12"),
+        );
+        assert!(tob.stage("NOT_A_STAGE", Some(1)).is_none());
+        assert!(ReplayLinks::new("blert.example").is_none());
+    }
+}
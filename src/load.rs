@@ -0,0 +1,265 @@
+//! Load shedding of deep analysis while the engine is saturated, and bounds on concurrent runs.
+//!
+//! Basic analysis runs after every recorded raid and always proceeds. Runs at deeper levels are
+//! more expensive and less urgent, so when the engine's queue of pending analyzers grows too
+//! deep they are either downgraded to basic analysis or deferred until the queue drains.
+//!
+//! Independently, every program run holds its full challenge in memory, so the number of runs in
+//! progress at once can be bounded. Runs beyond the bound wait in a queue of limited size, and
+//! runs requested while the queue is full are rejected.
+
+use std::env;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use serde::Serialize;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use utoipa::ToSchema;
+
+use crate::analysis::Level;
+use crate::error::{Error, Result};
+
+/// What to do with a deep analysis run requested while the engine is saturated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShedAction {
+    /// Run the program at the basic level instead, flagging the downgrade in its results.
+    Downgrade,
+    /// Hold the run in the queue until the engine is no longer saturated.
+    Defer,
+}
+
+/// Configures when and how the engine sheds load.
+#[derive(Debug, Clone, Copy)]
+pub struct LoadSheddingPolicy {
+    /// Number of analyzers waiting for a worker at or above which the engine is saturated.
+    pub max_queue_depth: usize,
+    pub action: ShedAction,
+}
+
+impl LoadSheddingPolicy {
+    /// Reads the policy from the `BLERT_MAX_QUEUE_DEPTH` and `BLERT_SHED_ACTION` (`downgrade` or
+    /// `defer`) environment variables. Returns `None` if no maximum queue depth is set, in which
+    /// case load is never shed.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(depth) = env::var("BLERT_MAX_QUEUE_DEPTH") else {
+            return Ok(None);
+        };
+        let max_queue_depth = depth
+            .parse()
+            .map_err(|_| Error::Environment("BLERT_MAX_QUEUE_DEPTH"))?;
+
+        let action = match env::var("BLERT_SHED_ACTION").as_deref() {
+            Ok("downgrade") | Err(_) => ShedAction::Downgrade,
+            Ok("defer") => ShedAction::Defer,
+            Ok(_) => return Err(Error::Environment("BLERT_SHED_ACTION")),
+        };
+
+        Ok(Some(Self {
+            max_queue_depth,
+            action,
+        }))
+    }
+
+    /// Returns the action to take for a run at the given level while `queue_depth` analyzers are
+    /// waiting for a worker, if it should be shed. Basic analysis is never shed.
+    pub fn action_for(&self, level: Level, queue_depth: usize) -> Option<ShedAction> {
+        let saturated = level != Level::Basic && queue_depth >= self.max_queue_depth;
+        saturated.then_some(self.action)
+    }
+}
+
+/// Bounds the number of program runs in progress at once.
+#[derive(Debug)]
+pub struct RunLimiter {
+    permits: Arc<Semaphore>,
+    max_queued: usize,
+    waiting: AtomicUsize,
+    rejected: AtomicU64,
+}
+
+impl RunLimiter {
+    pub fn new(max_concurrent_runs: usize, max_queued_runs: usize) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(max_concurrent_runs)),
+            max_queued: max_queued_runs,
+            waiting: AtomicUsize::new(0),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    /// Reads the limits from the `BLERT_MAX_CONCURRENT_RUNS` and `BLERT_MAX_QUEUED_RUNS`
+    /// environment variables. Returns `None` if no maximum number of concurrent runs is set, in
+    /// which case runs are unbounded. Without a maximum queue size, no runs are queued.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(runs) = env::var("BLERT_MAX_CONCURRENT_RUNS") else {
+            return Ok(None);
+        };
+        let max_concurrent_runs = runs
+            .parse()
+            .ok()
+            .filter(|&runs| runs > 0)
+            .ok_or(Error::Environment("BLERT_MAX_CONCURRENT_RUNS"))?;
+
+        let max_queued_runs = match env::var("BLERT_MAX_QUEUED_RUNS") {
+            Ok(queued) => queued
+                .parse()
+                .map_err(|_| Error::Environment("BLERT_MAX_QUEUED_RUNS"))?,
+            Err(_) => 0,
+        };
+
+        Ok(Some(Self::new(max_concurrent_runs, max_queued_runs)))
+    }
+
+    /// Reserves a place for a new run, either in progress or in the queue. Returns `None` if
+    /// every place is taken.
+    pub fn try_enqueue(self: &Arc<Self>) -> Option<QueuedRun> {
+        let capacity = self.permits.available_permits() + self.max_queued;
+        if self.waiting.fetch_add(1, Ordering::AcqRel) >= capacity {
+            self.waiting.fetch_sub(1, Ordering::AcqRel);
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+
+        Some(QueuedRun {
+            limiter: self.clone(),
+        })
+    }
+
+    /// Returns the number of runs waiting to start.
+    pub fn queued(&self) -> usize {
+        self.waiting
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.permits.available_permits())
+    }
+
+    /// Returns the number of runs rejected because the queue was full.
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+}
+
+/// A run's place in the queue of a [`RunLimiter`].
+#[derive(Debug)]
+pub struct QueuedRun {
+    limiter: Arc<RunLimiter>,
+}
+
+impl QueuedRun {
+    /// Waits until the run can start. The run remains in progress until the returned permit is
+    /// dropped.
+    pub async fn start(self) -> OwnedSemaphorePermit {
+        self.limiter
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("Run semaphore is never closed")
+    }
+}
+
+impl Drop for QueuedRun {
+    fn drop(&mut self) {
+        self.limiter.waiting.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Counts of the runs affected by load shedding since the engine started.
+#[derive(Debug, Default)]
+pub struct ShedCounters {
+    downgraded: AtomicU64,
+    deferred: AtomicU64,
+}
+
+impl ShedCounters {
+    pub fn record(&self, action: ShedAction) {
+        let counter = match action {
+            ShedAction::Downgrade => &self.downgraded,
+            ShedAction::Defer => &self.deferred,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn downgraded(&self) -> u64 {
+        self.downgraded.load(Ordering::Relaxed)
+    }
+
+    pub fn deferred(&self) -> u64 {
+        self.deferred.load(Ordering::Relaxed)
+    }
+}
+
+/// A snapshot of the engine's load.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadMetrics {
+    /// Number of analyzers waiting for a worker.
+    pub queue_depth: usize,
+    /// Number of program runs in progress, including deferred and queued runs.
+    pub runs_in_progress: usize,
+    /// Number of runs waiting for another run to finish before starting.
+    pub runs_queued: usize,
+    /// Number of runs rejected because too many runs were in progress.
+    pub runs_rejected: u64,
+    /// Number of runs downgraded to basic analysis due to load.
+    pub runs_downgraded: u64,
+    /// Number of runs deferred due to load.
+    pub runs_deferred: u64,
+    /// Number of workers restarted after dying.
+    pub workers_respawned: u64,
+    /// Number of analyzers abandoned after exceeding their time limit which are still running.
+    pub analyzers_abandoned: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_deep_analysis_is_shed_when_saturated() {
+        let policy = LoadSheddingPolicy {
+            max_queue_depth: 10,
+            action: ShedAction::Defer,
+        };
+
+        assert_eq!(policy.action_for(Level::MaxEff, 9), None);
+        assert_eq!(
+            policy.action_for(Level::MaxEff, 10),
+            Some(ShedAction::Defer)
+        );
+        assert_eq!(policy.action_for(Level::Basic, 10), None);
+        assert_eq!(policy.action_for(Level::Basic, 100), None);
+    }
+
+    #[tokio::test]
+    async fn runs_beyond_the_queue_are_rejected() {
+        let limiter = Arc::new(RunLimiter::new(2, 1));
+
+        // Two runs can be in progress and one more can wait for them.
+        let queued = (0..3)
+            .map(|_| limiter.try_enqueue().expect("run should be admitted"))
+            .collect::<Vec<_>>();
+        assert!(limiter.try_enqueue().is_none());
+        assert_eq!(limiter.rejected(), 1);
+
+        let mut queued = queued.into_iter();
+        let first = queued.next().unwrap().start().await;
+        let _second = queued.next().unwrap().start().await;
+        let waiting = queued.next().unwrap();
+        assert_eq!(limiter.queued(), 1);
+        assert!(limiter.try_enqueue().is_none());
+        assert_eq!(limiter.rejected(), 2);
+
+        // Finishing a run lets the waiting run start, freeing its place in the queue.
+        drop(first);
+        let _third = waiting.start().await;
+        assert_eq!(limiter.queued(), 0);
+        let next = limiter.try_enqueue().expect("run should be admitted");
+        assert_eq!(limiter.queued(), 1);
+        assert!(limiter.try_enqueue().is_none());
+
+        // Abandoning a place in the queue also frees it.
+        drop(next);
+        assert!(limiter.try_enqueue().is_some());
+        assert_eq!(limiter.rejected(), 3);
+    }
+}
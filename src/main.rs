@@ -5,79 +5,341 @@
     clippy::cast_possible_wrap
 )]
 
-use axum::Router;
-use std::{
-    env,
-    sync::{Arc, Mutex},
-};
+use axum::{middleware, Router};
+use std::{env, sync::Arc, time::Duration};
 use tokio::net::TcpListener;
 
-use data_repository::{DataRepository, FilesystemBackend, S3Backend};
+use data_repository::{
+    CachingBackend, DataRepository, FilesystemBackend, LoadLimits, S3Backend, S3Options,
+};
 use error::{Error, Result};
 
 mod analysis;
 mod analyzers;
 mod api;
+mod backfill;
+mod blackboard;
 mod challenge;
+mod challenge_cache;
+mod cli;
 mod data_repository;
+mod diff;
+mod dispatch;
 mod error;
+mod export;
+mod findings;
+#[cfg(test)]
+mod golden;
+mod grpc;
+mod history;
+#[cfg(feature = "nats")]
+mod ingest;
 mod item;
+mod jobs;
+mod links;
+mod load;
+mod messages;
 mod npc;
+mod privacy;
+mod ratings;
+mod report;
+mod results;
+mod retention;
+mod sandbox;
+mod schemas;
+mod search;
+mod sessions;
+mod submission;
+mod synthetic;
+mod time;
+mod tob;
+mod trends;
+mod usage;
+mod webhook;
 
 mod blert {
     #![allow(clippy::all)]
     include!(concat!(env!("OUT_DIR"), "/blert.rs"));
 }
 
+#[cfg(feature = "metered")]
+#[global_allocator]
+static ALLOCATOR: sandbox::MeteredAllocator = sandbox::MeteredAllocator;
+
+/// Time given to in-progress program runs to finish when the server is stopped.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Size limit of the local cache of challenge data fetched from S3, if one is configured.
+const DEFAULT_DATA_CACHE_SIZE_MB: u64 = 4096;
+
 fn var(name: &'static str) -> Result<String> {
     env::var(name).map_err(|_| Error::Environment(name))
 }
 
 pub struct AppState {
-    pub analysis_engine: Mutex<analysis::Engine>,
+    pub analysis_engine: analysis::Engine,
     pub data_repository: DataRepository,
-    pub database_pool: sqlx::PgPool,
+    pub database_pool: Option<sqlx::PgPool>,
+    pub results: Option<Arc<results::Store>>,
+    pub jobs: Arc<jobs::Registry>,
+    pub backfills: backfill::Registry,
+    pub challenge_cache: challenge_cache::ChallengeCache,
+    pub auth_disabled: bool,
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    env_logger::builder().format_timestamp_micros().init();
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .init();
+
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    if cli::run(&args).await? {
+        return Ok(());
+    }
 
     let repository = initialize_data_repository().await?;
-    let database_pool = sqlx::postgres::PgPoolOptions::new()
-        .connect(&var("BLERT_DATABASE_URI")?)
-        .await?;
+
+    // API keys are stored in the database, so the API is only served without authentication if
+    // explicitly requested, e.g. for local development.
+    let auth_disabled = env::var("BLERT_AUTH_DISABLED").is_ok_and(|value| value == "1");
+    if auth_disabled {
+        tracing::warn!("BLERT_AUTH_DISABLED set; API requests are not authenticated");
+    }
+
+    // The database is optional: without it, challenges are loaded purely from the data
+    // repository and program results are not persisted.
+    let database_pool = match env::var("BLERT_DATABASE_URI") {
+        Ok(uri) => Some(sqlx::postgres::PgPoolOptions::new().connect(&uri).await?),
+        Err(_) if auth_disabled => {
+            tracing::warn!("BLERT_DATABASE_URI not set; running without a database");
+            None
+        }
+        Err(_) => {
+            return Err(Error::Config(
+                "BLERT_DATABASE_URI is required to authenticate API requests \
+                 unless BLERT_AUTH_DISABLED=1"
+                    .into(),
+            ))
+        }
+    };
 
     let item_registry = item::Registry::load_from_file("resources/runescape_items.json")?;
+    let npc_registry = npc::Registry::shared()?;
+
+    let results = match &database_pool {
+        Some(pool) => {
+            // Stored results are pseudonymized, which requires a secret salt.
+            privacy::salt()?;
+            let results = Arc::new(results::Store::new(pool.clone()));
+            results.migrate().await?;
+            Some(results)
+        }
+        None => None,
+    };
+
+    // Stored runs are kept forever unless a retention policy is configured.
+    if let (Some(results), Ok(spec)) = (&results, env::var("BLERT_RESULTS_RETENTION")) {
+        let policy = retention::RetentionPolicy::parse(&spec)?;
+        if !policy.is_empty() {
+            retention::spawn_pruner(results.clone(), policy);
+        }
+    }
 
     let mut analysis_engine =
-        analysis::Engine::load_from_directory("./programs", item_registry).await?;
+        analysis::Engine::load_from_directory("./programs", item_registry, npc_registry).await?;
+    if let Some(results) = &results {
+        analysis_engine.set_result_store(results.clone());
+    }
+    if let Some(policy) = load::LoadSheddingPolicy::from_env()? {
+        analysis_engine.set_load_shedding(policy);
+    }
+    if let Some(limiter) = load::RunLimiter::from_env()? {
+        analysis_engine.set_run_limiter(limiter);
+    }
+    if let Some(reporter) = report::discord::Reporter::from_env()? {
+        analysis_engine.set_reporter(reporter);
+    }
+    analysis_engine.set_replay_links(links::ReplayLinks::from_env()?);
+    analysis_engine
+        .set_message_catalog(messages::Catalog::load_from_directory("resources/locales")?);
     analysis_engine.start(8);
+    let jobs = analysis_engine.jobs();
 
     let state = Arc::new(AppState {
-        analysis_engine: Mutex::new(analysis_engine),
+        analysis_engine,
         data_repository: repository,
         database_pool,
+        results,
+        jobs,
+        backfills: backfill::Registry::new(),
+        challenge_cache: challenge_cache::ChallengeCache::from_env()?,
+        auth_disabled,
     });
 
+    // A backfill can be started with the server, e.g. to reanalyze past challenges after a
+    // deployment which changes analyzers.
+    if let Ok(spec) = env::var("BLERT_BACKFILL") {
+        let request = serde_json::from_str(&spec)
+            .map_err(|e| Error::Config(format!("Invalid BLERT_BACKFILL: {e}")))?;
+        state.backfills.start(state.clone(), request)?;
+    }
+
+    // The gRPC API is only served if a port is configured for it.
+    if let Ok(port) = env::var("GRPC_PORT") {
+        let port = port.parse().expect("Invalid gRPC port number");
+        let grpc_state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = grpc::serve(grpc_state, port, shutdown_signal()).await {
+                tracing::error!("{e}");
+            }
+        });
+    }
+
+    #[cfg(feature = "nats")]
+    if let Some(config) = ingest::Config::from_env() {
+        ingest::spawn_consumer(state.clone(), config).await?;
+    }
+
     let port = match env::var("PORT") {
         Ok(port) => port.parse().expect("Invalid port number"),
         Err(_) => 3033,
     };
 
-    let app = Router::new()
+    let analyze_routes = Router::new()
         .route("/analyze", axum::routing::post(api::analyze))
-        .with_state(state);
+        .route(
+            "/analyze/raw",
+            axum::routing::post(api::analyze_raw).layer(axum::extract::DefaultBodyLimit::max(
+                api::MAX_RAW_BUNDLE_BYTES,
+            )),
+        )
+        .route("/jobs/:id/cancel", axum::routing::post(api::cancel_job))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            api::require_analyze,
+        ));
+
+    // Maintenance operations act on many challenges at once, so they are reserved for admin keys.
+    let admin_routes = Router::new()
+        .route(
+            "/maintenance/reanalyze-outdated",
+            axum::routing::post(api::reanalyze_outdated),
+        )
+        .route(
+            "/maintenance/backfill",
+            axum::routing::post(api::start_backfill),
+        )
+        .route(
+            "/maintenance/backfill/:id",
+            axum::routing::get(api::get_backfill),
+        )
+        .route(
+            "/maintenance/backfill/:id/cancel",
+            axum::routing::post(api::cancel_backfill),
+        )
+        .route(
+            "/maintenance/verify/:uuid",
+            axum::routing::get(api::verify_challenge),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            api::require_admin,
+        ));
+
+    let read_routes = Router::new()
+        .route("/programs", axum::routing::get(api::get_programs))
+        .route("/analyzers", axum::routing::get(api::get_analyzers))
+        .route("/metrics", axum::routing::get(api::get_metrics))
+        .route("/analysis/:uuid", axum::routing::get(api::get_analysis))
+        .route(
+            "/analysis/:uuid/report",
+            axum::routing::get(api::get_player_reports),
+        )
+        .route("/jobs/:id", axum::routing::get(api::get_job))
+        .route("/jobs/:id/events", axum::routing::get(api::job_events))
+        .route("/sessions/:uuid", axum::routing::get(api::get_session))
+        .route(
+            "/players/:name/trends",
+            axum::routing::get(api::get_player_trends),
+        )
+        .route(
+            "/players/:name/ratings",
+            axum::routing::get(api::get_player_ratings),
+        )
+        .route(
+            "/challenges/search",
+            axum::routing::get(api::search_challenges),
+        )
+        .route("/export/results", axum::routing::get(api::export_results))
+        .route(
+            "/challenges/:uuid/states",
+            axum::routing::get(api::export_player_states),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            api::require_read_results,
+        ));
+
+    let v1_routes = Router::new()
+        .merge(analyze_routes)
+        .merge(admin_routes)
+        .merge(read_routes)
+        .layer(middleware::from_fn(api::wrap_in_envelope));
+
+    let app = Router::new()
+        .route("/openapi.json", axum::routing::get(api::openapi))
+        .route(
+            &format!("/schemas/{}", api::API_VERSION),
+            axum::routing::get(api::list_schemas),
+        )
+        .route(
+            &format!("/schemas/{}/:name", api::API_VERSION),
+            axum::routing::get(api::get_schema),
+        )
+        .nest(&format!("/{}", api::API_VERSION), v1_routes)
+        .layer(middleware::from_fn(api::assign_request_id))
+        .with_state(state.clone());
     let listener = TcpListener::bind(("127.0.0.1", port))
         .await
         .expect("Failed to bind port");
 
-    log::info!("Server listening on port {port}");
-    axum::serve(listener, app).await.expect("Server failed");
+    tracing::info!("Server listening on port {port}");
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await
+        .expect("Server failed");
+
+    tracing::info!("Shutting down; waiting for program runs to finish");
+    state.analysis_engine.shutdown(SHUTDOWN_TIMEOUT).await;
 
     Ok(())
 }
 
+/// Resolves when the process is asked to terminate.
+async fn shutdown_signal() {
+    let interrupt = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for interrupt");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for termination")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = interrupt => {},
+        () = terminate => {},
+    }
+}
+
 async fn initialize_data_repository() -> Result<DataRepository> {
     use data_repository::Backend;
 
@@ -86,11 +348,30 @@ async fn initialize_data_repository() -> Result<DataRepository> {
     let backend: Box<dyn Backend + Sync + Send + 'static> = match uri.split_once("://") {
         Some(("file", path)) => Box::new(FilesystemBackend::new(std::path::Path::new(path))),
         Some(("s3", bucket)) => {
-            let endpoint = var("BLERT_S3_ENDPOINT")?;
-            Box::new(S3Backend::init(&endpoint, bucket).await)
+            let options = S3Options::from_env()?;
+            let backend: Box<dyn Backend + Sync + Send> =
+                Box::new(S3Backend::init(bucket, &options).await);
+
+            // Fetched files can be cached locally, with a size limit in megabytes.
+            match env::var("BLERT_DATA_CACHE_DIR") {
+                Ok(dir) => {
+                    let megabytes = match env::var("BLERT_DATA_CACHE_SIZE_MB") {
+                        Ok(size) => size
+                            .parse::<u64>()
+                            .map_err(|_| Error::Environment("BLERT_DATA_CACHE_SIZE_MB"))?,
+                        Err(_) => DEFAULT_DATA_CACHE_SIZE_MB,
+                    };
+                    Box::new(CachingBackend::new(
+                        backend,
+                        std::path::Path::new(&dir),
+                        megabytes * 1024 * 1024,
+                    )?)
+                }
+                Err(_) => backend,
+            }
         }
         Some((_, _)) | None => return Err(Error::Environment("BLERT_DATA_REPOSITORY")),
     };
 
-    Ok(DataRepository::new(backend))
+    Ok(DataRepository::new(backend).with_limits(LoadLimits::from_env()?))
 }
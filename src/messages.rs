@@ -0,0 +1,183 @@
+//! Catalogs of the human-readable messages in findings and reports, translated into each
+//! supported locale.
+//!
+//! Each locale's messages are a TOML table of templates keyed by message ID. Templates refer to
+//! their arguments by name in braces, e.g. `Died {deaths} times`. A message missing from a locale
+//! falls back to the locale's language, e.g. `pt` for `pt-BR`, and then to English, whose
+//! messages are built in.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::error::{Error, Result};
+use crate::findings::Finding;
+
+/// Locale whose messages are used when a message is missing from the requested locale.
+pub const DEFAULT_LOCALE: &str = "en";
+
+const BUILT_IN_MESSAGES: &str = include_str!("../resources/locales/en.toml");
+
+/// Message templates for each supported locale.
+#[derive(Debug)]
+pub struct Catalog {
+    locales: HashMap<String, HashMap<String, String>>,
+}
+
+impl Default for Catalog {
+    fn default() -> Self {
+        let messages = toml::from_str(BUILT_IN_MESSAGES).expect("built-in messages are valid");
+        Self {
+            locales: HashMap::from([(DEFAULT_LOCALE.to_owned(), messages)]),
+        }
+    }
+}
+
+impl Catalog {
+    /// Loads the messages of each locale from the TOML files in the directory at `path`, each
+    /// named by its locale, e.g. `pt-BR.toml`. English messages missing from the directory are
+    /// taken from the built-in catalog.
+    pub fn load_from_directory(path: impl AsRef<Path>) -> Result<Self> {
+        let mut catalog = Self::default();
+
+        for entry in fs::read_dir(path)? {
+            let path = entry?.path();
+            if path.extension().map_or(true, |ext| ext != "toml") {
+                continue;
+            }
+            let Some(locale) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+
+            let messages: HashMap<String, String> = toml::from_str(&fs::read_to_string(&path)?)
+                .map_err(|e| Error::Config(format!("{}: {e}", path.display())))?;
+            catalog
+                .locales
+                .entry(normalize(locale))
+                .or_default()
+                .extend(messages);
+        }
+
+        Ok(catalog)
+    }
+
+    /// Returns a localizer formatting messages in `locale`, or in English if it is `None` or
+    /// unsupported.
+    pub fn localizer(&self, locale: Option<&str>) -> Localizer<'_> {
+        let mut chain = Vec::new();
+        if let Some(locale) = locale.map(normalize) {
+            chain.extend(self.locales.get(&locale));
+            if let Some((language, _)) = locale.split_once('-') {
+                chain.extend(self.locales.get(language));
+            }
+        }
+        chain.extend(self.locales.get(DEFAULT_LOCALE));
+        Localizer { chain }
+    }
+}
+
+/// Normalizes a locale tag for lookup, e.g. `pt_BR` to `pt-br`.
+fn normalize(locale: &str) -> String {
+    locale.to_lowercase().replace('_', "-")
+}
+
+/// Formats messages in a single locale.
+pub struct Localizer<'a> {
+    /// Messages of the locale, followed by those of each fallback locale.
+    chain: Vec<&'a HashMap<String, String>>,
+}
+
+impl Localizer<'_> {
+    /// Formats the message with the given ID, substituting its arguments. Returns the ID itself if
+    /// no locale has the message.
+    pub fn message(&self, id: &str, args: &[(&str, &dyn Display)]) -> String {
+        self.format(id, |name| {
+            args.iter()
+                .find(|(arg, _)| *arg == name)
+                .map(|(_, value)| value.to_string())
+        })
+        .unwrap_or_else(|| id.to_owned())
+    }
+
+    /// Sets a finding's message from the template for its category, `finding.<category>`. The
+    /// template's arguments are taken from the finding's data, along with its `player` and
+    /// `stage`. Findings without a template use their category as their message.
+    pub fn localize_finding(&self, finding: &mut Finding) {
+        let id = format!("finding.{}", finding.category);
+        let message = self.format(&id, |name| match name {
+            "player" => finding.player.clone(),
+            "stage" => finding.stage.clone(),
+            _ => match finding.data.as_ref()?.get(name)? {
+                Value::String(value) => Some(value.clone()),
+                value => Some(value.to_string()),
+            },
+        });
+        finding.message = message.unwrap_or_else(|| finding.category.clone());
+    }
+
+    fn format(&self, id: &str, arg: impl Fn(&str) -> Option<String>) -> Option<String> {
+        let template = self.chain.iter().find_map(|messages| messages.get(id))?;
+        Some(substitute(template, arg))
+    }
+}
+
+/// Replaces each `{name}` in `template` with the value of the named argument. References to
+/// unknown arguments are left as they are.
+fn substitute(template: &str, arg: impl Fn(&str) -> Option<String>) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        let Some(length) = rest[start..].find('}') else {
+            break;
+        };
+        result.push_str(&rest[..start]);
+        let end = start + length;
+        match arg(&rest[start + 1..end]) {
+            Some(value) => result.push_str(&value),
+            None => result.push_str(&rest[start..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::findings::Severity;
+
+    #[test]
+    fn messages_fall_back_to_english() {
+        let mut catalog = Catalog::default();
+        catalog.locales.insert(
+            "de".into(),
+            HashMap::from([(
+                "report.mistake.died".into(),
+                "{deaths} Mal gestorben".into(),
+            )]),
+        );
+
+        let german = catalog.localizer(Some("de_AT"));
+        assert_eq!(
+            german.message("report.mistake.died", &[("deaths", &3)]),
+            "3 Mal gestorben"
+        );
+        assert_eq!(german.message("report.mistake.died_once", &[]), "Died once");
+        assert_eq!(german.message("report.unknown", &[]), "report.unknown");
+
+        let mut finding = Finding::new(Severity::Minor, "bloat.late_entry")
+            .with_data(json!({ "down": 2, "ticks": 3 }));
+        german.localize_finding(&mut finding);
+        assert_eq!(finding.message, "Started attacking down 2 3 ticks late");
+
+        assert_eq!(substitute("{a} and {b", |_| Some("x".into())), "x and {b");
+    }
+}
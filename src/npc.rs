@@ -1,31 +1,194 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer};
+
 use crate::blert;
+use crate::error::{Error, Result};
+
+/// The part an NPC plays within its stage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Boss,
+    MaidenMatomenos,
+}
+
+/// Static definition of an NPC, loaded from game data.
+#[derive(Debug, Deserialize)]
+pub struct Npc {
+    pub id: u32,
+    pub name: String,
+    #[serde(default, deserialize_with = "deserialize_stage_opt")]
+    pub stage: Option<blert::Stage>,
+    pub role: Option<Role>,
+    #[serde(default, deserialize_with = "deserialize_mode_opt")]
+    pub mode: Option<blert::ChallengeMode>,
+    pub size: u32,
+    pub hitpoints: Option<u32>,
+    /// Phase of its boss fight which begins when the NPC appears, for bosses which transform into
+    /// a different NPC at the start of each phase.
+    pub phase: Option<u8>,
+}
+
+fn deserialize_stage_opt<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Option<blert::Stage>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|name| {
+            blert::Stage::from_str_name(&name)
+                .ok_or_else(|| serde::de::Error::custom(format!("invalid stage: {name}")))
+        })
+        .transpose()
+}
+
+fn deserialize_mode_opt<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Option<blert::ChallengeMode>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|name| {
+            blert::ChallengeMode::from_str_name(&name)
+                .ok_or_else(|| serde::de::Error::custom(format!("invalid mode: {name}")))
+        })
+        .transpose()
+}
+
+/// A `Registry` is a collection of NPC definitions, indexed by NPC ID. As game updates may shift
+/// the IDs of NPCs, and each challenge mode has its own variants, NPC data is loaded from a file
+/// rather than hardcoded.
+#[derive(Debug, Default)]
+pub struct Registry {
+    npcs: HashMap<u32, Arc<Npc>>,
+}
+
+static SHARED: OnceLock<Arc<Registry>> = OnceLock::new();
+
+impl Registry {
+    /// Path of the NPC definitions shipped with the service.
+    pub const DEFAULT_PATH: &'static str = "resources/npcs.json";
+
+    /// Returns the registry of the NPC definitions at [`Self::DEFAULT_PATH`], shared by the whole
+    /// process. It is loaded on first use, which is at startup so that an invalid file is
+    /// reported there.
+    pub fn shared() -> Result<Arc<Self>> {
+        if let Some(registry) = SHARED.get() {
+            return Ok(registry.clone());
+        }
+        let registry = Arc::new(Self::load_from_file(Self::DEFAULT_PATH)?);
+        Ok(SHARED.get_or_init(|| registry).clone())
+    }
+
+    /// Returns the shared registry for lookups which cannot fail, such as those made while
+    /// building a challenge's stages. If it cannot be loaded, no NPCs are known.
+    fn shared_or_empty() -> &'static Self {
+        static EMPTY: OnceLock<Registry> = OnceLock::new();
+        if SHARED.get().is_none() && Self::shared().is_err() {
+            return EMPTY.get_or_init(Registry::default);
+        }
+        SHARED.get().expect("shared registry was loaded")
+    }
+
+    /// Reads NPC definitions into a registry from a JSON file.
+    pub fn load_from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let reader = fs::File::open(path)?;
+        let npcs: Vec<Npc> = serde_json::from_reader(reader).map_err(|e| {
+            tracing::error!("Failed to parse NPCs file: {}", e);
+            Error::IncompleteData
+        })?;
 
-pub struct Id {}
+        Ok(Self {
+            npcs: npcs
+                .into_iter()
+                .map(|npc| (npc.id, Arc::new(npc)))
+                .collect(),
+        })
+    }
+
+    /// Looks up an NPC by its ID.
+    pub fn get(&self, id: u32) -> Option<&Arc<Npc>> {
+        self.npcs.get(&id)
+    }
+
+    /// Returns whether the NPC with the given ID has the specified role.
+    pub fn has_role(&self, id: u32, role: Role) -> bool {
+        self.get(id).is_some_and(|npc| npc.role == Some(role))
+    }
 
-impl Id {
-    pub const MAIDEN_MATOMENOS_ENTRY: u32 = 10820;
-    pub const MAIDEN_MATOMENOS_REGULAR: u32 = 8366;
-    pub const MAIDEN_MATOMENOS_HARD: u32 = 10828;
+    /// Returns the phase of its boss fight which the NPC with the given ID begins, if any.
+    pub fn phase(&self, id: u32) -> Option<u8> {
+        self.get(id).and_then(|npc| npc.phase)
+    }
 }
 
+/// Looks up NPCs in the [shared registry](Registry::shared).
 #[allow(clippy::module_name_repetitions)]
 pub trait NpcExt {
+    /// Returns the ID of the NPC.
+    fn npc_id(&self) -> u32;
+
     /// Returns whether the NPC is a red crab at Maiden.
-    fn is_maiden_matomenos(&self) -> bool;
+    fn is_maiden_matomenos(&self) -> bool {
+        Registry::shared_or_empty().has_role(self.npc_id(), Role::MaidenMatomenos)
+    }
+
+    /// Returns the phase of its boss fight which the NPC begins, if any.
+    fn boss_phase(&self) -> Option<u8> {
+        Registry::shared_or_empty().phase(self.npc_id())
+    }
 }
 
 impl NpcExt for blert::event::Npc {
-    fn is_maiden_matomenos(&self) -> bool {
-        self.id == Id::MAIDEN_MATOMENOS_ENTRY
-            || self.id == Id::MAIDEN_MATOMENOS_REGULAR
-            || self.id == Id::MAIDEN_MATOMENOS_HARD
+    fn npc_id(&self) -> u32 {
+        self.id
     }
 }
 
 impl NpcExt for blert::challenge_data::StageNpc {
-    fn is_maiden_matomenos(&self) -> bool {
-        self.spawn_npc_id == Id::MAIDEN_MATOMENOS_ENTRY
-            || self.spawn_npc_id == Id::MAIDEN_MATOMENOS_REGULAR
-            || self.spawn_npc_id == Id::MAIDEN_MATOMENOS_HARD
+    fn npc_id(&self) -> u32 {
+        self.spawn_npc_id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_mode_has_its_variants() {
+        let registry = Registry::load_from_file(Registry::DEFAULT_PATH).unwrap();
+
+        for mode in [
+            blert::ChallengeMode::TobEntry,
+            blert::ChallengeMode::TobRegular,
+            blert::ChallengeMode::TobHard,
+        ] {
+            let in_mode = |npc: &&Arc<Npc>| npc.mode == Some(mode);
+            let bosses = registry
+                .npcs
+                .values()
+                .filter(in_mode)
+                .filter(|npc| npc.role == Some(Role::Boss))
+                .filter_map(|npc| npc.stage)
+                .collect::<std::collections::HashSet<_>>();
+            assert_eq!(bosses.len(), 6, "{mode:?} is missing bosses");
+
+            let mut phases = registry
+                .npcs
+                .values()
+                .filter(in_mode)
+                .filter_map(|npc| npc.phase)
+                .collect::<Vec<_>>();
+            phases.sort_unstable();
+            assert_eq!(phases, [1, 2, 3], "{mode:?} is missing Verzik's forms");
+        }
     }
 }
@@ -0,0 +1,286 @@
+//! Pseudonymization of players who have opted out of having their names published.
+//!
+//! Persisted analyzer outputs and exported data replace the names of opted-out players with
+//! stable pseudonyms. Only names are replaced, so party-level statistics remain intact and a
+//! player's data can still be aggregated across challenges. Names are only replaced where they
+//! are known to appear, so that values which happen to equal a player's name are left intact.
+
+use std::collections::HashSet;
+use std::env;
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::error::{Error, Result};
+use crate::findings::Finding;
+use crate::results;
+
+/// Environment variable holding the secret salt used to derive pseudonyms. Without a secret
+/// salt, pseudonyms could be reversed by hashing known usernames.
+const SALT_VAR: &str = "BLERT_PSEUDONYM_SALT";
+
+/// Replaces the names of opted-out players with pseudonyms.
+#[derive(Debug, Clone, Default)]
+pub struct Pseudonymizer {
+    salt: String,
+    /// Lowercase usernames of opted-out players.
+    opted_out: HashSet<String>,
+}
+
+impl Pseudonymizer {
+    pub fn new(salt: String, opted_out: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            salt,
+            opted_out: opted_out.into_iter().map(|u| u.to_lowercase()).collect(),
+        }
+    }
+
+    /// Returns whether a player has opted out.
+    pub fn is_opted_out(&self, username: &str) -> bool {
+        self.opted_out.contains(&username.to_lowercase())
+    }
+
+    /// Returns the name under which a player's data is published: a pseudonym if they have
+    /// opted out, or their username otherwise.
+    pub fn name(&self, username: &str) -> String {
+        if !self.is_opted_out(username) {
+            return username.to_owned();
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(self.salt.as_bytes());
+        hasher.update(username.to_lowercase().as_bytes());
+        let digest = hasher.finalize();
+        let suffix = digest[..5]
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>();
+        format!("Anonymous-{suffix}")
+    }
+
+    /// Resolves a name under which a player's data is published back to the username recorded
+    /// in their challenges. Opted-out players are only found by their pseudonym, so that their
+    /// username cannot be linked to it; returns `None` for an opted-out player's username.
+    pub fn username(&self, name: &str) -> Option<String> {
+        if self.is_opted_out(name) {
+            return None;
+        }
+
+        let username = self
+            .opted_out
+            .iter()
+            .find(|username| self.name(username).eq_ignore_ascii_case(name))
+            .cloned();
+        Some(username.unwrap_or_else(|| name.to_owned()))
+    }
+
+    /// Replaces opted-out players' names in the given fields of a JSON value, as declared by
+    /// [`Analyzer::player_fields`](crate::analysis::Analyzer::player_fields).
+    pub fn apply_to_fields(&self, value: &mut Value, fields: &[&str]) {
+        if self.opted_out.is_empty() {
+            return;
+        }
+        visit_fields(value, fields, &mut |value| self.replace_names(value));
+    }
+
+    /// Replaces the names held directly by a value: the keys of a map keyed by username, the
+    /// elements of a list of usernames, or a single username.
+    fn replace_names(&self, value: &mut Value) {
+        match value {
+            Value::String(username) => {
+                if self.is_opted_out(username) {
+                    *username = self.name(username);
+                }
+            }
+            Value::Array(values) => {
+                for value in values {
+                    if let Value::String(username) = value {
+                        if self.is_opted_out(username) {
+                            *username = self.name(username);
+                        }
+                    }
+                }
+            }
+            Value::Object(map) => {
+                let entries = std::mem::take(map);
+                for (username, value) in entries {
+                    map.insert(self.name(&username), value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Replaces the names of opted-out players in findings: the player each finding concerns, and
+    /// every opted-out member of `party` named in its message or in the text of its data, from
+    /// which messages are written again when the findings are served.
+    pub fn apply_to_findings(&self, findings: &mut [Finding], party: &[String]) {
+        let mut hidden = party
+            .iter()
+            .chain(
+                findings
+                    .iter()
+                    .filter_map(|finding| finding.player.as_ref()),
+            )
+            .filter(|username| self.is_opted_out(username))
+            .map(|username| (username.clone(), self.name(username)))
+            .collect::<Vec<_>>();
+        if hidden.is_empty() {
+            return;
+        }
+
+        // Longer names are replaced first, so that a name containing another is not left partly
+        // replaced.
+        hidden.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+        hidden.dedup_by(|(a, _), (b, _)| a == b);
+        let replace = |text: &mut String| {
+            for (username, alias) in &hidden {
+                if text.contains(username.as_str()) {
+                    *text = text.replace(username.as_str(), alias);
+                }
+            }
+        };
+
+        for finding in findings {
+            if let Some(player) = &mut finding.player {
+                if self.is_opted_out(player) {
+                    *player = self.name(player);
+                }
+            }
+            replace(&mut finding.message);
+            if let Some(Value::Object(data)) = &mut finding.data {
+                for value in data.values_mut() {
+                    if let Value::String(text) = value {
+                        replace(text);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Calls `visit` with each of the given fields of a JSON value which is present. Fields are paths
+/// as declared by [`Analyzer::player_fields`](crate::analysis::Analyzer::player_fields).
+pub fn visit_fields(value: &mut Value, fields: &[&str], visit: &mut impl FnMut(&mut Value)) {
+    for field in fields {
+        let path = field
+            .split('/')
+            .filter(|key| !key.is_empty())
+            .collect::<Vec<_>>();
+        visit_path(value, &path, visit);
+    }
+}
+
+fn visit_path(value: &mut Value, path: &[&str], visit: &mut impl FnMut(&mut Value)) {
+    match path.split_first() {
+        None => visit(value),
+        Some((&"*", rest)) => {
+            if let Value::Array(values) = value {
+                values.iter_mut().for_each(|v| visit_path(v, rest, visit));
+            }
+        }
+        Some((key, rest)) => {
+            if let Some(value) = value.get_mut(*key) {
+                visit_path(value, rest, visit);
+            }
+        }
+    }
+}
+
+/// Reads the secret salt from which pseudonyms are derived. Fails if it is not set, as
+/// pseudonyms derived without it would not be secret.
+pub fn salt() -> Result<String> {
+    env::var(SALT_VAR)
+        .ok()
+        .filter(|salt| !salt.is_empty())
+        .ok_or(Error::Environment(SALT_VAR))
+}
+
+impl results::Store {
+    /// Loads the list of players who have opted out of having their names published.
+    pub async fn load_pseudonymizer(&self) -> Result<Pseudonymizer> {
+        let opted_out: Vec<(String,)> =
+            sqlx::query_as("SELECT username FROM player_privacy_opt_outs")
+                .fetch_all(self.pool())
+                .await?;
+
+        Ok(Pseudonymizer::new(
+            salt()?,
+            opted_out.into_iter().map(|(username,)| username),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::findings::Severity;
+    use serde_json::json;
+
+    #[test]
+    fn pseudonymizes_opted_out_players() {
+        let pseudonymizer = Pseudonymizer::new("salt".into(), ["Hidden Guy".to_string()]);
+        let alias = pseudonymizer.name("hidden guy");
+        assert!(alias.starts_with("Anonymous-"));
+        assert_eq!(pseudonymizer.name("Hidden Guy"), alias);
+        assert_eq!(pseudonymizer.name("Visible"), "Visible");
+
+        let mut output = json!({
+            "players": {
+                "Hidden Guy": { "damage": 120, "role": "Hidden Guy" },
+                "Visible": { "damage": 80, "role": "Mage" },
+            },
+            "downs": [{ "party": ["Hidden Guy", "Visible"] }],
+            "Hidden Guy": "Hidden Guy",
+        });
+        pseudonymizer.apply_to_fields(&mut output, &["players", "downs/*/party"]);
+
+        assert_eq!(
+            output,
+            json!({
+                "players": {
+                    alias.clone(): { "damage": 120, "role": "Hidden Guy" },
+                    "Visible": { "damage": 80, "role": "Mage" },
+                },
+                "downs": [{ "party": [alias.clone(), "Visible"] }],
+                "Hidden Guy": "Hidden Guy",
+            })
+        );
+
+        let mut finding = Finding::new(Severity::Minor, "bloat.late_entry");
+        finding.player = Some("Hidden Guy".into());
+        finding.message = "Hidden Guy entered late".into();
+        let mut party_finding = Finding::new(Severity::Minor, "bloat.missed_down")
+            .with_data(json!({ "partner": "Hidden Guy" }));
+        party_finding.message = "Visible and Hidden Guy missed a down".into();
+        let mut findings = [finding, party_finding];
+        pseudonymizer.apply_to_findings(
+            &mut findings,
+            &["Visible".to_string(), "Hidden Guy".to_string()],
+        );
+        assert_eq!(findings[0].player.as_deref(), Some(alias.as_str()));
+        assert_eq!(findings[0].message, format!("{alias} entered late"));
+        assert_eq!(findings[1].player, None);
+        assert_eq!(
+            findings[1].message,
+            format!("Visible and {alias} missed a down")
+        );
+        assert_eq!(findings[1].data, Some(json!({ "partner": alias.clone() })));
+    }
+
+    #[test]
+    fn opted_out_players_are_resolved_only_by_pseudonym() {
+        let pseudonymizer = Pseudonymizer::new("salt".into(), ["Hidden Guy".to_string()]);
+        let alias = pseudonymizer.name("Hidden Guy");
+
+        assert_eq!(pseudonymizer.username("hidden guy"), None);
+        assert_eq!(
+            pseudonymizer.username(&alias.to_lowercase()).as_deref(),
+            Some("hidden guy")
+        );
+        assert_eq!(
+            pseudonymizer.username("Visible").as_deref(),
+            Some("Visible")
+        );
+    }
+}
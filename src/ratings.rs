@@ -0,0 +1,229 @@
+//! Skill ratings of players in each role, updated after every analyzed challenge so that clans
+//! can track their members' progression.
+//!
+//! Ratings follow the Elo model. After each challenge, every rated player is compared with each
+//! other member of their party: they win the comparison if their performance score was higher, and
+//! draw if it was equal. As in Glicko, a player's rating moves quickly while it is provisional and
+//! settles as they complete more challenges in the role.
+
+use serde::Serialize;
+use sqlx::types::time::OffsetDateTime;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::error::Result;
+use crate::results;
+
+/// Rating given to a player the first time they are rated in a role.
+const INITIAL_RATING: f64 = 1500.0;
+
+/// Largest change in rating from a single challenge, applied to provisional ratings.
+const MAX_K_FACTOR: f64 = 40.0;
+
+/// Smallest change in rating from a single challenge, applied to established ratings.
+const MIN_K_FACTOR: f64 = 10.0;
+
+/// Role in which players are rated when the program does not classify roles.
+pub const UNCLASSIFIED_ROLE: &str = "Unclassified";
+
+/// A player's performance in a single challenge.
+#[derive(Debug, Clone)]
+pub struct Performance {
+    pub username: String,
+    pub role: String,
+    /// Performance score from 0 to 100.
+    pub score: u8,
+}
+
+/// A player's current rating in a role.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RoleRating {
+    pub role: String,
+    pub rating: f64,
+    /// Number of rated challenges in which the player played the role.
+    pub challenges: u32,
+    /// Unix timestamp at which the rating last changed.
+    pub updated_at: i64,
+}
+
+#[derive(sqlx::FromRow)]
+struct RatingRow {
+    role: String,
+    rating: f64,
+    challenges: i32,
+    updated_at: OffsetDateTime,
+}
+
+/// Returns the maximum change in rating from a single challenge for a player who has completed
+/// `challenges` rated challenges in a role.
+fn k_factor(challenges: u32) -> f64 {
+    (MAX_K_FACTOR / (1.0 + f64::from(challenges) / 10.0).sqrt()).max(MIN_K_FACTOR)
+}
+
+/// Returns the probability of a player with rating `rating` beating one with rating `opponent`.
+fn expected_score(rating: f64, opponent: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((opponent - rating) / 400.0))
+}
+
+/// Computes the new rating of each player from their current rating, number of rated challenges,
+/// and performance score in a challenge. A player's change in rating is averaged over their
+/// comparisons with the rest of the party.
+fn updated_ratings(players: &[(f64, u32, u8)]) -> Vec<f64> {
+    if players.len() < 2 {
+        return players.iter().map(|&(rating, _, _)| rating).collect();
+    }
+
+    players
+        .iter()
+        .enumerate()
+        .map(|(i, &(rating, challenges, score))| {
+            let delta = players
+                .iter()
+                .enumerate()
+                .filter(|&(j, _)| j != i)
+                .map(|(_, &(opponent, _, opponent_score))| {
+                    let actual = match score.cmp(&opponent_score) {
+                        std::cmp::Ordering::Greater => 1.0,
+                        std::cmp::Ordering::Equal => 0.5,
+                        std::cmp::Ordering::Less => 0.0,
+                    };
+                    actual - expected_score(rating, opponent)
+                })
+                .sum::<f64>()
+                / (players.len() - 1) as f64;
+            rating + k_factor(challenges) * delta
+        })
+        .collect()
+}
+
+impl results::Store {
+    /// Updates the ratings of a challenge's party from their performances in it. Each challenge
+    /// is only rated once; returns `false` without changing any ratings if it already was.
+    ///
+    /// Ratings are stored under the name with which the player's data is published.
+    pub async fn update_ratings(
+        &self,
+        challenge_uuid: Uuid,
+        performances: &[Performance],
+    ) -> Result<bool> {
+        let pseudonymizer = self.load_pseudonymizer().await?;
+        let mut tx = self.pool().begin().await?;
+
+        let inserted = sqlx::query(
+            "INSERT INTO rated_challenges (challenge_uuid) VALUES ($1) ON CONFLICT DO NOTHING",
+        )
+        .bind(challenge_uuid)
+        .execute(&mut *tx)
+        .await?;
+        if inserted.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        // Rows are locked in a consistent order so that concurrent updates for overlapping
+        // parties cannot deadlock.
+        let mut performances = performances
+            .iter()
+            .map(|performance| {
+                let username = pseudonymizer.name(&performance.username).to_lowercase();
+                (username, performance)
+            })
+            .collect::<Vec<_>>();
+        performances.sort_by(|(a, p), (b, q)| (a, &p.role).cmp(&(b, &q.role)));
+
+        let mut players = Vec::with_capacity(performances.len());
+        for (username, performance) in &performances {
+            let current: Option<(f64, i32)> = sqlx::query_as(
+                "
+                SELECT rating, challenges FROM player_ratings
+                WHERE username = $1 AND role = $2
+                FOR UPDATE
+                ",
+            )
+            .bind(username)
+            .bind(&performance.role)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+            let (rating, challenges) = current.unwrap_or((INITIAL_RATING, 0));
+            players.push((rating, challenges.max(0) as u32));
+        }
+
+        let ratings = updated_ratings(
+            &players
+                .iter()
+                .zip(&performances)
+                .map(|((rating, challenges), (_, p))| (*rating, *challenges, p.score))
+                .collect::<Vec<_>>(),
+        );
+
+        for (((_, challenges), (username, performance)), rating) in
+            players.iter().zip(&performances).zip(ratings)
+        {
+            sqlx::query(
+                "
+                INSERT INTO player_ratings (username, role, rating, challenges, updated_at)
+                VALUES ($1, $2, $3, $4, NOW())
+                ON CONFLICT (username, role) DO UPDATE
+                SET rating = EXCLUDED.rating,
+                    challenges = EXCLUDED.challenges,
+                    updated_at = EXCLUDED.updated_at
+                ",
+            )
+            .bind(username)
+            .bind(&performance.role)
+            .bind(rating)
+            .bind(*challenges as i32 + 1)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Loads a player's current rating in every role they have played, highest first.
+    pub async fn load_ratings(&self, username: &str) -> Result<Vec<RoleRating>> {
+        let rows: Vec<RatingRow> = sqlx::query_as(
+            "
+            SELECT role, rating, challenges, updated_at
+            FROM player_ratings
+            WHERE username = lower($1)
+            ORDER BY rating DESC
+            ",
+        )
+        .bind(username)
+        .fetch_all(self.pool())
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| RoleRating {
+                role: row.role,
+                rating: row.rating,
+                challenges: row.challenges.max(0) as u32,
+                updated_at: row.updated_at.unix_timestamp(),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ratings_follow_relative_performance() {
+        let ratings = updated_ratings(&[(1500.0, 0, 90), (1500.0, 0, 60), (1500.0, 0, 60)]);
+        assert!((ratings[0] - (INITIAL_RATING + MAX_K_FACTOR * 0.5)).abs() < 1e-9);
+        assert!((ratings[1] - ratings[2]).abs() < 1e-9);
+        assert!(ratings[1] < INITIAL_RATING);
+
+        // Established ratings move less than provisional ones for the same result.
+        let established = updated_ratings(&[(1500.0, 200, 90), (1500.0, 0, 60)]);
+        assert!(established[0] - INITIAL_RATING < INITIAL_RATING - established[1]);
+        assert!((k_factor(1000) - MIN_K_FACTOR).abs() < f64::EPSILON);
+
+        assert_eq!(updated_ratings(&[(1600.0, 3, 100)]), [1600.0]);
+    }
+}
@@ -0,0 +1,339 @@
+//! Delivery of program run reports to Discord.
+//!
+//! Clans register a Discord webhook along with their members' usernames. When a program run on a
+//! challenge with a clan member in its party finishes, the outputs of the analyzers which the
+//! program opts into reporting are formatted into an embed and posted to the clan's webhook.
+//! Each clan only receives the per-player outputs of its own members, and the names of players
+//! who have opted out of publication are pseudonymized.
+
+use std::env;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::challenge::Challenge;
+use crate::error::{Error, Result};
+use crate::privacy::{self, Pseudonymizer};
+use crate::results::RunStatus;
+use crate::sessions::Session;
+use crate::{time, webhook};
+
+/// Maximum number of fields Discord accepts in an embed.
+const MAX_FIELDS: usize = 25;
+
+/// Maximum length of an embed field's value accepted by Discord.
+const MAX_FIELD_LENGTH: usize = 1024;
+
+/// Maximum length of an embed's title accepted by Discord.
+const MAX_TITLE_LENGTH: usize = 256;
+
+/// Maximum combined length of an embed's title, description, field names and values, and footer
+/// accepted by Discord.
+const MAX_EMBED_LENGTH: usize = 6000;
+
+/// The output of an analyzer which the program opts into reporting.
+#[derive(Debug)]
+pub struct ReportedOutput<'a> {
+    pub analyzer: &'a str,
+    pub output: &'a Value,
+    /// Fields of the output which hold player usernames.
+    pub player_fields: &'static [&'static str],
+}
+
+#[derive(Debug, Deserialize)]
+struct ClanFile {
+    clans: Vec<Clan>,
+}
+
+/// A clan to which reports are delivered.
+#[derive(Debug, Deserialize)]
+struct Clan {
+    name: String,
+    webhook_url: String,
+    /// Usernames of the clan's members. Matched case-insensitively.
+    members: Vec<String>,
+}
+
+impl Clan {
+    fn includes(&self, username: &str) -> bool {
+        self.members
+            .iter()
+            .any(|member| member.eq_ignore_ascii_case(username))
+    }
+
+    fn includes_any(&self, party: &[String]) -> bool {
+        party.iter().any(|username| self.includes(username))
+    }
+
+    /// Removes the entries of players outside of the clan from each of an output's player fields.
+    fn retain_members(&self, output: &mut Value, fields: &[&str]) {
+        privacy::visit_fields(output, fields, &mut |value| match value {
+            Value::Object(players) => players.retain(|username, _| self.includes(username)),
+            Value::Array(players) => players.retain(|player| match player {
+                Value::String(username) => self.includes(username),
+                _ => true,
+            }),
+            Value::String(username) if !self.includes(username) => *value = Value::Null,
+            _ => {}
+        });
+    }
+}
+
+/// Posts reports of finished program runs to the webhooks of the clans involved.
+pub struct Reporter {
+    clans: Vec<Clan>,
+    client: reqwest::Client,
+}
+
+impl Reporter {
+    /// Reads the clans to report to from the TOML file named by `BLERT_REPORT_CLANS`. Returns
+    /// `None` if the variable is not set, in which case no reports are delivered.
+    pub fn from_env() -> Result<Option<Self>> {
+        let Ok(path) = env::var("BLERT_REPORT_CLANS") else {
+            return Ok(None);
+        };
+
+        let contents = std::fs::read_to_string(&path)?;
+        let file: ClanFile = toml::from_str(&contents)
+            .map_err(|e| Error::Config(format!("Invalid report clans file {path}: {e}")))?;
+        tracing::info!("Delivering reports to {} clans", file.clans.len());
+
+        Ok(Some(Self {
+            clans: file.clans,
+            client: webhook::client()?,
+        }))
+    }
+
+    /// Delivers a report of a finished program run to every clan with a member in the
+    /// challenge's party. `findings` are the outputs of the analyzers opted into reporting, and
+    /// `session` is the practice session to which the challenge belongs, if any.
+    pub async fn deliver(
+        &self,
+        challenge: &Challenge,
+        program: &str,
+        status: RunStatus,
+        duration: Duration,
+        findings: &[ReportedOutput<'_>],
+        session: Option<&Session>,
+        pseudonymizer: &Pseudonymizer,
+    ) {
+        let clans = self
+            .clans
+            .iter()
+            .filter(|clan| clan.includes_any(challenge.party()));
+
+        for clan in clans {
+            let findings = findings
+                .iter()
+                .map(|finding| {
+                    let mut output = finding.output.clone();
+                    clan.retain_members(&mut output, finding.player_fields);
+                    pseudonymizer.apply_to_fields(&mut output, finding.player_fields);
+                    (finding.analyzer, output)
+                })
+                .collect::<Vec<_>>();
+            let embed = build_embed(
+                challenge,
+                program,
+                status,
+                duration,
+                &findings,
+                session,
+                pseudonymizer,
+            );
+            let payload = json!({ "embeds": [embed] });
+
+            let result = self
+                .client
+                .post(&clan.webhook_url)
+                .json(&payload)
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status);
+            if let Err(e) = result {
+                tracing::warn!(
+                    r#"Failed to deliver report of challenge {} to clan "{}": {e}"#,
+                    challenge.uuid(),
+                    clan.name,
+                );
+            }
+        }
+    }
+}
+
+/// Builds a Discord embed summarizing a program run, with one field per finding. Fields which
+/// do not fit within Discord's limits on the size of an embed are cut short or left out.
+fn build_embed(
+    challenge: &Challenge,
+    program: &str,
+    status: RunStatus,
+    duration: Duration,
+    findings: &[(&str, Value)],
+    session: Option<&Session>,
+    pseudonymizer: &Pseudonymizer,
+) -> Value {
+    let color = match status {
+        RunStatus::Completed => 0x2e_cc71,
+        RunStatus::Partial => 0xf1_c40f,
+        RunStatus::Failed | RunStatus::Cancelled => 0xe7_4c3c,
+    };
+
+    let party = challenge
+        .party()
+        .iter()
+        .map(|username| pseudonymizer.name(username))
+        .collect::<Vec<_>>();
+    let title = truncate(
+        &format!("{} ({})", party.join(", "), challenge.mode().as_str_name()),
+        MAX_TITLE_LENGTH,
+    );
+    let mut description = match challenge.duration() {
+        Some(duration) => format!(
+            "{} in {}, analyzed {}.",
+            challenge.status(),
+            time::format_duration(duration),
+            status.as_str(),
+        ),
+        None => format!("{}, analyzed {}.", challenge.status(), status.as_str()),
+    };
+    // A session of a single challenge says nothing beyond the challenge itself.
+    if let Some(session) = session.filter(|session| session.challenges.len() > 1) {
+        description.push_str(&format!("\nSession: {}.", session.summary()));
+    }
+    let footer = format!(
+        "{program} · {} · {}ms",
+        challenge.uuid(),
+        duration.as_millis()
+    );
+
+    let budget = MAX_EMBED_LENGTH.saturating_sub(
+        title.chars().count() + description.chars().count() + footer.chars().count(),
+    );
+
+    json!({
+        "title": title,
+        "description": description,
+        "color": color,
+        "fields": build_fields(findings, budget),
+        "footer": { "text": footer },
+    })
+}
+
+/// Formats findings into embed fields whose names and values total at most `budget` characters.
+fn build_fields(findings: &[(&str, Value)], budget: usize) -> Vec<Value> {
+    let mut remaining = budget;
+    let mut fields = Vec::new();
+    for (analyzer, output) in findings.iter().take(MAX_FIELDS) {
+        let name = truncate(analyzer, remaining.min(MAX_TITLE_LENGTH));
+        let value_length = remaining
+            .saturating_sub(name.chars().count())
+            .min(MAX_FIELD_LENGTH);
+        if name.is_empty() || value_length == 0 {
+            break;
+        }
+
+        let value = truncate(&format_finding(output), value_length);
+        remaining -= name.chars().count() + value.chars().count();
+        fields.push(json!({
+            "name": name,
+            "value": value,
+            "inline": false,
+        }));
+    }
+    fields
+}
+
+/// Formats an analyzer output as Discord markdown, listing the top-level entries of objects on
+/// separate lines.
+fn format_finding(output: &Value) -> String {
+    match output {
+        Value::Object(entries) => entries
+            .iter()
+            .map(|(key, value)| format!("**{key}**: {}", format_value(value)))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        value => format_value(value),
+    }
+}
+
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => "—".into(),
+        value => value.to_string(),
+    }
+}
+
+/// Shortens `s` to at most `max` characters, marking it with an ellipsis if it was cut.
+fn truncate(s: &str, max: usize) -> String {
+    if s.chars().count() <= max {
+        return s.to_owned();
+    }
+    if max == 0 {
+        return String::new();
+    }
+    let mut truncated = s.chars().take(max - 1).collect::<String>();
+    truncated.push('…');
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn findings_are_formatted_within_limits() {
+        let finding = json!({"alice": {"deaths": 1}, "bob": "mage", "carol": null});
+        assert_eq!(
+            format_finding(&finding),
+            "**alice**: {\"deaths\":1}\n**bob**: mage\n**carol**: —"
+        );
+
+        let long = "x".repeat(MAX_FIELD_LENGTH + 10);
+        let truncated = truncate(&long, MAX_FIELD_LENGTH);
+        assert_eq!(truncated.chars().count(), MAX_FIELD_LENGTH);
+        assert!(truncated.ends_with('…'));
+    }
+
+    #[test]
+    fn clans_match_members_case_insensitively() {
+        let clan = Clan {
+            name: "Example".into(),
+            webhook_url: "https://discord.invalid/webhook".into(),
+            members: vec!["Alice".into()],
+        };
+        assert!(clan.includes_any(&["bob".into(), "alice".into()]));
+        assert!(!clan.includes_any(&["bob".into()]));
+
+        let mut output = json!({
+            "players": { "alice": 1, "bob": 2 },
+            "party": ["bob", "ALICE"],
+            "mvp": "bob",
+        });
+        clan.retain_members(&mut output, &["players", "party", "mvp"]);
+        assert_eq!(
+            output,
+            json!({ "players": { "alice": 1 }, "party": ["ALICE"], "mvp": null })
+        );
+    }
+
+    #[test]
+    fn fields_fit_within_embed_budget() {
+        let long = json!("x".repeat(MAX_FIELD_LENGTH * 2));
+        let findings = (0..10)
+            .map(|_| ("AnalyzerName", long.clone()))
+            .collect::<Vec<_>>();
+
+        let fields = build_fields(&findings, 3000);
+        let total = fields
+            .iter()
+            .map(|field| {
+                field["name"].as_str().unwrap().chars().count()
+                    + field["value"].as_str().unwrap().chars().count()
+            })
+            .sum::<usize>();
+        assert!(total <= 3000);
+        assert_eq!(fields.len(), 3);
+    }
+}
@@ -0,0 +1,524 @@
+//! Human-readable reports on each player's performance in a challenge.
+//!
+//! Reports are built from the stored outputs of a program run rather than from live analyzers, so
+//! they can be produced for any past run. Each recognized analyzer implementation contributes
+//! strengths and mistakes; the analysis level controls how much detail is included. Reports are
+//! written in the locale of the given localizer.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::analysis::Level;
+use crate::findings::TickRange;
+use crate::links::ChallengeLinks;
+use crate::messages::Localizer;
+use crate::time;
+
+pub mod discord;
+
+/// Damage taken above this multiple of a player's typical damage is reported as a mistake.
+const EXCESS_DAMAGE_FACTOR: f64 = 1.25;
+
+/// Mistakes costing fewer ticks than this are considered minor, and only reported at the most
+/// detailed level.
+const MINOR_TICKS: u64 = 3;
+
+/// Implementations whose outputs are keyed by username.
+const PLAYER_IMPLEMENTATIONS: [&str; 4] = [
+    "TobRoleAnalyzer",
+    "GearSwitchAnalyzer",
+    "MetricAnalyzer",
+    "DamageTakenAnalyzer",
+];
+
+/// A report on a single player's performance.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerReport {
+    pub username: String,
+    pub level: Level,
+    /// The player's classified role, if known.
+    pub role: Option<String>,
+    pub strengths: Vec<String>,
+    pub mistakes: Vec<Mistake>,
+    /// Advice on addressing the player's mistakes. Empty at the basic level.
+    pub suggestions: Vec<String>,
+    /// The report rendered as plain text.
+    pub text: String,
+}
+
+/// A mistake made by a player.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Mistake {
+    /// Canonical name of the stage in which the mistake was made, if specific to one.
+    pub stage: Option<String>,
+    /// Ticks of the stage over which the mistake was made. Only included at the casual level and
+    /// above.
+    pub ticks: Option<TickRange>,
+    pub description: String,
+    /// URL of the replay of the challenge at the start of the mistake.
+    pub link: Option<String>,
+}
+
+/// A mistake along with the metadata used to decide whether and how to report it.
+struct Finding {
+    stage: Option<String>,
+    ticks: Option<TickRange>,
+    description: String,
+    /// ID of the message suggesting how to avoid the mistake.
+    suggestion: &'static str,
+    minor: bool,
+}
+
+#[derive(Default)]
+struct Draft {
+    role: Option<String>,
+    strengths: Vec<String>,
+    findings: Vec<Finding>,
+}
+
+/// Builds a report for every player appearing in the outputs of a program run, ordered by
+/// username. `outputs` maps analyzer implementation names to their outputs; outputs of
+/// implementations which do not contribute to reports are ignored. Mistakes are linked to the
+/// challenge's replay if `links` are given.
+pub fn build_reports(
+    outputs: &HashMap<&str, &Value>,
+    level: Level,
+    localizer: &Localizer,
+    links: Option<&ChallengeLinks>,
+) -> Vec<PlayerReport> {
+    let mut players = BTreeSet::new();
+    for implementation in PLAYER_IMPLEMENTATIONS {
+        if let Some(Value::Object(by_player)) = outputs.get(implementation) {
+            players.extend(by_player.keys().cloned());
+        }
+    }
+
+    let party_strengths = outputs
+        .get("TobSplitsAnalyzer")
+        .map(|splits| split_strengths(localizer, splits))
+        .unwrap_or_default();
+
+    players
+        .into_iter()
+        .map(|username| {
+            let mut draft = Draft {
+                strengths: party_strengths.clone(),
+                ..Default::default()
+            };
+
+            draft.role = outputs
+                .get("TobRoleAnalyzer")
+                .and_then(|roles| roles.get(&username)?.get(0)?.as_str())
+                .map(str::to_owned);
+            if let Some(output) = outputs.get("GearSwitchAnalyzer") {
+                add_gear_switches(&mut draft, localizer, output, &username);
+            }
+            if let Some(output) = outputs.get("TobBloatAnalyzer") {
+                add_bloat_downs(&mut draft, localizer, output, &username);
+            }
+            if let Some(output) = outputs.get("MetricAnalyzer") {
+                add_deaths(&mut draft, localizer, output, &username);
+            }
+            if let Some(output) = outputs.get("DamageTakenAnalyzer") {
+                add_damage_taken(&mut draft, localizer, output, &username);
+            }
+
+            finish(username, level, draft, localizer, links)
+        })
+        .collect()
+}
+
+/// Selects the findings to include at `level` and renders the report.
+fn finish(
+    username: String,
+    level: Level,
+    draft: Draft,
+    localizer: &Localizer,
+    links: Option<&ChallengeLinks>,
+) -> PlayerReport {
+    let with_suggestions = level != Level::Basic;
+    let with_ticks = matches!(level, Level::Casual | Level::MaxEff);
+    let with_minor = level == Level::MaxEff;
+
+    let mut suggestions = Vec::<String>::new();
+    let mistakes = draft
+        .findings
+        .into_iter()
+        .filter(|finding| with_minor || !finding.minor)
+        .map(|finding| {
+            let suggestion = localizer.message(finding.suggestion, &[]);
+            if with_suggestions && !suggestions.contains(&suggestion) {
+                suggestions.push(suggestion);
+            }
+            let link = links
+                .zip(finding.stage.as_deref())
+                .and_then(|(links, stage)| {
+                    links.stage(stage, finding.ticks.map(|ticks| ticks.start))
+                });
+            Mistake {
+                stage: finding.stage,
+                ticks: finding.ticks.filter(|_| with_ticks),
+                description: finding.description,
+                link,
+            }
+        })
+        .collect();
+
+    let mut report = PlayerReport {
+        username,
+        level,
+        role: draft.role,
+        strengths: draft.strengths,
+        mistakes,
+        suggestions,
+        text: String::new(),
+    };
+    report.text = render(&report, localizer);
+    report
+}
+
+/// Renders a report as plain text.
+fn render(report: &PlayerReport, localizer: &Localizer) -> String {
+    let mut text = match &report.role {
+        Some(role) => localizer.message(
+            "report.heading_with_role",
+            &[
+                ("username", &report.username),
+                ("role", role),
+                ("level", &report.level),
+            ],
+        ),
+        None => localizer.message(
+            "report.heading",
+            &[("username", &report.username), ("level", &report.level)],
+        ),
+    };
+    text.push('\n');
+
+    if !report.strengths.is_empty() {
+        let _ = writeln!(text, "\n{}", localizer.message("report.strengths", &[]));
+        for strength in &report.strengths {
+            let _ = writeln!(text, "  - {strength}");
+        }
+    }
+
+    let _ = writeln!(text, "\n{}", localizer.message("report.mistakes", &[]));
+    if report.mistakes.is_empty() {
+        let _ = writeln!(text, "  {}", localizer.message("report.no_mistakes", &[]));
+    }
+    for mistake in &report.mistakes {
+        let location = match (&mistake.stage, mistake.ticks) {
+            (Some(stage), Some(ticks)) => localizer.message(
+                "report.location_with_ticks",
+                &[
+                    ("room", &room_name(stage)),
+                    ("start", &ticks.start),
+                    ("end", &ticks.end),
+                ],
+            ),
+            (Some(stage), None) => {
+                localizer.message("report.location", &[("room", &room_name(stage))])
+            }
+            (None, _) => String::new(),
+        };
+        if location.is_empty() {
+            let _ = writeln!(text, "  - {}", mistake.description);
+        } else {
+            let _ = writeln!(text, "  - {location} {}", mistake.description);
+        }
+    }
+
+    if !report.suggestions.is_empty() {
+        let _ = writeln!(text, "\n{}", localizer.message("report.suggestions", &[]));
+        for suggestion in &report.suggestions {
+            let _ = writeln!(text, "  - {suggestion}");
+        }
+    }
+
+    text
+}
+
+/// Returns a short display name for a stage from its canonical name, e.g. `Maiden` for
+/// `TOB_MAIDEN`.
+fn room_name(stage: &str) -> String {
+    let name = stage.split_once('_').map_or(stage, |(_, room)| room);
+    name.split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            chars.next().map_or_else(String::new, |first| {
+                first.to_string() + &chars.as_str().to_lowercase()
+            })
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn as_u64(value: Option<&Value>) -> u64 {
+    value.and_then(Value::as_u64).unwrap_or(0)
+}
+
+fn tick_range(value: Option<&Value>) -> Option<TickRange> {
+    value.and_then(|range| TickRange::deserialize(range).ok())
+}
+
+/// Rooms the party completed faster than their benchmark, shared by every player.
+fn split_strengths(localizer: &Localizer, splits: &Value) -> Vec<String> {
+    let Some(Value::Object(rooms)) = splits.get("rooms") else {
+        return Vec::new();
+    };
+
+    rooms
+        .iter()
+        .filter_map(|(stage, room)| {
+            let delta = room.get("deltaTicks")?.as_i64().filter(|&d| d < 0)?;
+            let saved = delta.unsigned_abs() as u32;
+            Some(localizer.message(
+                "report.strength.fast_split",
+                &[
+                    ("room", &room_name(stage)),
+                    ("ticks", &saved),
+                    ("time", &time::format_ticks(saved)),
+                ],
+            ))
+        })
+        .collect()
+}
+
+fn add_gear_switches(draft: &mut Draft, localizer: &Localizer, output: &Value, username: &str) {
+    let Some(Value::Object(rooms)) = output.get(username).and_then(|p| p.get("by_stage")) else {
+        return;
+    };
+    let or = format!(" {} ", localizer.message("report.or", &[]));
+
+    for (stage, room) in rooms {
+        let missing = room
+            .get("missingStyles")
+            .and_then(Value::as_array)
+            .map(|styles| styles.iter().filter_map(Value::as_str).collect::<Vec<_>>())
+            .unwrap_or_default();
+        if !missing.is_empty() {
+            draft.findings.push(Finding {
+                stage: Some(stage.clone()),
+                ticks: None,
+                description: localizer.message(
+                    "report.mistake.missing_styles",
+                    &[("styles", &missing.join(&or))],
+                ),
+                suggestion: "report.suggestion.gear_styles",
+                minor: false,
+            });
+        }
+
+        let ticks_lost = as_u64(room.get("ticksLost"));
+        if ticks_lost > 0 {
+            draft.findings.push(Finding {
+                stage: Some(stage.clone()),
+                ticks: None,
+                description: localizer
+                    .message("report.mistake.slow_switch", &[("ticks", &ticks_lost)]),
+                suggestion: "report.suggestion.switch_speed",
+                minor: ticks_lost < MINOR_TICKS,
+            });
+        }
+    }
+}
+
+fn add_bloat_downs(draft: &mut Draft, localizer: &Localizer, output: &Value, username: &str) {
+    let stage = Some("TOB_BLOAT".to_owned());
+    let mut attended = false;
+    let mut clean = true;
+
+    for down in output
+        .get("downs")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        let Some(player) = down.get("players").and_then(|p| p.get(username)) else {
+            continue;
+        };
+        attended = true;
+
+        let number = as_u64(down.get("number"));
+
+        let late = as_u64(player.get("lateEntryTicks"));
+        if late > 0 {
+            clean = false;
+            draft.findings.push(Finding {
+                stage: stage.clone(),
+                ticks: tick_range(player.get("lateEntry")),
+                description: localizer.message(
+                    "report.mistake.late_entry",
+                    &[("down", &number), ("ticks", &late)],
+                ),
+                suggestion: "report.suggestion.down_early",
+                minor: late < MINOR_TICKS,
+            });
+        }
+
+        let early = as_u64(player.get("earlyExitTicks"));
+        if early > 0 {
+            clean = false;
+            draft.findings.push(Finding {
+                stage: stage.clone(),
+                ticks: tick_range(player.get("earlyExit")),
+                description: localizer.message(
+                    "report.mistake.early_exit",
+                    &[("down", &number), ("ticks", &early)],
+                ),
+                suggestion: "report.suggestion.down_late",
+                minor: early < MINOR_TICKS,
+            });
+        }
+
+        if player.get("stomped").and_then(Value::as_bool) == Some(true) {
+            clean = false;
+            let end = as_u64(down.get("endTick")) as u32;
+            draft.findings.push(Finding {
+                stage: stage.clone(),
+                ticks: Some(TickRange { start: end, end }),
+                description: localizer.message("report.mistake.stomped", &[("down", &number)]),
+                suggestion: "report.suggestion.avoid_stomp",
+                minor: false,
+            });
+        }
+    }
+
+    let walking_specs = as_u64(output.get("walkingSpecs").and_then(|s| s.get(username)));
+    if walking_specs > 0 {
+        draft.findings.push(Finding {
+            stage: stage.clone(),
+            ticks: None,
+            description: localizer
+                .message("report.mistake.walking_spec", &[("specs", &walking_specs)]),
+            suggestion: "report.suggestion.save_specs",
+            minor: false,
+        });
+    }
+
+    if attended && clean {
+        draft
+            .strengths
+            .push(localizer.message("report.strength.clean_downs", &[]));
+    }
+}
+
+fn add_deaths(draft: &mut Draft, localizer: &Localizer, output: &Value, username: &str) {
+    let deaths = as_u64(output.get(username).and_then(|m| m.get("deaths")));
+    if deaths > 0 {
+        draft.findings.push(Finding {
+            stage: None,
+            ticks: None,
+            description: if deaths == 1 {
+                localizer.message("report.mistake.died_once", &[])
+            } else {
+                localizer.message("report.mistake.died", &[("deaths", &deaths)])
+            },
+            suggestion: "report.suggestion.review_deaths",
+            minor: false,
+        });
+    }
+}
+
+fn add_damage_taken(draft: &mut Draft, localizer: &Localizer, output: &Value, username: &str) {
+    let Some(damage) = output.get(username) else {
+        return;
+    };
+    let total = as_u64(damage.get("total"));
+    let Some(typical) = damage.get("typical_total").and_then(Value::as_f64) else {
+        return;
+    };
+
+    if total as f64 > typical * EXCESS_DAMAGE_FACTOR {
+        draft.findings.push(Finding {
+            stage: None,
+            ticks: None,
+            description: localizer.message(
+                "report.mistake.excess_damage",
+                &[("total", &total), ("typical", &typical.round())],
+            ),
+            suggestion: "report.suggestion.avoid_damage",
+            minor: true,
+        });
+    } else if (total as f64) < typical {
+        draft.strengths.push(localizer.message(
+            "report.strength.less_damage",
+            &[("total", &total), ("typical", &typical.round())],
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::blert;
+    use crate::links::ReplayLinks;
+    use crate::messages::Catalog;
+
+    #[test]
+    fn reports_include_detail_by_level() {
+        let roles = json!({"alice": ["Mage", []], "bob": ["Melee", []]});
+        let bloat = json!({
+            "downs": [{
+                "number": 1,
+                "walkTicks": 30,
+                "startTick": 40,
+                "endTick": 71,
+                "ticksLost": 5,
+                "players": {
+                    "alice": {
+                        "lateEntryTicks": 4,
+                        "earlyExitTicks": 1,
+                        "lateEntry": {"start": 42, "end": 45},
+                        "earlyExit": {"start": 70, "end": 70},
+                    },
+                    "bob": {"lateEntryTicks": 0, "earlyExitTicks": 0},
+                },
+            }],
+            "walkingSpecs": {},
+        });
+        let outputs = HashMap::from([("TobRoleAnalyzer", &roles), ("TobBloatAnalyzer", &bloat)]);
+
+        let localizer = Catalog::default();
+        let localizer = localizer.localizer(None);
+        let basic = build_reports(&outputs, Level::Basic, &localizer, None);
+        assert_eq!(basic.len(), 2);
+        let alice = &basic[0];
+        assert_eq!(alice.role.as_deref(), Some("Mage"));
+        assert_eq!(alice.mistakes.len(), 1);
+        assert_eq!(alice.mistakes[0].ticks, None);
+        assert!(alice.suggestions.is_empty());
+        assert_eq!(basic[1].strengths, ["Made full use of every Bloat down"]);
+
+        let links = ReplayLinks::default();
+        let links = links.challenge(blert::Challenge::Tob, Uuid::nil());
+        let max_eff = build_reports(&outputs, Level::MaxEff, &localizer, Some(&links));
+        let alice = &max_eff[0];
+        assert_eq!(alice.mistakes.len(), 2);
+        assert_eq!(
+            alice.mistakes[0].ticks,
+            Some(TickRange { start: 42, end: 45 })
+        );
+        assert_eq!(
+            alice.mistakes[1].ticks,
+            Some(TickRange { start: 70, end: 70 })
+        );
+        assert!(alice.mistakes[0]
+            .link
+            .as_deref()
+            .is_some_and(|link| link.ends_with("/bloat?tick=42")));
+        assert_eq!(alice.suggestions.len(), 2);
+        assert!(alice
+            .text
+            .contains("[Bloat, ticks 42–45] Started attacking down 1 4 ticks late"));
+    }
+}
@@ -0,0 +1,454 @@
+//! Persistence of analysis program results.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::Serialize;
+use sqlx::types::time::OffsetDateTime;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::analysis::{AnalyzerOutcome, AnalyzerStatus, Level};
+use crate::error::Result;
+use crate::findings::Finding;
+use crate::usage::ResourceUsage;
+
+/// Final status of a program run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    Completed,
+    /// Some analyzers failed, but the outputs of the others are available.
+    Partial,
+    Failed,
+    Cancelled,
+}
+
+impl RunStatus {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            RunStatus::Completed => "completed",
+            RunStatus::Partial => "partial",
+            RunStatus::Failed => "failed",
+            RunStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// The results of a finished program run, to be persisted.
+#[derive(Debug)]
+pub struct RunRecord {
+    pub challenge_uuid: Uuid,
+    /// Usernames of the challenge's party, whose opted-out members are pseudonymized in findings.
+    pub party: Vec<String>,
+    /// Number of the run within the analyzer process which produced it.
+    pub run_number: u32,
+    pub program: String,
+    /// Version of the program's definition and analyzer implementations which produced the run.
+    pub program_version: String,
+    pub level: Level,
+    pub status: RunStatus,
+    pub started_at: OffsetDateTime,
+    /// Players to which the run was restricted, if it did not analyze the whole party.
+    pub player_scope: Option<Vec<String>>,
+    /// Stages to which the run was restricted, if it did not analyze every recorded stage.
+    pub stage_scope: Option<Vec<String>>,
+    /// Locale in which the run's findings are written, if not the default.
+    pub locale: Option<String>,
+    pub outputs: Vec<(String, serde_json::Value)>,
+    /// Outputs of shadow analyzers, stored apart from the regular outputs.
+    pub shadow_outputs: Vec<(String, serde_json::Value)>,
+    /// Versions of the analyzers which produced the outputs.
+    pub analyzer_versions: HashMap<String, u32>,
+    /// Fields of each analyzer's output which hold player usernames.
+    pub player_fields: HashMap<String, &'static [&'static str]>,
+    pub analyzer_telemetry: Vec<AnalyzerTelemetry>,
+    /// Auxiliary values published by analyzers during the run.
+    pub blackboard: BTreeMap<String, serde_json::Value>,
+    /// Fields of each blackboard value which hold player usernames.
+    pub blackboard_player_fields: HashMap<String, &'static [&'static str]>,
+    /// Findings emitted by analyzers during the run.
+    pub findings: Vec<Finding>,
+    pub usage: ResourceUsage,
+}
+
+/// Runtime telemetry of a single analyzer within a program run.
+#[derive(Debug)]
+pub struct AnalyzerTelemetry {
+    pub analyzer: String,
+    pub status: &'static str,
+    /// Why the analyzer failed or was skipped.
+    pub error: Option<String>,
+    /// Time taken to run the analyzer. Zero for analyzers which did not run.
+    pub wall_time: Duration,
+    /// Size of the analyzer's serialized output, if it produced one.
+    pub output_bytes: Option<u64>,
+}
+
+/// A previously persisted program run.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredRun {
+    pub id: i64,
+    pub program: String,
+    /// Version of the program which produced the run, if it was recorded.
+    pub program_version: Option<String>,
+    pub level: String,
+    pub status: String,
+    /// Unix timestamp at which the run started.
+    pub started_at: i64,
+    /// Unix timestamp at which the run finished.
+    pub finished_at: i64,
+    pub outputs: BTreeMap<String, serde_json::Value>,
+    pub blackboard: Option<BTreeMap<String, serde_json::Value>>,
+    pub findings: Option<Vec<Finding>>,
+    pub usage: Option<ResourceUsage>,
+}
+
+/// Filters applied when loading stored results.
+#[derive(Debug, Default)]
+pub struct ResultsFilter<'a> {
+    pub program: Option<&'a str>,
+    pub analyzer: Option<&'a str>,
+}
+
+#[derive(sqlx::FromRow)]
+struct RunRow {
+    id: i64,
+    program: String,
+    program_version: Option<String>,
+    level: String,
+    status: String,
+    started_at: OffsetDateTime,
+    finished_at: OffsetDateTime,
+    blackboard: Option<sqlx::types::Json<BTreeMap<String, serde_json::Value>>>,
+    findings: Option<sqlx::types::Json<Vec<Finding>>>,
+    usage: Option<sqlx::types::Json<ResourceUsage>>,
+}
+
+#[derive(sqlx::FromRow)]
+struct OutputRow {
+    run_id: i64,
+    analyzer: String,
+    output: serde_json::Value,
+}
+
+/// A `Store` persists and retrieves the results of analysis program runs.
+#[derive(Debug, Clone)]
+pub struct Store {
+    pool: sqlx::PgPool,
+}
+
+impl Store {
+    pub fn new(pool: sqlx::PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub(crate) fn pool(&self) -> &sqlx::PgPool {
+        &self.pool
+    }
+
+    /// Applies any outstanding schema migrations for the results tables.
+    pub async fn migrate(&self) -> Result<()> {
+        sqlx::migrate!("./migrations")
+            .run(&self.pool)
+            .await
+            .map_err(sqlx::Error::from)?;
+        Ok(())
+    }
+
+    /// Persists the results of a finished program run, returning the ID of the stored run.
+    ///
+    /// The names of players who have opted out of publication are pseudonymized in the stored
+    /// outputs and blackboard values, at the fields declared by their analyzers, and in the stored
+    /// findings.
+    pub async fn save_run(&self, record: &RunRecord) -> Result<i64> {
+        let pseudonymizer = self.load_pseudonymizer().await?;
+        let mut blackboard = record.blackboard.clone();
+        for (key, value) in &mut blackboard {
+            if let Some(fields) = record.blackboard_player_fields.get(key) {
+                pseudonymizer.apply_to_fields(value, fields);
+            }
+        }
+        let blackboard = serde_json::to_value(&blackboard)?;
+        let mut findings = record.findings.clone();
+        pseudonymizer.apply_to_findings(&mut findings, &record.party);
+        let findings = serde_json::to_value(&findings)?;
+        let player_scope = record.player_scope.as_ref().map(|players| {
+            players
+                .iter()
+                .map(|player| pseudonymizer.name(player))
+                .collect::<Vec<_>>()
+        });
+
+        let mut tx = self.pool.begin().await?;
+
+        let (run_id,): (i64,) = sqlx::query_as(
+            "
+            INSERT INTO analysis_runs
+                (challenge_uuid, program, program_version, level, status, started_at, usage,
+                 blackboard, findings, player_scope, stage_scope, locale)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            RETURNING id
+            ",
+        )
+        .bind(record.challenge_uuid)
+        .bind(&record.program)
+        .bind(&record.program_version)
+        .bind(record.level.to_string())
+        .bind(record.status.as_str())
+        .bind(record.started_at)
+        .bind(sqlx::types::Json(record.usage))
+        .bind(blackboard)
+        .bind(findings)
+        .bind(player_scope)
+        .bind(&record.stage_scope)
+        .bind(&record.locale)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let outputs = record
+            .outputs
+            .iter()
+            .map(|output| ("analyzer_outputs", output))
+            .chain(
+                record
+                    .shadow_outputs
+                    .iter()
+                    .map(|output| ("shadow_outputs", output)),
+            );
+        for (table, (analyzer, output)) in outputs {
+            let mut output = output.clone();
+            if let Some(fields) = record.player_fields.get(analyzer) {
+                pseudonymizer.apply_to_fields(&mut output, fields);
+            }
+
+            sqlx::query(&format!(
+                "
+                INSERT INTO {table} (run_id, analyzer, output, analyzer_version)
+                VALUES ($1, $2, $3, $4)
+                "
+            ))
+            .bind(run_id)
+            .bind(analyzer)
+            .bind(output)
+            .bind(
+                record
+                    .analyzer_versions
+                    .get(analyzer)
+                    .map(|&version| i64::from(version)),
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for telemetry in &record.analyzer_telemetry {
+            sqlx::query(
+                "
+                INSERT INTO analyzer_runs
+                    (run_id, challenge_uuid, run_number, analyzer, status, wall_time_us,
+                     output_bytes, error)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                ",
+            )
+            .bind(run_id)
+            .bind(record.challenge_uuid)
+            .bind(i64::from(record.run_number))
+            .bind(&telemetry.analyzer)
+            .bind(telemetry.status)
+            .bind(i64::try_from(telemetry.wall_time.as_micros()).unwrap_or(i64::MAX))
+            .bind(
+                telemetry
+                    .output_bytes
+                    .map(|bytes| i64::try_from(bytes).unwrap_or(i64::MAX)),
+            )
+            .bind(&telemetry.error)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(run_id)
+    }
+
+    /// Loads every stored run for a challenge, most recent first.
+    pub async fn load_results(
+        &self,
+        challenge_uuid: Uuid,
+        filter: &ResultsFilter<'_>,
+    ) -> Result<Vec<StoredRun>> {
+        let runs: Vec<RunRow> = sqlx::query_as(
+            "
+            SELECT
+                id, program, program_version, level, status, started_at, finished_at, usage,
+                blackboard, findings
+            FROM analysis_runs
+            WHERE challenge_uuid = $1 AND ($2::TEXT IS NULL OR program = $2)
+            ORDER BY started_at DESC
+            ",
+        )
+        .bind(challenge_uuid)
+        .bind(filter.program)
+        .fetch_all(&self.pool)
+        .await?;
+
+        self.with_outputs(runs, filter.analyzer).await
+    }
+
+    /// Finds the most recent completed run of a program version on a challenge at the given
+    /// level and locale, whose results can be served instead of running the program again. Only
+    /// runs which analyzed the whole challenge are considered.
+    pub async fn find_cached_run(
+        &self,
+        challenge_uuid: Uuid,
+        program: &str,
+        program_version: &str,
+        level: Level,
+        locale: Option<&str>,
+    ) -> Result<Option<StoredRun>> {
+        let runs: Vec<RunRow> = sqlx::query_as(
+            "
+            SELECT
+                id, program, program_version, level, status, started_at, finished_at, usage,
+                blackboard, findings
+            FROM analysis_runs
+            WHERE challenge_uuid = $1
+                AND program = $2
+                AND program_version = $3
+                AND level = $4
+                AND status = $5
+                AND player_scope IS NULL
+                AND stage_scope IS NULL
+                AND locale IS NOT DISTINCT FROM $6
+            ORDER BY started_at DESC
+            LIMIT 1
+            ",
+        )
+        .bind(challenge_uuid)
+        .bind(program)
+        .bind(program_version)
+        .bind(level.to_string())
+        .bind(RunStatus::Completed.as_str())
+        .bind(locale)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(self.with_outputs(runs, None).await?.pop())
+    }
+
+    /// Loads the recorded outcome of every analyzer in a stored run, keyed by analyzer name.
+    pub async fn analyzer_outcomes(
+        &self,
+        run_id: i64,
+    ) -> Result<BTreeMap<String, AnalyzerOutcome>> {
+        let rows: Vec<(String, String, Option<String>)> =
+            sqlx::query_as("SELECT analyzer, status, error FROM analyzer_runs WHERE run_id = $1")
+                .bind(run_id)
+                .fetch_all(&self.pool)
+                .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|(analyzer, status, error)| {
+                let status = AnalyzerStatus::from_str(&status)?;
+                Some((analyzer, AnalyzerOutcome { status, error }))
+            })
+            .collect())
+    }
+
+    /// Finds challenges whose latest run of `program` at any level includes outputs from an
+    /// analyzer version other than the one in `analyzer_versions`, returning up to `limit` of
+    /// them along with the level of the outdated run.
+    pub async fn find_outdated_runs(
+        &self,
+        program: &str,
+        analyzer_versions: &HashMap<String, u32>,
+        limit: usize,
+    ) -> Result<Vec<(Uuid, Level)>> {
+        let (analyzers, versions): (Vec<&str>, Vec<i64>) = analyzer_versions
+            .iter()
+            .map(|(analyzer, &version)| (analyzer.as_str(), i64::from(version)))
+            .unzip();
+
+        let rows: Vec<(Uuid, String)> = sqlx::query_as(
+            "
+            SELECT DISTINCT run.challenge_uuid, run.level
+            FROM analysis_runs run
+            JOIN analyzer_outputs output ON output.run_id = run.id
+            JOIN UNNEST($2::TEXT[], $3::BIGINT[]) AS current (analyzer, version)
+                ON current.analyzer = output.analyzer
+            WHERE run.program = $1
+                AND output.analyzer_version IS DISTINCT FROM current.version
+                AND run.id = (
+                    SELECT MAX(latest.id)
+                    FROM analysis_runs latest
+                    WHERE latest.challenge_uuid = run.challenge_uuid
+                        AND latest.program = run.program
+                        AND latest.level = run.level
+                )
+            LIMIT $4
+            ",
+        )
+        .bind(program)
+        .bind(&analyzers)
+        .bind(&versions)
+        .bind(i64::try_from(limit).unwrap_or(i64::MAX))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|(uuid, level)| {
+                let level = serde_json::from_value(serde_json::Value::String(level)).ok()?;
+                Some((uuid, level))
+            })
+            .collect())
+    }
+
+    /// Loads the analyzer outputs of stored runs, optionally only those of a single analyzer.
+    async fn with_outputs(
+        &self,
+        runs: Vec<RunRow>,
+        analyzer: Option<&str>,
+    ) -> Result<Vec<StoredRun>> {
+        let run_ids = runs.iter().map(|r| r.id).collect::<Vec<_>>();
+        let outputs: Vec<OutputRow> = sqlx::query_as(
+            "
+            SELECT run_id, analyzer, output
+            FROM analyzer_outputs
+            WHERE run_id = ANY($1) AND ($2::TEXT IS NULL OR analyzer = $2)
+            ",
+        )
+        .bind(&run_ids)
+        .bind(analyzer)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut outputs_by_run: BTreeMap<i64, BTreeMap<String, serde_json::Value>> =
+            BTreeMap::new();
+        for row in outputs {
+            outputs_by_run
+                .entry(row.run_id)
+                .or_default()
+                .insert(row.analyzer, row.output);
+        }
+
+        Ok(runs
+            .into_iter()
+            .map(|run| StoredRun {
+                id: run.id,
+                outputs: outputs_by_run.remove(&run.id).unwrap_or_default(),
+                program: run.program,
+                program_version: run.program_version,
+                level: run.level,
+                status: run.status,
+                started_at: run.started_at.unix_timestamp(),
+                finished_at: run.finished_at.unix_timestamp(),
+                blackboard: run.blackboard.map(|blackboard| blackboard.0),
+                findings: run.findings.map(|findings| findings.0),
+                usage: run.usage.map(|usage| usage.0),
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,124 @@
+//! Retention of persisted program runs.
+//!
+//! Results are kept for a configurable amount of time depending on the level of analysis which
+//! produced them, and are periodically pruned once they expire. Levels without a configured
+//! retention period are kept forever.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+
+use crate::analysis::Level;
+use crate::error::{Error, Result};
+use crate::results;
+
+/// How often expired runs are pruned.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Maximum age of stored runs for each level of analysis.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetentionPolicy {
+    max_age: HashMap<Level, Duration>,
+}
+
+impl RetentionPolicy {
+    /// Parses a policy from a comma-separated list of `level=days` entries, e.g.
+    /// `basic=90,learner=180`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut max_age = HashMap::new();
+
+        for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let invalid = || Error::Config(format!(r#"Invalid retention entry "{entry}""#));
+
+            let (level, days) = entry.split_once('=').ok_or_else(invalid)?;
+            let level = match level.trim() {
+                "basic" => Level::Basic,
+                "learner" => Level::Learner,
+                "casual" => Level::Casual,
+                "maxeff" => Level::MaxEff,
+                _ => return Err(invalid()),
+            };
+            let days: u64 = days.trim().parse().map_err(|_| invalid())?;
+
+            max_age.insert(level, Duration::from_secs(days * SECONDS_PER_DAY));
+        }
+
+        Ok(Self { max_age })
+    }
+
+    /// Returns the maximum age of runs at a level, or `None` if they are kept forever.
+    pub fn max_age(&self, level: Level) -> Option<Duration> {
+        self.max_age.get(&level).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.max_age.is_empty()
+    }
+}
+
+impl results::Store {
+    /// Deletes every stored run which has outlived the retention period of its level, returning
+    /// the number of runs deleted.
+    pub async fn prune(&self, policy: &RetentionPolicy) -> Result<u64> {
+        let mut deleted = 0;
+
+        for (level, max_age) in &policy.max_age {
+            let result = sqlx::query(
+                "
+                DELETE FROM analysis_runs
+                WHERE level = $1 AND finished_at < NOW() - make_interval(secs => $2)
+                ",
+            )
+            .bind(level.to_string())
+            .bind(max_age.as_secs_f64())
+            .execute(self.pool())
+            .await?;
+            deleted += result.rows_affected();
+        }
+
+        Ok(deleted)
+    }
+}
+
+/// Spawns a task which periodically prunes expired runs from `store`.
+pub fn spawn_pruner(store: Arc<results::Store>, policy: RetentionPolicy) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match store.prune(&policy).await {
+                Ok(0) => {}
+                Ok(deleted) => tracing::info!("Pruned {deleted} expired program run(s)"),
+                Err(e) => tracing::error!("Failed to prune expired program runs: {e:?}"),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_policy() {
+        let policy = RetentionPolicy::parse("basic=90, learner=180").unwrap();
+        assert_eq!(
+            policy.max_age(Level::Basic),
+            Some(Duration::from_secs(90 * SECONDS_PER_DAY))
+        );
+        assert_eq!(
+            policy.max_age(Level::Learner),
+            Some(Duration::from_secs(180 * SECONDS_PER_DAY))
+        );
+        assert_eq!(policy.max_age(Level::MaxEff), None);
+
+        assert!(RetentionPolicy::parse("").unwrap().is_empty());
+        assert!(RetentionPolicy::parse("basic").is_err());
+        assert!(RetentionPolicy::parse("expert=30").is_err());
+        assert!(RetentionPolicy::parse("basic=-1").is_err());
+    }
+}
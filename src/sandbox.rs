@@ -0,0 +1,312 @@
+//! Resource limits enforced on individual analyzer runs.
+//!
+//! An analyzer runs inside a [`Sandbox`] which meters the challenge events it reads and, when the
+//! `metered` feature is enabled, the memory it holds allocated. Limits are checked at checkpoints
+//! within the challenge data and context accessors: once one is exceeded, the next checkpoint
+//! unwinds out of the analyzer with a [`Violation`], which its worker reports as an error.
+//!
+//! Threads cannot be forcibly stopped, so an analyzer which exceeds its time limit is abandoned
+//! and keeps running until its next checkpoint. Abandoned analyzers still running are counted,
+//! and no further analyzers are run once there are [`MAX_ABANDONED`] of them.
+
+use std::{
+    any::Any,
+    cell::Cell,
+    fmt, ptr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Limits on the resources consumed by a single analyzer run. Unset limits are not enforced.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Limits {
+    /// Maximum number of challenge events the analyzer may iterate over.
+    pub max_events: Option<u64>,
+    /// Maximum number of bytes the analyzer may hold allocated at once. Only enforced in builds
+    /// with the `metered` feature.
+    pub max_allocated_bytes: Option<u64>,
+    /// Time after which the analyzer is abandoned, regardless of whether it has observed its
+    /// program's cancellation.
+    pub timeout_secs: Option<u64>,
+}
+
+impl Limits {
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout_secs.map(Duration::from_secs)
+    }
+}
+
+/// A limit exceeded by an analyzer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Violation {
+    Events(u64),
+    Allocation(u64),
+    Killed,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Events(limit) => write!(f, "read more than {limit} events"),
+            Violation::Allocation(limit) => write!(f, "held more than {limit} bytes allocated"),
+            Violation::Killed => write!(f, "exceeded its time limit"),
+        }
+    }
+}
+
+/// Maximum number of abandoned analyzers which may still be running before further analyzers are
+/// refused.
+pub const MAX_ABANDONED: u64 = 4;
+
+/// Number of abandoned analyzers still running.
+static ABANDONED: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the number of analyzers abandoned after exceeding their time limit which are still
+/// running.
+pub fn abandoned() -> u64 {
+    ABANDONED.load(Ordering::Relaxed)
+}
+
+const RUNNING: u8 = 0;
+const FINISHED: u8 = 1;
+const ABANDONED_STATE: u8 = 2;
+
+/// Resource accounting for a single analyzer run.
+#[derive(Debug, Default)]
+pub struct Sandbox {
+    limits: Limits,
+    events: AtomicU64,
+    /// Bytes currently allocated by the analyzer.
+    live_bytes: AtomicU64,
+    killed: AtomicBool,
+    state: AtomicU8,
+}
+
+thread_local! {
+    static CURRENT: Cell<*const Sandbox> = const { Cell::new(ptr::null()) };
+}
+
+/// Restores the previously active sandbox on the thread when dropped.
+pub struct Guard {
+    _sandbox: Arc<Sandbox>,
+    previous: *const Sandbox,
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        CURRENT.with(|current| current.set(self.previous));
+    }
+}
+
+impl Sandbox {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            ..Default::default()
+        }
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Makes the sandbox active on the calling thread until the returned guard is dropped.
+    pub fn enter(self: &Arc<Self>) -> Guard {
+        let previous = CURRENT.with(|current| current.replace(Arc::as_ptr(self)));
+        Guard {
+            _sandbox: self.clone(),
+            previous,
+        }
+    }
+
+    /// Returns the sandbox active on the calling thread, if any. Analyzers which spawn threads
+    /// should enter it on each of them.
+    pub fn current() -> Option<Arc<Sandbox>> {
+        let sandbox = CURRENT.with(Cell::get);
+        if sandbox.is_null() {
+            return None;
+        }
+
+        // SAFETY: The pointer was obtained from an `Arc` kept alive by the guard which installed
+        // it, and is cleared before that guard releases its reference.
+        unsafe {
+            Arc::increment_strong_count(sandbox);
+            Some(Arc::from_raw(sandbox))
+        }
+    }
+
+    /// Marks the sandbox as killed, causing the analyzer to unwind at its next checkpoint.
+    pub fn kill(&self) {
+        self.killed.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns whether the sandbox has been killed. Analyzers which cannot reach a checkpoint, such
+    /// as plugins, should poll this instead.
+    pub fn is_killed(&self) -> bool {
+        self.killed.load(Ordering::Relaxed)
+    }
+
+    /// Kills the sandbox and stops waiting for its analyzer. The analyzer is counted as abandoned
+    /// until it finishes, unless it already has.
+    pub fn abandon(&self) {
+        self.kill();
+        if self
+            .state
+            .compare_exchange(
+                RUNNING,
+                ABANDONED_STATE,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_ok()
+        {
+            ABANDONED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Records that the sandbox's analyzer has finished running.
+    pub fn finish(&self) {
+        if self.state.swap(FINISHED, Ordering::AcqRel) == ABANDONED_STATE {
+            ABANDONED.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    #[cfg(feature = "metered")]
+    fn release(&self, bytes: u64) {
+        // Memory allocated outside of the sandbox may be freed within it.
+        let _ = self
+            .live_bytes
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |live| {
+                Some(live.saturating_sub(bytes))
+            });
+    }
+
+    fn violation(&self) -> Option<Violation> {
+        if self.is_killed() {
+            return Some(Violation::Killed);
+        }
+        if let Some(limit) = self.limits.max_events {
+            if self.events.load(Ordering::Relaxed) > limit {
+                return Some(Violation::Events(limit));
+            }
+        }
+        if let Some(limit) = self.limits.max_allocated_bytes {
+            if self.live_bytes.load(Ordering::Relaxed) > limit {
+                return Some(Violation::Allocation(limit));
+            }
+        }
+        None
+    }
+}
+
+/// Charges `count` events read to the active sandbox, unwinding out of the analyzer if any of
+/// its limits has been exceeded.
+pub fn charge_events(count: u64) {
+    let sandbox = CURRENT.with(Cell::get);
+    if sandbox.is_null() {
+        return;
+    }
+
+    // SAFETY: See `Sandbox::current`.
+    let sandbox = unsafe { &*sandbox };
+    sandbox.events.fetch_add(count, Ordering::Relaxed);
+    if let Some(violation) = sandbox.violation() {
+        std::panic::panic_any(violation);
+    }
+}
+
+/// Unwinds out of the analyzer if any limit of the active sandbox has been exceeded, or if it
+/// has been killed.
+pub fn checkpoint() {
+    charge_events(0);
+}
+
+/// Returns the limit violation which caused a panic, if that is why it occurred.
+pub fn violation(payload: &(dyn Any + Send)) -> Option<Violation> {
+    payload.downcast_ref::<Violation>().copied()
+}
+
+/// Global allocator which attributes allocations to the sandbox active on the allocating thread,
+/// and deallocations to the sandbox active on the deallocating thread. It never fails an
+/// allocation itself; overruns are reported at the analyzer's next checkpoint.
+#[cfg(feature = "metered")]
+pub struct MeteredAllocator;
+
+#[cfg(feature = "metered")]
+unsafe impl std::alloc::GlobalAlloc for MeteredAllocator {
+    unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+        // `try_with` as the thread-local may already be destroyed during thread teardown.
+        let _ = CURRENT.try_with(|current| {
+            let sandbox = current.get();
+            if !sandbox.is_null() {
+                (*sandbox)
+                    .live_bytes
+                    .fetch_add(layout.size() as u64, Ordering::Relaxed);
+            }
+        });
+        std::alloc::System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+        let _ = CURRENT.try_with(|current| {
+            let sandbox = current.get();
+            if !sandbox.is_null() {
+                (*sandbox).release(layout.size() as u64);
+            }
+        });
+        std::alloc::System.dealloc(ptr, layout);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_limit_unwinds_with_violation() {
+        let sandbox = Arc::new(Sandbox::new(Limits {
+            max_events: Some(10),
+            ..Default::default()
+        }));
+
+        let result = std::panic::catch_unwind(|| {
+            let _guard = sandbox.enter();
+            charge_events(10);
+            assert!(Sandbox::current().is_some());
+            charge_events(1);
+        });
+
+        let payload = result.unwrap_err();
+        assert_eq!(violation(payload.as_ref()), Some(Violation::Events(10)));
+        assert!(Sandbox::current().is_none());
+    }
+
+    #[test]
+    fn abandoned_analyzers_are_counted_until_they_finish() {
+        let finished = Sandbox::default();
+        finished.finish();
+        finished.abandon();
+        assert_eq!(abandoned(), 0);
+
+        let abandoned_sandbox = Arc::new(Sandbox::default());
+        abandoned_sandbox.abandon();
+        assert_eq!(abandoned(), 1);
+
+        let result = std::panic::catch_unwind(|| {
+            let _guard = abandoned_sandbox.enter();
+            checkpoint();
+        });
+        assert_eq!(
+            violation(result.unwrap_err().as_ref()),
+            Some(Violation::Killed)
+        );
+
+        abandoned_sandbox.finish();
+        assert_eq!(abandoned(), 0);
+    }
+}
@@ -0,0 +1,48 @@
+//! JSON Schemas describing the outputs of the analysis engine, published so that external
+//! consumers can validate results and generate code against them.
+//!
+//! Schemas are versioned alongside the API: a breaking change to any output requires a new API
+//! version.
+
+use std::collections::BTreeMap;
+
+use schemars::{schema::RootSchema, schema_for};
+
+use crate::analysis::ProgramResult;
+use crate::analyzers;
+
+/// Name of the schema describing a program run's final results.
+pub const PROGRAM_RESULT: &str = "ProgramResult";
+
+/// Returns every published schema, keyed by name. Analyzer output schemas are named after their
+/// analyzer implementations.
+pub fn all(version: &str) -> BTreeMap<&'static str, RootSchema> {
+    let mut schemas = analyzers::output_schemas()
+        .into_iter()
+        .collect::<BTreeMap<_, _>>();
+    schemas.insert(PROGRAM_RESULT, schema_for!(ProgramResult));
+
+    for (name, schema) in &mut schemas {
+        schema.schema.metadata().id = Some(format!("/schemas/{version}/{name}"));
+    }
+    schemas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schemas_are_identified_by_version() {
+        let schemas = all("v1");
+        assert!(schemas.contains_key(PROGRAM_RESULT));
+        assert!(schemas.contains_key("GearAnalyzer"));
+
+        let id = schemas[PROGRAM_RESULT]
+            .schema
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.id.as_deref());
+        assert_eq!(id, Some("/schemas/v1/ProgramResult"));
+    }
+}
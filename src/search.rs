@@ -0,0 +1,233 @@
+//! Searching of challenges by their properties and by metrics derived from their analysis.
+
+use serde::{Deserialize, Serialize};
+use sqlx::types::time::OffsetDateTime;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::challenge::Status;
+use crate::error::{Error, Result};
+use crate::{blert, results};
+
+/// Filters applied to a challenge search. Every filter is optional; unset filters match all
+/// challenges.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchFilter {
+    /// Challenge type, e.g. `tob`.
+    pub r#type: Option<String>,
+    /// Challenge mode, e.g. `tob_hard`.
+    pub mode: Option<String>,
+    /// Final status of the challenge: `completed`, `wiped`, or `reset`.
+    pub status: Option<String>,
+    /// Number of players in the party.
+    pub scale: Option<i16>,
+    /// Maximum duration of the challenge, in ticks.
+    pub max_ticks: Option<i32>,
+    /// Maximum number of deaths across the party.
+    pub max_deaths: Option<i32>,
+    /// A player who must have been in the party.
+    pub player: Option<String>,
+    /// Role which `player` must have been assigned by the `TobRoleAnalyzer`, e.g. `Mage`.
+    pub role: Option<String>,
+    /// Maximum number of challenges to return.
+    pub limit: Option<u32>,
+    /// Number of matching challenges to skip.
+    pub offset: Option<u32>,
+}
+
+impl SearchFilter {
+    pub const DEFAULT_LIMIT: u32 = 25;
+    pub const MAX_LIMIT: u32 = 100;
+
+    fn limit(&self) -> u32 {
+        self.limit
+            .unwrap_or(Self::DEFAULT_LIMIT)
+            .clamp(1, Self::MAX_LIMIT)
+    }
+
+    fn r#type(&self) -> Result<Option<i16>> {
+        self.r#type.as_deref().map(parse_type).transpose()
+    }
+
+    fn mode(&self) -> Result<Option<i16>> {
+        self.mode.as_deref().map(parse_mode).transpose()
+    }
+
+    fn status(&self) -> Result<Option<i16>> {
+        self.status
+            .as_deref()
+            .map(|s| match s {
+                "completed" => Ok(Status::Completed.into()),
+                "wiped" => Ok(Status::Wiped.into()),
+                "reset" => Ok(Status::Reset.into()),
+                _ => Err(Error::InvalidField("status".into())),
+            })
+            .transpose()
+    }
+}
+
+/// Parses a challenge type name, e.g. `tob`, into its database representation.
+pub(crate) fn parse_type(name: &str) -> Result<i16> {
+    blert::Challenge::from_str_name(&name.to_uppercase())
+        .map(|t| t as i16)
+        .ok_or_else(|| Error::InvalidField("type".into()))
+}
+
+/// Parses a challenge mode name, e.g. `tob_hard`, into its database representation.
+pub(crate) fn parse_mode(name: &str) -> Result<i16> {
+    blert::ChallengeMode::from_str_name(&name.to_uppercase())
+        .map(|m| m as i16)
+        .ok_or_else(|| Error::InvalidField("mode".into()))
+}
+
+/// A challenge matching a search.
+#[derive(Debug, Serialize, ToSchema, sqlx::FromRow)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResult {
+    pub uuid: Uuid,
+    pub r#type: i16,
+    pub mode: Option<i16>,
+    pub status: Option<i16>,
+    pub scale: i16,
+    pub party: Vec<String>,
+    #[serde(with = "time_unix")]
+    #[schema(value_type = i64)]
+    pub start_time: OffsetDateTime,
+    pub challenge_ticks: Option<i32>,
+    pub total_deaths: Option<i32>,
+}
+
+/// A page of search results.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchPage {
+    pub challenges: Vec<SearchResult>,
+    /// Offset of the next page, if there are more results.
+    pub next_offset: Option<u32>,
+}
+
+mod time_unix {
+    use serde::Serializer;
+    use sqlx::types::time::OffsetDateTime;
+
+    pub fn serialize<S: Serializer>(
+        time: &OffsetDateTime,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_i64(time.unix_timestamp())
+    }
+}
+
+impl results::Store {
+    /// Searches for challenges matching a filter, most recent first. Opted-out players in the
+    /// returned parties are pseudonymized, and can only be searched for by their pseudonym.
+    ///
+    /// Role filters are evaluated against the stored outputs of `role_analyzers`, the `(program,
+    /// analyzer)` pairs which assign roles, so only analyzed challenges can match them. Outputs of
+    /// opted-out players are stored under their pseudonym, which is looked up instead.
+    pub async fn search_challenges(
+        &self,
+        filter: &SearchFilter,
+        role_analyzers: &[(String, String)],
+    ) -> Result<SearchPage> {
+        if filter.role.is_some() && filter.player.is_none() {
+            return Err(Error::InvalidField("role requires player".into()));
+        }
+
+        let limit = filter.limit();
+        let offset = filter.offset.unwrap_or(0);
+        let (role_programs, role_analyzers): (Vec<_>, Vec<_>) =
+            role_analyzers.iter().cloned().unzip();
+
+        // Opted-out players are only matched by their pseudonym, as a search by their username
+        // would link it to the pseudonymized parties returned.
+        let pseudonymizer = self.load_pseudonymizer().await?;
+        let player = match filter.player.as_deref() {
+            Some(name) => match pseudonymizer.username(name) {
+                Some(username) => Some(username),
+                None => {
+                    return Ok(SearchPage {
+                        challenges: Vec::new(),
+                        next_offset: None,
+                    })
+                }
+            },
+            None => None,
+        };
+        let output_key = player
+            .as_deref()
+            .filter(|player| pseudonymizer.is_opted_out(player))
+            .map(|player| pseudonymizer.name(player));
+
+        // One extra row is fetched to determine whether there is another page.
+        let mut challenges: Vec<SearchResult> = sqlx::query_as(
+            "
+            SELECT c.uuid, c.type, c.mode, c.status, c.scale, c.start_time,
+                   c.challenge_ticks, c.total_deaths, ARRAY(
+                       SELECT cp.username FROM challenge_players cp
+                       WHERE cp.challenge_id = c.id
+                       ORDER BY cp.orb
+                   ) AS party
+            FROM challenges c
+            WHERE ($1::SMALLINT IS NULL OR c.type = $1)
+              AND ($2::SMALLINT IS NULL OR c.mode = $2)
+              AND ($3::SMALLINT IS NULL OR c.status = $3)
+              AND ($4::SMALLINT IS NULL OR c.scale = $4)
+              AND ($5::INTEGER IS NULL OR c.challenge_ticks <= $5)
+              AND ($6::INTEGER IS NULL OR c.total_deaths <= $6)
+              AND ($7::TEXT IS NULL OR EXISTS (
+                  SELECT 1 FROM challenge_players cp
+                  WHERE cp.challenge_id = c.id AND lower(cp.username) = lower($7)
+              ))
+              AND ($8::TEXT IS NULL OR EXISTS (
+                  SELECT 1
+                  FROM analysis_runs r
+                  JOIN analyzer_outputs o ON o.run_id = r.id
+                  JOIN challenge_players cp ON cp.challenge_id = c.id
+                  WHERE r.challenge_uuid = c.uuid
+                    AND (r.program, o.analyzer) IN (
+                        SELECT * FROM UNNEST($11::TEXT[], $12::TEXT[])
+                    )
+                    AND lower(cp.username) = lower($7)
+                    AND o.output -> COALESCE($13, cp.username) ->> 0 = $8
+              ))
+            ORDER BY c.start_time DESC
+            LIMIT $9 OFFSET $10
+            ",
+        )
+        .bind(filter.r#type()?)
+        .bind(filter.mode()?)
+        .bind(filter.status()?)
+        .bind(filter.scale)
+        .bind(filter.max_ticks)
+        .bind(filter.max_deaths)
+        .bind(player.as_deref())
+        .bind(filter.role.as_deref())
+        .bind(i64::from(limit) + 1)
+        .bind(i64::from(offset))
+        .bind(&role_programs)
+        .bind(&role_analyzers)
+        .bind(output_key)
+        .fetch_all(self.pool())
+        .await?;
+
+        for challenge in &mut challenges {
+            for username in &mut challenge.party {
+                *username = pseudonymizer.name(username);
+            }
+        }
+
+        let next_offset = if challenges.len() > limit as usize {
+            challenges.truncate(limit as usize);
+            Some(offset + limit)
+        } else {
+            None
+        };
+
+        Ok(SearchPage {
+            challenges,
+            next_offset,
+        })
+    }
+}
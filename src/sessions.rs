@@ -0,0 +1,432 @@
+//! Grouping of consecutive challenges by the same party into practice sessions.
+//!
+//! Parties often reset a challenge many times in a row while practicing an early stage, or while
+//! going for a fast time. Rather than treating every challenge independently, challenges by the
+//! same party of the same type and mode which start shortly after one another are grouped into a
+//! single session.
+
+use serde::Serialize;
+use sqlx::types::time::{Duration, OffsetDateTime};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::challenge::Status;
+use crate::error::{Error, Result};
+use crate::{blert, results, time as ticks};
+
+/// Maximum idle time between the end of a challenge and the start of the next for the two to be
+/// considered part of the same session.
+pub const SESSION_GAP: Duration = Duration::minutes(30);
+
+/// How far around a challenge to look for other challenges in its session.
+const SEARCH_WINDOW: Duration = Duration::hours(12);
+
+/// Summary of a single challenge used for session grouping.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ChallengeSummary {
+    pub uuid: Uuid,
+    pub status: Option<i16>,
+    pub stage: Option<i16>,
+    pub start_time: OffsetDateTime,
+    pub challenge_ticks: Option<i32>,
+}
+
+impl ChallengeSummary {
+    fn status(&self) -> Option<Status> {
+        self.status.and_then(|status| Status::try_from(status).ok())
+    }
+
+    /// Returns when the challenge ended. Challenges without a recorded duration are treated as
+    /// ending when they started.
+    fn end_time(&self) -> OffsetDateTime {
+        let ticks = self.challenge_ticks.unwrap_or(0).max(0) as u32;
+        self.start_time + ticks::ticks_to_duration(ticks)
+    }
+
+    /// Returns whether the challenge was reset early, before reaching the later stages of the
+    /// challenge.
+    fn is_early_reset(&self, challenge_type: blert::Challenge) -> bool {
+        if self.status() != Some(Status::Reset) {
+            return false;
+        }
+
+        let last_early_stage = match challenge_type {
+            blert::Challenge::Tob => blert::Stage::TobBloat,
+            blert::Challenge::Colosseum => blert::Stage::ColosseumWave3,
+            _ => return false,
+        };
+        self.stage
+            .is_some_and(|stage| i32::from(stage) <= last_early_stage as i32)
+    }
+}
+
+/// A series of consecutive challenges by the same party.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Session {
+    pub id: i64,
+    pub party: Vec<String>,
+    pub challenges: Vec<Uuid>,
+    pub resets: u32,
+    pub early_resets: u32,
+    pub completions: u32,
+    /// Duration of the fastest completion in the session, in ticks.
+    pub best_ticks: Option<u32>,
+    /// Unix timestamp at which the session started.
+    pub started_at: i64,
+    /// Unix timestamp at which the session ended.
+    pub ended_at: i64,
+}
+
+impl Session {
+    /// Returns a short human-readable summary of the session.
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "{} challenges, {} resets ({} early), {} completions",
+            self.challenges.len(),
+            self.resets,
+            self.early_resets,
+            self.completions,
+        );
+        if let Some(best) = self.best_ticks {
+            summary.push_str(&format!(", best time {}", ticks::format_ticks(best)));
+        }
+        summary
+    }
+}
+
+/// Returns the group of consecutive challenges from `challenges` which contains the challenge
+/// `uuid`. `challenges` must be sorted by start time.
+pub fn session_containing(challenges: &[ChallengeSummary], uuid: Uuid) -> &[ChallengeSummary] {
+    let Some(index) = challenges.iter().position(|c| c.uuid == uuid) else {
+        return &[];
+    };
+
+    let mut start = index;
+    while start > 0
+        && challenges[start].start_time - challenges[start - 1].end_time() <= SESSION_GAP
+    {
+        start -= 1;
+    }
+
+    let mut end = index;
+    while end + 1 < challenges.len()
+        && challenges[end + 1].start_time - challenges[end].end_time() <= SESSION_GAP
+    {
+        end += 1;
+    }
+
+    &challenges[start..=end]
+}
+
+#[derive(sqlx::FromRow)]
+struct ChallengeRow {
+    r#type: i16,
+    mode: Option<i16>,
+    start_time: OffsetDateTime,
+    party: Vec<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct SessionRow {
+    id: i64,
+    party: Vec<String>,
+    resets: i32,
+    early_resets: i32,
+    completions: i32,
+    best_ticks: Option<i32>,
+    started_at: OffsetDateTime,
+    ended_at: OffsetDateTime,
+}
+
+impl results::Store {
+    /// Assigns a challenge to its practice session, creating or extending the session as
+    /// necessary, and returns the updated session.
+    ///
+    /// Sessions are matched by the party's usernames, but the party is stored and returned with
+    /// opted-out players pseudonymized.
+    pub async fn update_session(&self, uuid: Uuid) -> Result<Session> {
+        let challenge: ChallengeRow = sqlx::query_as(
+            "
+            SELECT c.type, c.mode, c.start_time, ARRAY(
+                SELECT cp.username FROM challenge_players cp
+                WHERE cp.challenge_id = c.id
+                ORDER BY cp.username
+            ) AS party
+            FROM challenges c
+            WHERE c.uuid = $1
+            ",
+        )
+        .bind(uuid)
+        .fetch_one(self.pool())
+        .await?;
+
+        let challenges: Vec<ChallengeSummary> = sqlx::query_as(
+            "
+            SELECT c.uuid, c.status, c.stage, c.start_time, c.challenge_ticks
+            FROM challenges c
+            WHERE c.type = $1
+              AND c.mode IS NOT DISTINCT FROM $2
+              AND c.start_time BETWEEN $3 AND $4
+              AND ARRAY(
+                SELECT cp.username FROM challenge_players cp
+                WHERE cp.challenge_id = c.id
+                ORDER BY cp.username
+              ) = $5
+            ORDER BY c.start_time
+            ",
+        )
+        .bind(challenge.r#type)
+        .bind(challenge.mode)
+        .bind(challenge.start_time - SEARCH_WINDOW)
+        .bind(challenge.start_time + SEARCH_WINDOW)
+        .bind(&challenge.party)
+        .fetch_all(self.pool())
+        .await?;
+
+        let pseudonymizer = self.load_pseudonymizer().await?;
+        let party = challenge
+            .party
+            .iter()
+            .map(|username| pseudonymizer.name(username))
+            .collect::<Vec<_>>();
+
+        let challenge_type = blert::Challenge::try_from(i32::from(challenge.r#type))
+            .map_err(|_| Error::InvalidField("type".to_string()))?;
+        let member_uuids = session_containing(&challenges, uuid)
+            .iter()
+            .map(|c| c.uuid)
+            .collect::<Vec<_>>();
+
+        let mut tx = self.pool().begin().await?;
+
+        // Adding a challenge may bridge previously separate sessions, so every existing session
+        // containing a member is merged into the oldest.
+        let existing: Vec<(i64,)> = sqlx::query_as(
+            "
+            SELECT DISTINCT session_id
+            FROM practice_session_challenges
+            WHERE challenge_uuid = ANY($1)
+            ORDER BY session_id
+            ",
+        )
+        .bind(&member_uuids)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let session_id = if let Some(&(id,)) = existing.first() {
+            // Merged sessions may have members outside of the search window, which must be moved
+            // before the sessions are deleted along with their remaining challenges.
+            let merged = existing[1..].iter().map(|(id,)| *id).collect::<Vec<_>>();
+            sqlx::query(
+                "
+                UPDATE practice_session_challenges
+                SET session_id = $1
+                WHERE session_id = ANY($2)
+                ",
+            )
+            .bind(id)
+            .bind(&merged)
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query("DELETE FROM practice_sessions WHERE id = ANY($1)")
+                .bind(&merged)
+                .execute(&mut *tx)
+                .await?;
+            id
+        } else {
+            let (id,): (i64,) = sqlx::query_as(
+                "
+                INSERT INTO practice_sessions (type, mode, party, started_at, ended_at)
+                VALUES ($1, $2, $3, $4, $5)
+                RETURNING id
+                ",
+            )
+            .bind(challenge.r#type)
+            .bind(challenge.mode)
+            .bind(&party)
+            .bind(challenge.start_time)
+            .bind(challenge.start_time)
+            .fetch_one(&mut *tx)
+            .await?;
+            id
+        };
+
+        sqlx::query(
+            "
+            INSERT INTO practice_session_challenges (challenge_uuid, session_id)
+            SELECT UNNEST($1::UUID[]), $2
+            ON CONFLICT (challenge_uuid) DO UPDATE SET session_id = EXCLUDED.session_id
+            ",
+        )
+        .bind(&member_uuids)
+        .bind(session_id)
+        .execute(&mut *tx)
+        .await?;
+
+        // The session is summarized from all of its members, including those of merged sessions.
+        let members: Vec<ChallengeSummary> = sqlx::query_as(
+            "
+            SELECT c.uuid, c.status, c.stage, c.start_time, c.challenge_ticks
+            FROM practice_session_challenges sc
+            JOIN challenges c ON c.uuid = sc.challenge_uuid
+            WHERE sc.session_id = $1
+            ORDER BY c.start_time
+            ",
+        )
+        .bind(session_id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        let resets = members
+            .iter()
+            .filter(|c| c.status() == Some(Status::Reset))
+            .count() as i32;
+        let early_resets = members
+            .iter()
+            .filter(|c| c.is_early_reset(challenge_type))
+            .count() as i32;
+        let completed = members
+            .iter()
+            .filter(|c| c.status() == Some(Status::Completed))
+            .collect::<Vec<_>>();
+        let best_ticks = completed.iter().filter_map(|c| c.challenge_ticks).min();
+        let started_at = members
+            .first()
+            .map_or(challenge.start_time, |c| c.start_time);
+        let ended_at = members
+            .iter()
+            .map(ChallengeSummary::end_time)
+            .max()
+            .unwrap_or(challenge.start_time);
+
+        sqlx::query(
+            "
+            UPDATE practice_sessions
+            SET started_at = $2, ended_at = $3, resets = $4, early_resets = $5,
+                completions = $6, best_ticks = $7
+            WHERE id = $1
+            ",
+        )
+        .bind(session_id)
+        .bind(started_at)
+        .bind(ended_at)
+        .bind(resets)
+        .bind(early_resets)
+        .bind(completed.len() as i32)
+        .bind(best_ticks)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(Session {
+            id: session_id,
+            party,
+            challenges: members.iter().map(|c| c.uuid).collect(),
+            resets: resets as u32,
+            early_resets: early_resets as u32,
+            completions: completed.len() as u32,
+            best_ticks: best_ticks.map(|t| t as u32),
+            started_at: started_at.unix_timestamp(),
+            ended_at: ended_at.unix_timestamp(),
+        })
+    }
+
+    /// Loads the practice session containing a challenge, if it has been assigned to one.
+    /// Players who opted out after the session was stored are pseudonymized as it is loaded.
+    pub async fn load_session(&self, uuid: Uuid) -> Result<Option<Session>> {
+        let session: Option<SessionRow> = sqlx::query_as(
+            "
+            SELECT s.id, s.party, s.resets, s.early_resets, s.completions, s.best_ticks,
+                   s.started_at, s.ended_at
+            FROM practice_sessions s
+            JOIN practice_session_challenges sc ON sc.session_id = s.id
+            WHERE sc.challenge_uuid = $1
+            ",
+        )
+        .bind(uuid)
+        .fetch_optional(self.pool())
+        .await?;
+
+        let Some(session) = session else {
+            return Ok(None);
+        };
+
+        let challenges: Vec<(Uuid,)> = sqlx::query_as(
+            "
+            SELECT sc.challenge_uuid
+            FROM practice_session_challenges sc
+            JOIN challenges c ON c.uuid = sc.challenge_uuid
+            WHERE sc.session_id = $1
+            ORDER BY c.start_time
+            ",
+        )
+        .bind(session.id)
+        .fetch_all(self.pool())
+        .await?;
+
+        let pseudonymizer = self.load_pseudonymizer().await?;
+        Ok(Some(Session {
+            id: session.id,
+            party: session
+                .party
+                .iter()
+                .map(|username| pseudonymizer.name(username))
+                .collect(),
+            challenges: challenges.into_iter().map(|(uuid,)| uuid).collect(),
+            resets: session.resets as u32,
+            early_resets: session.early_resets as u32,
+            completions: session.completions as u32,
+            best_ticks: session.best_ticks.map(|t| t as u32),
+            started_at: session.started_at.unix_timestamp(),
+            ended_at: session.ended_at.unix_timestamp(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn challenge(minute: i64, ticks: i32) -> ChallengeSummary {
+        ChallengeSummary {
+            uuid: Uuid::from_u128(minute as u128),
+            status: Some(3),
+            stage: Some(blert::Stage::TobMaiden as i16),
+            start_time: OffsetDateTime::UNIX_EPOCH + Duration::minutes(minute),
+            challenge_ticks: Some(ticks),
+        }
+    }
+
+    #[test]
+    fn groups_consecutive_challenges() {
+        // One minute is 100 ticks.
+        let challenges = [
+            challenge(0, 100),
+            challenge(5, 100),
+            challenge(20, 200),
+            challenge(120, 100),
+            challenge(140, 100),
+        ];
+
+        let session = session_containing(&challenges, Uuid::from_u128(5));
+        assert_eq!(session.len(), 3);
+        assert_eq!(session[0].uuid, Uuid::from_u128(0));
+
+        let session = session_containing(&challenges, Uuid::from_u128(140));
+        assert_eq!(session.len(), 2);
+        assert_eq!(session[0].uuid, Uuid::from_u128(120));
+
+        assert!(session_containing(&challenges, Uuid::from_u128(999)).is_empty());
+    }
+
+    #[test]
+    fn early_resets() {
+        let mut reset = challenge(0, 100);
+        assert!(reset.is_early_reset(blert::Challenge::Tob));
+
+        reset.stage = Some(blert::Stage::TobXarpus as i16);
+        assert!(!reset.is_early_reset(blert::Challenge::Tob));
+    }
+}
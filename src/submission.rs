@@ -0,0 +1,250 @@
+//! Submission of analysis requests, shared by the HTTP and gRPC APIs so that both serve the same
+//! request identically.
+//!
+//! A request is served from the stored results of an earlier run of the same program version
+//! when possible. Otherwise, its challenge is loaded, through the challenge cache unless the
+//! request forces a fresh run or restricts its stages, and its programs are started.
+
+use std::sync::Arc;
+
+use uuid::Uuid;
+
+use crate::analysis::{
+    AnalyzerOutcome, DuplicatePolicy, Level, ProgramResult, ProgramRunHandle, RunOptions,
+};
+use crate::blert;
+use crate::challenge::Challenge;
+use crate::dispatch::Priority;
+use crate::error::Error;
+use crate::results::RunStatus;
+use crate::AppState;
+
+/// A request to analyze a challenge, as received by either API.
+#[derive(Debug, Clone)]
+pub struct AnalysisRequest {
+    pub uuid: Uuid,
+    /// Program to run. If unset, the default program for the challenge's type is used.
+    pub program: Option<String>,
+    /// Additional programs to run on the same challenge, each as its own job.
+    pub additional_programs: Vec<String>,
+    pub callback_url: Option<String>,
+    /// Players to restrict the analysis to. If unset, the whole party is analyzed.
+    pub players: Option<Vec<String>>,
+    /// Stages to restrict the analysis to. If unset, every recorded stage is analyzed.
+    pub stages: Option<Vec<blert::Stage>>,
+    pub level: Level,
+    pub on_duplicate: DuplicatePolicy,
+    pub priority: Priority,
+    /// Whether to run the program even if the same version of it has already analyzed the
+    /// challenge.
+    pub force: bool,
+    pub locale: Option<String>,
+    /// ID correlating the request with the log messages it produces.
+    pub request_id: Option<String>,
+}
+
+impl AnalysisRequest {
+    fn run_options(&self) -> RunOptions {
+        RunOptions {
+            callback_url: self.callback_url.clone(),
+            players: self
+                .players
+                .as_ref()
+                .map(|players| players.iter().cloned().collect()),
+            request_id: self.request_id.clone(),
+            level: self.level,
+            on_duplicate: self.on_duplicate,
+            priority: self.priority,
+            locale: self.locale.clone(),
+            ..RunOptions::default()
+        }
+    }
+}
+
+/// The outcome of a submitted analysis request.
+#[derive(Debug)]
+pub enum Submission {
+    /// Stored results of an earlier run of the requested program, returned instead of running it.
+    Cached(ProgramResult),
+    /// Handles of the started runs, with the requested program's first.
+    Started(Vec<ProgramRunHandle>),
+}
+
+/// Reasons an analysis request could not be submitted.
+#[derive(Debug)]
+pub enum SubmitError {
+    /// The challenge could not be loaded. Retryable errors may succeed if resubmitted.
+    Load(Error),
+    /// No program was requested and the challenge's type has no default program.
+    NoDefaultProgram,
+    /// The engine refused to start the program runs.
+    Run(Error),
+}
+
+/// Serves an analysis request from stored results, or starts running its programs.
+pub async fn submit(
+    state: &Arc<AppState>,
+    request: &AnalysisRequest,
+) -> Result<Submission, SubmitError> {
+    let uuid = request.uuid;
+
+    // Programs requested by name can be served from stored results before loading the challenge.
+    if let Some(program) = &request.program {
+        if let Some(result) = serve_cached_run(state, program, request).await {
+            return Ok(Submission::Cached(result));
+        }
+    }
+
+    // Forced runs reload the challenge, refreshing any cached copy of it.
+    let cached = if request.force || request.stages.is_some() {
+        None
+    } else {
+        state.challenge_cache.get(uuid)
+    };
+    let challenge = match cached {
+        Some(challenge) => challenge,
+        None => {
+            let stage_scope = request.stages.as_deref();
+            let challenge = match &state.database_pool {
+                Some(pool) => {
+                    Challenge::load_with_scope(pool, &state.data_repository, uuid, stage_scope)
+                        .await
+                }
+                None => {
+                    Challenge::load_from_repository_with_scope(
+                        &state.data_repository,
+                        uuid,
+                        stage_scope,
+                    )
+                    .await
+                }
+            }
+            .map_err(|e| {
+                if e.is_retryable() {
+                    tracing::warn!("Failed to load challenge {uuid}: {e}");
+                }
+                SubmitError::Load(e)
+            })?;
+
+            let challenge = Arc::new(challenge);
+            state.challenge_cache.insert(challenge.clone());
+            challenge
+        }
+    };
+
+    let engine = &state.analysis_engine;
+    let program = match &request.program {
+        Some(program) => program.clone(),
+        None => {
+            let program = engine
+                .default_program(challenge.r#type(), challenge.mode())
+                .ok_or(SubmitError::NoDefaultProgram)?
+                .to_owned();
+            if let Some(result) = serve_cached_run(state, &program, request).await {
+                return Ok(Submission::Cached(result));
+            }
+            program
+        }
+    };
+
+    let programs = std::iter::once(program.as_str())
+        .chain(request.additional_programs.iter().map(String::as_str))
+        .collect::<Vec<_>>();
+    engine
+        .run_programs(&programs, challenge, &request.run_options())
+        .map(Submission::Started)
+        .map_err(SubmitError::Run)
+}
+
+/// Serves an analysis request from stored results, if they can be returned instead of running
+/// `program`, notifying the request's callback URL as a new run would. Only requests for the
+/// full analysis of a single program are served from stored results.
+async fn serve_cached_run(
+    state: &Arc<AppState>,
+    program: &str,
+    request: &AnalysisRequest,
+) -> Option<ProgramResult> {
+    let result = find_cached_run(state, program, request).await?;
+
+    if let Some(url) = &request.callback_url {
+        let state = state.clone();
+        let url = url.clone();
+        let program = program.to_owned();
+        let uuid = request.uuid;
+        let result = result.clone();
+        tokio::spawn(async move {
+            state
+                .analysis_engine
+                .send_cached_callback(&url, &program, uuid, &result)
+                .await;
+        });
+    }
+
+    Some(result)
+}
+
+/// Looks up stored results of a completed run of `program` matching an analysis request.
+/// Failures to look up results are logged and treated as a cache miss.
+async fn find_cached_run(
+    state: &AppState,
+    program: &str,
+    request: &AnalysisRequest,
+) -> Option<ProgramResult> {
+    if request.force
+        || request.players.is_some()
+        || request.stages.is_some()
+        || !request.additional_programs.is_empty()
+    {
+        return None;
+    }
+
+    let uuid = request.uuid;
+    let results = state.results.as_ref()?;
+    let version = state.analysis_engine.program_version(program)?;
+
+    let locale = request.locale.as_deref();
+    let lookup = async {
+        let Some(run) = results
+            .find_cached_run(uuid, program, version, request.level, locale)
+            .await?
+        else {
+            return Ok(None);
+        };
+        let outcomes = results.analyzer_outcomes(run.id).await?;
+        Ok::<_, Error>(Some((run, outcomes)))
+    };
+    let (run, mut analyzers) = match lookup.await {
+        Ok(found) => found?,
+        Err(e) => {
+            tracing::warn!("Failed to look up cached results for challenge {uuid}: {e}");
+            return None;
+        }
+    };
+
+    // Runs stored before analyzer outcomes were recorded only have the outputs of the analyzers
+    // which completed.
+    for analyzer in run.outputs.keys() {
+        analyzers
+            .entry(analyzer.clone())
+            .or_insert_with(AnalyzerOutcome::completed);
+    }
+
+    // Messages are written again from the stored findings, whose player names may have been
+    // pseudonymized.
+    let localizer = state.analysis_engine.message_catalog().localizer(locale);
+    let mut findings = run.findings.unwrap_or_default();
+    findings
+        .iter_mut()
+        .for_each(|finding| localizer.localize_finding(finding));
+
+    Some(ProgramResult {
+        challenge_uuid: uuid,
+        status: RunStatus::Completed,
+        analyzers,
+        outputs: run.outputs,
+        blackboard: run.blackboard.unwrap_or_default(),
+        findings,
+        usage: run.usage.unwrap_or_default(),
+        downgraded_from: None,
+    })
+}
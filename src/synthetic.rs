@@ -0,0 +1,277 @@
+//! Procedural generation of challenge data for load and performance testing.
+//!
+//! Generated challenges are Theatre of Blood raids which follow the shape of recorded data: every
+//! player sends an update on each tick, attacks whenever their weapon comes off cooldown, and a
+//! configurable number of NPCs are updated alongside them. They are not meant to be analyzed for
+//! meaningful results, only to exercise the same code paths as real challenges at a chosen size.
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use uuid::Uuid;
+
+use crate::blert;
+use crate::data_repository::MemoryBackend;
+
+/// Stages of a Theatre of Blood raid, in order.
+const TOB_STAGES: [blert::Stage; 6] = [
+    blert::Stage::TobMaiden,
+    blert::Stage::TobBloat,
+    blert::Stage::TobNylocas,
+    blert::Stage::TobSotetseg,
+    blert::Stage::TobXarpus,
+    blert::Stage::TobVerzik,
+];
+
+/// Parameters controlling the size of generated challenges.
+#[derive(Debug, Clone, Copy)]
+pub struct SyntheticConfig {
+    /// Number of players in the party.
+    pub scale: usize,
+
+    /// Number of stages recorded, starting from the first. Capped at the number of stages in the
+    /// challenge.
+    pub stages: usize,
+
+    /// Number of ticks recorded in each stage.
+    pub ticks_per_stage: u32,
+
+    /// Number of NPC updates recorded on each tick, in addition to player events.
+    pub npcs_per_tick: u32,
+
+    /// Seed for the random number generator. The same seed always generates the same challenges.
+    pub seed: u64,
+}
+
+impl Default for SyntheticConfig {
+    fn default() -> Self {
+        Self {
+            scale: 5,
+            stages: TOB_STAGES.len(),
+            ticks_per_stage: 500,
+            npcs_per_tick: 4,
+            seed: 0,
+        }
+    }
+}
+
+/// A generated challenge, ready to be stored in a data repository.
+#[derive(Debug, Clone)]
+pub struct SyntheticChallenge {
+    pub uuid: Uuid,
+    pub data: blert::ChallengeData,
+    pub stages: Vec<blert::ChallengeEvents>,
+}
+
+impl SyntheticChallenge {
+    /// Stores the challenge's data and events in a memory backend, from which it can be loaded
+    /// as though it was recorded.
+    pub fn store(&self, backend: &MemoryBackend) {
+        backend.insert_challenge(self.uuid, &self.data);
+        for stage in &self.stages {
+            backend.insert_stage_events(self.uuid, stage);
+        }
+    }
+
+    /// Returns the total number of events across all of the challenge's stages.
+    pub fn total_events(&self) -> usize {
+        self.stages.iter().map(|stage| stage.events.len()).sum()
+    }
+}
+
+/// Generates challenges from a seeded random source.
+pub struct Generator {
+    config: SyntheticConfig,
+    rng: StdRng,
+}
+
+impl Generator {
+    pub fn new(config: SyntheticConfig) -> Self {
+        Self {
+            config,
+            rng: StdRng::seed_from_u64(config.seed),
+        }
+    }
+
+    /// Generates the next challenge.
+    pub fn generate(&mut self) -> SyntheticChallenge {
+        let uuid = Uuid::from_u128(self.rng.gen());
+        let party = (0..self.config.scale)
+            .map(|i| format!("synthetic {i}"))
+            .collect::<Vec<_>>();
+
+        let stages = TOB_STAGES
+            .iter()
+            .take(self.config.stages.max(1))
+            .map(|&stage| self.generate_stage(stage, &party))
+            .collect::<Vec<_>>();
+
+        let mut data = blert::ChallengeData {
+            party,
+            ..Default::default()
+        };
+        data.set_type(blert::Challenge::Tob);
+        data.set_mode(blert::ChallengeMode::TobRegular);
+        data.set_stage(
+            stages
+                .last()
+                .map_or(TOB_STAGES[0], blert::ChallengeEvents::stage),
+        );
+
+        SyntheticChallenge { uuid, data, stages }
+    }
+
+    fn generate_stage(&mut self, stage: blert::Stage, party: &[String]) -> blert::ChallengeEvents {
+        let mut positions = (0..party.len())
+            .map(|_| (self.rng.gen_range(0..64), self.rng.gen_range(0..64)))
+            .collect::<Vec<(i32, i32)>>();
+        let mut off_cooldown = vec![0; party.len()];
+        let mut events = Vec::new();
+
+        for tick in 0..self.config.ticks_per_stage {
+            for (index, position) in positions.iter_mut().enumerate() {
+                position.0 += self.rng.gen_range(-1..=1);
+                position.1 += self.rng.gen_range(-1..=1);
+
+                if tick >= off_cooldown[index] {
+                    let mut attack = player_event(tick, index, blert::event::Type::PlayerAttack);
+                    attack.x_coord = position.0;
+                    attack.y_coord = position.1;
+                    events.push(attack);
+                    off_cooldown[index] = tick + self.rng.gen_range(2..=5);
+                }
+
+                let mut update = player_event(tick, index, blert::event::Type::PlayerUpdate);
+                update.x_coord = position.0;
+                update.y_coord = position.1;
+                if let Some(player) = update.player.as_mut() {
+                    player.off_cooldown_tick = off_cooldown[index];
+                    player.hitpoints = Some(skill_level(99, self.rng.gen_range(1..=99)));
+                    player.prayer = Some(skill_level(99, self.rng.gen_range(0..=99)));
+                }
+                events.push(update);
+            }
+
+            for room_id in 0..self.config.npcs_per_tick {
+                let mut event = blert::Event {
+                    tick,
+                    npc: Some(blert::event::Npc {
+                        room_id: u64::from(room_id) + 1,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                };
+                event.set_type(blert::event::Type::NpcUpdate);
+                events.push(event);
+            }
+        }
+
+        let mut stage_events = blert::ChallengeEvents {
+            party_names: party.to_vec(),
+            events,
+            ..Default::default()
+        };
+        stage_events.set_stage(stage);
+        stage_events
+    }
+}
+
+fn player_event(tick: u32, party_index: usize, r#type: blert::event::Type) -> blert::Event {
+    let mut event = blert::Event {
+        tick,
+        player: Some(blert::event::Player {
+            party_index: party_index as u32,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    event.set_type(r#type);
+    event
+}
+
+/// Packs a skill's base and current levels in the format used by player updates.
+fn skill_level(base: u32, current: u32) -> u32 {
+    (current << 16) | base
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::challenge::Challenge;
+    use crate::data_repository::DataRepository;
+
+    #[test]
+    fn generation_is_deterministic() {
+        let config = SyntheticConfig {
+            ticks_per_stage: 20,
+            ..SyntheticConfig::default()
+        };
+
+        let a = Generator::new(config).generate();
+        let b = Generator::new(config).generate();
+        assert_eq!(a.uuid, b.uuid);
+        assert_eq!(a.data, b.data);
+        assert_eq!(a.stages, b.stages);
+    }
+
+    #[tokio::test]
+    async fn generated_challenges_can_be_loaded() {
+        let config = SyntheticConfig {
+            scale: 3,
+            stages: 2,
+            ticks_per_stage: 50,
+            ..SyntheticConfig::default()
+        };
+        let synthetic = Generator::new(config).generate();
+
+        let backend = MemoryBackend::new();
+        synthetic.store(&backend);
+        let repository = DataRepository::new(Box::new(backend));
+
+        let challenge = Challenge::load_from_repository(&repository, synthetic.uuid)
+            .await
+            .unwrap();
+        assert_eq!(challenge.scale(), 3);
+        assert_eq!(
+            challenge.stages().collect::<Vec<_>>(),
+            [blert::Stage::TobMaiden, blert::Stage::TobBloat]
+        );
+
+        let maiden = challenge.stage_info(blert::Stage::TobMaiden).unwrap();
+        assert_eq!(maiden.total_ticks(), 49);
+        assert!(maiden.player_state("synthetic 0").is_some());
+
+        let scope = [blert::Stage::TobBloat];
+        let challenge =
+            Challenge::load_from_repository_with_scope(&repository, synthetic.uuid, Some(&scope))
+                .await
+                .unwrap();
+        assert_eq!(
+            challenge.stages().collect::<Vec<_>>(),
+            [blert::Stage::TobBloat]
+        );
+        assert_eq!(challenge.stage_scope(), Some(&scope[..]));
+    }
+
+    #[tokio::test]
+    async fn challenges_load_without_missing_stages() {
+        let config = SyntheticConfig {
+            stages: 3,
+            ticks_per_stage: 20,
+            ..SyntheticConfig::default()
+        };
+        let mut synthetic = Generator::new(config).generate();
+        synthetic.stages.remove(1);
+
+        let backend = MemoryBackend::new();
+        synthetic.store(&backend);
+        let repository = DataRepository::new(Box::new(backend));
+
+        let challenge = Challenge::load_from_repository(&repository, synthetic.uuid)
+            .await
+            .unwrap();
+        assert_eq!(
+            challenge.stages().collect::<Vec<_>>(),
+            [blert::Stage::TobMaiden, blert::Stage::TobNylocas]
+        );
+        assert_eq!(challenge.missing_stages(), [blert::Stage::TobBloat]);
+    }
+}
@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+/// Duration of a single game tick.
+pub const TICK_DURATION: Duration = Duration::from_millis(600);
+
+/// Converts a number of game ticks to their real-time duration.
+pub fn ticks_to_duration(ticks: u32) -> Duration {
+    TICK_DURATION * ticks
+}
+
+/// Formats a number of game ticks as a real-time string in `mm:ss.cs` form, e.g. `1:23.40`.
+pub fn format_ticks(ticks: u32) -> String {
+    format_duration(ticks_to_duration(ticks))
+}
+
+/// Formats a real-time duration in `mm:ss.cs` form, e.g. `1:23.40`.
+pub fn format_duration(duration: Duration) -> String {
+    let centiseconds = duration.as_millis() / 10;
+    let minutes = centiseconds / 6000;
+    let seconds = (centiseconds % 6000) / 100;
+    let centiseconds = centiseconds % 100;
+    format!("{minutes}:{seconds:02}.{centiseconds:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_to_duration_converts() {
+        assert_eq!(ticks_to_duration(0), Duration::ZERO);
+        assert_eq!(ticks_to_duration(1), Duration::from_millis(600));
+        assert_eq!(ticks_to_duration(100), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn format_ticks_formats() {
+        assert_eq!(format_ticks(0), "0:00.00");
+        assert_eq!(format_ticks(1), "0:00.60");
+        assert_eq!(format_ticks(5), "0:03.00");
+        assert_eq!(format_ticks(100), "1:00.00");
+        assert_eq!(format_ticks(139), "1:23.40");
+        assert_eq!(format_ticks(2000), "20:00.00");
+        assert_eq!(format_duration(Duration::from_millis(83_409)), "1:23.40");
+    }
+}
@@ -0,0 +1,115 @@
+//! Benchmark room times for Theatre of Blood raids.
+//!
+//! Rotations, supplies, and phase timings differ substantially with the size of the party, so
+//! each benchmark profile applies to a single mode and scale. Raids without a matching profile
+//! are not compared against any benchmark.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::blert;
+use crate::error::{Error, Result};
+
+/// A benchmark profile as stored on disk.
+#[derive(Debug, Deserialize)]
+struct RawProfile {
+    mode: String,
+    scale: usize,
+    rooms: HashMap<String, u32>,
+}
+
+/// Benchmark room times for raids of a specific mode and scale.
+#[derive(Debug)]
+pub struct Profile {
+    pub mode: blert::ChallengeMode,
+    pub scale: usize,
+    rooms: HashMap<blert::Stage, u32>,
+}
+
+impl Profile {
+    /// Returns the benchmark time of a room in ticks, if it has one.
+    pub fn room_ticks(&self, stage: blert::Stage) -> Option<u32> {
+        self.rooms.get(&stage).copied()
+    }
+}
+
+/// A collection of benchmark profiles.
+#[derive(Debug)]
+pub struct Benchmarks {
+    profiles: Vec<Profile>,
+}
+
+impl Benchmarks {
+    /// Loads benchmark profiles from a JSON file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::parse(&fs::read(path)?)
+    }
+
+    fn parse(json: &[u8]) -> Result<Self> {
+        let raw: Vec<RawProfile> = serde_json::from_slice(json)?;
+
+        let profiles = raw
+            .into_iter()
+            .map(|profile| {
+                let mode = blert::ChallengeMode::from_str_name(&profile.mode)
+                    .ok_or_else(|| Error::Config(format!("Unknown mode: {}", profile.mode)))?;
+                let rooms = profile
+                    .rooms
+                    .into_iter()
+                    .map(|(stage, ticks)| {
+                        blert::Stage::from_str_name(&stage)
+                            .map(|stage| (stage, ticks))
+                            .ok_or_else(|| Error::Config(format!("Unknown stage: {stage}")))
+                    })
+                    .collect::<Result<_>>()?;
+
+                Ok(Profile {
+                    mode,
+                    scale: profile.scale,
+                    rooms,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { profiles })
+    }
+
+    /// Returns the profile for raids of the given mode and scale, if one exists.
+    pub fn profile(&self, mode: blert::ChallengeMode, scale: usize) -> Option<&Profile> {
+        self.profiles
+            .iter()
+            .find(|profile| profile.mode == mode && profile.scale == scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_are_selected_by_mode_and_scale() {
+        let benchmarks = Benchmarks::parse(
+            br#"[
+                {"mode": "TOB_REGULAR", "scale": 2, "rooms": {"TOB_MAIDEN": 283}},
+                {"mode": "TOB_REGULAR", "scale": 5, "rooms": {"TOB_MAIDEN": 200}}
+            ]"#,
+        )
+        .unwrap();
+
+        let duo = benchmarks
+            .profile(blert::ChallengeMode::TobRegular, 2)
+            .unwrap();
+        assert_eq!(duo.room_ticks(blert::Stage::TobMaiden), Some(283));
+        assert_eq!(duo.room_ticks(blert::Stage::TobBloat), None);
+
+        assert!(benchmarks
+            .profile(blert::ChallengeMode::TobRegular, 1)
+            .is_none());
+        assert!(benchmarks
+            .profile(blert::ChallengeMode::TobHard, 5)
+            .is_none());
+    }
+}
@@ -0,0 +1,177 @@
+//! Model of Pestilent Bloat's walk/down cycle.
+//!
+//! Bloat walks laps of its room, during which it is dangerous to approach, until it has taken
+//! enough damage to go down. While down, it sits still for a fixed number of ticks before standing
+//! up and walking again. Nearly all damage is dealt during downs, so the fight's length depends on
+//! how much of each down the team spends attacking. Players still beneath Bloat when it stands up
+//! are stomped, taking damage and being stunned.
+
+use crate::blert;
+use crate::challenge::StageInfo;
+
+/// Number of ticks Bloat stays down before standing up.
+pub const DOWN_TICKS: u32 = 32;
+
+/// Length of each side of the square of tiles Bloat occupies.
+pub const SIZE: u32 = 5;
+
+/// A single down of Bloat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Down {
+    /// One-indexed number of the down within the room.
+    pub number: u32,
+    /// Number of ticks Bloat walked before going down.
+    pub walk_ticks: u32,
+    /// Tick on which Bloat went down.
+    pub start_tick: u32,
+    /// Tick on which Bloat stood back up, or the end of the room if it died while down.
+    pub end_tick: u32,
+    /// Whether Bloat stood back up at the end of the down, rather than dying.
+    pub stood_up: bool,
+}
+
+impl Down {
+    /// Returns whether the tick falls within the down.
+    pub fn contains(&self, tick: u32) -> bool {
+        (self.start_tick..self.end_tick).contains(&tick)
+    }
+
+    /// Returns the tick until which a player who died on `death_tick`, if they died at all, could
+    /// attack during the down, or `None` if they were already dead when it started.
+    pub fn participation_end(&self, death_tick: Option<u32>) -> Option<u32> {
+        match death_tick {
+            Some(tick) if tick <= self.start_tick => None,
+            Some(tick) => Some(tick.min(self.end_tick)),
+            None => Some(self.end_tick),
+        }
+    }
+}
+
+/// Returns every down of Bloat recorded during a stage, in order.
+pub fn downs(stage: &StageInfo) -> Vec<Down> {
+    let starts = stage
+        .events_for_type(blert::event::Type::TobBloatDown)
+        .map(|event| {
+            let (number, walk_ticks) = event
+                .bloat_down
+                .as_ref()
+                .map_or((0, 0), |down| (down.down_number, down.walk_time));
+            (event.tick, number, walk_ticks)
+        })
+        .collect::<Vec<_>>();
+    let ups = stage
+        .events_for_type(blert::event::Type::TobBloatUp)
+        .map(|event| event.tick)
+        .collect::<Vec<_>>();
+
+    pair_downs(&starts, &ups, stage.total_ticks())
+}
+
+/// Returns the tile on which Bloat, the stage NPC `bloat`, sat during a down, if its position was
+/// recorded. Bloat does not move while down, so any update within the down gives its tile.
+pub fn position_during(
+    stage: &StageInfo,
+    bloat: &blert::challenge_data::StageNpc,
+    down: &Down,
+) -> Option<blert::Coords> {
+    stage
+        .events_for_type(blert::event::Type::NpcUpdate)
+        .filter(|event| down.contains(event.tick))
+        .find(|event| {
+            event
+                .npc
+                .as_ref()
+                .is_some_and(|npc| npc.room_id == bloat.room_id)
+        })
+        .map(|event| blert::Coords {
+            x: event.x_coord,
+            y: event.y_coord,
+        })
+}
+
+/// Returns whether `tile` lies beneath Bloat, given its southwest tile `bloat` and its `size`.
+pub fn is_beneath(bloat: &blert::Coords, size: u32, tile: &blert::Coords) -> bool {
+    let size = size as i32;
+    (bloat.x..bloat.x + size).contains(&tile.x) && (bloat.y..bloat.y + size).contains(&tile.y)
+}
+
+/// Pairs the start of each down with the first time Bloat stood up after it. A down without a
+/// following stand-up lasts until `end_tick`, unless it would have ended first.
+fn pair_downs(starts: &[(u32, u32, u32)], ups: &[u32], end_tick: u32) -> Vec<Down> {
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, &(start_tick, number, walk_ticks))| {
+            let next_start = starts.get(i + 1).map(|&(tick, _, _)| tick);
+            let up = ups
+                .iter()
+                .copied()
+                .find(|&up| up > start_tick && !next_start.is_some_and(|next| up > next));
+
+            let (end_tick, stood_up) = match up {
+                Some(up) => (up, true),
+                None if next_start.is_some() || start_tick + DOWN_TICKS < end_tick => {
+                    // The stand-up was not recorded, but Bloat must have stood up to go down
+                    // again or to still be alive after the down.
+                    (start_tick + DOWN_TICKS, true)
+                }
+                None => (end_tick, false),
+            };
+
+            Down {
+                number: if number == 0 { i as u32 + 1 } else { number },
+                walk_ticks,
+                start_tick,
+                end_tick,
+                stood_up,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downs_end_when_bloat_stands_up_or_dies() {
+        let downs = pair_downs(&[(40, 1, 40), (110, 2, 38), (180, 0, 38)], &[72], 200);
+
+        assert_eq!(downs.len(), 3);
+        assert_eq!((downs[0].start_tick, downs[0].end_tick), (40, 72));
+        assert!(downs[0].stood_up);
+
+        // The second stand-up was missed, but a third down followed.
+        assert_eq!((downs[1].start_tick, downs[1].end_tick), (110, 142));
+        assert!(downs[1].stood_up);
+
+        // Bloat died during its third down.
+        assert_eq!(downs[2].number, 3);
+        assert_eq!((downs[2].start_tick, downs[2].end_tick), (180, 200));
+        assert!(!downs[2].stood_up);
+        assert!(downs[2].contains(199));
+    }
+
+    #[test]
+    fn dead_players_take_no_part_in_downs() {
+        let down = pair_downs(&[(40, 1, 40)], &[72], 200)[0];
+
+        assert_eq!(down.participation_end(None), Some(72));
+        assert_eq!(down.participation_end(Some(120)), Some(72));
+        assert_eq!(down.participation_end(Some(50)), Some(50));
+        assert_eq!(down.participation_end(Some(40)), None);
+        assert_eq!(down.participation_end(Some(10)), None);
+    }
+
+    #[test]
+    fn players_beneath_bloat_are_stomped() {
+        let bloat = blert::Coords { x: 3288, y: 4445 };
+        let at = |x, y| blert::Coords { x, y };
+
+        assert!(is_beneath(&bloat, SIZE, &at(3288, 4445)));
+        assert!(is_beneath(&bloat, SIZE, &at(3292, 4449)));
+        assert!(!is_beneath(&bloat, SIZE, &at(3293, 4449)));
+        assert!(!is_beneath(&bloat, SIZE, &at(3290, 4444)));
+        assert!(!is_beneath(&bloat, SIZE, &at(3287, 4447)));
+    }
+}
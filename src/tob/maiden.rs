@@ -0,0 +1,144 @@
+//! Model of the crab spawns at the Maiden of Sugadinti.
+//!
+//! Maiden spawns a set of blood crabs (matomenos) each time she reaches 70%, 50%, and 30% of her
+//! hitpoints. Each crab spawns at one of several positions on the north and south sides of the
+//! room.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::blert;
+use crate::challenge::StageInfo;
+use crate::npc::NpcExt;
+
+pub use blert::event::npc::maiden_crab::{Position, Spawn};
+
+/// Maiden phases at which crabs spawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Phase {
+    Seventies,
+    Fifties,
+    Thirties,
+}
+
+impl From<Spawn> for Phase {
+    fn from(spawn: Spawn) -> Self {
+        match spawn {
+            Spawn::Seventies => Phase::Seventies,
+            Spawn::Fifties => Phase::Fifties,
+            Spawn::Thirties => Phase::Thirties,
+        }
+    }
+}
+
+/// Returns whether a crab position is on the north side of the room.
+pub fn is_north(position: Position) -> bool {
+    matches!(
+        position,
+        Position::N1 | Position::N2 | Position::N3 | Position::N4Inner | Position::N4Outer
+    )
+}
+
+/// A set of crabs spawned together during a Maiden phase.
+#[derive(Debug)]
+pub struct SpawnSet<'a> {
+    /// Tick on which the set spawned.
+    pub spawn_tick: u32,
+
+    /// The crabs in the set with their spawn positions.
+    pub crabs: Vec<(Position, &'a Arc<blert::challenge_data::StageNpc>)>,
+}
+
+impl<'a> SpawnSet<'a> {
+    /// Returns an iterator over the crabs which spawned on the north side of the room.
+    pub fn north(&self) -> impl Iterator<Item = &'a Arc<blert::challenge_data::StageNpc>> + '_ {
+        self.crabs
+            .iter()
+            .filter_map(|&(p, crab)| is_north(p).then_some(crab))
+    }
+
+    /// Returns an iterator over the crabs which spawned on the south side of the room.
+    pub fn south(&self) -> impl Iterator<Item = &'a Arc<blert::challenge_data::StageNpc>> + '_ {
+        self.crabs
+            .iter()
+            .filter_map(|&(p, crab)| (!is_north(p)).then_some(crab))
+    }
+}
+
+/// Groups the crabs recorded during a Maiden stage into their spawn sets, ordered by phase.
+///
+/// Each crab records the phase at which it spawned, so a set is only reported for phases whose
+/// crabs were recorded.
+pub fn spawn_sets(stage: &StageInfo) -> Vec<SpawnSet<'_>> {
+    group_sets(stage.npcs().filter(|npc| npc.is_maiden_matomenos()))
+}
+
+fn group_sets<'a>(
+    crabs: impl Iterator<Item = &'a Arc<blert::challenge_data::StageNpc>>,
+) -> Vec<SpawnSet<'a>> {
+    use blert::challenge_data::stage_npc::Type;
+
+    let mut by_phase: BTreeMap<Phase, Vec<(Position, &Arc<blert::challenge_data::StageNpc>)>> =
+        BTreeMap::new();
+
+    for npc in crabs {
+        if let Some(Type::MaidenCrab(ref crab)) = npc.r#type {
+            by_phase
+                .entry(crab.spawn().into())
+                .or_default()
+                .push((crab.position(), npc));
+        }
+    }
+
+    by_phase
+        .into_values()
+        .map(|crabs| {
+            let spawn_tick = crabs
+                .iter()
+                .map(|(_, npc)| npc.spawn_tick)
+                .min()
+                .unwrap_or_default();
+            SpawnSet { spawn_tick, crabs }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crab(spawn: Spawn, position: Position, tick: u32) -> Arc<blert::challenge_data::StageNpc> {
+        use blert::challenge_data::stage_npc::Type;
+
+        let mut crab = blert::event::npc::MaidenCrab::default();
+        crab.set_spawn(spawn);
+        crab.set_position(position);
+
+        Arc::new(blert::challenge_data::StageNpc {
+            spawn_tick: tick,
+            r#type: Some(Type::MaidenCrab(crab)),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn sets_take_their_phase_from_their_crabs() {
+        // The 70s crabs were not recorded, so the first set is the 50s.
+        let crabs = [
+            crab(Spawn::Fifties, Position::N1, 40),
+            crab(Spawn::Fifties, Position::S1, 40),
+            crab(Spawn::Thirties, Position::N1, 70),
+        ];
+
+        let sets = group_sets(crabs.iter());
+
+        assert_eq!(sets.len(), 2);
+        assert_eq!(sets[0].spawn_tick, 40);
+        assert_eq!(sets[0].crabs.len(), 2);
+        assert_eq!(sets[0].north().count(), 1);
+        assert_eq!(sets[0].south().count(), 1);
+        assert_eq!(sets[1].spawn_tick, 70);
+        assert_eq!(sets[1].north().count(), 1);
+        assert_eq!(sets[1].south().count(), 0);
+    }
+}
@@ -0,0 +1,7 @@
+//! Models of Theatre of Blood room mechanics shared across analyzers.
+
+pub mod benchmarks;
+pub mod bloat;
+pub mod maiden;
+pub mod nylo;
+pub mod phases;
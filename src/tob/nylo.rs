@@ -0,0 +1,309 @@
+//! Canonical model of the Nylocas room's wave spawns.
+//!
+//! The room consists of 31 waves, each spawning nylos from some combination of the west, south,
+//! and east lanes. A lane spawns either a pair of small nylos or a single big nylo. The lanes and
+//! sizes of every wave are fixed, while nylo styles are randomized, so recorded nylos are matched
+//! against the schedule by lane and size alone.
+//!
+//! Waves nominally spawn every [`WAVE_INTERVAL`] ticks, but are delayed ("stalled") by
+//! [`STALL_TICKS`] whenever the number of nylos alive in the room is at or above the room's cap.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::blert;
+use crate::challenge::StageInfo;
+
+/// Total number of waves in the Nylocas room.
+pub const TOTAL_WAVES: u32 = 31;
+
+/// Number of ticks between consecutive waves when the room is not stalled.
+pub const WAVE_INTERVAL: u32 = 4;
+
+/// Number of ticks by which a wave is delayed each time it stalls.
+pub const STALL_TICKS: u32 = 4;
+
+/// Lanes from which nylos enter the room.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lane {
+    West,
+    South,
+    East,
+}
+
+impl Lane {
+    const VALUES: [Lane; 3] = [Lane::West, Lane::South, Lane::East];
+
+    /// Returns the lane from which a spawned nylo entered the room, or `None` if the nylo was
+    /// created by a big nylo splitting.
+    pub fn of(nylo: &blert::event::npc::Nylo) -> Option<Lane> {
+        use blert::event::npc::nylo::SpawnType;
+
+        match nylo.spawn_type() {
+            SpawnType::West => Some(Lane::West),
+            SpawnType::South => Some(Lane::South),
+            SpawnType::East => Some(Lane::East),
+            SpawnType::Split => None,
+        }
+    }
+}
+
+/// What spawns from a single lane in a wave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LaneSpawn {
+    /// Nothing spawns from the lane.
+    Empty,
+
+    /// A pair of small nylos spawns from the lane.
+    Double,
+
+    /// A single big nylo spawns from the lane.
+    Big,
+}
+
+impl LaneSpawn {
+    /// Returns the number of small and big nylos the spawn consists of.
+    fn counts(self) -> (usize, usize) {
+        match self {
+            LaneSpawn::Empty => (0, 0),
+            LaneSpawn::Double => (2, 0),
+            LaneSpawn::Big => (0, 1),
+        }
+    }
+}
+
+/// The canonical definition of a single Nylocas wave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Wave {
+    /// One-indexed number of the wave.
+    pub number: u32,
+
+    /// Spawns from the west, south, and east lanes, respectively.
+    lanes: [LaneSpawn; 3],
+}
+
+impl Wave {
+    /// Returns what spawns from the given lane in the wave.
+    pub fn lane(&self, lane: Lane) -> LaneSpawn {
+        self.lanes[lane as usize]
+    }
+}
+
+const fn wave(number: u32, west: LaneSpawn, south: LaneSpawn, east: LaneSpawn) -> Wave {
+    Wave {
+        number,
+        lanes: [west, south, east],
+    }
+}
+
+use LaneSpawn::{Big as B, Double as D, Empty as E};
+
+/// The canonical spawn schedule of every wave, in order.
+pub const WAVES: [Wave; TOTAL_WAVES as usize] = [
+    wave(1, D, E, D),
+    wave(2, E, D, E),
+    wave(3, D, E, D),
+    wave(4, E, D, E),
+    wave(5, D, B, D),
+    wave(6, E, D, E),
+    wave(7, D, E, D),
+    wave(8, B, D, B),
+    wave(9, D, E, D),
+    wave(10, E, D, E),
+    wave(11, D, E, D),
+    wave(12, D, D, D),
+    wave(13, E, B, E),
+    wave(14, D, E, D),
+    wave(15, B, D, B),
+    wave(16, D, E, D),
+    wave(17, E, D, E),
+    wave(18, D, B, D),
+    wave(19, D, D, D),
+    wave(20, B, E, B),
+    wave(21, D, D, D),
+    wave(22, D, B, D),
+    wave(23, B, D, B),
+    wave(24, D, D, D),
+    wave(25, D, B, D),
+    wave(26, B, D, D),
+    wave(27, D, D, B),
+    wave(28, B, D, B),
+    wave(29, D, B, D),
+    wave(30, B, D, B),
+    wave(31, B, B, B),
+];
+
+/// Returns the number of times a wave stalled given its spawn tick and the spawn tick of the
+/// wave before it.
+pub fn stalls_between(previous_spawn_tick: u32, spawn_tick: u32) -> u32 {
+    spawn_tick
+        .saturating_sub(previous_spawn_tick)
+        .saturating_sub(WAVE_INTERVAL)
+        / STALL_TICKS
+}
+
+/// The result of aligning a wave's observed spawns with its canonical definition.
+#[derive(Debug)]
+pub struct WaveMatch<'a> {
+    /// Canonical definition of the wave.
+    pub wave: &'static Wave,
+
+    /// Tick on which the wave was observed to spawn, if any of its nylos were recorded.
+    pub spawn_tick: Option<u32>,
+
+    /// Number of times the wave stalled before spawning.
+    pub stalls: u32,
+
+    /// Lanes whose expected spawn was not (fully) recorded.
+    pub missing: Vec<Lane>,
+
+    /// Recorded nylos which do not fit the wave's definition.
+    pub unexpected: Vec<&'a Arc<blert::challenge_data::StageNpc>>,
+}
+
+impl WaveMatch<'_> {
+    /// Returns whether the recorded wave exactly matches its canonical definition.
+    pub fn is_exact(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty()
+    }
+}
+
+type ObservedNylo<'a> = (Lane, bool, &'a Arc<blert::challenge_data::StageNpc>);
+
+/// Aligns the nylos spawned during a Nylocas stage against the canonical wave schedule,
+/// returning a match for every wave in order. Nylos created from big splits are ignored.
+pub fn match_waves(stage: &StageInfo) -> Vec<WaveMatch<'_>> {
+    use blert::challenge_data::stage_npc::Type;
+
+    let mut by_wave: HashMap<u32, Vec<ObservedNylo<'_>>> = HashMap::new();
+
+    for npc in stage.npcs() {
+        let Some(Type::Nylo(ref nylo)) = npc.r#type else {
+            continue;
+        };
+        if let Some(lane) = Lane::of(nylo) {
+            by_wave
+                .entry(nylo.wave)
+                .or_default()
+                .push((lane, nylo.big, npc));
+        }
+    }
+
+    let mut previous_spawn_tick = None;
+
+    WAVES
+        .iter()
+        .map(|wave| {
+            let observed = by_wave.remove(&wave.number).unwrap_or_default();
+            let wave_match = match_wave(wave, &observed, previous_spawn_tick);
+
+            // A wave without any recorded nylos is assumed to have spawned on schedule, so that
+            // the next wave's stalls are not counted against it.
+            previous_spawn_tick = wave_match
+                .spawn_tick
+                .or_else(|| previous_spawn_tick.map(|tick| tick + WAVE_INTERVAL));
+            wave_match
+        })
+        .collect()
+}
+
+/// Aligns the nylos observed in a single wave against the wave's definition.
+fn match_wave<'a>(
+    wave: &'static Wave,
+    observed: &[ObservedNylo<'a>],
+    previous_spawn_tick: Option<u32>,
+) -> WaveMatch<'a> {
+    let spawn_tick = observed.iter().map(|(_, _, npc)| npc.spawn_tick).min();
+
+    let stalls = match (previous_spawn_tick, spawn_tick) {
+        (Some(previous), Some(tick)) => stalls_between(previous, tick),
+        _ => 0,
+    };
+
+    let mut wave_match = WaveMatch {
+        wave,
+        spawn_tick,
+        stalls,
+        missing: Vec::new(),
+        unexpected: Vec::new(),
+    };
+
+    for lane in Lane::VALUES {
+        let (mut smalls, mut bigs) = wave.lane(lane).counts();
+
+        for &(_, big, npc) in observed.iter().filter(|(l, _, _)| *l == lane) {
+            let remaining = if big { &mut bigs } else { &mut smalls };
+            if *remaining > 0 {
+                *remaining -= 1;
+            } else {
+                wave_match.unexpected.push(npc);
+            }
+        }
+
+        if smalls > 0 || bigs > 0 {
+            wave_match.missing.push(lane);
+        }
+    }
+
+    wave_match
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waves_are_ordered() {
+        for (i, wave) in WAVES.iter().enumerate() {
+            assert_eq!(wave.number as usize, i + 1);
+            assert!(Lane::VALUES
+                .iter()
+                .any(|&lane| wave.lane(lane) != LaneSpawn::Empty));
+        }
+    }
+
+    fn npc(spawn_tick: u32) -> Arc<blert::challenge_data::StageNpc> {
+        Arc::new(blert::challenge_data::StageNpc {
+            spawn_tick,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn mismatched_spawns_are_unexpected() {
+        static WAVE: Wave = wave(1, D, E, B);
+
+        let west_small = [npc(10), npc(10), npc(10)];
+        let east_big = npc(11);
+        let observed = [
+            (Lane::West, false, &west_small[0]),
+            (Lane::West, false, &west_small[1]),
+            (Lane::West, false, &west_small[2]),
+            (Lane::East, true, &east_big),
+        ];
+        let wave_match = match_wave(&WAVE, &observed, Some(2));
+
+        assert_eq!(wave_match.spawn_tick, Some(10));
+        assert_eq!(wave_match.stalls, 1);
+        assert_eq!(wave_match.unexpected.len(), 1);
+        assert!(wave_match.missing.is_empty());
+        assert!(!wave_match.is_exact());
+
+        let observed = [
+            (Lane::West, false, &west_small[0]),
+            (Lane::East, false, &east_big),
+        ];
+        let wave_match = match_wave(&WAVE, &observed, None);
+        assert_eq!(wave_match.missing, vec![Lane::West, Lane::East]);
+        assert_eq!(wave_match.unexpected.len(), 1);
+        assert!(match_wave(&WAVE, &[], None).spawn_tick.is_none());
+    }
+
+    #[test]
+    fn stalls_between_waves() {
+        assert_eq!(stalls_between(10, 14), 0);
+        assert_eq!(stalls_between(10, 18), 1);
+        assert_eq!(stalls_between(10, 26), 3);
+        assert_eq!(stalls_between(10, 12), 0);
+    }
+}
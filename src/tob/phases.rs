@@ -0,0 +1,191 @@
+//! Boss phases within Theatre of Blood rooms.
+//!
+//! Phase boundaries are derived once per stage from NPC spawns and transformations, so that every
+//! analyzer reasoning about phases agrees on where they begin and end. See
+//! [`StageInfo::phases`](crate::challenge::StageInfo::phases).
+
+use std::collections::BTreeSet;
+use std::iter;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::blert;
+use crate::challenge::StageInfo;
+use crate::npc::NpcExt;
+
+/// A labeled phase of a boss fight.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum Phase {
+    /// Start of the Maiden room up to the spawn of her 70s crabs.
+    MaidenSeventies,
+    /// From the 70s crabs up to the spawn of the 50s crabs.
+    MaidenFifties,
+    /// From the 50s crabs up to the spawn of the 30s crabs.
+    MaidenThirties,
+    /// From the 30s crabs until Maiden dies.
+    MaidenFinal,
+    /// Start of the Nylocas room up to the spawn of the final wave.
+    NyloWaves,
+    /// From the final wave until the boss spawns.
+    NyloCleanup,
+    /// From the boss's spawn until it dies.
+    NyloBoss,
+    VerzikP1,
+    VerzikP2,
+    VerzikP3,
+}
+
+/// A phase and the ticks over which it lasted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhaseInterval {
+    pub phase: Phase,
+    /// First tick of the phase.
+    pub start_tick: u32,
+    /// Tick after the last tick of the phase.
+    pub end_tick: u32,
+}
+
+impl PhaseInterval {
+    /// Returns whether the tick falls within the phase.
+    pub fn contains(&self, tick: u32) -> bool {
+        (self.start_tick..self.end_tick).contains(&tick)
+    }
+
+    /// Returns the number of ticks the phase lasted.
+    pub fn ticks(&self) -> u32 {
+        self.end_tick - self.start_tick
+    }
+}
+
+/// Determines the phases reached within a stage. Stages without tracked phases have none.
+pub(crate) fn compute(stage: &StageInfo) -> Vec<PhaseInterval> {
+    let boundaries = match stage.stage() {
+        blert::Stage::TobMaiden => maiden_boundaries(stage),
+        blert::Stage::TobNylocas => nylocas_boundaries(stage),
+        blert::Stage::TobVerzik => verzik_boundaries(stage),
+        _ => return Vec::new(),
+    };
+
+    intervals(&boundaries, stage.total_ticks() + 1)
+}
+
+/// Converts the start ticks of consecutive phases into intervals, with the last phase lasting
+/// until `end_tick`.
+fn intervals(boundaries: &[(Phase, u32)], end_tick: u32) -> Vec<PhaseInterval> {
+    boundaries
+        .iter()
+        .enumerate()
+        .map(|(i, &(phase, start_tick))| PhaseInterval {
+            phase,
+            start_tick,
+            end_tick: boundaries.get(i + 1).map_or(end_tick, |&(_, next)| next),
+        })
+        .collect()
+}
+
+fn maiden_boundaries(stage: &StageInfo) -> Vec<(Phase, u32)> {
+    let crab_ticks = stage
+        .npcs()
+        .filter(|npc| npc.is_maiden_matomenos())
+        .map(|npc| npc.spawn_tick)
+        .collect::<BTreeSet<_>>();
+
+    // Each set of crabs begins the next phase.
+    iter::once((Phase::MaidenSeventies, 0))
+        .chain(
+            [
+                Phase::MaidenFifties,
+                Phase::MaidenThirties,
+                Phase::MaidenFinal,
+            ]
+            .into_iter()
+            .zip(crab_ticks),
+        )
+        .collect()
+}
+
+fn nylocas_boundaries(stage: &StageInfo) -> Vec<(Phase, u32)> {
+    use blert::challenge_data::stage_npc::Type;
+
+    let mut boundaries = vec![(Phase::NyloWaves, 0)];
+
+    let last_wave_tick = stage
+        .npcs()
+        .filter_map(|npc| match &npc.r#type {
+            Some(Type::Nylo(nylo)) if nylo.wave == super::nylo::TOTAL_WAVES => Some(npc.spawn_tick),
+            _ => None,
+        })
+        .min();
+    let Some(last_wave_tick) = last_wave_tick else {
+        return boundaries;
+    };
+    boundaries.push((Phase::NyloCleanup, last_wave_tick));
+
+    // The room's other NPCs (its pillars) spawn at its start, so the boss is the first
+    // non-nylo NPC to spawn after the waves.
+    let boss_tick = stage
+        .npcs()
+        .filter(|npc| !matches!(npc.r#type, Some(Type::Nylo(_))))
+        .map(|npc| npc.spawn_tick)
+        .filter(|&tick| tick > last_wave_tick)
+        .min();
+    if let Some(boss_tick) = boss_tick {
+        boundaries.push((Phase::NyloBoss, boss_tick));
+    }
+
+    boundaries
+}
+
+fn verzik_boundaries(stage: &StageInfo) -> Vec<(Phase, u32)> {
+    // Verzik transforms into a different NPC at the start of each phase.
+    let first_update_as = |phase: u8| {
+        stage
+            .events_for_type(blert::event::Type::NpcUpdate)
+            .find(|event| {
+                event
+                    .npc
+                    .as_ref()
+                    .is_some_and(|npc| npc.boss_phase() == Some(phase))
+            })
+            .map(|event| event.tick)
+    };
+
+    let mut boundaries = vec![(Phase::VerzikP1, 0)];
+    if let Some(tick) = first_update_as(2) {
+        boundaries.push((Phase::VerzikP2, tick));
+    }
+    if let Some(tick) = first_update_as(3) {
+        boundaries.push((Phase::VerzikP3, tick));
+    }
+    boundaries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intervals_end_at_next_phase() {
+        let phases = intervals(
+            &[
+                (Phase::VerzikP1, 0),
+                (Phase::VerzikP2, 120),
+                (Phase::VerzikP3, 400),
+            ],
+            650,
+        );
+
+        assert_eq!(phases.len(), 3);
+        assert_eq!((phases[0].start_tick, phases[0].end_tick), (0, 120));
+        assert_eq!((phases[1].start_tick, phases[1].end_tick), (120, 400));
+        assert_eq!((phases[2].start_tick, phases[2].end_tick), (400, 650));
+        assert!(phases[1].contains(120));
+        assert!(!phases[1].contains(400));
+        assert_eq!(phases[2].ticks(), 250);
+    }
+}
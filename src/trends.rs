@@ -0,0 +1,381 @@
+//! Trends in a player's performance across their recent challenges.
+//!
+//! Trends are computed from the stored outputs of the latest successful program run on each of
+//! the player's challenges, so they only cover challenges which have been analyzed.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::analysis::Engine;
+use crate::error::Result;
+use crate::privacy::Pseudonymizer;
+use crate::results;
+
+/// Minimum number of values from which a trend is computed.
+const MIN_TREND_VALUES: usize = 3;
+
+/// Changes smaller than this fraction of a metric's mean over the window are considered stable.
+const STABLE_FRACTION: f64 = 0.05;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+pub struct TrendsRequest {
+    /// Only consider runs of this program. Defaults to the latest run of any program.
+    pub program: Option<String>,
+    /// Number of recent challenges to consider. Defaults to 20, up to 100.
+    pub raids: Option<u32>,
+}
+
+impl TrendsRequest {
+    const DEFAULT_RAIDS: u32 = 20;
+    const MAX_RAIDS: u32 = 100;
+
+    fn raids(&self) -> u32 {
+        self.raids
+            .unwrap_or(Self::DEFAULT_RAIDS)
+            .clamp(1, Self::MAX_RAIDS)
+    }
+}
+
+/// Direction in which a metric is moving, where lower values are better.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Improving,
+    Stable,
+    Declining,
+}
+
+/// The values of a metric over the player's recent challenges.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Trend {
+    /// Values of the metric, from the oldest challenge to the most recent.
+    pub values: Vec<f64>,
+    /// Least-squares change in the metric per challenge.
+    pub slope: f64,
+    pub direction: Direction,
+}
+
+impl Trend {
+    /// Fits a trend to `values`, ordered from oldest to newest. Returns `None` if there are too
+    /// few values to establish one.
+    fn fit(values: Vec<f64>) -> Option<Self> {
+        if values.len() < MIN_TREND_VALUES {
+            return None;
+        }
+
+        let n = values.len() as f64;
+        let mean_x = (n - 1.0) / 2.0;
+        let mean_y = values.iter().sum::<f64>() / n;
+        let (covariance, variance) =
+            values
+                .iter()
+                .enumerate()
+                .fold((0.0, 0.0), |(covariance, variance), (i, &y)| {
+                    let dx = i as f64 - mean_x;
+                    (covariance + dx * (y - mean_y), variance + dx * dx)
+                });
+        let slope = covariance / variance;
+
+        let change = slope * (n - 1.0);
+        let direction = if change.abs() <= (mean_y.abs() * STABLE_FRACTION).max(f64::EPSILON) {
+            Direction::Stable
+        } else if change < 0.0 {
+            Direction::Improving
+        } else {
+            Direction::Declining
+        };
+
+        Some(Self {
+            values,
+            slope,
+            direction,
+        })
+    }
+}
+
+/// How consistently a player plays the same role.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RoleConsistency {
+    /// The role the player played most often.
+    pub primary_role: String,
+    /// Fraction of challenges with a known role in which the player played their primary role.
+    pub consistency: f64,
+    /// Number of challenges in which the player played each role.
+    pub roles: BTreeMap<String, usize>,
+}
+
+/// Trends in a player's performance over their recent analyzed challenges.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerTrends {
+    pub username: String,
+    /// Number of analyzed challenges considered.
+    pub challenges: usize,
+    pub roles: Option<RoleConsistency>,
+    /// Deaths per challenge.
+    pub deaths: Option<Trend>,
+    /// Room times in ticks, keyed by stage.
+    pub splits: BTreeMap<String, Trend>,
+}
+
+/// The outputs of a run on one of the player's challenges, keyed by analyzer implementation.
+struct PlayerChallenge {
+    /// The player's username as recorded in the challenge.
+    username: String,
+    outputs: HashMap<String, Value>,
+}
+
+#[derive(sqlx::FromRow)]
+struct TrendRunRow {
+    id: i64,
+    program: String,
+    username: String,
+}
+
+#[derive(sqlx::FromRow)]
+struct TrendOutputRow {
+    run_id: i64,
+    analyzer: String,
+    output: Value,
+}
+
+impl results::Store {
+    /// Loads the latest successful run on each of a player's most recent challenges, most recent
+    /// first, along with the player's username as recorded in the challenge.
+    async fn load_player_runs(
+        &self,
+        username: &str,
+        request: &TrendsRequest,
+    ) -> Result<(Vec<TrendRunRow>, Vec<TrendOutputRow>)> {
+        let runs: Vec<TrendRunRow> = sqlx::query_as(
+            "
+            SELECT r.id, r.program, cp.username
+            FROM analysis_runs r
+            JOIN challenges c ON c.uuid = r.challenge_uuid
+            JOIN challenge_players cp ON cp.challenge_id = c.id
+            WHERE lower(cp.username) = lower($1)
+              AND r.status IN ('completed', 'partial')
+              AND r.id = (
+                  SELECT MAX(latest.id)
+                  FROM analysis_runs latest
+                  WHERE latest.challenge_uuid = r.challenge_uuid
+                    AND ($2::TEXT IS NULL OR latest.program = $2)
+                    AND latest.status IN ('completed', 'partial')
+              )
+            ORDER BY c.start_time DESC
+            LIMIT $3
+            ",
+        )
+        .bind(username)
+        .bind(&request.program)
+        .bind(i64::from(request.raids()))
+        .fetch_all(self.pool())
+        .await?;
+
+        let run_ids = runs.iter().map(|run| run.id).collect::<Vec<_>>();
+        let outputs: Vec<TrendOutputRow> = sqlx::query_as(
+            "
+            SELECT run_id, analyzer, output
+            FROM analyzer_outputs
+            WHERE run_id = ANY($1)
+            ",
+        )
+        .bind(&run_ids)
+        .fetch_all(self.pool())
+        .await?;
+
+        Ok((runs, outputs))
+    }
+}
+
+/// Computes the trends in a player's performance over their recent analyzed challenges.
+///
+/// Outputs are recognized by the implementation of the analyzer which produced them, resolved
+/// through the engine's loaded programs. Opted-out players are only found by their pseudonym, the
+/// name under which their per-player outputs are stored.
+pub async fn player_trends(
+    store: &results::Store,
+    engine: &Engine,
+    name: &str,
+    request: &TrendsRequest,
+) -> Result<PlayerTrends> {
+    let pseudonymizer = store.load_pseudonymizer().await?;
+    let Some(username) = pseudonymizer.username(name) else {
+        return Ok(compute_trends(name, &[], &pseudonymizer));
+    };
+    let (runs, outputs) = store.load_player_runs(&username, request).await?;
+
+    let mut by_run = HashMap::<i64, HashMap<String, Value>>::new();
+    let programs = runs
+        .iter()
+        .map(|run| (run.id, run.program.as_str()))
+        .collect::<HashMap<_, _>>();
+    for row in outputs {
+        let implementation = engine
+            .analyzer_implementation(programs[&row.run_id], &row.analyzer)
+            .map_or(row.analyzer.clone(), str::to_owned);
+        by_run
+            .entry(row.run_id)
+            .or_default()
+            .insert(implementation, row.output);
+    }
+
+    // Trends run from oldest to newest.
+    let challenges = runs
+        .into_iter()
+        .rev()
+        .map(|run| PlayerChallenge {
+            outputs: by_run.remove(&run.id).unwrap_or_default(),
+            username: run.username,
+        })
+        .collect::<Vec<_>>();
+
+    Ok(compute_trends(name, &challenges, &pseudonymizer))
+}
+
+fn compute_trends(
+    username: &str,
+    challenges: &[PlayerChallenge],
+    pseudonymizer: &Pseudonymizer,
+) -> PlayerTrends {
+    let player_output = |challenge: &'_ PlayerChallenge, implementation: &str| {
+        challenge
+            .outputs
+            .get(implementation)
+            .and_then(|output| output.get(pseudonymizer.name(&challenge.username)))
+            .cloned()
+    };
+
+    let mut roles = BTreeMap::<String, usize>::new();
+    for challenge in challenges {
+        if let Some(role) = player_output(challenge, "TobRoleAnalyzer")
+            .and_then(|roles| roles.get(0)?.as_str().map(str::to_owned))
+        {
+            *roles.entry(role).or_default() += 1;
+        }
+    }
+    let roles = roles
+        .iter()
+        .max_by_key(|(_, &count)| count)
+        .map(|(role, &count)| RoleConsistency {
+            primary_role: role.clone(),
+            consistency: count as f64 / roles.values().sum::<usize>() as f64,
+            roles: roles.clone(),
+        });
+
+    let deaths = challenges
+        .iter()
+        .filter_map(|challenge| {
+            player_output(challenge, "MetricAnalyzer")?
+                .get("deaths")?
+                .as_f64()
+        })
+        .collect();
+
+    let mut room_ticks = BTreeMap::<String, Vec<f64>>::new();
+    for challenge in challenges {
+        let Some(Value::Object(rooms)) = challenge
+            .outputs
+            .get("TobSplitsAnalyzer")
+            .and_then(|splits| splits.get("rooms"))
+        else {
+            continue;
+        };
+        for (stage, room) in rooms {
+            if let Some(ticks) = room.get("ticks").and_then(Value::as_f64) {
+                room_ticks.entry(stage.clone()).or_default().push(ticks);
+            }
+        }
+    }
+
+    PlayerTrends {
+        username: username.to_owned(),
+        challenges: challenges.len(),
+        roles,
+        deaths: Trend::fit(deaths),
+        splits: room_ticks
+            .into_iter()
+            .filter_map(|(stage, ticks)| Some((stage, Trend::fit(ticks)?)))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn challenge(role: &str, deaths: u32, maiden_ticks: u32) -> PlayerChallenge {
+        challenge_keyed_by("Alice", role, deaths, maiden_ticks)
+    }
+
+    /// Returns a challenge of Alice's whose per-player outputs are stored under `key`.
+    fn challenge_keyed_by(
+        key: &str,
+        role: &str,
+        deaths: u32,
+        maiden_ticks: u32,
+    ) -> PlayerChallenge {
+        PlayerChallenge {
+            username: "Alice".into(),
+            outputs: HashMap::from([
+                ("TobRoleAnalyzer".into(), json!({ key: [role, []] })),
+                (
+                    "MetricAnalyzer".into(),
+                    json!({ key: { "deaths": deaths } }),
+                ),
+                (
+                    "TobSplitsAnalyzer".into(),
+                    json!({ "rooms": { "TOB_MAIDEN": { "ticks": maiden_ticks } } }),
+                ),
+            ]),
+        }
+    }
+
+    #[test]
+    fn trends_are_computed_oldest_first() {
+        let challenges = [
+            challenge("Mage", 3, 200),
+            challenge("Mage", 2, 200),
+            challenge("Ranger", 1, 201),
+            challenge("Mage", 0, 200),
+        ];
+        let trends = compute_trends("alice", &challenges, &Pseudonymizer::new(String::new(), []));
+
+        assert_eq!(trends.challenges, 4);
+        let roles = trends.roles.unwrap();
+        assert_eq!(roles.primary_role, "Mage");
+        assert!((roles.consistency - 0.75).abs() < f64::EPSILON);
+
+        let deaths = trends.deaths.unwrap();
+        assert_eq!(deaths.values, [3.0, 2.0, 1.0, 0.0]);
+        assert!((deaths.slope + 1.0).abs() < 1e-9);
+        assert_eq!(deaths.direction, Direction::Improving);
+
+        assert_eq!(trends.splits["TOB_MAIDEN"].direction, Direction::Stable);
+        assert!(Trend::fit(vec![1.0, 2.0]).is_none());
+    }
+
+    #[test]
+    fn opted_out_players_outputs_are_found_by_pseudonym() {
+        let pseudonymizer = Pseudonymizer::new("salt".into(), ["Alice".to_string()]);
+        let alias = pseudonymizer.name("Alice");
+        let challenges = [
+            challenge_keyed_by(&alias, "Mage", 2, 200),
+            challenge_keyed_by(&alias, "Mage", 1, 201),
+            challenge_keyed_by(&alias, "Mage", 0, 199),
+        ];
+        let trends = compute_trends("alice", &challenges, &pseudonymizer);
+
+        assert_eq!(trends.roles.unwrap().primary_role, "Mage");
+        assert_eq!(trends.deaths.unwrap().values, [2.0, 1.0, 0.0]);
+        assert!(trends.splits.contains_key("TOB_MAIDEN"));
+    }
+}
@@ -0,0 +1,56 @@
+//! Accounting of the resources consumed by a program run, used to attribute infrastructure cost
+//! to programs and to identify pathological challenges.
+
+use std::time::Duration;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::data_repository::FetchStats;
+
+/// Resources consumed while loading and analyzing a challenge. Times are in microseconds.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema, JsonSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceUsage {
+    /// Total size of the files fetched from the data repository.
+    pub bytes_fetched: u64,
+
+    /// Time spent reading files from the data repository.
+    pub fetch_time_us: u64,
+
+    /// Time spent decoding fetched protobuf data.
+    pub decode_time_us: u64,
+
+    /// Time spent building the challenge model (event indices and player states) from the
+    /// decoded data.
+    pub state_build_time_us: u64,
+
+    /// Total time spent running analyzers, summed across workers.
+    pub analyzer_time_us: u64,
+}
+
+impl ResourceUsage {
+    /// Records a fetch from the data repository.
+    pub fn add_fetch(&mut self, stats: &FetchStats) {
+        self.bytes_fetched += stats.bytes;
+        self.fetch_time_us += micros(stats.fetch_time);
+        self.decode_time_us += micros(stats.decode_time);
+    }
+
+    /// Records time spent building the challenge model.
+    pub fn add_state_build_time(&mut self, duration: Duration) {
+        self.state_build_time_us += micros(duration);
+    }
+
+    /// Records time spent running an analyzer.
+    pub fn add_analyzer_time(&mut self, duration: Duration) {
+        self.analyzer_time_us += micros(duration);
+    }
+}
+
+fn micros(duration: Duration) -> u64 {
+    duration.as_micros().try_into().unwrap_or(u64::MAX)
+}
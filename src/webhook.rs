@@ -0,0 +1,156 @@
+//! Delivery of HTTP notifications, such as run completion callbacks, to external endpoints.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+
+use reqwest::Url;
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+
+/// Time allowed to establish a connection to an endpoint.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time allowed for a whole request to an endpoint, including its response.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Builds an HTTP client for notifying external endpoints. Requests time out so that a slow
+/// endpoint cannot hold up the run which notifies it, and redirects are not followed, as they
+/// could lead to an internal address.
+pub fn client() -> Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .map_err(|e| Error::Dependency(format!("Failed to build HTTP client: {e}")))
+}
+
+/// Checks that a caller-provided URL is safe to notify: it must use HTTPS and must not name a
+/// loopback, private or link-local host.
+pub fn validate_url(url: &str) -> Result<Url> {
+    let invalid = |reason: &str| Error::InvalidField(format!("callback URL {reason}"));
+
+    let url = Url::parse(url).map_err(|_| invalid("is not a valid URL"))?;
+    if url.scheme() != "https" {
+        return Err(invalid("must use https"));
+    }
+
+    let host = url.host_str().ok_or_else(|| invalid("has no host"))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let host = host.strip_suffix('.').unwrap_or(host).to_ascii_lowercase();
+    if host == "localhost" || host.ends_with(".localhost") {
+        return Err(invalid("must not be a local address"));
+    }
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        if !is_public(ip) {
+            return Err(invalid("must not be a local address"));
+        }
+    }
+
+    Ok(url)
+}
+
+/// POSTs `payload` as JSON to a caller-provided URL. The URL's host is resolved first, and the
+/// request is not sent unless it resolves only to public addresses, as its DNS records may have
+/// changed since the URL was validated.
+pub async fn post(client: &reqwest::Client, url: &str, payload: &impl Serialize) -> Result<()> {
+    let url = validate_url(url)?;
+    check_resolved(&url).await?;
+
+    client
+        .post(url)
+        .json(payload)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|e| Error::Dependency(e.to_string()))?;
+    Ok(())
+}
+
+/// Resolves the host of a validated URL, failing if any of its addresses is not public.
+async fn check_resolved(url: &Url) -> Result<()> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| Error::InvalidField("callback URL has no host".into()))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = url.port_or_known_default().unwrap_or(443);
+
+    let mut addresses = tokio::net::lookup_host((host, port)).await?.peekable();
+    if addresses.peek().is_none() {
+        return Err(Error::InvalidField(format!("{host} does not resolve")));
+    }
+    if let Some(address) = addresses.find(|address| !is_public(address.ip())) {
+        return Err(Error::InvalidField(format!(
+            "{host} resolves to non-public address {}",
+            address.ip()
+        )));
+    }
+    Ok(())
+}
+
+/// Returns whether an address is reachable on the public internet, as opposed to being a
+/// loopback, private, link-local or otherwise reserved address.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    let shared = a == 100 && (b & 0xc0) == 64;
+
+    !(ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || shared
+        || a == 0)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    let unique_local = (first & 0xfe00) == 0xfc00;
+    let link_local = (first & 0xffc0) == 0xfe80;
+
+    !(ip.is_loopback() || ip.is_unspecified() || ip.is_multicast() || unique_local || link_local)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_public_https_urls_are_accepted() {
+        assert!(validate_url("https://example.com/hook").is_ok());
+        assert!(validate_url("https://93.184.216.34/hook").is_ok());
+
+        for url in [
+            "http://example.com/hook",
+            "ftp://example.com/hook",
+            "not a url",
+            "https://localhost/hook",
+            "https://api.localhost./hook",
+            "https://127.0.0.1/hook",
+            "https://10.1.2.3/hook",
+            "https://192.168.0.1/hook",
+            "https://169.254.169.254/latest/meta-data",
+            "https://100.64.0.1/hook",
+            "https://0.0.0.0/hook",
+            "https://[::1]/hook",
+            "https://[fd00::1]/hook",
+            "https://[fe80::1]/hook",
+            "https://[::ffff:127.0.0.1]/hook",
+        ] {
+            assert!(validate_url(url).is_err(), "{url} was accepted");
+        }
+    }
+}